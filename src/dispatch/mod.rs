@@ -3,19 +3,29 @@
 use std::{collections::HashMap, convert::Infallible, marker::PhantomData, pin::Pin, task::Poll};
 
 use anyhow::{anyhow, bail};
-use futures::{future::BoxFuture, Future, FutureExt};
-use thiserror::Error;
+use futures::{Future, FutureExt};
 use tower::{util::BoxService, Service};
+use tracing::{debug_span, Instrument};
 
 use crate::resp;
 
-#[derive(Debug, Error)]
-pub enum Error {}
+pub mod table;
 
+/// A command named `name`, whose arguments `args` are raw RESP values unless they were already
+/// parsed by the caller
 #[derive(Clone)]
-pub struct Command {
+pub struct Command<A = Vec<resp::Value>> {
     name: String,
-    args: Vec<resp::Value>,
+    args: A,
+}
+
+impl<A> Command<A> {
+    pub fn new(name: impl Into<String>, args: A) -> Self {
+        Self {
+            name: name.into(),
+            args,
+        }
+    }
 }
 
 impl TryFrom<resp::Value> for Command {
@@ -46,67 +56,56 @@ impl TryFrom<resp::Value> for Command {
     }
 }
 
-pub trait CommandHandlerRegistry:
-    Service<
-    Command,
-    Response = resp::Value,
-    Error = Infallible,
-    Future = BoxFuture<'static, Result<resp::Value, Infallible>>,
->
-{
-    fn handled_commands(&self) -> &[&'static str];
-}
-
 pub trait IntoValue {
     fn into_value(self) -> resp::Value;
 }
 
-impl<E> IntoValue for E
-where
-    E: AsRef<dyn std::error::Error>,
-{
+impl IntoValue for anyhow::Error {
     fn into_value(self) -> resp::Value {
-        resp::Value::error(self.as_ref().to_string())
+        resp::Value::error(self.to_string())
     }
 }
 
-struct Blah;
-
-impl IntoValue for Blah {
+impl IntoValue for resp::Value {
     fn into_value(self) -> resp::Value {
-        resp::Value::simple("lol")
+        self
     }
 }
 
-impl IntoValue for resp::Value {
+/// Arguments handed over as they are can not fail to convert
+impl IntoValue for Infallible {
     fn into_value(self) -> resp::Value {
-        self
+        match self {}
     }
 }
 
-pub trait IntoHandlerService<S> {
+/// A handler of commands whose arguments are of type `A`, once bound to its state
+pub type HandlerService<A> = BoxService<Command<A>, resp::Value, Infallible>;
+
+pub trait IntoHandlerService<S, A = Vec<resp::Value>> {
     fn name(&self) -> &'static str;
 
-    fn into_service(self, state: S) -> BoxService<Command, resp::Value, Infallible>;
+    fn into_service(self, state: S) -> HandlerService<A>;
 }
 
-struct MakeHandlerService<C, H> {
+pub struct MakeHandlerService<C, H, A = Vec<resp::Value>> {
     handler: H,
     name: &'static str,
-    _phantom: PhantomData<C>,
+    _phantom: PhantomData<fn(C, A)>,
 }
 
-impl<C, S, H> IntoHandlerService<S> for MakeHandlerService<C, H>
+impl<C, S, A, H> IntoHandlerService<S, A> for MakeHandlerService<C, H, A>
 where
-    H: CommandHandler<C, S>,
+    H: CommandHandler<C, S, A>,
     C: 'static,
     S: Clone + Send + 'static,
+    A: 'static,
 {
     fn name(&self) -> &'static str {
         self.name
     }
 
-    fn into_service(self, state: S) -> BoxService<Command, resp::Value, Infallible> {
+    fn into_service(self, state: S) -> HandlerService<A> {
         BoxService::new(CommandHandlerService {
             handler: self.handler,
             state,
@@ -115,14 +114,15 @@ where
     }
 }
 
-pub struct CommandHandlerInvoker<S> {
+pub struct CommandHandlerInvoker<S, A = Vec<resp::Value>> {
     state: S,
-    invokers: HashMap<&'static str, Vec<BoxService<Command, resp::Value, Infallible>>>,
+    invokers: HashMap<&'static str, Vec<HandlerService<A>>>,
 }
 
-impl<S> CommandHandlerInvoker<S>
+impl<S, A> CommandHandlerInvoker<S, A>
 where
     S: Clone + Send + 'static,
+    A: Clone,
 {
     pub fn with_state(state: S) -> Self {
         Self {
@@ -133,7 +133,7 @@ where
 
     pub fn handles<H>(&mut self, svc: H) -> &mut Self
     where
-        H: IntoHandlerService<S>,
+        H: IntoHandlerService<S, A>,
     {
         let name = svc.name();
         self.invokers
@@ -143,13 +143,19 @@ where
         self
     }
 
-    async fn call(&mut self, cmd: Command) -> Vec<resp::Value> {
+    /// Whether some handlers were registered for the command named `name`
+    pub fn dispatches(&self, name: &str) -> bool {
+        self.invokers.contains_key(name)
+    }
+
+    pub async fn call(&mut self, cmd: Command<A>) -> Vec<resp::Value> {
         let mut responses = Vec::new();
 
         if let Some(invokers) = self.invokers.get_mut(cmd.name.as_str()) {
             for invoker in invokers {
                 let res = invoker
                     .call(cmd.clone())
+                    .instrument(debug_span!("dispatch", command = %cmd.name))
                     .await
                     .expect("calling a handler service is infaillible");
                 responses.push(res);
@@ -160,12 +166,12 @@ where
     }
 }
 
-pub trait CommandHandler<C, S>: Copy + Clone + Send + 'static {
+pub trait CommandHandler<C, S, A = Vec<resp::Value>>: Copy + Clone + Send + 'static {
     type Future: Future<Output = resp::Value> + Send + 'static;
 
-    fn handle(self, cmd: Command, state: S) -> Self::Future;
+    fn handle(self, cmd: Command<A>, state: S) -> Self::Future;
 
-    fn into_service(self, name: &'static str) -> MakeHandlerService<C, Self> {
+    fn into_service(self, name: &'static str) -> MakeHandlerService<C, Self, A> {
         MakeHandlerService {
             handler: self,
             name,
@@ -180,9 +186,9 @@ struct CommandHandlerService<C, S, H, Fut> {
     _phantom: PhantomData<fn(C) -> Fut>,
 }
 
-impl<C, S, H, Fut> Service<Command> for CommandHandlerService<C, S, H, Fut>
+impl<C, S, A, H, Fut> Service<Command<A>> for CommandHandlerService<C, S, H, Fut>
 where
-    H: CommandHandler<C, S, Future = Fut>,
+    H: CommandHandler<C, S, A, Future = Fut>,
     S: Clone + Send + 'static,
     Fut: Future<Output = resp::Value> + Send + 'static,
 {
@@ -195,10 +201,9 @@ where
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Command) -> Self::Future {
+    fn call(&mut self, req: Command<A>) -> Self::Future {
         let res = CommandHandler::handle(self.handler, req, self.state.clone());
-        let res = res.map(Ok as _);
-        res
+        res.map(Ok as _)
     }
 }
 
@@ -219,8 +224,7 @@ where
         // This is safe because we are already behind a Pin
         let fut = unsafe {
             let Self { fut } = self.get_unchecked_mut();
-            let fut = Pin::new_unchecked(fut);
-            fut
+            Pin::new_unchecked(fut)
         };
 
         // Poll the future
@@ -234,10 +238,10 @@ where
     }
 }
 
-impl<C, S, E, F, Fut, R> CommandHandler<C, S> for F
+impl<C, S, A, E, F, Fut, R> CommandHandler<C, S, A> for F
 where
     F: FnOnce(C, S) -> Fut + Copy + Clone + Send + 'static,
-    C: TryFrom<Vec<resp::Value>, Error = E>,
+    C: TryFrom<A, Error = E>,
     E: IntoValue,
     Fut: Future<Output = R> + Send + 'static,
     R: Into<resp::Value>,
@@ -245,7 +249,7 @@ where
     type Future =
         futures::future::Either<CommandHandlerFuture<Fut>, futures::future::Ready<resp::Value>>;
 
-    fn handle(self, cmd: Command, state: S) -> Self::Future {
+    fn handle(self, cmd: Command<A>, state: S) -> Self::Future {
         // First try to convert the create the typed commands from the list of arguments
         match C::try_from(cmd.args) {
             Ok(cmd) => {
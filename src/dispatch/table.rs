@@ -0,0 +1,1471 @@
+//! Declarative table of the commands known to the server, along with their arity, flags, ACL
+//! categories, key specifications, subcommands and documentation.
+//!
+//! The table is what `COMMAND` reports to clients, and what arities and flags get checked
+//! against. Subcommands are named `<command>|<subcommand>`, like Redis does.
+
+use thiserror::Error;
+
+use crate::resp::Value;
+
+#[derive(Debug, Error)]
+pub enum GetKeysError {
+    #[error("Invalid command specified")]
+    InvalidCommand,
+
+    #[error("Invalid number of arguments specified for command")]
+    InvalidArity,
+
+    #[error("Invalid arguments specified for command")]
+    InvalidArguments,
+
+    #[error("The command has no key arguments")]
+    NoKeys,
+}
+
+/// A flag of a command, as reported by `COMMAND INFO`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Flag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    PubSub,
+    NoScript,
+    Blocking,
+    Loading,
+    Stale,
+    SkipSlowlog,
+    Fast,
+    NoAuth,
+
+    /// The command may feed the replication stream, and is held back by `CLIENT PAUSE WRITE`
+    MayReplicate,
+    NoMandatoryKeys,
+    AllowBusy,
+}
+
+impl Flag {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Write => "write",
+            Self::ReadOnly => "readonly",
+            Self::DenyOom => "denyoom",
+            Self::Admin => "admin",
+            Self::PubSub => "pubsub",
+            Self::NoScript => "noscript",
+            Self::Blocking => "blocking",
+            Self::Loading => "loading",
+            Self::Stale => "stale",
+            Self::SkipSlowlog => "skip_slowlog",
+            Self::Fast => "fast",
+            Self::NoAuth => "no_auth",
+            Self::MayReplicate => "may_replicate",
+            Self::NoMandatoryKeys => "no_mandatory_keys",
+            Self::AllowBusy => "allow_busy",
+        }
+    }
+}
+
+use Flag::*;
+
+/// How the keys of a command are found, from where their search begins
+#[derive(Debug, Copy, Clone)]
+pub enum FindKeys {
+    /// Keys up to the argument at `lastkey` from the beginning, or from the end if negative.
+    /// Every `step` argument is a key, and a `limit` other than 0 or 1 only takes that fraction
+    /// of the remaining arguments
+    Range {
+        lastkey: i32,
+        step: usize,
+        limit: usize,
+    },
+
+    /// Keys whose number is given by the argument at `keynumidx`, the first key being at
+    /// `firstkey` and every `step` argument being a key
+    Keynum {
+        keynumidx: usize,
+        firstkey: usize,
+        step: usize,
+    },
+}
+
+/// How to find some of the keys of a command, and how they are accessed
+#[derive(Debug, Copy, Clone)]
+pub struct KeySpec {
+    /// Index of the argument where the search for the keys begins, counting from the end if
+    /// negative
+    pub begin_search: i32,
+    pub find_keys: FindKeys,
+    pub flags: &'static [&'static str],
+}
+
+/// A single key, at `index`
+const fn key(index: i32, flags: &'static [&'static str]) -> KeySpec {
+    KeySpec {
+        begin_search: index,
+        find_keys: FindKeys::Range {
+            lastkey: 0,
+            step: 1,
+            limit: 0,
+        },
+        flags,
+    }
+}
+
+/// Every argument from `index` onwards, as keys
+const fn keys_from(index: i32, flags: &'static [&'static str]) -> KeySpec {
+    KeySpec {
+        begin_search: index,
+        find_keys: FindKeys::Range {
+            lastkey: -1,
+            step: 1,
+            limit: 0,
+        },
+        flags,
+    }
+}
+
+/// Keys preceded by their number at `index`, like the keys of `EVAL`
+const fn numkeys(index: i32, flags: &'static [&'static str]) -> KeySpec {
+    KeySpec {
+        begin_search: index,
+        find_keys: FindKeys::Keynum {
+            keynumidx: 0,
+            firstkey: 1,
+            step: 1,
+        },
+        flags,
+    }
+}
+
+/// Description of a command
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+
+    /// Number of arguments, the name of the command included. A negative arity is the minimum
+    /// number of arguments
+    pub arity: i32,
+
+    pub flags: &'static [Flag],
+
+    /// Names of the ACL categories of the command
+    pub categories: &'static [&'static str],
+
+    pub key_specs: &'static [KeySpec],
+    pub subcommands: &'static [CommandSpec],
+
+    pub group: &'static str,
+    pub since: &'static str,
+    pub summary: &'static str,
+    pub complexity: &'static str,
+}
+
+/// A command taking `arity` arguments, without flags, categories or keys
+const fn command(
+    name: &'static str,
+    arity: i32,
+    group: &'static str,
+    since: &'static str,
+    summary: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags: &[],
+        categories: &[],
+        key_specs: &[],
+        subcommands: &[],
+        group,
+        since,
+        summary,
+        complexity: "O(1)",
+    }
+}
+
+impl CommandSpec {
+    const fn flags(self, flags: &'static [Flag]) -> Self {
+        Self { flags, ..self }
+    }
+
+    const fn categories(self, categories: &'static [&'static str]) -> Self {
+        Self { categories, ..self }
+    }
+
+    const fn keys(self, key_specs: &'static [KeySpec]) -> Self {
+        Self { key_specs, ..self }
+    }
+
+    const fn subcommands(self, subcommands: &'static [CommandSpec]) -> Self {
+        Self {
+            subcommands,
+            ..self
+        }
+    }
+
+    const fn complexity(self, complexity: &'static str) -> Self {
+        Self { complexity, ..self }
+    }
+
+    /// Whether the command can be called with `argc` arguments, its name included
+    pub fn accepts(&self, argc: usize) -> bool {
+        if self.arity >= 0 {
+            argc == self.arity as usize
+        } else {
+            argc >= self.arity.unsigned_abs() as usize
+        }
+    }
+
+    /// Whether the command can be called as is, rather than only through its subcommands
+    pub fn callable(&self) -> bool {
+        self.subcommands.is_empty() || self.accepts(1)
+    }
+
+    /// Whether the keys of the command can not be found at fixed positions
+    fn movablekeys(&self) -> bool {
+        self.key_specs
+            .iter()
+            .any(|spec| matches!(spec.find_keys, FindKeys::Keynum { .. }))
+    }
+
+    /// Position of the first key, of the last key and step between keys, as reported by
+    /// `COMMAND INFO` before key specifications existed
+    fn legacy_range(&self) -> (i64, i64, i64) {
+        if self.movablekeys() {
+            return (0, 0, 0);
+        }
+
+        match self.key_specs.first() {
+            Some(KeySpec {
+                begin_search: first,
+                find_keys: FindKeys::Range { lastkey, step, .. },
+                ..
+            }) => {
+                let last = if *lastkey < 0 {
+                    *lastkey
+                } else {
+                    first + lastkey
+                };
+                (*first as i64, last as i64, *step as i64)
+            }
+            _ => (0, 0, 0),
+        }
+    }
+
+    /// Reply of `COMMAND INFO` describing the command
+    pub fn info(&self) -> Value {
+        let mut flags = self
+            .flags
+            .iter()
+            .map(|flag| Value::simple(flag.name()))
+            .collect::<Vec<_>>();
+        if self.movablekeys() {
+            flags.push(Value::simple("movablekeys"));
+        }
+
+        let (first, last, step) = self.legacy_range();
+        Value::from_iter([
+            Value::bulk(self.name),
+            Value::Int(self.arity as i64),
+            Value::Array(flags),
+            Value::Int(first),
+            Value::Int(last),
+            Value::Int(step),
+            Value::from_iter(
+                self.categories
+                    .iter()
+                    .map(|category| Value::simple(format!("@{category}"))),
+            ),
+            Value::Array(Vec::new()),
+            Value::from_iter(self.key_specs.iter().map(KeySpec::info)),
+            Value::from_iter(self.subcommands.iter().map(CommandSpec::info)),
+        ])
+    }
+
+    /// Reply of `COMMAND DOCS` documenting the command
+    pub fn docs(&self) -> Value {
+        let mut docs = vec![
+            Value::bulk("summary"),
+            Value::bulk(self.summary),
+            Value::bulk("since"),
+            Value::bulk(self.since),
+            Value::bulk("group"),
+            Value::bulk(self.group),
+            Value::bulk("complexity"),
+            Value::bulk(self.complexity),
+        ];
+
+        if !self.subcommands.is_empty() {
+            docs.push(Value::bulk("subcommands"));
+            docs.push(Value::from_iter(
+                self.subcommands
+                    .iter()
+                    .flat_map(|sub| [Value::bulk(sub.name), sub.docs()]),
+            ));
+        }
+
+        Value::Array(docs)
+    }
+
+    /// Index in `argv` of every key of the command called with `argv`, along with the flags of
+    /// the key
+    pub fn keys_of(
+        &self,
+        argv: &[String],
+    ) -> Result<Vec<(usize, &'static [&'static str])>, GetKeysError> {
+        let mut keys = Vec::new();
+        for spec in self.key_specs {
+            keys.extend(spec.find(argv)?.into_iter().map(|i| (i, spec.flags)));
+        }
+        Ok(keys)
+    }
+}
+
+impl KeySpec {
+    /// Indexes of the keys found by this specification in `argv`
+    fn find(&self, argv: &[String]) -> Result<Vec<usize>, GetKeysError> {
+        let argc = argv.len() as i64;
+
+        let start = if self.begin_search >= 0 {
+            self.begin_search as i64
+        } else {
+            argc + self.begin_search as i64
+        };
+        if start <= 0 || start >= argc {
+            return Ok(Vec::new());
+        }
+
+        match self.find_keys {
+            FindKeys::Range {
+                lastkey,
+                step,
+                limit,
+            } => {
+                let last = if lastkey >= 0 {
+                    start + lastkey as i64
+                } else if limit <= 1 {
+                    argc + lastkey as i64
+                } else {
+                    start + (argc - start) / limit as i64 + lastkey as i64
+                };
+
+                Ok((start..=last.min(argc - 1))
+                    .step_by(step.max(1))
+                    .map(|i| i as usize)
+                    .collect())
+            }
+            FindKeys::Keynum {
+                keynumidx,
+                firstkey,
+                step,
+            } => {
+                let start = start as usize;
+                let count: usize = argv
+                    .get(start + keynumidx)
+                    .and_then(|count| count.parse().ok())
+                    .ok_or(GetKeysError::InvalidArguments)?;
+
+                let first = start + firstkey;
+                let keys = (0..count)
+                    .map(|i| first + i * step.max(1))
+                    .collect::<Vec<_>>();
+                if keys.last().is_some_and(|last| *last >= argv.len()) {
+                    return Err(GetKeysError::InvalidArguments);
+                }
+                Ok(keys)
+            }
+        }
+    }
+
+    /// Description of the specification, as reported by `COMMAND INFO`
+    fn info(&self) -> Value {
+        let begin_search = Value::from_iter([
+            Value::bulk("type"),
+            Value::bulk("index"),
+            Value::bulk("spec"),
+            Value::from_iter([Value::bulk("index"), Value::Int(self.begin_search as i64)]),
+        ]);
+
+        let find_keys = match self.find_keys {
+            FindKeys::Range {
+                lastkey,
+                step,
+                limit,
+            } => Value::from_iter([
+                Value::bulk("type"),
+                Value::bulk("range"),
+                Value::bulk("spec"),
+                Value::from_iter([
+                    Value::bulk("lastkey"),
+                    Value::Int(lastkey as i64),
+                    Value::bulk("step"),
+                    Value::Int(step as i64),
+                    Value::bulk("limit"),
+                    Value::Int(limit as i64),
+                ]),
+            ]),
+            FindKeys::Keynum {
+                keynumidx,
+                firstkey,
+                step,
+            } => Value::from_iter([
+                Value::bulk("type"),
+                Value::bulk("keynum"),
+                Value::bulk("spec"),
+                Value::from_iter([
+                    Value::bulk("keynumidx"),
+                    Value::Int(keynumidx as i64),
+                    Value::bulk("firstkey"),
+                    Value::Int(firstkey as i64),
+                    Value::bulk("step"),
+                    Value::Int(step as i64),
+                ]),
+            ]),
+        };
+
+        Value::from_iter([
+            Value::bulk("flags"),
+            Value::from_iter(self.flags.iter().map(|flag| Value::simple(*flag))),
+            Value::bulk("begin_search"),
+            begin_search,
+            Value::bulk("find_keys"),
+            find_keys,
+        ])
+    }
+}
+
+/// The command named `name`, or its subcommand named `sub` if it has one
+pub fn find(name: &str, sub: Option<&str>) -> Option<&'static CommandSpec> {
+    let spec = COMMANDS
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))?;
+
+    let sub = sub.and_then(|sub| {
+        spec.subcommands.iter().find(|spec| {
+            spec.name
+                .split_once('|')
+                .is_some_and(|(_, name)| name.eq_ignore_ascii_case(sub))
+        })
+    });
+    Some(sub.unwrap_or(spec))
+}
+
+/// The command or subcommand with the full name `name`, such as `get` or `config|get`
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    match name.split_once('|') {
+        Some((name, sub)) => find(name, Some(sub)).filter(|spec| spec.name.contains('|')),
+        None => find(name, None),
+    }
+}
+
+/// Every command along with its subcommands, each command preceding its subcommands
+pub fn all() -> impl Iterator<Item = &'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .flat_map(|spec| std::iter::once(spec).chain(spec.subcommands))
+}
+
+/// Keys of the command called with `argv`, along with their flags
+pub fn keys(argv: &[String]) -> Result<Vec<(&str, &'static [&'static str])>, GetKeysError> {
+    let name = argv.first().ok_or(GetKeysError::InvalidCommand)?;
+    let spec = find(name, argv.get(1).map(String::as_str)).ok_or(GetKeysError::InvalidCommand)?;
+    if !spec.accepts(argv.len()) {
+        return Err(GetKeysError::InvalidArity);
+    }
+
+    let keys = spec.keys_of(argv)?;
+    if keys.is_empty() {
+        return Err(GetKeysError::NoKeys);
+    }
+    Ok(keys
+        .into_iter()
+        .map(|(i, flags)| (argv[i].as_str(), flags))
+        .collect())
+}
+
+const RO: &[&str] = &["RO"];
+const RO_ACCESS: &[&str] = &["RO", "ACCESS"];
+const RW_ACCESS_UPDATE: &[&str] = &["RW", "ACCESS", "UPDATE"];
+
+const GET_KEYS: &[KeySpec] = &[key(1, RO_ACCESS)];
+// Without the GET option, SET only overwrites its key
+const SET_KEYS: &[KeySpec] = &[key(1, &["OW", "UPDATE"])];
+const DEL_KEYS: &[KeySpec] = &[keys_from(1, &["RM", "DELETE"])];
+const MOVE_KEYS: &[KeySpec] = &[key(1, &["RW", "ACCESS", "DELETE"])];
+const WATCH_KEYS: &[KeySpec] = &[keys_from(1, RO)];
+const SUBCOMMAND_KEY: &[KeySpec] = &[key(2, RO)];
+const SCRIPT_KEYS: &[KeySpec] = &[numkeys(2, RW_ACCESS_UPDATE)];
+const SCRIPT_RO_KEYS: &[KeySpec] = &[numkeys(2, RO_ACCESS)];
+
+const CONNECTION: &[&str] = &["fast", "connection"];
+const ADMIN: &[&str] = &["admin", "slow", "dangerous"];
+const SLOW_CONNECTION: &[&str] = &["slow", "connection"];
+const ADMIN_CONNECTION: &[&str] = &["admin", "slow", "dangerous", "connection"];
+const PUBSUB: &[&str] = &["pubsub", "slow"];
+const SCRIPTING: &[&str] = &["slow", "scripting"];
+const WRITE_SCRIPTING: &[&str] = &["write", "slow", "scripting"];
+const KEYSPACE_READ: &[&str] = &["keyspace", "read", "slow"];
+
+/// Flags of the commands that can not be called from scripts but work on stale replicas
+const NOSCRIPT_STALE: &[Flag] = &[NoScript, Loading, Stale];
+
+const PUBSUB_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "pubsub|channels",
+        -2,
+        "pubsub",
+        "2.8.0",
+        "Returns the active channels.",
+    )
+    .flags(&[PubSub, Loading, Stale])
+    .categories(PUBSUB)
+    .complexity("O(N) where N is the number of active channels"),
+    command(
+        "pubsub|numsub",
+        -2,
+        "pubsub",
+        "2.8.0",
+        "Returns a count of subscribers to channels.",
+    )
+    .flags(&[PubSub, Loading, Stale])
+    .categories(PUBSUB)
+    .complexity("O(N) for the NUMSUB subcommand, where N is the number of requested channels"),
+    command(
+        "pubsub|numpat",
+        2,
+        "pubsub",
+        "2.8.0",
+        "Returns a count of unique pattern subscriptions.",
+    )
+    .flags(&[PubSub, Loading, Stale])
+    .categories(PUBSUB),
+    command(
+        "pubsub|shardchannels",
+        -2,
+        "pubsub",
+        "7.0.0",
+        "Returns the active shard channels.",
+    )
+    .flags(&[PubSub, Loading, Stale])
+    .categories(PUBSUB)
+    .complexity("O(N) where N is the number of active shard channels"),
+    command(
+        "pubsub|shardnumsub",
+        -2,
+        "pubsub",
+        "7.0.0",
+        "Returns the count of subscribers of shard channels.",
+    )
+    .flags(&[PubSub, Loading, Stale])
+    .categories(PUBSUB)
+    .complexity(
+        "O(N) for the SHARDNUMSUB subcommand, where N is the number of requested shard channels",
+    ),
+];
+
+const SCRIPT_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "script|load",
+        3,
+        "scripting",
+        "2.6.0",
+        "Loads a server-side Lua script to the script cache.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SCRIPTING)
+    .complexity("O(N) with N being the length in bytes of the script body."),
+    command(
+        "script|exists",
+        -3,
+        "scripting",
+        "2.6.0",
+        "Determines whether server-side Lua scripts exist in the script cache.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SCRIPTING)
+    .complexity("O(N) with N being the number of scripts to check"),
+    command(
+        "script|flush",
+        -2,
+        "scripting",
+        "2.6.0",
+        "Removes all server-side Lua scripts from the script cache.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SCRIPTING)
+    .complexity("O(N) with N being the number of scripts in cache"),
+    command(
+        "script|kill",
+        2,
+        "scripting",
+        "2.6.0",
+        "Terminates a server-side Lua script during execution.",
+    )
+    .flags(&[NoScript, Loading, Stale, AllowBusy])
+    .categories(SCRIPTING),
+];
+
+const FUNCTION_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "function|load",
+        -3,
+        "scripting",
+        "7.0.0",
+        "Creates a library.",
+    )
+    .flags(&[Write, DenyOom, NoScript])
+    .categories(WRITE_SCRIPTING)
+    .complexity("O(1) (considering compilation time is redundant)"),
+    command(
+        "function|list",
+        -2,
+        "scripting",
+        "7.0.0",
+        "Returns information about all libraries.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SCRIPTING)
+    .complexity("O(N) where N is the number of functions"),
+    command(
+        "function|delete",
+        3,
+        "scripting",
+        "7.0.0",
+        "Deletes a library and its functions.",
+    )
+    .flags(&[Write, NoScript])
+    .categories(WRITE_SCRIPTING),
+    command(
+        "function|flush",
+        -2,
+        "scripting",
+        "7.0.0",
+        "Deletes all libraries and functions.",
+    )
+    .flags(&[Write, NoScript])
+    .categories(WRITE_SCRIPTING)
+    .complexity("O(N) where N is the number of functions deleted"),
+    command(
+        "function|dump",
+        2,
+        "scripting",
+        "7.0.0",
+        "Dumps all libraries into a serialized binary payload.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SCRIPTING)
+    .complexity("O(N) where N is the number of functions"),
+    command(
+        "function|restore",
+        -3,
+        "scripting",
+        "7.0.0",
+        "Restores all libraries from a payload.",
+    )
+    .flags(&[Write, DenyOom, NoScript])
+    .categories(WRITE_SCRIPTING)
+    .complexity("O(N) where N is the number of functions on the payload"),
+];
+
+const ACL_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "acl|setuser",
+        -3,
+        "server",
+        "6.0.0",
+        "Creates and modifies an ACL user and its rules.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N). Where N is the number of rules provided."),
+    command(
+        "acl|getuser",
+        3,
+        "server",
+        "6.0.0",
+        "Lists the ACL rules of a user.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity(
+        "O(N). Where N is the number of password, command and pattern rules that the user has.",
+    ),
+    command(
+        "acl|deluser",
+        -3,
+        "server",
+        "6.0.0",
+        "Deletes ACL users, and terminates their connections.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(1) amortized time considering the typical user."),
+    command(
+        "acl|list",
+        2,
+        "server",
+        "6.0.0",
+        "Dumps the effective rules in ACL file format.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N). Where N is the number of configured users."),
+    command("acl|users", 2, "server", "6.0.0", "Lists all ACL users.")
+        .flags(&[Admin, NoScript, Loading, Stale])
+        .categories(ADMIN)
+        .complexity("O(N). Where N is the number of configured users."),
+    command(
+        "acl|whoami",
+        2,
+        "server",
+        "6.0.0",
+        "Returns the authenticated username of the current connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(&["slow"]),
+    command(
+        "acl|cat",
+        -2,
+        "server",
+        "6.0.0",
+        "Lists the ACL categories, or the commands inside a category.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(&["slow"])
+    .complexity("O(1) since the categories and commands are a fixed set."),
+    command(
+        "acl|log",
+        -2,
+        "server",
+        "6.0.0",
+        "Lists recent security events generated due to ACL rules.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) with N being the number of entries shown."),
+    command(
+        "acl|dryrun",
+        -4,
+        "server",
+        "7.0.0",
+        "Simulates the execution of a command by a user, without executing the command.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "acl|genpass",
+        -2,
+        "server",
+        "6.0.0",
+        "Generates a pseudorandom, secure password that can be used to identify ACL users.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(&["slow"]),
+    command(
+        "acl|save",
+        2,
+        "server",
+        "6.0.0",
+        "Saves the effective ACL rules in the configured ACL file.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N). Where N is the number of configured users."),
+    command(
+        "acl|load",
+        2,
+        "server",
+        "6.0.0",
+        "Reloads the rules from the configured ACL file.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N). Where N is the number of configured users."),
+];
+
+const OBJECT_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "object|freq",
+        3,
+        "generic",
+        "4.0.0",
+        "Returns the logarithmic access frequency counter of a Redis object.",
+    )
+    .flags(&[ReadOnly])
+    .categories(KEYSPACE_READ)
+    .keys(SUBCOMMAND_KEY),
+    command(
+        "object|idletime",
+        3,
+        "generic",
+        "2.2.3",
+        "Returns the time since the last access to a Redis object.",
+    )
+    .flags(&[ReadOnly])
+    .categories(KEYSPACE_READ)
+    .keys(SUBCOMMAND_KEY),
+    command(
+        "object|encoding",
+        3,
+        "generic",
+        "2.2.3",
+        "Returns the internal encoding of a Redis object.",
+    )
+    .flags(&[ReadOnly])
+    .categories(KEYSPACE_READ)
+    .keys(SUBCOMMAND_KEY),
+    command(
+        "object|refcount",
+        3,
+        "generic",
+        "2.2.3",
+        "Returns the reference count of a value of a key.",
+    )
+    .flags(&[ReadOnly])
+    .categories(KEYSPACE_READ)
+    .keys(SUBCOMMAND_KEY),
+];
+
+const MEMORY_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "memory|usage",
+        -3,
+        "server",
+        "4.0.0",
+        "Estimates the memory usage of a key.",
+    )
+    .flags(&[ReadOnly])
+    .categories(&["read", "slow"])
+    .keys(SUBCOMMAND_KEY)
+    .complexity("O(N) where N is the number of samples."),
+    command(
+        "memory|stats",
+        2,
+        "server",
+        "4.0.0",
+        "Returns details about memory usage.",
+    )
+    .flags(&[Stale])
+    .categories(&["slow"]),
+    command(
+        "memory|doctor",
+        2,
+        "server",
+        "4.0.0",
+        "Outputs a memory problems report.",
+    )
+    .flags(&[Stale])
+    .categories(&["slow"]),
+];
+
+const CLIENT_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "client|id",
+        2,
+        "connection",
+        "5.0.0",
+        "Returns the unique client ID of the connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|setname",
+        3,
+        "connection",
+        "2.6.9",
+        "Sets the connection name.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|getname",
+        2,
+        "connection",
+        "2.6.9",
+        "Returns the name of the connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|list",
+        -2,
+        "connection",
+        "2.4.0",
+        "Lists open connections.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN_CONNECTION)
+    .complexity("O(N) where N is the number of client connections"),
+    command(
+        "client|info",
+        2,
+        "connection",
+        "6.2.0",
+        "Returns information about the connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|kill",
+        -3,
+        "connection",
+        "2.4.0",
+        "Terminates open connections.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN_CONNECTION)
+    .complexity("O(N) where N is the number of client connections"),
+    command(
+        "client|pause",
+        -3,
+        "connection",
+        "3.0.0",
+        "Suspends commands processing.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN_CONNECTION),
+    command(
+        "client|unpause",
+        2,
+        "connection",
+        "6.2.0",
+        "Resumes processing commands from paused clients.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN_CONNECTION)
+    .complexity("O(N) Where N is the number of paused clients"),
+    command(
+        "client|no-evict",
+        3,
+        "connection",
+        "7.0.0",
+        "Sets the client eviction mode of the connection.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN_CONNECTION),
+    command(
+        "client|no-touch",
+        3,
+        "connection",
+        "7.2.0",
+        "Controls whether commands sent by the client affect the LRU/LFU of accessed keys.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|reply",
+        3,
+        "connection",
+        "3.2.0",
+        "Instructs the server whether to reply to commands.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|setinfo",
+        4,
+        "connection",
+        "7.2.0",
+        "Sets information specific to the client or connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|tracking",
+        -3,
+        "connection",
+        "6.0.0",
+        "Controls server-assisted client-side caching for the connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION)
+    .complexity("O(1). Some options may introduce additional complexity."),
+    command(
+        "client|caching",
+        3,
+        "connection",
+        "6.0.0",
+        "Instructs the server whether to track the keys in the next request.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|trackinginfo",
+        2,
+        "connection",
+        "6.2.0",
+        "Returns information about server-assisted client-side caching for the connection.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+    command(
+        "client|getredir",
+        2,
+        "connection",
+        "6.0.0",
+        "Returns the client ID to which the connection's tracking notifications are redirected.",
+    )
+    .flags(NOSCRIPT_STALE)
+    .categories(SLOW_CONNECTION),
+];
+
+const CONFIG_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "config|get",
+        -3,
+        "server",
+        "2.0.0",
+        "Returns the effective values of configuration parameters.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) when N is the number of configuration parameters provided"),
+    command(
+        "config|set",
+        -4,
+        "server",
+        "2.0.0",
+        "Sets configuration parameters in-flight.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) when N is the number of configuration parameters provided"),
+    command(
+        "config|rewrite",
+        2,
+        "server",
+        "2.8.0",
+        "Persists the effective configuration to file.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "config|resetstat",
+        2,
+        "server",
+        "2.0.0",
+        "Resets the server's statistics.",
+    )
+    .flags(&[Admin, NoScript, Loading, Stale])
+    .categories(ADMIN),
+];
+
+const SLOWLOG_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "slowlog|get",
+        -2,
+        "server",
+        "2.2.12",
+        "Returns the slow log's entries.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) where N is the number of entries returned"),
+    command(
+        "slowlog|len",
+        2,
+        "server",
+        "2.2.12",
+        "Returns the number of entries in the slow log.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "slowlog|reset",
+        2,
+        "server",
+        "2.2.12",
+        "Clears all entries from the slow log.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) where N is the number of entries in the slowlog"),
+];
+
+const LATENCY_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "latency|latest",
+        2,
+        "server",
+        "2.8.13",
+        "Returns the latest latency samples for all events.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "latency|history",
+        3,
+        "server",
+        "2.8.13",
+        "Returns timestamp-latency samples for an event.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "latency|reset",
+        -2,
+        "server",
+        "2.8.13",
+        "Resets the latency data for one or more events.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "latency|graph",
+        3,
+        "server",
+        "2.8.13",
+        "Returns a latency graph for an event.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "latency|doctor",
+        2,
+        "server",
+        "2.8.13",
+        "Returns a human-readable latency analysis report.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN),
+    command(
+        "latency|histogram",
+        -2,
+        "server",
+        "7.0.0",
+        "Returns the cumulative distribution of latencies of a subset or all commands.",
+    )
+    .flags(&[Admin, Loading, Stale])
+    .categories(ADMIN)
+    .complexity("O(N) where N is the number of commands with latency information being retrieved."),
+];
+
+const COMMAND_SUBCOMMANDS: &[CommandSpec] = &[
+    command(
+        "command|count",
+        2,
+        "server",
+        "2.8.13",
+        "Returns a count of commands.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION),
+    command(
+        "command|list",
+        -2,
+        "server",
+        "7.0.0",
+        "Returns a list of command names.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION)
+    .complexity("O(N) where N is the total number of commands"),
+    command(
+        "command|info",
+        -2,
+        "server",
+        "2.8.13",
+        "Returns information about one, multiple or all commands.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION)
+    .complexity("O(N) where N is the number of commands to look up"),
+    command(
+        "command|docs",
+        -2,
+        "server",
+        "7.0.0",
+        "Returns documentary information about one, multiple or all commands.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION)
+    .complexity("O(N) where N is the number of commands to look up"),
+    command(
+        "command|getkeys",
+        -3,
+        "server",
+        "2.8.13",
+        "Extracts the key names from an arbitrary command.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION)
+    .complexity("O(N) where N is the number of arguments to the command"),
+    command(
+        "command|getkeysandflags",
+        -3,
+        "server",
+        "7.0.0",
+        "Extracts the key names and access flags for an arbitrary command.",
+    )
+    .flags(&[Loading, Stale])
+    .categories(SLOW_CONNECTION)
+    .complexity("O(N) where N is the number of arguments to the command"),
+];
+
+/// Every command known to the server
+pub const COMMANDS: &[CommandSpec] = &[
+    command("ping", -1, "connection", "1.0.0", "Returns the server's liveliness response.")
+        .flags(&[Fast, Stale])
+        .categories(CONNECTION),
+    command("echo", 2, "connection", "1.0.0", "Returns the given string.")
+        .flags(&[Fast, Stale])
+        .categories(CONNECTION),
+    command("quit", -1, "connection", "1.0.0", "Closes the connection.")
+        .flags(&[NoScript, Loading, Stale, NoAuth, AllowBusy])
+        .categories(CONNECTION),
+    command("auth", -2, "connection", "1.0.0", "Authenticates the connection.")
+        .flags(&[NoScript, Loading, Stale, Fast, NoAuth, AllowBusy])
+        .categories(CONNECTION)
+        .complexity("O(N) where N is the number of passwords defined for the user"),
+    command("hello", -1, "connection", "6.0.0", "Handshakes with the Redis server.")
+        .flags(&[NoScript, Loading, Stale, Fast, NoAuth, AllowBusy])
+        .categories(CONNECTION)
+        .complexity("O(1)"),
+    command("select", 2, "connection", "1.0.0", "Changes the selected database.")
+        .flags(&[Loading, Stale, Fast])
+        .categories(CONNECTION),
+    command("set", -3, "string", "1.0.0", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.")
+        .flags(&[Write, DenyOom])
+        .categories(&["write", "string", "slow"])
+        .keys(SET_KEYS),
+    command("get", 2, "string", "1.0.0", "Returns the string value of a key.")
+        .flags(&[ReadOnly, Fast])
+        .categories(&["read", "string", "fast"])
+        .keys(GET_KEYS),
+    command("del", -2, "generic", "1.0.0", "Deletes one or more keys.")
+        .flags(&[Write])
+        .categories(&["keyspace", "write", "slow"])
+        .keys(DEL_KEYS)
+        .complexity("O(N) where N is the number of keys that will be removed."),
+    command("move", 3, "generic", "1.0.0", "Moves a key to another database.")
+        .flags(&[Write])
+        .categories(&["keyspace", "write", "fast"])
+        .keys(MOVE_KEYS),
+    command("keys", 2, "generic", "1.0.0", "Returns all key names that match a pattern.")
+        .flags(&[ReadOnly])
+        .categories(&["keyspace", "read", "slow", "dangerous"])
+        .complexity("O(N) with N being the number of keys in the database"),
+    command("scan", -2, "generic", "2.8.0", "Iterates over the key names in the database.")
+        .flags(&[ReadOnly])
+        .categories(KEYSPACE_READ)
+        .complexity("O(1) for every call. O(N) for a complete iteration."),
+    command("swapdb", 3, "server", "4.0.0", "Swaps two databases.")
+        .flags(&[Write])
+        .categories(&["keyspace", "write", "fast", "dangerous"])
+        .complexity("O(N) where N is the count of clients watching or blocking on keys from both databases."),
+    command("flushdb", -1, "server", "1.0.0", "Removes all keys from the current database.")
+        .flags(&[Write])
+        .categories(&["keyspace", "write", "slow", "dangerous"])
+        .complexity("O(N) where N is the number of keys in the selected database"),
+    command("flushall", -1, "server", "1.0.0", "Removes all keys from all databases.")
+        .flags(&[Write])
+        .categories(&["keyspace", "write", "slow", "dangerous"])
+        .complexity("O(N) where N is the total number of keys in all databases"),
+    command("object", -2, "generic", "2.2.3", "A container for object introspection commands.")
+        .categories(&["slow"])
+        .subcommands(OBJECT_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("info", -1, "server", "1.0.0", "Returns information and statistics about the server.")
+        .flags(&[Loading, Stale])
+        .categories(&["slow", "dangerous"]),
+    command("memory", -2, "server", "4.0.0", "A container for memory diagnostics commands.")
+        .categories(&["slow"])
+        .subcommands(MEMORY_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("replconf", -1, "server", "3.0.0", "An internal command for configuring the replication stream.")
+        .flags(&[Admin, NoScript, Loading, Stale, AllowBusy])
+        .categories(ADMIN),
+    command("psync", -3, "server", "2.8.0", "An internal command used in replication.")
+        .flags(&[Admin, NoScript, NoAuth])
+        .categories(ADMIN),
+    command("replicaof", 3, "server", "5.0.0", "Configures a server as replica of another, or promotes it to a master.")
+        .flags(&[Admin, NoScript, Stale])
+        .categories(ADMIN),
+    command("wait", 3, "generic", "3.0.0", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.")
+        .flags(&[NoScript, Blocking, MayReplicate])
+        .categories(SLOW_CONNECTION),
+    command("waitaof", 4, "generic", "7.2.0", "Blocks until all of the preceding write commands sent by the connection are written to the append-only file of the master and/or replicas.")
+        .flags(&[NoScript, Blocking, MayReplicate])
+        .categories(SLOW_CONNECTION),
+    command("subscribe", -2, "pubsub", "2.0.0", "Listens for messages published to channels.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of channels to subscribe to."),
+    command("unsubscribe", -1, "pubsub", "2.0.0", "Stops listening to messages posted to channels.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of channels to unsubscribe."),
+    command("psubscribe", -2, "pubsub", "2.0.0", "Listens for messages published to channels that match one or more patterns.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of patterns to subscribe to."),
+    command("punsubscribe", -1, "pubsub", "2.0.0", "Stops listening to messages published to channels that match one or more patterns.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of patterns to unsubscribe."),
+    command("ssubscribe", -2, "pubsub", "7.0.0", "Listens for messages published to shard channels.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of shard channels to subscribe to."),
+    command("sunsubscribe", -1, "pubsub", "7.0.0", "Stops listening to messages posted to shard channels.")
+        .flags(&[PubSub, NoScript, Loading, Stale])
+        .categories(PUBSUB)
+        .complexity("O(N) where N is the number of shard channels to unsubscribe."),
+    command("publish", 3, "pubsub", "2.0.0", "Posts a message to a channel.")
+        .flags(&[PubSub, Loading, Stale, Fast, MayReplicate])
+        .categories(&["pubsub", "fast"])
+        .complexity("O(N+M) where N is the number of clients subscribed to the receiving channel and M is the total number of subscribed patterns (by any client)."),
+    command("spublish", 3, "pubsub", "7.0.0", "Posts a message to a shard channel.")
+        .flags(&[PubSub, Loading, Stale, Fast, MayReplicate])
+        .categories(&["pubsub", "fast"])
+        .complexity("O(N) where N is the number of clients subscribed to the receiving shard channel."),
+    command("pubsub", -2, "pubsub", "2.8.0", "A container for Pub/Sub commands.")
+        .categories(&["slow"])
+        .subcommands(PUBSUB_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("multi", 1, "transactions", "1.2.0", "Starts a transaction.")
+        .flags(&[NoScript, Loading, Stale, Fast, AllowBusy])
+        .categories(&["fast", "transaction"]),
+    command("exec", 1, "transactions", "1.2.0", "Executes all commands in a transaction.")
+        .flags(&[NoScript, Loading, Stale, SkipSlowlog])
+        .categories(&["slow", "transaction"])
+        .complexity("Depends on commands in the transaction"),
+    command("discard", 1, "transactions", "2.0.0", "Discards a transaction.")
+        .flags(&[NoScript, Loading, Stale, Fast, AllowBusy])
+        .categories(&["fast", "transaction"])
+        .complexity("O(N), when N is the number of queued commands"),
+    command("watch", -2, "transactions", "2.2.0", "Monitors changes to keys to determine the execution of a transaction.")
+        .flags(&[NoScript, Loading, Stale, Fast, AllowBusy])
+        .categories(&["fast", "transaction"])
+        .keys(WATCH_KEYS)
+        .complexity("O(1) for every key."),
+    command("unwatch", 1, "transactions", "2.2.0", "Forgets about watched keys of a transaction.")
+        .flags(&[NoScript, Loading, Stale, Fast, AllowBusy])
+        .categories(&["fast", "transaction"]),
+    command("eval", -3, "scripting", "2.6.0", "Executes a server-side Lua script.")
+        .flags(&[NoScript, Stale, MayReplicate, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_KEYS)
+        .complexity("Depends on the script that is executed."),
+    command("eval_ro", -3, "scripting", "7.0.0", "Executes a read-only server-side Lua script.")
+        .flags(&[NoScript, Stale, ReadOnly, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_RO_KEYS)
+        .complexity("Depends on the script that is executed."),
+    command("evalsha", -3, "scripting", "2.6.0", "Executes a server-side Lua script by SHA1 digest.")
+        .flags(&[NoScript, Stale, MayReplicate, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_KEYS)
+        .complexity("Depends on the script that is executed."),
+    command("evalsha_ro", -3, "scripting", "7.0.0", "Executes a read-only server-side Lua script by SHA1 digest.")
+        .flags(&[NoScript, Stale, ReadOnly, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_RO_KEYS)
+        .complexity("Depends on the script that is executed."),
+    command("script", -2, "scripting", "2.6.0", "A container for Lua scripts management commands.")
+        .categories(&["slow"])
+        .subcommands(SCRIPT_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("function", -2, "scripting", "7.0.0", "A container for function commands.")
+        .categories(&["slow"])
+        .subcommands(FUNCTION_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("fcall", -3, "scripting", "7.0.0", "Invokes a function.")
+        .flags(&[NoScript, Stale, MayReplicate, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_KEYS)
+        .complexity("Depends on the function that is executed."),
+    command("fcall_ro", -3, "scripting", "7.0.0", "Invokes a read-only function.")
+        .flags(&[NoScript, Stale, ReadOnly, NoMandatoryKeys])
+        .categories(SCRIPTING)
+        .keys(SCRIPT_RO_KEYS)
+        .complexity("Depends on the function that is executed."),
+    command("acl", -2, "server", "6.0.0", "A container for Access List Control commands.")
+        .categories(&["slow"])
+        .subcommands(ACL_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("client", -2, "connection", "2.4.0", "A container for client connection commands.")
+        .categories(&["slow"])
+        .subcommands(CLIENT_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("config", -2, "server", "2.0.0", "A container for server configuration commands.")
+        .categories(&["slow"])
+        .subcommands(CONFIG_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("slowlog", -2, "server", "2.2.12", "A container for slow log commands.")
+        .categories(&["slow"])
+        .subcommands(SLOWLOG_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("latency", -2, "server", "2.8.13", "A container for latency diagnostics commands.")
+        .categories(&["slow"])
+        .subcommands(LATENCY_SUBCOMMANDS)
+        .complexity("Depends on subcommand."),
+    command("monitor", 1, "server", "1.0.0", "Listens for all requests received by the server in real-time.")
+        .flags(&[Admin, NoScript, Loading, Stale])
+        .categories(ADMIN),
+    command("command", -1, "server", "2.8.13", "Returns detailed information about all commands.")
+        .flags(&[Loading, Stale])
+        .categories(SLOW_CONNECTION)
+        .subcommands(COMMAND_SUBCOMMANDS)
+        .complexity("O(N) where N is the total number of commands"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn should_find_commands_and_subcommands() {
+        assert_eq!(find("GET", None).map(|spec| spec.name), Some("get"));
+        assert_eq!(
+            find("config", Some("GET")).map(|spec| spec.name),
+            Some("config|get")
+        );
+        assert_eq!(
+            find("config", Some("unknown")).map(|spec| spec.name),
+            Some("config")
+        );
+        assert_eq!(lookup("config|unknown").map(|spec| spec.name), None);
+        assert!(find("unknown", None).is_none());
+
+        let set = lookup("set").unwrap();
+        assert!(!set.accepts(2) && set.accepts(3) && set.accepts(5));
+        assert!(lookup("echo").unwrap().accepts(2));
+        assert!(!lookup("echo").unwrap().accepts(3));
+    }
+
+    #[test]
+    fn should_extract_keys() {
+        let names = |args: &[&str]| {
+            keys(&argv(args)).map(|keys| {
+                keys.into_iter()
+                    .map(|(key, _)| key.to_owned())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(names(&["set", "k", "v", "ex", "10"]).unwrap(), ["k"]);
+        assert_eq!(names(&["DEL", "a", "b", "c"]).unwrap(), ["a", "b", "c"]);
+        assert_eq!(names(&["object", "encoding", "k"]).unwrap(), ["k"]);
+        assert_eq!(
+            names(&["eval", "return 1", "2", "a", "b", "arg"]).unwrap(),
+            ["a", "b"]
+        );
+
+        assert!(matches!(names(&["ping"]), Err(GetKeysError::NoKeys)));
+        assert!(matches!(names(&["get"]), Err(GetKeysError::InvalidArity)));
+        assert!(matches!(
+            names(&["nope", "k"]),
+            Err(GetKeysError::InvalidCommand)
+        ));
+        assert!(matches!(
+            names(&["eval", "return 1", "3", "a"]),
+            Err(GetKeysError::InvalidArguments)
+        ));
+    }
+}
@@ -1,7 +1,5 @@
 use clap::Parser;
-use server::Memora;
-use tokio::net::ToSocketAddrs;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use server::{log, role::AnyRole, Memora};
 
 use crate::opts::Opts;
 
@@ -12,30 +10,32 @@ mod server;
 
 const DEFAULT_HOSTNAME: &str = "127.0.0.1";
 
-async fn run<R>(addr: impl ToSocketAddrs, role: R) -> anyhow::Result<()>
-where
-    R: server::Role,
-{
-    let memora = Memora::new(addr, role).await?;
-    memora.start().await?;
-
-    Ok(())
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(EnvFilter::from_env("REDIS_LOG"))
-        .init();
-
-    let opts = Opts::parse();
-    let addr = (DEFAULT_HOSTNAME, opts.port);
-    if let Some((host, port)) = opts.replica_of()? {
-        run(addr, server::role::Replica::of(opts.port, host, port)).await
+    let config = Opts::parse().config()?;
+    log::init(config.loglevel);
+
+    let addr = (DEFAULT_HOSTNAME, config.port);
+    let role = if let Some((host, port)) = config.replicaof.clone() {
+        let tls = config
+            .tls
+            .replication
+            .then(|| config.tls.connector())
+            .transpose()?;
+
+        AnyRole::from(server::role::Replica::of(
+            config.port,
+            host,
+            port,
+            config.repl_backlog_size,
+            tls,
+        ))
     } else {
-        run(addr, server::role::Master::new()).await
-    }?;
+        AnyRole::from(server::role::Master::new(config.repl_backlog_size))
+    };
+
+    let memora = Memora::new(addr, role, config).await?;
+    memora.start().await?;
 
     Ok(())
 }
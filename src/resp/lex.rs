@@ -16,6 +16,12 @@ pub enum Token {
     #[token("+")]
     Plus,
 
+    #[token("-")]
+    Minus,
+
+    #[token(":")]
+    Colon,
+
     #[regex(r"-?(?:0|[1-9]\d*)", |lex| std::str::from_utf8(lex.slice()).expect("invalid utf-8").parse::<i64>().expect("failed to parse integer"))]
     Int(i64),
 
@@ -6,7 +6,7 @@ use std::io;
 use thiserror::Error;
 
 mod lex;
-pub(self) mod parser;
+mod parser;
 pub mod value;
 
 pub use lex::Token;
@@ -6,6 +6,8 @@ use super::{
     RespError, RespResult,
 };
 
+const CRLF: &[u8] = b"\r\n";
+
 pub(super) struct Parser<'a> {
     lexer: Lexer<'a, Token>,
 }
@@ -35,30 +37,49 @@ impl<'a> Parser<'a> {
 
         // This is not a bulk string, attempt to convert the length to a `usize`
         // and error otherwise
-        let Ok(length) = length.try_into() else {
+        let Ok(length) = usize::try_from(length) else {
             return Err(RespError::InvalidLength(length));
         };
 
-        // Read the string
-        let Some(token) = self.try_next()? else {
+        // The payload of a bulk string is binary-safe, so we can not rely on the lexer to read it.
+        // Instead, read exactly `length` bytes surrounded by CRLF terminators from the raw input
+        let remainder = self.lexer.remainder();
+        if remainder.len() < length + 4 {
             return Ok(None);
-        };
+        }
+
+        if &remainder[..2] != CRLF || &remainder[length + 2..length + 4] != CRLF {
+            return Err(RespError::InvalidToken);
+        }
+
+        let str = std::str::from_utf8(&remainder[2..length + 2])
+            .map_err(|_| RespError::InvalidToken)?
+            .to_owned();
+        self.lexer.bump(length + 4);
 
-        let str: String = token.try_into()?;
+        Ok(Some(Some(str)))
+    }
 
-        // The length of the string we read does not match the expected length,
-        // which means that we read a partial string
-        if str.len() != length {
+    /// Parse a CRLF-terminated line, used by simple strings and errors
+    /// On success, return `Some` if a complete line has been parsed or `None` otherwise
+    fn parse_line(&mut self) -> RespResult<Option<String>> {
+        let remainder = self.lexer.remainder();
+        let Some(end) = remainder.windows(2).position(|w| w == CRLF) else {
             return Ok(None);
-        }
+        };
+
+        let line = std::str::from_utf8(&remainder[..end])
+            .map_err(|_| RespError::InvalidToken)?
+            .to_owned();
+        self.lexer.bump(end + 2);
 
-        Ok(Some(Some(str.to_owned())))
+        Ok(Some(line))
     }
 
     /// Attempt to parse a RESP array
     /// On success, return `Some` if a complete array has been parsed or `None`
     /// if a partial array has been parsed
-    fn parse_array(&mut self) -> RespResult<Option<Vec<Value>>> {
+    fn parse_array(&mut self) -> RespResult<Option<Value>> {
         // Read length
         let Some(length) = self.try_next()? else {
             return Ok(None);
@@ -68,12 +89,16 @@ impl<'a> Parser<'a> {
             return Err(RespError::InvalidToken);
         };
 
+        if length == -1 {
+            return Ok(Some(Value::NullArray));
+        }
+
         let Ok(length) = length.try_into() else {
             return Err(RespError::InvalidLength(length));
         };
 
         let values = (0usize..length).map(|_| self.parse_one());
-        values.collect()
+        Ok(values.collect::<RespResult<Option<_>>>()?.map(Value::Array))
     }
 
     pub fn parse(&mut self) -> RespResult<Option<(Value, &'a [u8])>> {
@@ -93,12 +118,7 @@ impl<'a> Parser<'a> {
         };
 
         match token {
-            Token::Star => {
-                let Some(values) = self.parse_array()? else {
-                    return Ok(None);
-                };
-                Ok(Some(Value::Array(values)))
-            }
+            Token::Star => self.parse_array(),
             Token::Dollar => {
                 let Some(bulk) = self.parse_bulk()? else {
                     return Ok(None);
@@ -106,18 +126,31 @@ impl<'a> Parser<'a> {
                 Ok(Some(Value::Str(StringValue::Bulk(bulk))))
             }
             Token::Plus => {
-                let str = self.try_next()?;
-                let Some(str) = str else {
+                let Some(str) = self.parse_line()? else {
+                    return Ok(None);
+                };
+
+                Ok(Some(Value::Str(StringValue::Simple(str))))
+            }
+            Token::Minus => {
+                let Some(err) = self.parse_line()? else {
+                    return Ok(None);
+                };
+
+                Ok(Some(Value::Error(err)))
+            }
+            Token::Colon => {
+                let Some(int) = self.try_next()? else {
                     return Ok(None);
                 };
 
-                let Token::Str(str) = str else {
+                let Some(int) = int.as_int() else {
                     return Err(RespError::InvalidToken);
                 };
 
-                Ok(Some(Value::Str(StringValue::Simple(str))))
+                Ok(Some(Value::Int(int)))
             }
-            _ => todo!(),
+            _ => Err(RespError::InvalidToken),
         }
     }
 
@@ -153,7 +186,7 @@ mod tests {
         let lex = Token::lexer(b"*2\r\n$4\r\necho\r\n$3\r\nhey\r\n");
 
         for (expected, tok) in expected.into_iter().zip(lex) {
-            let tok = tok.expect(&format!("expected token {:?}", expected));
+            let tok = tok.unwrap_or_else(|_| panic!("expected token {:?}", expected));
             assert_eq!(tok, expected);
         }
     }
@@ -185,4 +218,46 @@ mod tests {
 
         assert_eq!(value, Value::simple("OK"))
     }
+
+    #[test]
+    fn parse_binary_safe_bulk() {
+        let lex = Token::lexer(b"*3\r\n$5\r\nPSYNC\r\n$11\r\nab12 cd:3\r\n\r\n$2\r\n-1\r\n");
+        let mut parser = Parser::new(lex);
+
+        let value = parser
+            .parse_one()
+            .expect("parse value")
+            .expect("parse value");
+        assert_eq!(
+            value,
+            Value::from_iter([
+                Value::bulk("PSYNC"),
+                Value::bulk("ab12 cd:3\r\n"),
+                Value::bulk("-1")
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_partial_bulk() {
+        let lex = Token::lexer(b"$10\r\nhello");
+        let mut parser = Parser::new(lex);
+
+        assert_eq!(parser.parse_one().expect("parse value"), None);
+    }
+
+    #[test]
+    fn parse_error_and_int() {
+        let lex = Token::lexer(b"-ERR unknown command\r\n:-42\r\n");
+        let mut parser = Parser::new(lex);
+
+        assert_eq!(
+            parser.parse_one().expect("parse value"),
+            Some(Value::error("ERR unknown command"))
+        );
+        assert_eq!(
+            parser.parse_one().expect("parse value"),
+            Some(Value::Int(-42))
+        );
+    }
 }
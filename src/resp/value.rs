@@ -13,9 +13,6 @@ pub enum StringValue {
     /// A bulk string represents a single binary string. The string can be of any size, but by default, Redis limits it to 512 MB
     /// A value of [`None`] represents a null bulk string
     Bulk(Option<String>),
-
-    /// A null string
-    Null,
 }
 
 impl StringValue {
@@ -29,15 +26,14 @@ impl StringValue {
                 let len = str.len();
                 write!(buf, "${len}\r\n{str}")
             }
-            Self::Null | Self::Bulk(None) => write!(buf, "$-1"),
+            Self::Bulk(None) => write!(buf, "$-1"),
         }?)
     }
 
     pub fn as_str(&self) -> Option<&str> {
         match self {
             Self::Simple(str) => Some(str.as_str()),
-            Self::Bulk(str) => str.as_ref().map(|s| s.as_str()),
-            _ => None,
+            Self::Bulk(str) => str.as_deref(),
         }
     }
 
@@ -45,7 +41,6 @@ impl StringValue {
         match self {
             Self::Simple(s) => Some(s),
             Self::Bulk(s) => s,
-            Self::Null => None,
         }
     }
 }
@@ -57,6 +52,9 @@ pub enum Value {
     /// Clients send commands to the Redis server as RESP arrays.
     Array(Vec<Value>),
 
+    /// A null array, used by some commands to signal the absence of a reply.
+    NullArray,
+
     /// A string value
     Str(StringValue),
 
@@ -65,6 +63,12 @@ pub enum Value {
 
     /// CRLF-terminated string that represents a signed, base-10, 64-bit integer.
     Int(i64),
+
+    /// A RESP3 map of key-value pairs, only sent to clients that switched protocols with `HELLO 3`
+    Map(Vec<(Value, Value)>),
+
+    /// Out-of-band data pushed to RESP3 clients, like invalidation messages
+    Push(Vec<Value>),
 }
 
 impl Value {
@@ -110,6 +114,8 @@ impl Value {
                 Ok(())
             }
 
+            Self::NullArray => Ok(write!(buf, "*-1\r\n")?),
+
             Self::Str(s) => {
                 s.encode(buf)?;
                 write!(buf, "\r\n")
@@ -117,7 +123,30 @@ impl Value {
 
             Self::Error(s) => Ok(write!(buf, "-{s}\r\n")?),
 
-            Self::Int(i) => Ok(write!(buf, "{i}\r\n")?),
+            Self::Int(i) => Ok(write!(buf, ":{i}\r\n")?),
+
+            Self::Map(pairs) => {
+                let len = pairs.len();
+                write!(buf, "%{len}\r\n")?;
+
+                for (key, value) in pairs {
+                    key.encode(buf)?;
+                    value.encode(buf)?;
+                }
+
+                Ok(())
+            }
+
+            Self::Push(values) => {
+                let len = values.len();
+                write!(buf, ">{len}\r\n")?;
+
+                for value in values {
+                    value.encode(buf)?;
+                }
+
+                Ok(())
+            }
         }?;
 
         Ok(())
@@ -162,4 +191,34 @@ mod tests {
 
         assert_eq!(str, "*2\r\n$4\r\necho\r\n$3\r\nhey\r\n");
     }
+
+    #[test]
+    fn should_encode_int_and_null_bulk() {
+        let value = Value::from_iter([Value::Int(-42), Value::Str(StringValue::Bulk(None))]);
+
+        let mut buf = Cursor::new(Vec::new());
+        let mut str = String::new();
+        value.encode(&mut buf).expect("encode");
+
+        buf.seek(SeekFrom::Start(0)).expect("seek to start");
+        buf.read_to_string(&mut str).expect("read encoded value");
+
+        assert_eq!(str, "*2\r\n:-42\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn should_encode_resp3_values() {
+        let mut buf = Vec::new();
+        Value::Map(vec![(Value::bulk("proto"), Value::Int(3))])
+            .encode(&mut buf)
+            .expect("encode map");
+        Value::Push(vec![Value::bulk("invalidate"), Value::null_bulk()])
+            .encode(&mut buf)
+            .expect("encode push");
+
+        assert_eq!(
+            String::from_utf8(buf).expect("utf-8"),
+            "%1\r\n$5\r\nproto\r\n:3\r\n>2\r\n$10\r\ninvalidate\r\n$-1\r\n"
+        );
+    }
 }
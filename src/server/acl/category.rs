@@ -0,0 +1,140 @@
+//! Categories of commands, which ACL rules can allow or deny as a whole with `+@<category>` and
+//! `-@<category>`
+
+use crate::dispatch::table;
+
+/// A category of commands
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Category {
+    Keyspace,
+    Read,
+    Write,
+    Set,
+    SortedSet,
+    List,
+    Hash,
+    String,
+    Bitmap,
+    HyperLogLog,
+    Geo,
+    Stream,
+    PubSub,
+    Admin,
+    Fast,
+    Slow,
+    Blocking,
+    Dangerous,
+    Connection,
+    Transaction,
+    Scripting,
+}
+
+use Category::*;
+
+impl Category {
+    pub(super) const ALL: &'static [Self] = &[
+        Keyspace,
+        Read,
+        Write,
+        Set,
+        SortedSet,
+        List,
+        Hash,
+        String,
+        Bitmap,
+        HyperLogLog,
+        Geo,
+        Stream,
+        PubSub,
+        Admin,
+        Fast,
+        Slow,
+        Blocking,
+        Dangerous,
+        Connection,
+        Transaction,
+        Scripting,
+    ];
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Keyspace => "keyspace",
+            Read => "read",
+            Write => "write",
+            Set => "set",
+            SortedSet => "sortedset",
+            List => "list",
+            Hash => "hash",
+            String => "string",
+            Bitmap => "bitmap",
+            HyperLogLog => "hyperloglog",
+            Geo => "geo",
+            Stream => "stream",
+            PubSub => "pubsub",
+            Admin => "admin",
+            Fast => "fast",
+            Slow => "slow",
+            Blocking => "blocking",
+            Dangerous => "dangerous",
+            Connection => "connection",
+            Transaction => "transaction",
+            Scripting => "scripting",
+        }
+    }
+
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|category| category.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+}
+
+/// Every command that can be called along with its categories, as declared by the command
+/// table. Subcommands are named `<command>|<subcommand>`, as returned by
+/// [`Command::name`](crate::server::cmd::Command::name)
+fn table() -> impl Iterator<Item = (&'static str, &'static [&'static str])> {
+    table::all()
+        .filter(|spec| spec.callable())
+        .map(|spec| (spec.name, spec.categories))
+}
+
+/// Names of every command that can be called
+pub(super) fn all() -> impl Iterator<Item = &'static str> {
+    table().map(|(name, _)| name)
+}
+
+/// Names of the commands in `category`
+pub(super) fn commands(category: Category) -> impl Iterator<Item = &'static str> {
+    table()
+        .filter(move |(_, categories)| categories.contains(&category.name()))
+        .map(|(name, _)| name)
+}
+
+/// Names of the commands matching a command rule: either a single subcommand with
+/// `<command>|<subcommand>`, or a command along with all its subcommands
+pub(super) fn matching(rule: &str) -> impl Iterator<Item = &'static str> + '_ {
+    all().filter(move |name| {
+        name.eq_ignore_ascii_case(rule)
+            || name
+                .split_once('|')
+                .is_some_and(|(parent, _)| parent.eq_ignore_ascii_case(rule))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_only_use_known_categories() {
+        for (name, categories) in table() {
+            for category in categories {
+                assert!(
+                    Category::parse(category).is_some(),
+                    "unknown category {category} of {name}"
+                );
+            }
+        }
+    }
+}
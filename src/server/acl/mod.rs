@@ -0,0 +1,628 @@
+//! Access control lists: the users clients authenticate as with `AUTH`, and the commands, keys
+//! and channels each of them is allowed to access.
+//!
+//! Users are described by rules, as given to `ACL SETUSER` or written in an ACL file:
+//! - `on` and `off` enable or disable the user
+//! - `>password`, `<password`, `#digest`, `!digest`, `nopass` and `resetpass` manage passwords,
+//!   which are only kept as SHA-256 digests
+//! - `+command`, `-command`, `+@category`, `-@category`, `allcommands` and `nocommands` manage
+//!   the allowed commands
+//! - `~pattern`, `%R~pattern`, `%W~pattern`, `allkeys` and `resetkeys` manage the allowed keys
+//! - `&pattern`, `allchannels` and `resetchannels` manage the allowed Pub/Sub channels
+//! - `(rules)` adds a selector, an alternative set of permissions, and `clearselectors` removes
+//!   them
+//! - `reset` removes every permission
+
+mod category;
+mod user;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use rand::RngCore;
+use thiserror::Error;
+use tracing::info;
+
+use crate::resp::Value;
+
+use self::{
+    category::Category,
+    user::{Denied, User},
+};
+use super::{cmd::Command, ClientHandle};
+
+/// Name of the user new connections are authenticated as
+pub const DEFAULT_USER: &str = "default";
+
+/// Maximum number of entries kept in the ACL log
+const LOG_MAX_LEN: usize = 128;
+
+/// Time during which similar security events are grouped in a single entry of the ACL log
+const LOG_GROUPING_MILLIS: i64 = 60_000;
+
+#[derive(Debug, Error)]
+pub enum AclError {
+    #[error("Authentication required.")]
+    NoAuth,
+
+    #[error("invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?")]
+    NoDefaultPassword,
+
+    #[error("User {user} has no permissions to run the '{command}' command")]
+    CommandDenied { user: String, command: &'static str },
+
+    #[error("No permissions to access a key")]
+    KeyDenied,
+
+    #[error("No permissions to access a channel")]
+    ChannelDenied,
+
+    #[error("Error in ACL SETUSER modifier '{rule}': {reason}")]
+    InvalidRule { rule: String, reason: String },
+
+    #[error("User '{0}' not found")]
+    UnknownUser(String),
+
+    #[error("The 'default' user cannot be removed")]
+    DeleteDefault,
+
+    #[error("Unknown category '{0}'")]
+    UnknownCategory(String),
+
+    #[error("ACL GENPASS argument must be the number of bits for the output password, a positive number up to 4096")]
+    InvalidBits,
+
+    #[error("This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration.")]
+    NoFile,
+
+    #[error("{path}:{line}: {message}")]
+    File {
+        path: String,
+        line: usize,
+        message: String,
+    },
+
+    #[error("failed to access ACL file: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type AclResult<T> = std::result::Result<T, AclError>;
+
+/// Where a command got executed, as reported by the ACL log
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Context {
+    Toplevel,
+    Multi,
+    Lua,
+}
+
+impl Context {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Toplevel => "toplevel",
+            Self::Multi => "multi",
+            Self::Lua => "lua",
+        }
+    }
+}
+
+/// A security event: a denied command or a failed authentication
+#[derive(Debug)]
+struct LogEntry {
+    id: u64,
+    count: u64,
+    reason: &'static str,
+    context: Context,
+    object: String,
+    username: String,
+    client_info: String,
+    created: chrono::DateTime<Utc>,
+    updated: chrono::DateTime<Utc>,
+}
+
+/// Number of security events, by reason, as reported by `INFO stats`
+#[derive(Debug, Default)]
+pub(super) struct Denials {
+    pub(super) auth: u64,
+    pub(super) command: u64,
+    pub(super) key: u64,
+    pub(super) channel: u64,
+}
+
+/// The users of the server, along with the log of security events
+pub(super) struct Acl {
+    users: BTreeMap<String, User>,
+
+    /// Most recent security events first
+    log: VecDeque<LogEntry>,
+    next_log_id: u64,
+
+    pub(super) denials: Denials,
+
+    /// ACL file the users are loaded from and saved to
+    file: Option<PathBuf>,
+
+    /// Password protecting the `default` user, kept to protect it again when the ACL file gets
+    /// loaded
+    requirepass: Option<String>,
+}
+
+impl Acl {
+    /// Create the `default` user, protected by `requirepass` if given, and load the users of the
+    /// ACL `file` if any
+    pub(super) fn new(requirepass: Option<&str>, file: Option<PathBuf>) -> AclResult<Self> {
+        let mut acl = Self {
+            users: BTreeMap::from([(DEFAULT_USER.to_owned(), User::default_user())]),
+            log: VecDeque::new(),
+            next_log_id: 0,
+            denials: Denials::default(),
+            file,
+            requirepass: None,
+        };
+
+        if requirepass.is_some() {
+            acl.set_requirepass(requirepass);
+        }
+        if acl.file.is_some() {
+            acl.load()?;
+        }
+
+        Ok(acl)
+    }
+
+    /// Protect the `default` user with `requirepass`, or let anyone use it without a password
+    pub(super) fn set_requirepass(&mut self, requirepass: Option<&str>) {
+        self.requirepass = requirepass.map(str::to_owned);
+        if let Some(default) = self.users.get_mut(DEFAULT_USER) {
+            protect(default, requirepass);
+        }
+    }
+
+    /// User new connections are authenticated as, if they do not need to authenticate
+    pub(super) fn default_user(&self) -> Option<&str> {
+        self.users
+            .get(DEFAULT_USER)
+            .filter(|user| user.enabled && user.nopass)
+            .map(|user| user.name.as_str())
+    }
+
+    /// Authenticate `client` as `username`, or as the `default` user if no username is given
+    pub(super) fn authenticate(
+        &mut self,
+        client: &ClientHandle,
+        username: Option<&str>,
+        password: &str,
+    ) -> AclResult<()> {
+        if username.is_none() && self.default_user().is_some() {
+            return Err(AclError::NoDefaultPassword);
+        }
+
+        let username = username.unwrap_or(DEFAULT_USER);
+        match self.users.get(username) {
+            Some(user) if user.authenticates(password) => {
+                client.authenticate(username.to_owned());
+                Ok(())
+            }
+            _ => {
+                self.denials.auth += 1;
+                self.record("auth", Context::Toplevel, "AUTH", username, client);
+                Err(AclError::WrongPass)
+            }
+        }
+    }
+
+    /// Check whether `client` is allowed to run `cmd`, recording a security event if not
+    pub(super) fn check(
+        &mut self,
+        client: &ClientHandle,
+        cmd: &Command,
+        argv: &[String],
+        context: Context,
+    ) -> AclResult<()> {
+        // Anyone can attempt to authenticate
+        if matches!(
+            cmd,
+            Command::Auth { .. } | Command::Hello { auth: Some(_), .. }
+        ) {
+            return Ok(());
+        }
+
+        let username = client.user().ok_or(AclError::NoAuth)?;
+        let user = self.users.get(&username).ok_or(AclError::NoAuth)?;
+
+        let Err(denied) = user.check(cmd, argv) else {
+            return Ok(());
+        };
+
+        let (reason, object, e) = match denied {
+            Denied::Command => {
+                self.denials.command += 1;
+                let e = AclError::CommandDenied {
+                    user: username.clone(),
+                    command: cmd.name(),
+                };
+                ("command", cmd.name().to_owned(), e)
+            }
+            Denied::Key(key) => {
+                self.denials.key += 1;
+                ("key", key, AclError::KeyDenied)
+            }
+            Denied::Channel(channel) => {
+                self.denials.channel += 1;
+                ("channel", channel, AclError::ChannelDenied)
+            }
+        };
+
+        self.record(reason, context, &object, &username, client);
+        Err(e)
+    }
+
+    /// Create or modify the user `username` by applying `rules`, which are either all applied
+    /// or none of them is
+    pub(super) fn set_user(&mut self, username: &str, rules: &[String]) -> AclResult<()> {
+        let rules = user::rules(rules.iter().map(String::as_str)).map_err(|reason| {
+            AclError::InvalidRule {
+                rule: rules.join(" "),
+                reason,
+            }
+        })?;
+
+        let mut user = self
+            .users
+            .get(username)
+            .cloned()
+            .unwrap_or_else(|| User::new(username));
+        for rule in rules {
+            user.apply(&rule).map_err(|reason| AclError::InvalidRule {
+                rule,
+                reason: reason.to_owned(),
+            })?;
+        }
+
+        self.users.insert(username.to_owned(), user);
+        Ok(())
+    }
+
+    /// Reply of `ACL GETUSER`
+    pub(super) fn get_user(&self, username: &str) -> Value {
+        let Some(user) = self.users.get(username) else {
+            return Value::null_bulk();
+        };
+
+        let mut fields = vec![
+            Value::bulk("flags"),
+            Value::from_iter(user.flags().into_iter().map(Value::bulk)),
+            Value::bulk("passwords"),
+            Value::from_iter(user.passwords.iter().map(Value::bulk)),
+        ];
+        fields.extend(
+            user.root
+                .fields()
+                .into_iter()
+                .flat_map(|(name, value)| [Value::bulk(name), Value::bulk(value)]),
+        );
+        fields.extend([
+            Value::bulk("selectors"),
+            Value::from_iter(user.selectors.iter().map(|selector| {
+                Value::from_iter(
+                    selector
+                        .fields()
+                        .into_iter()
+                        .flat_map(|(name, value)| [Value::bulk(name), Value::bulk(value)]),
+                )
+            })),
+        ]);
+
+        Value::Array(fields)
+    }
+
+    /// Delete the given users, returning the names of the ones that existed
+    pub(super) fn delete_users(&mut self, usernames: &[String]) -> AclResult<Vec<String>> {
+        if usernames.iter().any(|username| username == DEFAULT_USER) {
+            return Err(AclError::DeleteDefault);
+        }
+
+        Ok(usernames
+            .iter()
+            .filter(|username| self.users.remove(username.as_str()).is_some())
+            .cloned()
+            .collect())
+    }
+
+    /// Describe every user, as lines of an ACL file
+    pub(super) fn list(&self) -> Vec<String> {
+        self.users.values().map(User::describe).collect()
+    }
+
+    pub(super) fn usernames(&self) -> impl Iterator<Item = &str> {
+        self.users.keys().map(String::as_str)
+    }
+
+    /// Reply of `ACL CAT`: the categories, or the commands of `category`
+    pub(super) fn categories(category: Option<&str>) -> AclResult<Value> {
+        Ok(match category {
+            None => Value::from_iter(
+                Category::ALL
+                    .iter()
+                    .map(|category| Value::bulk(category.name())),
+            ),
+            Some(name) => {
+                let category = Category::parse(name)
+                    .ok_or_else(|| AclError::UnknownCategory(name.to_owned()))?;
+                Value::from_iter(category::commands(category).map(Value::bulk))
+            }
+        })
+    }
+
+    /// Reply of `ACL LOG`: the `count` most recent security events
+    pub(super) fn log(&self, count: usize) -> Value {
+        let now = Utc::now();
+
+        Value::from_iter(self.log.iter().take(count).map(|entry| {
+            let age = (now - entry.updated).num_milliseconds() as f64 / 1000.0;
+            Value::from_iter([
+                Value::bulk("count"),
+                Value::Int(entry.count as i64),
+                Value::bulk("reason"),
+                Value::bulk(entry.reason),
+                Value::bulk("context"),
+                Value::bulk(entry.context.name()),
+                Value::bulk("object"),
+                Value::bulk(&entry.object),
+                Value::bulk("username"),
+                Value::bulk(&entry.username),
+                Value::bulk("age-seconds"),
+                Value::bulk(format!("{age:.3}")),
+                Value::bulk("client-info"),
+                Value::bulk(&entry.client_info),
+                Value::bulk("entry-id"),
+                Value::Int(entry.id as i64),
+                Value::bulk("timestamp-created"),
+                Value::Int(entry.created.timestamp_millis()),
+                Value::bulk("timestamp-last-updated"),
+                Value::Int(entry.updated.timestamp_millis()),
+            ])
+        }))
+    }
+
+    pub(super) fn reset_log(&mut self) {
+        self.log.clear();
+    }
+
+    /// Reply of `ACL DRYRUN`: whether `username` could run `cmd`, sent as `argv`
+    pub(super) fn dry_run(
+        &self,
+        username: &str,
+        cmd: &Command,
+        argv: &[String],
+    ) -> AclResult<Value> {
+        let user = self
+            .users
+            .get(username)
+            .ok_or_else(|| AclError::UnknownUser(username.to_owned()))?;
+
+        Ok(match user.check(cmd, argv) {
+            Ok(()) => Value::simple("OK"),
+            Err(Denied::Command) => Value::bulk(format!(
+                "User {username} has no permissions to run the '{}' command",
+                cmd.name()
+            )),
+            Err(Denied::Key(key)) => Value::bulk(format!(
+                "User {username} has no permissions to access the '{key}' key"
+            )),
+            Err(Denied::Channel(channel)) => Value::bulk(format!(
+                "User {username} has no permissions to access the '{channel}' channel"
+            )),
+        })
+    }
+
+    /// A random password of `bits` bits, hex-encoded
+    pub(super) fn generate_password(bits: Option<i64>) -> AclResult<String> {
+        let bits = bits.unwrap_or(256);
+        if !(1..=4096).contains(&bits) {
+            return Err(AclError::InvalidBits);
+        }
+
+        let chars = (bits as usize).div_ceil(4);
+        let mut bytes = vec![0; chars.div_ceil(2)];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        let mut password: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        password.truncate(chars);
+        Ok(password)
+    }
+
+    /// Write every user to the ACL file
+    pub(super) fn save(&self) -> AclResult<()> {
+        let path = self.file.as_deref().ok_or(AclError::NoFile)?;
+
+        let mut content = self.list().join("\n");
+        content.push('\n');
+
+        // Write to a temporary file first so that the ACL file is never left half-written
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Replace every user by the ones of the ACL file, returning the names of the users that got
+    /// deleted or modified
+    pub(super) fn load(&mut self) -> AclResult<Vec<String>> {
+        let path = self.file.as_deref().ok_or(AclError::NoFile)?;
+        let mut users = parse_file(path)?;
+        let default = users
+            .entry(DEFAULT_USER.to_owned())
+            .or_insert_with(User::default_user);
+
+        // The file must not open the server when `requirepass` is set
+        if let Some(password) = &self.requirepass {
+            protect(default, Some(password));
+        }
+
+        info!(
+            "loaded {} users from ACL file {}",
+            users.len(),
+            path.display()
+        );
+
+        let changed = self
+            .users
+            .values()
+            .filter(|user| {
+                users
+                    .get(&user.name)
+                    .is_none_or(|new| new.describe() != user.describe())
+            })
+            .map(|user| user.name.clone())
+            .collect();
+
+        self.users = users;
+        Ok(changed)
+    }
+
+    /// Record a security event in the ACL log, grouping it with a similar recent event
+    fn record(
+        &mut self,
+        reason: &'static str,
+        context: Context,
+        object: &str,
+        username: &str,
+        client: &ClientHandle,
+    ) {
+        let now = Utc::now();
+
+        if let Some(entry) = self.log.iter_mut().find(|entry| {
+            entry.reason == reason
+                && entry.context == context
+                && entry.object == object
+                && entry.username == username
+                && (now - entry.updated).num_milliseconds() < LOG_GROUPING_MILLIS
+        }) {
+            entry.count += 1;
+            entry.updated = now;
+            return;
+        }
+
+        self.log.push_front(LogEntry {
+            id: self.next_log_id,
+            count: 1,
+            reason,
+            context,
+            object: object.to_owned(),
+            username: username.to_owned(),
+            client_info: format!(
+                "id={} addr={} user={}",
+                client.id(),
+                client.addr(),
+                client.user().unwrap_or_default()
+            ),
+            created: now,
+            updated: now,
+        });
+        self.next_log_id += 1;
+        self.log.truncate(LOG_MAX_LEN);
+    }
+}
+
+/// Protect the `default` user with `requirepass`, or let anyone use it without a password
+fn protect(default: &mut User, requirepass: Option<&str>) {
+    let _ = default.apply("resetpass");
+    let _ = match requirepass {
+        Some(password) => default.apply(&format!(">{password}")),
+        None => default.apply("nopass"),
+    };
+}
+
+/// Parse the users of an ACL file, made of lines such as `user <username> [rule [rule ...]]`
+fn parse_file(path: &Path) -> AclResult<BTreeMap<String, User>> {
+    let content = fs::read_to_string(path)?;
+    let mut users = BTreeMap::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let error = |message: String| AclError::File {
+            path: path.display().to_string(),
+            line: i + 1,
+            message,
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => continue,
+            Some("user") => {}
+            Some(_) => return Err(error("line should start with user keyword".to_owned())),
+        }
+
+        let name = words
+            .next()
+            .ok_or_else(|| error("missing username".to_owned()))?;
+        let mut user = User::new(name);
+        for rule in user::rules(words).map_err(error)? {
+            user.apply(&rule)
+                .map_err(|reason| error(format!("Error in user declaration '{rule}': {reason}")))?;
+        }
+
+        if users.insert(name.to_owned(), user).is_some() {
+            return Err(error(format!("Duplicate user '{name}' found")));
+        }
+    }
+
+    Ok(users)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_save_and_load_users() {
+        let path = std::env::temp_dir().join(format!("memora-{}.acl", std::process::id()));
+        fs::write(&path, "user default on nopass ~* &* +@all\n").unwrap();
+
+        let mut acl = Acl::new(None, Some(path.clone())).unwrap();
+        acl.set_user(
+            "alice",
+            &["on".to_owned(), ">secret".to_owned(), "+get".to_owned()],
+        )
+        .unwrap();
+        acl.save().unwrap();
+
+        acl.set_user("alice", &["off".to_owned()]).unwrap();
+        assert_eq!(acl.load().unwrap(), ["alice"]);
+        assert!(acl.users["alice"].authenticates("secret"));
+
+        fs::write(&path, "user alice +nosuchcommand\n").unwrap();
+        assert!(matches!(acl.load(), Err(AclError::File { line: 1, .. })));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_keep_requirepass_when_loading_users() {
+        let path = std::env::temp_dir().join(format!("memora-{}-pass.acl", std::process::id()));
+        fs::write(&path, "user alice on >secret +get\n").unwrap();
+
+        // The file has no default user, which must not come back without a password
+        let mut acl = Acl::new(Some("hunter2"), Some(path.clone())).unwrap();
+        assert_eq!(acl.default_user(), None);
+        assert!(acl.users[DEFAULT_USER].authenticates("hunter2"));
+
+        fs::write(&path, "user default on nopass ~* &* +@all\n").unwrap();
+        acl.load().unwrap();
+        assert_eq!(acl.default_user(), None);
+        assert!(acl.users[DEFAULT_USER].authenticates("hunter2"));
+
+        acl.set_requirepass(None);
+        acl.load().unwrap();
+        assert_eq!(acl.default_user(), Some(DEFAULT_USER));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+//! ACL users and the rules describing their permissions
+
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+
+use crate::server::{
+    cmd::{Command, KeyAccess},
+    glob,
+};
+
+use super::category::{self, Category};
+
+/// Reason why a command was denied to a user
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(super) enum Denied {
+    Command,
+    Key(String),
+    Channel(String),
+}
+
+/// A glob pattern of keys, along with the access it grants to them
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl KeyPattern {
+    fn allows(&self, key: &str, access: KeyAccess) -> bool {
+        (self.read || !access.reads())
+            && (self.write || !access.writes())
+            && glob::matches(self.pattern.as_bytes(), key.as_bytes())
+    }
+
+    fn describe(&self) -> String {
+        match (self.read, self.write) {
+            (true, false) => format!("%R~{}", self.pattern),
+            (false, true) => format!("%W~{}", self.pattern),
+            _ => format!("~{}", self.pattern),
+        }
+    }
+}
+
+/// A set of permissions: the commands that can be run, and the keys and channels they can access
+#[derive(Debug, Clone, Default)]
+pub(super) struct Selector {
+    commands: BTreeSet<&'static str>,
+
+    /// Whether the command rules started from every command or from none of them
+    all_commands: bool,
+
+    /// Command rules applied since the commands were last reset, used to describe the selector
+    rules: Vec<String>,
+
+    keys: Vec<KeyPattern>,
+    channels: Vec<String>,
+}
+
+impl Selector {
+    /// Apply a single rule to this selector, returning the reason why it is invalid if it is
+    fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        if rule.eq_ignore_ascii_case("allcommands") || rule.eq_ignore_ascii_case("+@all") {
+            self.commands = category::all().collect();
+            self.all_commands = true;
+            self.rules.clear();
+        } else if rule.eq_ignore_ascii_case("nocommands") || rule.eq_ignore_ascii_case("-@all") {
+            self.commands.clear();
+            self.all_commands = false;
+            self.rules.clear();
+        } else if rule.eq_ignore_ascii_case("allkeys") {
+            self.keys = vec![KeyPattern {
+                pattern: "*".to_owned(),
+                read: true,
+                write: true,
+            }];
+        } else if rule.eq_ignore_ascii_case("resetkeys") {
+            self.keys.clear();
+        } else if rule.eq_ignore_ascii_case("allchannels") {
+            self.channels = vec!["*".to_owned()];
+        } else if rule.eq_ignore_ascii_case("resetchannels") {
+            self.channels.clear();
+        } else if let Some(pattern) = rule.strip_prefix('~') {
+            self.keys.push(KeyPattern {
+                pattern: pattern.to_owned(),
+                read: true,
+                write: true,
+            });
+        } else if let Some(rest) = rule.strip_prefix('%') {
+            let (access, pattern) = rest.split_once('~').ok_or("Syntax error")?;
+            let read = access.contains(['R', 'r']);
+            let write = access.contains(['W', 'w']);
+            if access.is_empty() || !access.chars().all(|c| "RWrw".contains(c)) {
+                return Err("Syntax error");
+            }
+
+            self.keys.push(KeyPattern {
+                pattern: pattern.to_owned(),
+                read,
+                write,
+            });
+        } else if let Some(pattern) = rule.strip_prefix('&') {
+            self.channels.push(pattern.to_owned());
+        } else if let Some((allow, name)) = rule
+            .strip_prefix('+')
+            .map(|name| (true, name))
+            .or_else(|| rule.strip_prefix('-').map(|name| (false, name)))
+        {
+            let commands: Vec<_> = match name.strip_prefix('@') {
+                Some(name) => category::commands(
+                    Category::parse(name).ok_or("Unknown command or category name in ACL")?,
+                )
+                .collect(),
+                None => category::matching(name).collect(),
+            };
+            if commands.is_empty() {
+                return Err("Unknown command or category name in ACL");
+            }
+
+            for command in commands {
+                if allow {
+                    self.commands.insert(command);
+                } else {
+                    self.commands.remove(command);
+                }
+            }
+            self.rules.push(rule.to_ascii_lowercase());
+        } else {
+            return Err("Syntax error");
+        }
+
+        Ok(())
+    }
+
+    fn check(&self, cmd: &Command, argv: &[String]) -> Result<(), Denied> {
+        if !self.commands.contains(cmd.name()) {
+            return Err(Denied::Command);
+        }
+
+        if let Some((key, _)) = cmd
+            .keys(argv)
+            .into_iter()
+            .find(|(key, access)| !self.keys.iter().any(|pattern| pattern.allows(key, *access)))
+        {
+            return Err(Denied::Key(key.to_owned()));
+        }
+
+        if let Some((channel, _)) = cmd
+            .channels()
+            .into_iter()
+            .find(|(channel, pattern)| !self.allows_channel(channel, *pattern))
+        {
+            return Err(Denied::Channel(channel.to_owned()));
+        }
+
+        Ok(())
+    }
+
+    /// Whether `channel` can be accessed. Patterns subscribed to by `PSUBSCRIBE` are only allowed
+    /// if they are literally one of the allowed patterns
+    fn allows_channel(&self, channel: &str, pattern: bool) -> bool {
+        self.channels.iter().any(|allowed| {
+            allowed == "*"
+                || if pattern {
+                    allowed == channel
+                } else {
+                    glob::matches(allowed.as_bytes(), channel.as_bytes())
+                }
+        })
+    }
+
+    fn keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(KeyPattern::describe)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn channels(&self) -> String {
+        self.channels
+            .iter()
+            .map(|channel| format!("&{channel}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn commands(&self) -> String {
+        let base = if self.all_commands { "+@all" } else { "-@all" };
+        std::iter::once(base)
+            .chain(self.rules.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Describe the selector with the rules that would create it
+    fn describe(&self) -> String {
+        let channels = if self.channels.is_empty() {
+            "resetchannels".to_owned()
+        } else {
+            self.channels()
+        };
+
+        [self.keys(), channels, self.commands()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Fields of the selector as reported by `ACL GETUSER`
+    pub(super) fn fields(&self) -> [(&'static str, String); 3] {
+        [
+            ("commands", self.commands()),
+            ("keys", self.keys()),
+            ("channels", self.channels()),
+        ]
+    }
+}
+
+/// A user, which clients can authenticate as
+#[derive(Debug, Clone)]
+pub(super) struct User {
+    pub(super) name: String,
+    pub(super) enabled: bool,
+
+    /// Whether any password is accepted for this user
+    pub(super) nopass: bool,
+
+    /// SHA-256 digests of the passwords of the user, hex-encoded
+    pub(super) passwords: BTreeSet<String>,
+
+    /// Permissions of the user
+    pub(super) root: Selector,
+
+    /// Additional permissions, a command being allowed if any selector allows it as a whole
+    pub(super) selectors: Vec<Selector>,
+}
+
+impl User {
+    /// A new user, disabled and without any permission
+    pub(super) fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            root: Selector::default(),
+            selectors: Vec::new(),
+        }
+    }
+
+    /// The `default` user, which can run every command without password
+    pub(super) fn default_user() -> Self {
+        let mut user = Self::new(super::DEFAULT_USER);
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            let _ = user.apply(rule);
+        }
+        user
+    }
+
+    /// Apply a single rule to this user, returning the reason why it is invalid if it is
+    pub(super) fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        if rule.eq_ignore_ascii_case("on") {
+            self.enabled = true;
+        } else if rule.eq_ignore_ascii_case("off") {
+            self.enabled = false;
+        } else if rule.eq_ignore_ascii_case("nopass") {
+            self.nopass = true;
+            self.passwords.clear();
+        } else if rule.eq_ignore_ascii_case("resetpass") {
+            self.nopass = false;
+            self.passwords.clear();
+        } else if let Some(password) = rule.strip_prefix('>') {
+            self.nopass = false;
+            self.passwords.insert(hash(password));
+        } else if let Some(password) = rule.strip_prefix('<') {
+            if !self.passwords.remove(&hash(password)) {
+                return Err("The password you are trying to remove from the user does not exist");
+            }
+        } else if let Some(digest) = rule.strip_prefix('#') {
+            if digest.len() != 64 || !digest.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+                return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+            }
+            self.nopass = false;
+            self.passwords.insert(digest.to_owned());
+        } else if let Some(digest) = rule.strip_prefix('!') {
+            if !self.passwords.remove(digest) {
+                return Err("The password you are trying to remove from the user does not exist");
+            }
+        } else if rule.eq_ignore_ascii_case("reset") {
+            let name = std::mem::take(&mut self.name);
+            *self = Self::new(name);
+        } else if rule.eq_ignore_ascii_case("clearselectors") {
+            self.selectors.clear();
+        } else if let Some(rules) = rule.strip_prefix('(') {
+            let rules = rules
+                .strip_suffix(')')
+                .ok_or("Unmatched parenthesis in acl selector")?;
+            let mut selector = Selector::default();
+            for rule in rules.split_whitespace() {
+                selector.apply(rule)?;
+            }
+            self.selectors.push(selector);
+        } else {
+            self.root.apply(rule)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `password` authenticates this user
+    pub(super) fn authenticates(&self, password: &str) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash(password)))
+    }
+
+    /// Check whether the user can run `cmd`, reporting the denial of its root permissions if no
+    /// selector allows it either
+    pub(super) fn check(&self, cmd: &Command, argv: &[String]) -> Result<(), Denied> {
+        self.root.check(cmd, argv).or_else(|denied| {
+            if self
+                .selectors
+                .iter()
+                .any(|selector| selector.check(cmd, argv).is_ok())
+            {
+                Ok(())
+            } else {
+                Err(denied)
+            }
+        })
+    }
+
+    /// Flags of the user as reported by `ACL GETUSER`
+    pub(super) fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// Describe the user with the rules that would create it, as a line of an ACL file
+    pub(super) fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().into_iter().map(str::to_owned));
+        parts.extend(self.passwords.iter().map(|digest| format!("#{digest}")));
+        parts.push(self.root.describe());
+        parts.extend(
+            self.selectors
+                .iter()
+                .map(|selector| format!("({})", selector.describe())),
+        );
+        parts.join(" ")
+    }
+}
+
+/// Group the arguments of a selector given as several arguments, such as `(~key` and `+get)`,
+/// into a single rule
+pub(super) fn rules<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>, String> {
+    let mut rules = Vec::new();
+    let mut selector: Option<String> = None;
+
+    for arg in args {
+        match &mut selector {
+            Some(rule) => {
+                rule.push(' ');
+                rule.push_str(arg);
+            }
+            None if arg.starts_with('(') => selector = Some(arg.to_owned()),
+            None => {
+                rules.push(arg.to_owned());
+                continue;
+            }
+        }
+
+        if arg.ends_with(')') {
+            rules.extend(selector.take());
+        }
+    }
+
+    match selector {
+        Some(rule) => Err(format!(
+            "Unmatched parenthesis in acl selector starting at '{rule}'."
+        )),
+        None => Ok(rules),
+    }
+}
+
+/// SHA-256 digest of `password`, hex-encoded
+fn hash(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resp::Value;
+
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("alice");
+        for rule in super::rules(rules.iter().copied()).unwrap() {
+            user.apply(&rule).unwrap();
+        }
+        user
+    }
+
+    fn check(user: &User, args: &[&str]) -> Result<(), Denied> {
+        let argv = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let cmd = Command::try_from(Value::from_iter(argv.iter().map(Value::bulk))).unwrap();
+        user.check(&cmd, &argv)
+    }
+
+    #[test]
+    fn should_check_commands_and_keys() {
+        let alice = user(&["on", ">secret", "+@read", "~cache:*", "%W~logs:*"]);
+        assert!(alice.authenticates("secret"));
+        assert!(!alice.authenticates("wrong"));
+
+        assert_eq!(check(&alice, &["get", "cache:1"]), Ok(()));
+        assert_eq!(
+            check(&alice, &["get", "logs:1"]),
+            Err(Denied::Key("logs:1".to_owned()))
+        );
+        assert_eq!(check(&alice, &["ping"]), Err(Denied::Command));
+
+        let alice = user(&["-@all", "(+get", "~logs:*)"]);
+        assert_eq!(check(&alice, &["get", "logs:1"]), Ok(()));
+        assert_eq!(check(&alice, &["get", "cache:1"]), Err(Denied::Command));
+    }
+
+    #[test]
+    fn should_check_key_access_from_key_specs() {
+        let bob = user(&["+@all", "%R~ro:*", "%W~wo:*"]);
+        assert_eq!(check(&bob, &["set", "wo:1", "v"]), Ok(()));
+        assert!(check(&bob, &["get", "wo:1"]).is_err());
+        assert_eq!(check(&bob, &["eval_ro", "return 1", "1", "ro:1"]), Ok(()));
+        assert!(check(&bob, &["eval", "return 1", "1", "ro:1"]).is_err());
+        assert_eq!(check(&bob, &["object", "encoding", "ro:1"]), Ok(()));
+    }
+
+    #[test]
+    fn should_describe_users() {
+        let alice = user(&[
+            "on",
+            "nopass",
+            "~*",
+            "&news.*",
+            "+@all",
+            "-set",
+            "(+get %R~a*)",
+        ]);
+        assert_eq!(
+            alice.describe(),
+            "user alice on nopass ~* &news.* +@all -set (%R~a* resetchannels -@all +get)"
+        );
+
+        assert!(rules(["(+get", "~a"]).is_err());
+        assert!(User::new("bob").apply("+nosuchcommand").is_err());
+    }
+}
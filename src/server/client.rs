@@ -0,0 +1,188 @@
+//! Metadata of the client connections, as reported by `CLIENT LIST` and `CLIENT INFO`
+
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use super::{cmd::ClientType, tracking::Tracker, ClientAddr, ClientHandle};
+
+/// What is known about a client connection, kept up to date by its session and the server
+#[derive(Debug)]
+pub(super) struct ClientRecord {
+    /// Name set with `CLIENT SETNAME`
+    pub(super) name: Option<String>,
+
+    /// Client library set with `CLIENT SETINFO`
+    pub(super) lib_name: Option<String>,
+    pub(super) lib_ver: Option<String>,
+
+    /// Address the client connected to
+    pub(super) laddr: Option<ClientAddr>,
+
+    /// Version of RESP spoken by the client, switched with `HELLO`
+    pub(super) resp: u8,
+
+    created: Instant,
+
+    /// When the last command of the client got received, and its name
+    pub(super) last_interaction: Instant,
+    pub(super) last_cmd: &'static str,
+
+    /// Whether the client is one of our replicas
+    pub(super) replica: bool,
+
+    /// Whether the client gets sent every command processed, after `MONITOR`
+    pub(super) monitor: bool,
+
+    /// Whether the client is never disconnected to stay under `maxmemory-clients`
+    pub(super) no_evict: bool,
+
+    /// Whether the commands of the client leave the LRU and LFU of the keys untouched
+    pub(super) no_touch: bool,
+
+    /// Number of commands queued by the transaction of the client, if it started one
+    pub(super) multi: Option<usize>,
+
+    /// Bytes received from the client that are not parsed yet, and free space of that buffer
+    pub(super) qbuf: usize,
+    pub(super) qbuf_free: usize,
+
+    /// Bytes waiting to be sent to the client, and space allocated for them
+    pub(super) obl: usize,
+    pub(super) omem: usize,
+}
+
+impl Default for ClientRecord {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            name: None,
+            lib_name: None,
+            lib_ver: None,
+            laddr: None,
+            resp: 2,
+            created: now,
+            last_interaction: now,
+            last_cmd: "NULL",
+            replica: false,
+            monitor: false,
+            no_evict: false,
+            no_touch: false,
+            multi: None,
+            qbuf: 0,
+            qbuf_free: 0,
+            obl: 0,
+            omem: 0,
+        }
+    }
+}
+
+impl ClientRecord {
+    /// How long the client has been connected for
+    pub(super) fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// Approximate number of bytes used by the buffers of the client
+    pub(super) fn memory(&self) -> usize {
+        self.qbuf + self.qbuf_free + self.omem
+    }
+
+    /// How long since the client sent its last command
+    pub(super) fn idle(&self) -> Duration {
+        self.last_interaction.elapsed()
+    }
+}
+
+/// Kind of `client`, given the number of its pub/sub subscriptions
+pub(super) fn client_type(client: &ClientHandle, subscriptions: usize) -> ClientType {
+    if client.is_master() {
+        ClientType::Master
+    } else if client.record().replica {
+        ClientType::Replica
+    } else if subscriptions > 0 {
+        ClientType::PubSub
+    } else {
+        ClientType::Normal
+    }
+}
+
+/// Line describing `client` in the reply of `CLIENT LIST`, given the number of its subscriptions
+/// to channels, patterns and shard channels, and its tracking state if it tracks keys
+pub(super) fn describe(
+    client: &ClientHandle,
+    [sub, psub, ssub]: [usize; 3],
+    tracker: Option<&Tracker>,
+) -> String {
+    let record = client.record();
+
+    let mut flags = String::new();
+    if client.is_master() {
+        flags.push('M');
+    }
+    if record.replica {
+        flags.push('S');
+    }
+    if record.monitor {
+        flags.push('O');
+    }
+    if sub + psub + ssub > 0 {
+        flags.push('P');
+    }
+    if record.multi.is_some() {
+        flags.push('x');
+    }
+    if record.no_evict {
+        flags.push('e');
+    }
+    if record.no_touch {
+        flags.push('T');
+    }
+    if let Some(tracker) = tracker {
+        flags.push('t');
+        if tracker.bcast {
+            flags.push('B');
+        }
+        if tracker.broken_redirect {
+            flags.push('R');
+        }
+    }
+    if flags.is_empty() {
+        flags.push('N');
+    }
+
+    let mut line = String::new();
+    let _ = write!(
+        line,
+        "id={} addr={} laddr={} name={} age={} idle={} flags={flags} db={} sub={sub} psub={psub} \
+         ssub={ssub} multi={} qbuf={} qbuf-free={} obl={} oll={} omem={} cmd={} user={} redir={} \
+         resp={} lib-name={} lib-ver={}",
+        client.id(),
+        client.addr(),
+        record
+            .laddr
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        record.name.as_deref().unwrap_or_default(),
+        record.age().as_secs(),
+        record.idle().as_secs(),
+        client.db(),
+        record.multi.map_or(-1, |queued| queued as i64),
+        record.qbuf,
+        record.qbuf_free,
+        record.obl,
+        client.pending_pushes(),
+        record.omem,
+        record.last_cmd,
+        client.user().unwrap_or_default(),
+        tracker
+            .and_then(|tracker| tracker.redirect)
+            .map_or(-1, |id| id as i64),
+        record.resp,
+        record.lib_name.as_deref().unwrap_or_default(),
+        record.lib_ver.as_deref().unwrap_or_default(),
+    );
+    line
+}
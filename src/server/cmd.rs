@@ -1,11 +1,18 @@
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
 
 use chrono::{DateTime, TimeDelta, Utc};
 use thiserror::Error;
 
-use crate::resp::{self, Value};
+use crate::{
+    dispatch::table::{self, Flag},
+    resp::{self, Value},
+};
+
+/// Number of entries returned by `SLOWLOG GET` unless asked otherwise
+const DEFAULT_SLOWLOG_GET_COUNT: usize = 10;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum SetError {
     #[error("missing key for `SET` command")]
     MissingKey,
@@ -15,6 +22,9 @@ pub enum SetError {
 
     #[error("missing expiry timestamp for `SET` command")]
     MissingExpiry,
+
+    #[error("invalid expire time in 'set' command")]
+    InvalidExpireTime,
 }
 
 #[derive(Debug, Error)]
@@ -24,9 +34,63 @@ pub enum GetError {
 }
 
 #[derive(Debug, Error)]
-pub enum InfoError {
-    #[error("unknown section {0} for `INFO` command")]
-    UnknownSection(String),
+pub enum ReplconfError {
+    #[error("missing value for `REPLCONF {0}` option")]
+    MissingValue(String),
+
+    #[error("unrecognized `REPLCONF` option: {0}")]
+    UnknownOption(String),
+}
+
+#[derive(Debug, Error)]
+pub enum PsyncError {
+    #[error("missing replication id for `PSYNC` command")]
+    MissingReplicationId,
+
+    #[error("missing offset for `PSYNC` command")]
+    MissingOffset,
+}
+
+#[derive(Debug, Error)]
+pub enum EvalError {
+    #[error("Number of keys can't be negative")]
+    NegativeKeys,
+
+    #[error("Number of keys can't be greater than number of args")]
+    TooManyKeys,
+}
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Client names cannot contain spaces, newlines or special characters.")]
+    InvalidName,
+
+    #[error("{0} cannot contain spaces, newlines or special characters.")]
+    InvalidInfo(&'static str),
+
+    #[error("Unrecognized option '{0}'")]
+    UnknownOption(String),
+
+    #[error("Unknown client type '{0}'")]
+    UnknownType(String),
+
+    #[error("timeout is not an integer or out of range")]
+    InvalidTimeout,
+
+    #[error("No such client")]
+    NoSuchClient,
+
+    #[error("You can't use OPTIN and OPTOUT together")]
+    OptInAndOptOut,
+
+    #[error("PREFIX option requires BCAST mode to be enabled")]
+    PrefixWithoutBcast,
+
+    #[error("Protocol version is not an integer or out of range")]
+    InvalidProtover,
+
+    #[error("unsupported protocol version")]
+    NoProto,
 }
 
 #[derive(Debug, Error)]
@@ -38,11 +102,35 @@ pub enum CommandError {
     Get(#[from] GetError),
 
     #[error(transparent)]
-    Info(#[from] InfoError),
+    Replconf(#[from] ReplconfError),
+
+    #[error(transparent)]
+    Psync(#[from] PsyncError),
+
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
 
     #[error("invalid argument for command: {0:?}")]
     InvalidArgument(resp::Value),
 
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+
+    #[error("unknown subcommand '{sub}'. Try {cmd} HELP.")]
+    UnknownSubcommand { cmd: &'static str, sub: String },
+
+    #[error("syntax error")]
+    Syntax,
+
+    #[error("invalid cursor")]
+    InvalidCursor,
+
+    #[error("count should be greater than or equal to -1")]
+    InvalidSlowLogCount,
+
     #[error("invalid command")]
     InvalidCommand,
 
@@ -58,11 +146,11 @@ pub enum Time {
     Millis(u64),
 }
 
-impl Into<Duration> for Time {
-    fn into(self) -> Duration {
-        match self {
-            Self::Seconds(secs) => Duration::from_secs(secs),
-            Self::Millis(millis) => Duration::from_millis(millis),
+impl From<Time> for Duration {
+    fn from(time: Time) -> Self {
+        match time {
+            Time::Seconds(secs) => Duration::from_secs(secs),
+            Time::Millis(millis) => Duration::from_millis(millis),
         }
     }
 }
@@ -74,23 +162,547 @@ pub enum Expiry {
 }
 
 impl Expiry {
-    /// Turn this raw expiry time a UTC [`chrono::DateTime`]
+    /// Turn this raw expiry time a UTC [`chrono::DateTime`], or [`None`] when it can not be
+    /// represented
     pub(crate) fn into_utc(self) -> Option<DateTime<Utc>> {
         match self {
             Self::Time(time) => {
                 let now = Utc::now();
                 let delta = TimeDelta::from_std(time.into()).ok()?;
-                Some(now + delta)
+                now.checked_add_signed(delta)
             }
 
             Self::Unix(ts) => match ts {
-                Time::Seconds(secs) => DateTime::from_timestamp(secs as i64, 0),
-                Time::Millis(millis) => DateTime::from_timestamp_millis(millis as i64),
+                Time::Seconds(secs) => DateTime::from_timestamp(secs.try_into().ok()?, 0),
+                Time::Millis(millis) => DateTime::from_timestamp_millis(millis.try_into().ok()?),
             },
         }
     }
 }
 
+/// An option of the `REPLCONF` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ReplconfOption {
+    /// Port the replica is listening to for client connections
+    ListeningPort(u16),
+
+    /// A capability supported by the replica
+    Capa(String),
+
+    /// Sent by replicas to acknowledge the amount of replication stream they processed
+    Ack(u64),
+
+    /// Sent along `ACK` by replicas to acknowledge the replication offset they fsynced to their AOF
+    Fack(u64),
+
+    /// Sent by masters to ask replicas to acknowledge their replication offset
+    GetAck,
+}
+
+/// A subcommand of the `ACL` command, which manages the users and their permissions
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum AclCommand {
+    /// Create or modify a user by applying ACL rules to it.
+    /// ACL SETUSER username [rule [rule ...]]
+    SetUser {
+        username: String,
+        rules: Vec<String>,
+    },
+
+    /// Describe the rules of a user.
+    /// ACL GETUSER username
+    GetUser(String),
+
+    /// Delete users and close the connections authenticated as them.
+    /// ACL DELUSER username [username ...]
+    DelUser(Vec<String>),
+
+    /// Describe every user in the ACL file format.
+    /// ACL LIST
+    List,
+
+    /// Names of every user.
+    /// ACL USERS
+    Users,
+
+    /// Name of the user the client is authenticated as.
+    /// ACL WHOAMI
+    WhoAmI,
+
+    /// List the command categories, or the commands of a category.
+    /// ACL CAT [category]
+    Cat(Option<String>),
+
+    /// List the most recent security events, denied commands and failed authentications.
+    /// ACL LOG [count]
+    Log(Option<usize>),
+
+    /// Clear the security events log.
+    /// ACL LOG RESET
+    LogReset,
+
+    /// Check whether a user could run a command, without running it.
+    /// ACL DRYRUN username command [arg [arg ...]]
+    DryRun { username: String, args: Vec<String> },
+
+    /// Generate a random password of the given number of bits.
+    /// ACL GENPASS [bits]
+    GenPass(Option<i64>),
+
+    /// Save the users to the configured ACL file.
+    /// ACL SAVE
+    Save,
+
+    /// Replace the users by the ones of the configured ACL file.
+    /// ACL LOAD
+    Load,
+}
+
+/// A subcommand of the `PUBSUB` introspection command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum PubSubCommand {
+    /// List the active channels, optionally matching a glob pattern.
+    /// PUBSUB CHANNELS [pattern]
+    Channels(Option<String>),
+
+    /// Number of subscribers of the given channels.
+    /// PUBSUB NUMSUB [channel [channel ...]]
+    NumSub(Vec<String>),
+
+    /// Number of unique patterns subscribed to.
+    /// PUBSUB NUMPAT
+    NumPat,
+
+    /// List the active shard channels, optionally matching a glob pattern.
+    /// PUBSUB SHARDCHANNELS [pattern]
+    ShardChannels(Option<String>),
+
+    /// Number of subscribers of the given shard channels.
+    /// PUBSUB SHARDNUMSUB [shardchannel [shardchannel ...]]
+    ShardNumSub(Vec<String>),
+}
+
+/// A subcommand of the `SCRIPT` command, which manages the scripts cache
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ScriptCommand {
+    /// Load a script into the scripts cache without executing it.
+    /// SCRIPT LOAD script
+    Load(String),
+
+    /// Check whether the scripts identified by their SHA1 digests exist in the scripts cache.
+    /// SCRIPT EXISTS sha1 [sha1 ...]
+    Exists(Vec<String>),
+
+    /// Flush the scripts cache.
+    /// SCRIPT FLUSH [ASYNC | SYNC]
+    Flush,
+
+    /// Kill the currently executing script, provided that it did not perform any write yet.
+    /// SCRIPT KILL
+    Kill,
+}
+
+/// A subcommand of the `FUNCTION` command, which manages the libraries of functions
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum FunctionCommand {
+    /// Load a library, optionally replacing an existing library with the same name.
+    /// FUNCTION LOAD [REPLACE] function-code
+    Load { code: String, replace: bool },
+
+    /// List the libraries, their functions and optionally their code.
+    /// FUNCTION LIST [LIBRARYNAME library-name-pattern] [WITHCODE]
+    List {
+        pattern: Option<String>,
+        withcode: bool,
+    },
+
+    /// Delete a library and all its functions.
+    /// FUNCTION DELETE library-name
+    Delete(String),
+
+    /// Delete every library.
+    /// FUNCTION FLUSH [ASYNC | SYNC]
+    Flush,
+
+    /// Serialize every library into a payload for `FUNCTION RESTORE`.
+    /// FUNCTION DUMP
+    Dump,
+
+    /// Restore libraries from a payload returned by `FUNCTION DUMP`.
+    /// FUNCTION RESTORE serialized-value [FLUSH | APPEND | REPLACE]
+    Restore {
+        payload: String,
+        policy: RestorePolicy,
+    },
+}
+
+/// What to do with existing libraries when restoring libraries with `FUNCTION RESTORE`
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub enum RestorePolicy {
+    /// Fail if a restored library already exists
+    #[default]
+    Append,
+
+    /// Replace existing libraries with the restored ones
+    Replace,
+
+    /// Delete every existing library before restoring
+    Flush,
+}
+
+/// Subcommands of the `OBJECT` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ObjectCommand {
+    /// Logarithmic access frequency counter of a key, with an LFU `maxmemory-policy`.
+    /// OBJECT FREQ key
+    Freq(String),
+
+    /// Number of seconds since a key got last accessed, without an LFU `maxmemory-policy`.
+    /// OBJECT IDLETIME key
+    IdleTime(String),
+
+    /// How the value of a key is laid out in memory.
+    /// OBJECT ENCODING key
+    Encoding(String),
+
+    /// Number of references to the value of a key.
+    /// OBJECT REFCOUNT key
+    RefCount(String),
+}
+
+/// Subcommands of the `MEMORY` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MemoryCommand {
+    /// Number of bytes used by a key and its value. Values are not aggregates, so every value
+    /// gets fully accounted for whatever the number of samples.
+    /// MEMORY USAGE key [SAMPLES count]
+    Usage { key: String, samples: Option<usize> },
+
+    /// Breakdown of the memory used by the server.
+    /// MEMORY STATS
+    Stats,
+
+    /// Report the memory issues of the server, if any.
+    /// MEMORY DOCTOR
+    Doctor,
+}
+
+/// Subcommands of the `CONFIG` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ConfigCommand {
+    /// Name and value of the parameters matching any of the patterns.
+    /// CONFIG GET parameter [parameter ...]
+    Get(Vec<String>),
+
+    /// Set several parameters at once, or none of them if any is invalid.
+    /// CONFIG SET parameter value [parameter value ...]
+    Set(Vec<(String, String)>),
+
+    /// Write the current configuration to the configuration file.
+    /// CONFIG REWRITE
+    Rewrite,
+
+    /// CONFIG RESETSTAT
+    ResetStat,
+}
+
+/// Subcommands of the `SLOWLOG` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum SlowLogCommand {
+    /// The most recent entries of the slow log, or every entry if no count is given.
+    /// SLOWLOG GET [count]
+    Get(Option<usize>),
+
+    /// SLOWLOG LEN
+    Len,
+
+    /// SLOWLOG RESET
+    Reset,
+}
+
+/// Subcommands of the `LATENCY` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum LatencyCommand {
+    /// Latest sample of every event.
+    /// LATENCY LATEST
+    Latest,
+
+    /// Samples of an event.
+    /// LATENCY HISTORY event
+    History(String),
+
+    /// Forget about the given events, or about every event.
+    /// LATENCY RESET [event [event ...]]
+    Reset(Vec<String>),
+
+    /// ASCII art graph of the samples of an event.
+    /// LATENCY GRAPH event
+    Graph(String),
+
+    /// Human readable analysis of the events.
+    /// LATENCY DOCTOR
+    Doctor,
+
+    /// Latency histogram of the given commands, or of every command.
+    /// LATENCY HISTOGRAM [command [command ...]]
+    Histogram(Vec<String>),
+}
+
+/// Filter of `COMMAND LIST`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum CommandFilter {
+    /// Commands of a module, of which there are none
+    Module(String),
+
+    /// Commands of an ACL category
+    AclCat(String),
+
+    /// Commands whose name match a glob-style pattern
+    Pattern(String),
+}
+
+/// Subcommands of the `COMMAND` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum CommandCommand {
+    /// Details about every command.
+    /// COMMAND
+    All,
+
+    /// COMMAND COUNT
+    Count,
+
+    /// Names of the commands, optionally filtered.
+    /// COMMAND LIST [FILTERBY <MODULE module-name | ACLCAT category | PATTERN pattern>]
+    List(Option<CommandFilter>),
+
+    /// Details about the given commands, or about every command.
+    /// COMMAND INFO [command-name [command-name ...]]
+    Info(Vec<String>),
+
+    /// Documentation of the given commands, or of every command.
+    /// COMMAND DOCS [command-name [command-name ...]]
+    Docs(Vec<String>),
+
+    /// Keys of a command.
+    /// COMMAND GETKEYS command [arg [arg ...]]
+    GetKeys(Vec<String>),
+
+    /// Keys of a command, along with their flags.
+    /// COMMAND GETKEYSANDFLAGS command [arg [arg ...]]
+    GetKeysAndFlags(Vec<String>),
+}
+
+/// Kind of client, as filtered by `CLIENT LIST` and `CLIENT KILL`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClientType {
+    Normal,
+
+    /// The replication link with our master
+    Master,
+    Replica,
+
+    /// A client subscribed to at least one channel or pattern
+    PubSub,
+}
+
+impl FromStr for ClientType {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("normal") {
+            Ok(Self::Normal)
+        } else if s.eq_ignore_ascii_case("master") {
+            Ok(Self::Master)
+        } else if s.eq_ignore_ascii_case("replica") || s.eq_ignore_ascii_case("slave") {
+            Ok(Self::Replica)
+        } else if s.eq_ignore_ascii_case("pubsub") {
+            Ok(Self::PubSub)
+        } else {
+            Err(ClientError::UnknownType(s.to_owned()))
+        }
+    }
+}
+
+/// Clients whose connection gets closed by `CLIENT KILL`, matching every given criteria
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub user: Option<String>,
+    pub ty: Option<ClientType>,
+
+    /// Only clients connected for more than this number of seconds
+    pub maxage: Option<u64>,
+
+    /// Whether the client calling `CLIENT KILL` is spared
+    pub skipme: bool,
+}
+
+impl Default for KillFilter {
+    fn default() -> Self {
+        Self {
+            id: None,
+            addr: None,
+            laddr: None,
+            user: None,
+            ty: None,
+            maxage: None,
+            skipme: true,
+        }
+    }
+}
+
+/// Commands held back by `CLIENT PAUSE`
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PauseMode {
+    /// Commands that may write to the dataset or to the replication stream
+    Write,
+
+    #[default]
+    All,
+}
+
+/// Which replies `CLIENT REPLY` lets through
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReplyMode {
+    On,
+    Off,
+
+    /// Skip the reply of the next command only
+    Skip,
+}
+
+/// Options of `CLIENT TRACKING ON`
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct TrackingOptions {
+    /// Client the invalidation messages get sent to instead of the tracking client
+    pub redirect: Option<u64>,
+
+    /// Prefixes of the keys broadcasted to the client, every key if empty
+    pub prefixes: Vec<String>,
+
+    /// Get invalidations for every key matching the prefixes, rather than for the keys read
+    pub bcast: bool,
+
+    /// Only track the keys read right after `CLIENT CACHING YES`
+    pub optin: bool,
+
+    /// Track every key read, except right after `CLIENT CACHING NO`
+    pub optout: bool,
+
+    /// Skip the invalidations of the keys modified by the client itself
+    pub noloop: bool,
+}
+
+/// Subcommands of the `CLIENT` command
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ClientCommand {
+    /// CLIENT ID
+    Id,
+
+    /// Name the connection, or clear its name if empty.
+    /// CLIENT SETNAME connection-name
+    SetName(String),
+
+    /// CLIENT GETNAME
+    GetName,
+
+    /// CLIENT LIST [TYPE type] [ID client-id [client-id ...]]
+    List {
+        ty: Option<ClientType>,
+        ids: Vec<u64>,
+    },
+
+    /// Describe the calling client, as `CLIENT LIST` does.
+    /// CLIENT INFO
+    Info,
+
+    /// Close the connection of a client by address, or of every client matching a filter.
+    /// CLIENT KILL ip:port | CLIENT KILL filter value [filter value ...]
+    Kill {
+        addr: Option<String>,
+        filter: KillFilter,
+    },
+
+    /// Hold back the commands of the clients for `timeout` milliseconds.
+    /// CLIENT PAUSE timeout [WRITE | ALL]
+    Pause { timeout: u64, mode: PauseMode },
+
+    /// CLIENT UNPAUSE
+    Unpause,
+
+    /// CLIENT NO-EVICT ON | OFF
+    NoEvict(bool),
+
+    /// Keep the commands of the client from altering the LRU and LFU of the keys.
+    /// CLIENT NO-TOUCH ON | OFF
+    NoTouch(bool),
+
+    /// CLIENT REPLY ON | OFF | SKIP
+    Reply(ReplyMode),
+
+    /// Set the name or version of the client library used by the connection.
+    /// CLIENT SETINFO LIB-NAME libname | LIB-VER libver
+    SetInfo { attr: ClientInfoAttr, value: String },
+
+    /// Turn on or off the tracking of the keys cached by the client.
+    /// CLIENT TRACKING ON | OFF [REDIRECT client-id] [PREFIX prefix [PREFIX prefix ...]] [BCAST]
+    /// [OPTIN] [OPTOUT] [NOLOOP]
+    Tracking { on: bool, options: TrackingOptions },
+
+    /// Whether the keys read by the next command get tracked, in `OPTIN` or `OPTOUT` mode.
+    /// CLIENT CACHING YES | NO
+    Caching(bool),
+
+    /// CLIENT TRACKINGINFO
+    TrackingInfo,
+
+    /// CLIENT GETREDIR
+    GetRedir,
+}
+
+/// Attributes of a connection set with `CLIENT SETINFO`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClientInfoAttr {
+    LibName,
+    LibVer,
+}
+
+impl ClientInfoAttr {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LibName => "lib-name",
+            Self::LibVer => "lib-ver",
+        }
+    }
+}
+
+/// Whether `s` only holds printable characters other than spaces, as required for client names
+fn printable(s: &str) -> bool {
+    s.bytes().all(|b| (b'!'..=b'~').contains(&b))
+}
+
+/// How `FLUSHDB` and `FLUSHALL` free the keys they remove
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub enum FlushMode {
+    #[default]
+    Sync,
+
+    /// Free the keys in the background
+    Async,
+}
+
+/// The script executed by `EVAL` or `EVALSHA`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Script {
+    /// Source code of the script
+    Body(String),
+
+    /// SHA1 digest of a script of the scripts cache
+    Sha(String),
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Command {
     Ping(Option<String>),
@@ -117,9 +729,873 @@ pub enum Command {
     /// The INFO command returns information and statistics about the server in a format that is simple to parse by
     /// computers and easy to read by humans.
     Info {
-        /// The optional parameter can be used to select a specific section of information
-        section: Option<String>,
+        /// The optional parameters can be used to select specific sections of information
+        sections: Vec<String>,
+    },
+
+    /// Internal command used by replicas to configure the replication link with their master.
+    /// REPLCONF <option> <value> [<option> <value> ...]
+    Replconf(Vec<ReplconfOption>),
+
+    /// Internal command used by replicas to initiate the replication stream from their master,
+    /// continuing the `replid` history from `offset` if possible.
+    /// PSYNC replicationid offset
+    Psync {
+        replid: String,
+        offset: i64,
+    },
+
+    /// Block the current client until all the previous write commands are successfully transferred
+    /// and acknowledged by at least the number of replicas specified, or until the timeout is reached.
+    /// WAIT numreplicas timeout
+    Wait {
+        numreplicas: usize,
+        /// Timeout in milliseconds, 0 to block forever
+        timeout: u64,
+    },
+
+    /// Block the current client until all the previous write commands are acknowledged as fsynced to
+    /// the AOF of the local instance and/or at least the specified number of replicas.
+    /// WAITAOF numlocal numreplicas timeout
+    WaitAof {
+        numlocal: usize,
+        numreplicas: usize,
+        /// Timeout in milliseconds, 0 to block forever
+        timeout: u64,
+    },
+
+    /// Make the server a replica of another instance, or promote it to a master with `NO ONE`.
+    /// REPLICAOF host port | NO ONE
+    ReplicaOf(Option<(String, u16)>),
+
+    /// Subscribe the client to the given channels.
+    /// SUBSCRIBE channel [channel ...]
+    Subscribe(Vec<String>),
+
+    /// Unsubscribe the client from the given channels, or from all of them if none is given.
+    /// UNSUBSCRIBE [channel [channel ...]]
+    Unsubscribe(Vec<String>),
+
+    /// Subscribe the client to the given glob-style patterns.
+    /// PSUBSCRIBE pattern [pattern ...]
+    PSubscribe(Vec<String>),
+
+    /// Unsubscribe the client from the given patterns, or from all of them if none is given.
+    /// PUNSUBSCRIBE [pattern [pattern ...]]
+    PUnsubscribe(Vec<String>),
+
+    /// Post a message to the given channel.
+    /// PUBLISH channel message
+    Publish {
+        channel: String,
+        message: String,
+    },
+
+    /// Subscribe the client to the given shard channels.
+    /// SSUBSCRIBE shardchannel [shardchannel ...]
+    SSubscribe(Vec<String>),
+
+    /// Unsubscribe the client from the given shard channels, or from all of them if none is given.
+    /// SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+    SUnsubscribe(Vec<String>),
+
+    /// Post a message to the given shard channel.
+    /// SPUBLISH shardchannel message
+    SPublish {
+        channel: String,
+        message: String,
+    },
+
+    /// Introspect the state of the publish/subscribe subsystem.
+    /// PUBSUB subcommand [argument [argument ...]]
+    PubSub(PubSubCommand),
+
+    /// Mark the start of a transaction block, queuing the following commands until `EXEC`.
+    /// MULTI
+    Multi,
+
+    /// Execute all the commands queued since `MULTI`.
+    /// EXEC
+    Exec,
+
+    /// Flush all the commands queued since `MULTI`.
+    /// DISCARD
+    Discard,
+
+    /// Mark the given keys to be watched for conditional execution of a transaction.
+    /// WATCH key [key ...]
+    Watch(Vec<String>),
+
+    /// Forget about all the keys watched by the client.
+    /// UNWATCH
+    Unwatch,
+
+    /// Execute a Lua script server side, either from its source code or from its SHA1 digest.
+    /// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+    /// EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]
+    Eval {
+        script: Script,
+        keys: Vec<String>,
+        args: Vec<String>,
+
+        /// Whether the script is only allowed to run read-only commands, as with `EVAL_RO`
+        readonly: bool,
+    },
+
+    /// Manage the scripts cache.
+    /// SCRIPT subcommand [argument [argument ...]]
+    Script(ScriptCommand),
+
+    /// Manage the libraries of functions.
+    /// FUNCTION subcommand [argument [argument ...]]
+    Function(FunctionCommand),
+
+    /// Call a function registered by a library.
+    /// FCALL function numkeys [key [key ...]] [arg [arg ...]]
+    FCall {
+        function: String,
+        keys: Vec<String>,
+        args: Vec<String>,
+
+        /// Whether only functions with the `no-writes` flag can be called, as with `FCALL_RO`
+        readonly: bool,
+    },
+
+    /// Authenticate the connection, as the `default` user if no username is given.
+    /// AUTH [username] password
+    Auth {
+        username: Option<String>,
+        password: String,
+    },
+
+    /// Switch the protocol of the connection, authenticating it and naming it along the way.
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    Hello {
+        protover: Option<u8>,
+        auth: Option<(String, String)>,
+        setname: Option<String>,
+    },
+
+    /// Manage the users and their permissions.
+    /// ACL subcommand [argument [argument ...]]
+    Acl(AclCommand),
+
+    /// Select the database the following commands of the client apply to.
+    /// SELECT index
+    Select(usize),
+
+    /// Swap the content of two databases.
+    /// SWAPDB index1 index2
+    SwapDb(usize, usize),
+
+    /// Move a key of the selected database to another database.
+    /// MOVE key db
+    Move {
+        key: String,
+        db: usize,
+    },
+
+    /// Remove every key of the selected database.
+    /// FLUSHDB [ASYNC | SYNC]
+    FlushDb(FlushMode),
+
+    /// Remove every key of every database.
+    /// FLUSHALL [ASYNC | SYNC]
+    FlushAll(FlushMode),
+
+    /// Find all the keys of the selected database matching a glob-style pattern.
+    /// KEYS pattern
+    Keys(String),
+
+    /// Incrementally iterate over the keys of the selected database.
+    /// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+    Scan {
+        cursor: u64,
+        pattern: Option<String>,
+        count: Option<usize>,
+        ty: Option<String>,
     },
+
+    /// Remove the specified keys.
+    /// DEL key [key ...]
+    Del(Vec<String>),
+
+    /// Inspect the internals of a key.
+    /// OBJECT subcommand [argument [argument ...]]
+    Object(ObjectCommand),
+
+    /// Inspect the memory used by the server.
+    /// MEMORY subcommand [argument [argument ...]]
+    Memory(MemoryCommand),
+
+    /// Manage the connection of the client and inspect the other connections.
+    /// CLIENT subcommand [argument [argument ...]]
+    Client(ClientCommand),
+
+    /// Inspect and change the configuration of the server.
+    /// CONFIG subcommand [argument [argument ...]]
+    Config(ConfigCommand),
+
+    /// Inspect the log of the slow commands.
+    /// SLOWLOG subcommand [argument]
+    SlowLog(SlowLogCommand),
+
+    /// Inspect the latency monitor.
+    /// LATENCY subcommand [argument [argument ...]]
+    Latency(LatencyCommand),
+
+    /// Stream every command processed by the server to the client.
+    /// MONITOR
+    Monitor,
+
+    /// Introspect the commands known to the server.
+    /// COMMAND [subcommand [argument [argument ...]]]
+    Introspect(CommandCommand),
+
+    /// Ask the server to close the connection.
+    /// QUIT
+    Quit,
+}
+
+/// How a command accesses a key
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeyAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl KeyAccess {
+    /// How a key gets accessed given the flags of its key specification: `RO` keys are read,
+    /// `OW` and `RM` keys are written, and `RW` keys are also read when flagged `ACCESS`
+    fn of(flags: &[&str]) -> Self {
+        let has = |flag| flags.contains(&flag);
+        if has("RO") {
+            Self::Read
+        } else if has("RW") && has("ACCESS") {
+            Self::ReadWrite
+        } else {
+            Self::Write
+        }
+    }
+
+    pub fn reads(self) -> bool {
+        matches!(self, Self::Read | Self::ReadWrite)
+    }
+
+    pub fn writes(self) -> bool {
+        matches!(self, Self::Write | Self::ReadWrite)
+    }
+}
+
+/// Flags describing how a command interacts with the dataset
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CommandFlags(u8);
+
+impl CommandFlags {
+    /// The command may modify the dataset
+    pub const WRITE: Self = Self(1 << 0);
+
+    /// The command only reads from the dataset
+    pub const READONLY: Self = Self(1 << 1);
+
+    /// The command is allowed while a replica has stale data
+    pub const STALE: Self = Self(1 << 2);
+
+    /// The command can not be called from scripts
+    pub const NOSCRIPT: Self = Self(1 << 3);
+
+    /// The command may grow the dataset, and is refused once `maxmemory` can not be honored
+    pub const DENYOOM: Self = Self(1 << 4);
+
+    /// The command runs in constant or logarithmic time, and gets sampled as a `fast-command`
+    /// by the latency monitor
+    pub const FAST: Self = Self(1 << 5);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for CommandFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Command {
+    /// Name of the command, as reported in statistics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ping(_) => "ping",
+            Self::Echo(_) => "echo",
+            Self::Set { .. } => "set",
+            Self::Get { .. } => "get",
+            Self::Info { .. } => "info",
+            Self::Replconf(_) => "replconf",
+            Self::Psync { .. } => "psync",
+            Self::Wait { .. } => "wait",
+            Self::WaitAof { .. } => "waitaof",
+            Self::ReplicaOf(_) => "replicaof",
+            Self::Subscribe(_) => "subscribe",
+            Self::Unsubscribe(_) => "unsubscribe",
+            Self::PSubscribe(_) => "psubscribe",
+            Self::PUnsubscribe(_) => "punsubscribe",
+            Self::Publish { .. } => "publish",
+            Self::PubSub(PubSubCommand::Channels(_)) => "pubsub|channels",
+            Self::PubSub(PubSubCommand::NumSub(_)) => "pubsub|numsub",
+            Self::PubSub(PubSubCommand::NumPat) => "pubsub|numpat",
+            Self::PubSub(PubSubCommand::ShardChannels(_)) => "pubsub|shardchannels",
+            Self::PubSub(PubSubCommand::ShardNumSub(_)) => "pubsub|shardnumsub",
+            Self::SSubscribe(_) => "ssubscribe",
+            Self::SUnsubscribe(_) => "sunsubscribe",
+            Self::SPublish { .. } => "spublish",
+            Self::Multi => "multi",
+            Self::Exec => "exec",
+            Self::Discard => "discard",
+            Self::Watch(_) => "watch",
+            Self::Unwatch => "unwatch",
+            Self::Eval {
+                script: Script::Body(_),
+                readonly,
+                ..
+            } => {
+                if *readonly {
+                    "eval_ro"
+                } else {
+                    "eval"
+                }
+            }
+            Self::Eval {
+                script: Script::Sha(_),
+                readonly,
+                ..
+            } => {
+                if *readonly {
+                    "evalsha_ro"
+                } else {
+                    "evalsha"
+                }
+            }
+            Self::Script(ScriptCommand::Load(_)) => "script|load",
+            Self::Script(ScriptCommand::Exists(_)) => "script|exists",
+            Self::Script(ScriptCommand::Flush) => "script|flush",
+            Self::Script(ScriptCommand::Kill) => "script|kill",
+            Self::Function(FunctionCommand::Load { .. }) => "function|load",
+            Self::Function(FunctionCommand::List { .. }) => "function|list",
+            Self::Function(FunctionCommand::Delete(_)) => "function|delete",
+            Self::Function(FunctionCommand::Flush) => "function|flush",
+            Self::Function(FunctionCommand::Dump) => "function|dump",
+            Self::Function(FunctionCommand::Restore { .. }) => "function|restore",
+            Self::FCall {
+                readonly: false, ..
+            } => "fcall",
+            Self::FCall { readonly: true, .. } => "fcall_ro",
+            Self::Auth { .. } => "auth",
+            Self::Hello { .. } => "hello",
+            Self::Acl(AclCommand::SetUser { .. }) => "acl|setuser",
+            Self::Acl(AclCommand::GetUser(_)) => "acl|getuser",
+            Self::Acl(AclCommand::DelUser(_)) => "acl|deluser",
+            Self::Acl(AclCommand::List) => "acl|list",
+            Self::Acl(AclCommand::Users) => "acl|users",
+            Self::Acl(AclCommand::WhoAmI) => "acl|whoami",
+            Self::Acl(AclCommand::Cat(_)) => "acl|cat",
+            Self::Acl(AclCommand::Log(_) | AclCommand::LogReset) => "acl|log",
+            Self::Acl(AclCommand::DryRun { .. }) => "acl|dryrun",
+            Self::Acl(AclCommand::GenPass(_)) => "acl|genpass",
+            Self::Acl(AclCommand::Save) => "acl|save",
+            Self::Acl(AclCommand::Load) => "acl|load",
+            Self::Select(_) => "select",
+            Self::SwapDb(..) => "swapdb",
+            Self::Move { .. } => "move",
+            Self::FlushDb(_) => "flushdb",
+            Self::FlushAll(_) => "flushall",
+            Self::Keys(_) => "keys",
+            Self::Scan { .. } => "scan",
+            Self::Del(_) => "del",
+            Self::Object(ObjectCommand::Freq(_)) => "object|freq",
+            Self::Object(ObjectCommand::IdleTime(_)) => "object|idletime",
+            Self::Object(ObjectCommand::Encoding(_)) => "object|encoding",
+            Self::Object(ObjectCommand::RefCount(_)) => "object|refcount",
+            Self::Memory(MemoryCommand::Usage { .. }) => "memory|usage",
+            Self::Memory(MemoryCommand::Stats) => "memory|stats",
+            Self::Memory(MemoryCommand::Doctor) => "memory|doctor",
+            Self::Client(ClientCommand::Id) => "client|id",
+            Self::Client(ClientCommand::SetName(_)) => "client|setname",
+            Self::Client(ClientCommand::GetName) => "client|getname",
+            Self::Client(ClientCommand::List { .. }) => "client|list",
+            Self::Client(ClientCommand::Info) => "client|info",
+            Self::Client(ClientCommand::Kill { .. }) => "client|kill",
+            Self::Client(ClientCommand::Pause { .. }) => "client|pause",
+            Self::Client(ClientCommand::Unpause) => "client|unpause",
+            Self::Client(ClientCommand::NoEvict(_)) => "client|no-evict",
+            Self::Client(ClientCommand::NoTouch(_)) => "client|no-touch",
+            Self::Client(ClientCommand::Reply(_)) => "client|reply",
+            Self::Client(ClientCommand::SetInfo { .. }) => "client|setinfo",
+            Self::Client(ClientCommand::Tracking { .. }) => "client|tracking",
+            Self::Client(ClientCommand::Caching(_)) => "client|caching",
+            Self::Client(ClientCommand::TrackingInfo) => "client|trackinginfo",
+            Self::Client(ClientCommand::GetRedir) => "client|getredir",
+            Self::Config(ConfigCommand::Get(_)) => "config|get",
+            Self::Config(ConfigCommand::Set(_)) => "config|set",
+            Self::Config(ConfigCommand::Rewrite) => "config|rewrite",
+            Self::Config(ConfigCommand::ResetStat) => "config|resetstat",
+            Self::SlowLog(SlowLogCommand::Get(_)) => "slowlog|get",
+            Self::SlowLog(SlowLogCommand::Len) => "slowlog|len",
+            Self::SlowLog(SlowLogCommand::Reset) => "slowlog|reset",
+            Self::Latency(LatencyCommand::Latest) => "latency|latest",
+            Self::Latency(LatencyCommand::History(_)) => "latency|history",
+            Self::Latency(LatencyCommand::Reset(_)) => "latency|reset",
+            Self::Latency(LatencyCommand::Graph(_)) => "latency|graph",
+            Self::Latency(LatencyCommand::Doctor) => "latency|doctor",
+            Self::Latency(LatencyCommand::Histogram(_)) => "latency|histogram",
+            Self::Monitor => "monitor",
+            Self::Introspect(CommandCommand::All) => "command",
+            Self::Introspect(CommandCommand::Count) => "command|count",
+            Self::Introspect(CommandCommand::List(_)) => "command|list",
+            Self::Introspect(CommandCommand::Info(_)) => "command|info",
+            Self::Introspect(CommandCommand::Docs(_)) => "command|docs",
+            Self::Introspect(CommandCommand::GetKeys(_)) => "command|getkeys",
+            Self::Introspect(CommandCommand::GetKeysAndFlags(_)) => "command|getkeysandflags",
+            Self::Quit => "quit",
+        }
+    }
+
+    /// Flags of the command, as declared by the command table
+    pub fn flags(&self) -> CommandFlags {
+        let flags = table::lookup(self.name()).map_or(&[][..], |spec| spec.flags);
+        flags
+            .iter()
+            .fold(CommandFlags(0), |flags, flag| match flag {
+                Flag::Write => flags | CommandFlags::WRITE,
+                Flag::ReadOnly => flags | CommandFlags::READONLY,
+                Flag::Stale => flags | CommandFlags::STALE,
+                Flag::NoScript => flags | CommandFlags::NOSCRIPT,
+                Flag::DenyOom => flags | CommandFlags::DENYOOM,
+                Flag::Fast => flags | CommandFlags::FAST,
+                _ => flags,
+            })
+    }
+
+    /// Keys accessed by the command sent as `argv`, along with how they are accessed, as found by
+    /// the key specifications of the command table
+    pub fn keys<'a>(&self, argv: &'a [String]) -> Vec<(&'a str, KeyAccess)> {
+        let keys = table::lookup(self.name()).and_then(|spec| spec.keys_of(argv).ok());
+        keys.unwrap_or_default()
+            .into_iter()
+            .map(|(i, flags)| (argv[i].as_str(), KeyAccess::of(flags)))
+            .collect()
+    }
+
+    /// Channels the command publishes or subscribes to, along with whether they are patterns
+    pub fn channels(&self) -> Vec<(&str, bool)> {
+        match self {
+            Self::Publish { channel, .. } | Self::SPublish { channel, .. } => {
+                vec![(channel, false)]
+            }
+            Self::Subscribe(channels) | Self::SSubscribe(channels) => channels
+                .iter()
+                .map(|channel| (channel.as_str(), false))
+                .collect(),
+            Self::PSubscribe(patterns) => patterns
+                .iter()
+                .map(|pattern| (pattern.as_str(), true))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the command may write to the dataset or feed the replication stream, and is held
+    /// back by `CLIENT PAUSE WRITE`
+    pub fn may_replicate(&self) -> bool {
+        table::lookup(self.name()).is_some_and(|spec| {
+            spec.flags
+                .iter()
+                .any(|flag| matches!(flag, Flag::Write | Flag::MayReplicate))
+        })
+    }
+
+    /// Whether the command can be issued by a client in subscriber mode
+    pub fn allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Self::Ping(_)
+                | Self::Quit
+                | Self::Subscribe(_)
+                | Self::Unsubscribe(_)
+                | Self::PSubscribe(_)
+                | Self::PUnsubscribe(_)
+                | Self::SSubscribe(_)
+                | Self::SUnsubscribe(_)
+        )
+    }
+}
+
+/// Parse the next argument of the `name` command
+fn next_arg<T, I>(values: &mut I, name: &'static str) -> CommandResult<T>
+where
+    T: FromStr,
+    I: Iterator<Item = Value>,
+{
+    let value = values.next().ok_or(CommandError::WrongArity(name))?;
+    value
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(CommandError::InvalidArgument(value))
+}
+
+/// Parse the arguments of `HELLO`
+fn parse_hello(mut values: impl Iterator<Item = Value>) -> CommandResult<Command> {
+    let Some(protover) = values.next() else {
+        return Ok(Command::Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        });
+    };
+    let protover = protover
+        .as_str()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or(ClientError::InvalidProtover)?;
+    if !(2..=3).contains(&protover) {
+        return Err(ClientError::NoProto.into());
+    }
+
+    let (mut auth, mut setname) = (None, None);
+    while let Some(opt) = values.next() {
+        let opt = opt.into_string().ok_or(CommandError::Syntax)?;
+        let mut arg = || {
+            values
+                .next()
+                .and_then(Value::into_string)
+                .ok_or(CommandError::Syntax)
+        };
+
+        if opt.eq_ignore_ascii_case("auth") {
+            auth = Some((arg()?, arg()?));
+        } else if opt.eq_ignore_ascii_case("setname") {
+            let name = arg()?;
+            if !printable(&name) {
+                return Err(ClientError::InvalidName.into());
+            }
+            setname = Some(name);
+        } else {
+            return Err(CommandError::Syntax);
+        }
+    }
+
+    Ok(Command::Hello {
+        protover: Some(protover as u8),
+        auth,
+        setname,
+    })
+}
+
+/// Parse the `numkeys` argument of the `name` command, followed by as many keys and the remaining
+/// arguments, as for `EVAL` or `FCALL`
+fn keys_and_args<I>(mut values: I, name: &'static str) -> CommandResult<(Vec<String>, Vec<String>)>
+where
+    I: Iterator<Item = Value>,
+{
+    let numkeys: i64 = next_arg(&mut values, name)?;
+    let mut args = rest_args(values, None)?;
+
+    let numkeys = usize::try_from(numkeys).map_err(|_| EvalError::NegativeKeys)?;
+    if numkeys > args.len() {
+        return Err(EvalError::TooManyKeys.into());
+    }
+    let keys = args.drain(..numkeys).collect();
+
+    Ok((keys, args))
+}
+
+/// Parse the remaining arguments of a command, requiring at least one of them for the `name`
+/// command if provided
+fn rest_args<I>(values: I, required: Option<&'static str>) -> CommandResult<Vec<String>>
+where
+    I: Iterator<Item = Value>,
+{
+    let args = values
+        .map(|value| value.into_string().ok_or(CommandError::InvalidCommand))
+        .collect::<CommandResult<Vec<_>>>()?;
+
+    match required {
+        Some(name) if args.is_empty() => Err(CommandError::WrongArity(name)),
+        _ => Ok(args),
+    }
+}
+
+/// Parse the single `ON` or `OFF` argument of the `name` command
+fn on_off(args: &[String], name: &'static str) -> CommandResult<bool> {
+    match args {
+        [arg] if arg.eq_ignore_ascii_case("on") => Ok(true),
+        [arg] if arg.eq_ignore_ascii_case("off") => Ok(false),
+        [_] => Err(CommandError::Syntax),
+        _ => Err(CommandError::WrongArity(name)),
+    }
+}
+
+/// Parse the subcommand of `CLIENT` and its arguments
+fn client<I>(mut values: I) -> CommandResult<ClientCommand>
+where
+    I: Iterator<Item = Value>,
+{
+    let sub: String = next_arg(&mut values, "client")?;
+    let args = rest_args(values, None)?;
+
+    let arity = |name: &'static str, count: usize| {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(CommandError::WrongArity(name))
+        }
+    };
+
+    match sub.to_ascii_lowercase().as_str() {
+        "id" => arity("client|id", 0).map(|_| ClientCommand::Id),
+        "getname" => arity("client|getname", 0).map(|_| ClientCommand::GetName),
+        "info" => arity("client|info", 0).map(|_| ClientCommand::Info),
+        "unpause" => arity("client|unpause", 0).map(|_| ClientCommand::Unpause),
+        "trackinginfo" => arity("client|trackinginfo", 0).map(|_| ClientCommand::TrackingInfo),
+        "getredir" => arity("client|getredir", 0).map(|_| ClientCommand::GetRedir),
+        "setname" => {
+            arity("client|setname", 1)?;
+            let name = args.into_iter().next().unwrap_or_default();
+            if !printable(&name) {
+                return Err(ClientError::InvalidName.into());
+            }
+            Ok(ClientCommand::SetName(name))
+        }
+        "list" => {
+            let (mut ty, mut ids) = (None, Vec::new());
+            let mut args = args.into_iter();
+            while let Some(arg) = args.next() {
+                if arg.eq_ignore_ascii_case("type") {
+                    let value = args.next().ok_or(CommandError::Syntax)?;
+                    ty = Some(value.parse()?);
+                } else if arg.eq_ignore_ascii_case("id") {
+                    for id in args.by_ref() {
+                        let id = id
+                            .parse()
+                            .map_err(|_| CommandError::InvalidArgument(Value::bulk(id)))?;
+                        ids.push(id);
+                    }
+                    if ids.is_empty() {
+                        return Err(CommandError::Syntax);
+                    }
+                } else {
+                    return Err(CommandError::Syntax);
+                }
+            }
+            Ok(ClientCommand::List { ty, ids })
+        }
+        "kill" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongArity("client|kill"));
+            }
+
+            // The old form only takes the address of the client
+            if args.len() == 1 {
+                return Ok(ClientCommand::Kill {
+                    addr: args.into_iter().next(),
+                    filter: KillFilter::default(),
+                });
+            }
+
+            if args.len() % 2 != 0 {
+                return Err(CommandError::Syntax);
+            }
+
+            let mut filter = KillFilter::default();
+            let mut args = args.into_iter();
+            while let (Some(name), Some(value)) = (args.next(), args.next()) {
+                let invalid = || CommandError::InvalidArgument(Value::bulk(&value));
+                match name.to_ascii_lowercase().as_str() {
+                    "id" => filter.id = Some(value.parse().map_err(|_| invalid())?),
+                    "addr" => filter.addr = Some(value),
+                    "laddr" => filter.laddr = Some(value),
+                    "user" => filter.user = Some(value),
+                    "type" => filter.ty = Some(value.parse()?),
+                    "maxage" => filter.maxage = Some(value.parse().map_err(|_| invalid())?),
+                    "skipme" if value.eq_ignore_ascii_case("yes") => filter.skipme = true,
+                    "skipme" if value.eq_ignore_ascii_case("no") => filter.skipme = false,
+                    _ => return Err(CommandError::Syntax),
+                }
+            }
+            Ok(ClientCommand::Kill { addr: None, filter })
+        }
+        "pause" => {
+            let mut args = args.into_iter();
+            let timeout = args
+                .next()
+                .ok_or(CommandError::WrongArity("client|pause"))?
+                .parse()
+                .map_err(|_| ClientError::InvalidTimeout)?;
+            let mode = match args.next() {
+                None => PauseMode::default(),
+                Some(mode) if mode.eq_ignore_ascii_case("write") => PauseMode::Write,
+                Some(mode) if mode.eq_ignore_ascii_case("all") => PauseMode::All,
+                Some(_) => return Err(CommandError::Syntax),
+            };
+            if args.next().is_some() {
+                return Err(CommandError::Syntax);
+            }
+            Ok(ClientCommand::Pause { timeout, mode })
+        }
+        "no-evict" => Ok(ClientCommand::NoEvict(on_off(&args, "client|no-evict")?)),
+        "no-touch" => Ok(ClientCommand::NoTouch(on_off(&args, "client|no-touch")?)),
+        "reply" => {
+            arity("client|reply", 1)?;
+            let mode = &args[0];
+            if mode.eq_ignore_ascii_case("on") {
+                Ok(ClientCommand::Reply(ReplyMode::On))
+            } else if mode.eq_ignore_ascii_case("off") {
+                Ok(ClientCommand::Reply(ReplyMode::Off))
+            } else if mode.eq_ignore_ascii_case("skip") {
+                Ok(ClientCommand::Reply(ReplyMode::Skip))
+            } else {
+                Err(CommandError::Syntax)
+            }
+        }
+        "setinfo" => {
+            arity("client|setinfo", 2)?;
+            let mut args = args.into_iter();
+            let (attr, value) = (
+                args.next().unwrap_or_default(),
+                args.next().unwrap_or_default(),
+            );
+
+            let attr = if attr.eq_ignore_ascii_case("lib-name") {
+                ClientInfoAttr::LibName
+            } else if attr.eq_ignore_ascii_case("lib-ver") {
+                ClientInfoAttr::LibVer
+            } else {
+                return Err(ClientError::UnknownOption(attr).into());
+            };
+            if !printable(&value) {
+                return Err(ClientError::InvalidInfo(attr.name()).into());
+            }
+            Ok(ClientCommand::SetInfo { attr, value })
+        }
+        "tracking" => {
+            if args.is_empty() {
+                return Err(CommandError::WrongArity("client|tracking"));
+            }
+            let on = on_off(&args[..1], "client|tracking")?;
+            let mut args = args.into_iter().skip(1);
+
+            let mut options = TrackingOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.to_ascii_lowercase().as_str() {
+                    "redirect" => {
+                        let id = args.next().ok_or(CommandError::Syntax)?;
+                        let id = id
+                            .parse()
+                            .map_err(|_| CommandError::InvalidArgument(Value::bulk(id)))?;
+                        options.redirect = Some(id);
+                    }
+                    "prefix" => options
+                        .prefixes
+                        .push(args.next().ok_or(CommandError::Syntax)?),
+                    "bcast" => options.bcast = true,
+                    "optin" => options.optin = true,
+                    "optout" => options.optout = true,
+                    "noloop" => options.noloop = true,
+                    _ => return Err(CommandError::Syntax),
+                }
+            }
+
+            if options.optin && options.optout {
+                return Err(ClientError::OptInAndOptOut.into());
+            }
+            if !options.bcast && !options.prefixes.is_empty() {
+                return Err(ClientError::PrefixWithoutBcast.into());
+            }
+            Ok(ClientCommand::Tracking { on, options })
+        }
+        "caching" => {
+            arity("client|caching", 1)?;
+            let yes = &args[0];
+            if yes.eq_ignore_ascii_case("yes") {
+                Ok(ClientCommand::Caching(true))
+            } else if yes.eq_ignore_ascii_case("no") {
+                Ok(ClientCommand::Caching(false))
+            } else {
+                Err(CommandError::Syntax)
+            }
+        }
+        _ => Err(CommandError::UnknownSubcommand { cmd: "CLIENT", sub }),
+    }
+}
+
+/// Parse the optional `ASYNC` or `SYNC` argument of `FLUSHDB` and `FLUSHALL`
+fn flush_mode<I>(values: I) -> CommandResult<FlushMode>
+where
+    I: Iterator<Item = Value>,
+{
+    match rest_args(values, None)?.as_slice() {
+        [] => Ok(FlushMode::default()),
+        [mode] if mode.eq_ignore_ascii_case("sync") => Ok(FlushMode::Sync),
+        [mode] if mode.eq_ignore_ascii_case("async") => Ok(FlushMode::Async),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Arguments a command got sent with, its name included
+pub type Argv = Vec<String>;
+
+/// Arguments of the command sent as `value`, with its binary and non-string arguments rendered
+/// as they got encoded
+pub fn argv(value: &Value) -> Argv {
+    let Value::Array(values) = value else {
+        return Vec::new();
+    };
+
+    values
+        .iter()
+        .map(|value| match value.as_str() {
+            Some(s) => s.to_owned(),
+            None => {
+                let mut buf = Vec::new();
+                let _ = value.encode(&mut buf);
+                String::from_utf8_lossy(&buf).into_owned()
+            }
+        })
+        .collect()
+}
+
+/// Hide the secrets, such as passwords, passed as arguments of a command
+pub fn redact(argv: &mut [String]) {
+    const REDACTED: &str = "(redacted)";
+
+    let redacted = |arg: &mut String| *arg = REDACTED.to_owned();
+    match argv {
+        [name, args @ ..] if name.eq_ignore_ascii_case("auth") => {
+            args.iter_mut().for_each(redacted)
+        }
+        [name, sub, _, rules @ ..]
+            if name.eq_ignore_ascii_case("acl") && sub.eq_ignore_ascii_case("setuser") =>
+        {
+            rules
+                .iter_mut()
+                .filter(|rule| rule.starts_with(['>', '<', '#', '!']))
+                .for_each(redacted);
+        }
+        [name, sub, pairs @ ..]
+            if name.eq_ignore_ascii_case("config") && sub.eq_ignore_ascii_case("set") =>
+        {
+            for pair in pairs.chunks_mut(2) {
+                if let [param, value] = pair {
+                    if param.eq_ignore_ascii_case("requirepass") {
+                        redacted(value);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 impl TryFrom<Value> for Command {
@@ -136,6 +1612,14 @@ impl TryFrom<Value> for Command {
 
                 let cmd = cmd.as_str().ok_or(CommandError::InvalidCommand)?;
 
+                // Arities of known commands are checked against the command table up front
+                let sub = values.as_slice().first().and_then(Value::as_str);
+                if let Some(spec) = table::find(cmd, sub) {
+                    if !spec.accepts(values.len() + 1) {
+                        return Err(CommandError::WrongArity(spec.name));
+                    }
+                }
+
                 if cmd.eq_ignore_ascii_case("ping") {
                     let msg = match values.next() {
                         Some(value) => {
@@ -223,17 +1707,550 @@ impl TryFrom<Value> for Command {
                         key: key.to_owned(),
                     })
                 } else if cmd.eq_ignore_ascii_case("info") {
-                    let section = match values.next() {
-                        Some(section) => Some(
-                            section
-                                .as_str()
-                                .map(|s| s.to_owned())
-                                .ok_or(CommandError::InvalidArgument(section))?,
-                        ),
-                        None => None,
+                    let sections = values
+                        .map(|section| section.into_string().ok_or(CommandError::InvalidCommand))
+                        .collect::<CommandResult<_>>()?;
+
+                    Ok(Self::Info { sections })
+                } else if cmd.eq_ignore_ascii_case("replconf") {
+                    let mut options = Vec::new();
+
+                    while let Some(option) = values.next() {
+                        let option = option.into_string().ok_or(CommandError::InvalidCommand)?;
+
+                        let value = values
+                            .next()
+                            .and_then(Value::into_string)
+                            .ok_or_else(|| ReplconfError::MissingValue(option.clone()))?;
+
+                        if option.eq_ignore_ascii_case("listening-port") {
+                            let port = value
+                                .parse()
+                                .map_err(|_| CommandError::InvalidArgument(Value::bulk(value)))?;
+                            options.push(ReplconfOption::ListeningPort(port));
+                        } else if option.eq_ignore_ascii_case("capa") {
+                            options.push(ReplconfOption::Capa(value));
+                        } else if option.eq_ignore_ascii_case("ack") {
+                            let offset = value
+                                .parse()
+                                .map_err(|_| CommandError::InvalidArgument(Value::bulk(value)))?;
+                            options.push(ReplconfOption::Ack(offset));
+                        } else if option.eq_ignore_ascii_case("fack") {
+                            let offset = value
+                                .parse()
+                                .map_err(|_| CommandError::InvalidArgument(Value::bulk(value)))?;
+                            options.push(ReplconfOption::Fack(offset));
+                        } else if option.eq_ignore_ascii_case("getack") {
+                            options.push(ReplconfOption::GetAck);
+                        } else {
+                            return Err(ReplconfError::UnknownOption(option).into());
+                        }
+                    }
+
+                    Ok(Self::Replconf(options))
+                } else if cmd.eq_ignore_ascii_case("psync") {
+                    let replid = values
+                        .next()
+                        .and_then(Value::into_string)
+                        .ok_or(PsyncError::MissingReplicationId)?;
+
+                    let offset = values.next().ok_or(PsyncError::MissingOffset)?;
+                    let offset = offset
+                        .as_str()
+                        .and_then(|offset| offset.parse().ok())
+                        .ok_or(CommandError::InvalidArgument(offset))?;
+
+                    Ok(Self::Psync { replid, offset })
+                } else if cmd.eq_ignore_ascii_case("wait") {
+                    Ok(Self::Wait {
+                        numreplicas: next_arg(&mut values, "wait")?,
+                        timeout: next_arg(&mut values, "wait")?,
+                    })
+                } else if cmd.eq_ignore_ascii_case("waitaof") {
+                    Ok(Self::WaitAof {
+                        numlocal: next_arg(&mut values, "waitaof")?,
+                        numreplicas: next_arg(&mut values, "waitaof")?,
+                        timeout: next_arg(&mut values, "waitaof")?,
+                    })
+                } else if cmd.eq_ignore_ascii_case("replicaof")
+                    || cmd.eq_ignore_ascii_case("slaveof")
+                {
+                    let host: String = next_arg(&mut values, "replicaof")?;
+                    let port: String = next_arg(&mut values, "replicaof")?;
+
+                    if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+                        Ok(Self::ReplicaOf(None))
+                    } else {
+                        let port = port
+                            .parse()
+                            .map_err(|_| CommandError::InvalidArgument(Value::bulk(port)))?;
+                        Ok(Self::ReplicaOf(Some((host, port))))
+                    }
+                } else if cmd.eq_ignore_ascii_case("subscribe") {
+                    Ok(Self::Subscribe(rest_args(values, Some("subscribe"))?))
+                } else if cmd.eq_ignore_ascii_case("unsubscribe") {
+                    Ok(Self::Unsubscribe(rest_args(values, None)?))
+                } else if cmd.eq_ignore_ascii_case("psubscribe") {
+                    Ok(Self::PSubscribe(rest_args(values, Some("psubscribe"))?))
+                } else if cmd.eq_ignore_ascii_case("punsubscribe") {
+                    Ok(Self::PUnsubscribe(rest_args(values, None)?))
+                } else if cmd.eq_ignore_ascii_case("publish") {
+                    Ok(Self::Publish {
+                        channel: next_arg(&mut values, "publish")?,
+                        message: next_arg(&mut values, "publish")?,
+                    })
+                } else if cmd.eq_ignore_ascii_case("ssubscribe") {
+                    Ok(Self::SSubscribe(rest_args(values, Some("ssubscribe"))?))
+                } else if cmd.eq_ignore_ascii_case("sunsubscribe") {
+                    Ok(Self::SUnsubscribe(rest_args(values, None)?))
+                } else if cmd.eq_ignore_ascii_case("spublish") {
+                    Ok(Self::SPublish {
+                        channel: next_arg(&mut values, "spublish")?,
+                        message: next_arg(&mut values, "spublish")?,
+                    })
+                } else if cmd.eq_ignore_ascii_case("pubsub") {
+                    let sub: String = next_arg(&mut values, "pubsub")?;
+                    let mut args = rest_args(values, None)?.into_iter();
+
+                    if sub.eq_ignore_ascii_case("channels") {
+                        Ok(Self::PubSub(PubSubCommand::Channels(args.next())))
+                    } else if sub.eq_ignore_ascii_case("numsub") {
+                        Ok(Self::PubSub(PubSubCommand::NumSub(args.collect())))
+                    } else if sub.eq_ignore_ascii_case("numpat") {
+                        Ok(Self::PubSub(PubSubCommand::NumPat))
+                    } else if sub.eq_ignore_ascii_case("shardchannels") {
+                        Ok(Self::PubSub(PubSubCommand::ShardChannels(args.next())))
+                    } else if sub.eq_ignore_ascii_case("shardnumsub") {
+                        Ok(Self::PubSub(PubSubCommand::ShardNumSub(args.collect())))
+                    } else {
+                        Err(CommandError::UnknownSubcommand { cmd: "PUBSUB", sub })
+                    }
+                } else if cmd.eq_ignore_ascii_case("multi") {
+                    Ok(Self::Multi)
+                } else if cmd.eq_ignore_ascii_case("exec") {
+                    Ok(Self::Exec)
+                } else if cmd.eq_ignore_ascii_case("discard") {
+                    Ok(Self::Discard)
+                } else if cmd.eq_ignore_ascii_case("watch") {
+                    Ok(Self::Watch(rest_args(values, Some("watch"))?))
+                } else if cmd.eq_ignore_ascii_case("unwatch") {
+                    Ok(Self::Unwatch)
+                } else if ["eval", "eval_ro", "evalsha", "evalsha_ro"]
+                    .iter()
+                    .any(|name| cmd.eq_ignore_ascii_case(name))
+                {
+                    let cmd = cmd.to_ascii_lowercase();
+                    let sha = cmd.starts_with("evalsha");
+                    let readonly = cmd.ends_with("_ro");
+
+                    let name = if sha { "evalsha" } else { "eval" };
+                    let script: String = next_arg(&mut values, name)?;
+                    let (keys, args) = keys_and_args(values, name)?;
+
+                    Ok(Self::Eval {
+                        script: if sha {
+                            Script::Sha(script)
+                        } else {
+                            Script::Body(script)
+                        },
+                        keys,
+                        args,
+                        readonly,
+                    })
+                } else if cmd.eq_ignore_ascii_case("script") {
+                    let sub: String = next_arg(&mut values, "script")?;
+
+                    if sub.eq_ignore_ascii_case("load") {
+                        Ok(Self::Script(ScriptCommand::Load(next_arg(
+                            &mut values,
+                            "script|load",
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("exists") {
+                        Ok(Self::Script(ScriptCommand::Exists(rest_args(
+                            values,
+                            Some("script|exists"),
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("flush") {
+                        Ok(Self::Script(ScriptCommand::Flush))
+                    } else if sub.eq_ignore_ascii_case("kill") {
+                        Ok(Self::Script(ScriptCommand::Kill))
+                    } else {
+                        Err(CommandError::UnknownSubcommand { cmd: "SCRIPT", sub })
+                    }
+                } else if cmd.eq_ignore_ascii_case("function") {
+                    let sub: String = next_arg(&mut values, "function")?;
+
+                    if sub.eq_ignore_ascii_case("load") {
+                        let mut code: String = next_arg(&mut values, "function|load")?;
+                        let replace = code.eq_ignore_ascii_case("replace");
+                        if replace {
+                            code = next_arg(&mut values, "function|load")?;
+                        }
+                        Ok(Self::Function(FunctionCommand::Load { code, replace }))
+                    } else if sub.eq_ignore_ascii_case("list") {
+                        let mut pattern = None;
+                        let mut withcode = false;
+                        while let Some(arg) = values.next() {
+                            let arg = arg.into_string().ok_or(CommandError::InvalidCommand)?;
+                            if arg.eq_ignore_ascii_case("withcode") {
+                                withcode = true;
+                            } else if arg.eq_ignore_ascii_case("libraryname") {
+                                pattern = Some(next_arg(&mut values, "function|list")?);
+                            } else {
+                                return Err(CommandError::Syntax);
+                            }
+                        }
+                        Ok(Self::Function(FunctionCommand::List { pattern, withcode }))
+                    } else if sub.eq_ignore_ascii_case("delete") {
+                        Ok(Self::Function(FunctionCommand::Delete(next_arg(
+                            &mut values,
+                            "function|delete",
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("flush") {
+                        Ok(Self::Function(FunctionCommand::Flush))
+                    } else if sub.eq_ignore_ascii_case("dump") {
+                        Ok(Self::Function(FunctionCommand::Dump))
+                    } else if sub.eq_ignore_ascii_case("restore") {
+                        let payload = next_arg(&mut values, "function|restore")?;
+                        let policy = match rest_args(values, None)?.as_slice() {
+                            [] => RestorePolicy::default(),
+                            [policy] if policy.eq_ignore_ascii_case("append") => {
+                                RestorePolicy::Append
+                            }
+                            [policy] if policy.eq_ignore_ascii_case("replace") => {
+                                RestorePolicy::Replace
+                            }
+                            [policy] if policy.eq_ignore_ascii_case("flush") => {
+                                RestorePolicy::Flush
+                            }
+                            _ => return Err(CommandError::Syntax),
+                        };
+                        Ok(Self::Function(FunctionCommand::Restore { payload, policy }))
+                    } else {
+                        Err(CommandError::UnknownSubcommand {
+                            cmd: "FUNCTION",
+                            sub,
+                        })
+                    }
+                } else if cmd.eq_ignore_ascii_case("fcall") || cmd.eq_ignore_ascii_case("fcall_ro")
+                {
+                    let readonly = cmd.eq_ignore_ascii_case("fcall_ro");
+                    let name = if readonly { "fcall_ro" } else { "fcall" };
+                    let function = next_arg(&mut values, name)?;
+                    let (keys, args) = keys_and_args(values, name)?;
+
+                    Ok(Self::FCall {
+                        function,
+                        keys,
+                        args,
+                        readonly,
+                    })
+                } else if cmd.eq_ignore_ascii_case("auth") {
+                    let first: String = next_arg(&mut values, "auth")?;
+                    let second: Option<String> = values
+                        .next()
+                        .map(|value| value.into_string().ok_or(CommandError::InvalidCommand))
+                        .transpose()?;
+                    if values.next().is_some() {
+                        return Err(CommandError::Syntax);
+                    }
+
+                    Ok(match second {
+                        Some(password) => Self::Auth {
+                            username: Some(first),
+                            password,
+                        },
+                        None => Self::Auth {
+                            username: None,
+                            password: first,
+                        },
+                    })
+                } else if cmd.eq_ignore_ascii_case("hello") {
+                    parse_hello(values)
+                } else if cmd.eq_ignore_ascii_case("acl") {
+                    let sub: String = next_arg(&mut values, "acl")?;
+
+                    let acl = if sub.eq_ignore_ascii_case("setuser") {
+                        AclCommand::SetUser {
+                            username: next_arg(&mut values, "acl|setuser")?,
+                            rules: rest_args(values, None)?,
+                        }
+                    } else if sub.eq_ignore_ascii_case("getuser") {
+                        AclCommand::GetUser(next_arg(&mut values, "acl|getuser")?)
+                    } else if sub.eq_ignore_ascii_case("deluser") {
+                        AclCommand::DelUser(rest_args(values, Some("acl|deluser"))?)
+                    } else if sub.eq_ignore_ascii_case("list") {
+                        AclCommand::List
+                    } else if sub.eq_ignore_ascii_case("users") {
+                        AclCommand::Users
+                    } else if sub.eq_ignore_ascii_case("whoami") {
+                        AclCommand::WhoAmI
+                    } else if sub.eq_ignore_ascii_case("cat") {
+                        AclCommand::Cat(rest_args(values, None)?.into_iter().next())
+                    } else if sub.eq_ignore_ascii_case("log") {
+                        match values.next() {
+                            None => AclCommand::Log(None),
+                            Some(value)
+                                if value
+                                    .as_str()
+                                    .is_some_and(|arg| arg.eq_ignore_ascii_case("reset")) =>
+                            {
+                                AclCommand::LogReset
+                            }
+                            Some(value) => AclCommand::Log(Some(
+                                value
+                                    .as_str()
+                                    .and_then(|count| count.parse().ok())
+                                    .ok_or(CommandError::InvalidArgument(value))?,
+                            )),
+                        }
+                    } else if sub.eq_ignore_ascii_case("dryrun") {
+                        AclCommand::DryRun {
+                            username: next_arg(&mut values, "acl|dryrun")?,
+                            args: rest_args(values, Some("acl|dryrun"))?,
+                        }
+                    } else if sub.eq_ignore_ascii_case("genpass") {
+                        AclCommand::GenPass(
+                            values
+                                .next()
+                                .map(|value| {
+                                    value
+                                        .as_str()
+                                        .and_then(|bits| bits.parse().ok())
+                                        .ok_or(CommandError::InvalidArgument(value))
+                                })
+                                .transpose()?,
+                        )
+                    } else if sub.eq_ignore_ascii_case("save") {
+                        AclCommand::Save
+                    } else if sub.eq_ignore_ascii_case("load") {
+                        AclCommand::Load
+                    } else {
+                        return Err(CommandError::UnknownSubcommand { cmd: "ACL", sub });
+                    };
+
+                    Ok(Self::Acl(acl))
+                } else if cmd.eq_ignore_ascii_case("select") {
+                    Ok(Self::Select(next_arg(&mut values, "select")?))
+                } else if cmd.eq_ignore_ascii_case("swapdb") {
+                    Ok(Self::SwapDb(
+                        next_arg(&mut values, "swapdb")?,
+                        next_arg(&mut values, "swapdb")?,
+                    ))
+                } else if cmd.eq_ignore_ascii_case("move") {
+                    Ok(Self::Move {
+                        key: next_arg(&mut values, "move")?,
+                        db: next_arg(&mut values, "move")?,
+                    })
+                } else if cmd.eq_ignore_ascii_case("flushdb") {
+                    Ok(Self::FlushDb(flush_mode(values)?))
+                } else if cmd.eq_ignore_ascii_case("flushall") {
+                    Ok(Self::FlushAll(flush_mode(values)?))
+                } else if cmd.eq_ignore_ascii_case("keys") {
+                    Ok(Self::Keys(next_arg(&mut values, "keys")?))
+                } else if cmd.eq_ignore_ascii_case("scan") {
+                    let cursor = values.next().ok_or(CommandError::WrongArity("scan"))?;
+                    let cursor = cursor
+                        .as_str()
+                        .and_then(|cursor| cursor.parse().ok())
+                        .ok_or(CommandError::InvalidCursor)?;
+
+                    let (mut pattern, mut count, mut ty) = (None, None, None);
+                    while let Some(arg) = values.next() {
+                        let arg = arg.into_string().ok_or(CommandError::InvalidCommand)?;
+                        if arg.eq_ignore_ascii_case("match") {
+                            pattern = Some(next_arg(&mut values, "scan")?);
+                        } else if arg.eq_ignore_ascii_case("count") {
+                            let value: usize = next_arg(&mut values, "scan")?;
+                            if value == 0 {
+                                return Err(CommandError::Syntax);
+                            }
+                            count = Some(value);
+                        } else if arg.eq_ignore_ascii_case("type") {
+                            ty = Some(next_arg(&mut values, "scan")?);
+                        } else {
+                            return Err(CommandError::Syntax);
+                        }
+                    }
+
+                    Ok(Self::Scan {
+                        cursor,
+                        pattern,
+                        count,
+                        ty,
+                    })
+                } else if cmd.eq_ignore_ascii_case("del") {
+                    Ok(Self::Del(rest_args(values, Some("del"))?))
+                } else if cmd.eq_ignore_ascii_case("object") {
+                    let sub: String = next_arg(&mut values, "object")?;
+
+                    if sub.eq_ignore_ascii_case("freq") {
+                        Ok(Self::Object(ObjectCommand::Freq(next_arg(
+                            &mut values,
+                            "object|freq",
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("idletime") {
+                        Ok(Self::Object(ObjectCommand::IdleTime(next_arg(
+                            &mut values,
+                            "object|idletime",
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("encoding") {
+                        Ok(Self::Object(ObjectCommand::Encoding(next_arg(
+                            &mut values,
+                            "object|encoding",
+                        )?)))
+                    } else if sub.eq_ignore_ascii_case("refcount") {
+                        Ok(Self::Object(ObjectCommand::RefCount(next_arg(
+                            &mut values,
+                            "object|refcount",
+                        )?)))
+                    } else {
+                        Err(CommandError::UnknownSubcommand { cmd: "OBJECT", sub })
+                    }
+                } else if cmd.eq_ignore_ascii_case("memory") {
+                    let sub: String = next_arg(&mut values, "memory")?;
+
+                    if sub.eq_ignore_ascii_case("usage") {
+                        let key = next_arg(&mut values, "memory|usage")?;
+                        let samples = match rest_args(values, None)?.as_slice() {
+                            [] => None,
+                            [arg, count] if arg.eq_ignore_ascii_case("samples") => {
+                                Some(count.parse().map_err(|_| {
+                                    CommandError::InvalidArgument(Value::bulk(count))
+                                })?)
+                            }
+                            _ => return Err(CommandError::Syntax),
+                        };
+                        Ok(Self::Memory(MemoryCommand::Usage { key, samples }))
+                    } else if sub.eq_ignore_ascii_case("stats") {
+                        Ok(Self::Memory(MemoryCommand::Stats))
+                    } else if sub.eq_ignore_ascii_case("doctor") {
+                        Ok(Self::Memory(MemoryCommand::Doctor))
+                    } else {
+                        Err(CommandError::UnknownSubcommand { cmd: "MEMORY", sub })
+                    }
+                } else if cmd.eq_ignore_ascii_case("client") {
+                    Ok(Self::Client(client(values)?))
+                } else if cmd.eq_ignore_ascii_case("slowlog") {
+                    let sub: String = next_arg(&mut values, "slowlog")?;
+                    let args = rest_args(values, None)?;
+
+                    if sub.eq_ignore_ascii_case("get") {
+                        let count = match args.as_slice() {
+                            [] => Some(DEFAULT_SLOWLOG_GET_COUNT),
+                            [count] => match count.parse::<i64>() {
+                                Ok(-1) => None,
+                                Ok(count) if count >= 0 => Some(count as usize),
+                                _ => return Err(CommandError::InvalidSlowLogCount),
+                            },
+                            _ => return Err(CommandError::WrongArity("slowlog|get")),
+                        };
+                        Ok(Self::SlowLog(SlowLogCommand::Get(count)))
+                    } else if sub.eq_ignore_ascii_case("len") {
+                        Ok(Self::SlowLog(SlowLogCommand::Len))
+                    } else if sub.eq_ignore_ascii_case("reset") {
+                        Ok(Self::SlowLog(SlowLogCommand::Reset))
+                    } else {
+                        Err(CommandError::UnknownSubcommand {
+                            cmd: "SLOWLOG",
+                            sub,
+                        })
+                    }
+                } else if cmd.eq_ignore_ascii_case("latency") {
+                    let sub: String = next_arg(&mut values, "latency")?;
+                    let args = rest_args(values, None)?;
+
+                    if sub.eq_ignore_ascii_case("latest") {
+                        Ok(Self::Latency(LatencyCommand::Latest))
+                    } else if sub.eq_ignore_ascii_case("history") {
+                        let [event] = args.as_slice() else {
+                            return Err(CommandError::WrongArity("latency|history"));
+                        };
+                        Ok(Self::Latency(LatencyCommand::History(event.clone())))
+                    } else if sub.eq_ignore_ascii_case("reset") {
+                        Ok(Self::Latency(LatencyCommand::Reset(args)))
+                    } else if sub.eq_ignore_ascii_case("graph") {
+                        let [event] = args.as_slice() else {
+                            return Err(CommandError::WrongArity("latency|graph"));
+                        };
+                        Ok(Self::Latency(LatencyCommand::Graph(event.clone())))
+                    } else if sub.eq_ignore_ascii_case("doctor") {
+                        Ok(Self::Latency(LatencyCommand::Doctor))
+                    } else if sub.eq_ignore_ascii_case("histogram") {
+                        Ok(Self::Latency(LatencyCommand::Histogram(
+                            args.iter().map(|arg| arg.to_ascii_lowercase()).collect(),
+                        )))
+                    } else {
+                        Err(CommandError::UnknownSubcommand {
+                            cmd: "LATENCY",
+                            sub,
+                        })
+                    }
+                } else if cmd.eq_ignore_ascii_case("config") {
+                    let sub: String = next_arg(&mut values, "config")?;
+                    let args = rest_args(values, None)?;
+
+                    if sub.eq_ignore_ascii_case("get") {
+                        Ok(Self::Config(ConfigCommand::Get(args)))
+                    } else if sub.eq_ignore_ascii_case("set") {
+                        if args.len() % 2 != 0 {
+                            return Err(CommandError::WrongArity("config|set"));
+                        }
+                        let mut pairs = Vec::with_capacity(args.len() / 2);
+                        let mut args = args.into_iter();
+                        while let (Some(name), Some(value)) = (args.next(), args.next()) {
+                            pairs.push((name, value));
+                        }
+                        Ok(Self::Config(ConfigCommand::Set(pairs)))
+                    } else if sub.eq_ignore_ascii_case("rewrite") {
+                        Ok(Self::Config(ConfigCommand::Rewrite))
+                    } else if sub.eq_ignore_ascii_case("resetstat") {
+                        Ok(Self::Config(ConfigCommand::ResetStat))
+                    } else {
+                        Err(CommandError::UnknownSubcommand { cmd: "CONFIG", sub })
+                    }
+                } else if cmd.eq_ignore_ascii_case("monitor") {
+                    Ok(Self::Monitor)
+                } else if cmd.eq_ignore_ascii_case("command") {
+                    let args = rest_args(values, None)?;
+                    let Some((sub, args)) = args.split_first() else {
+                        return Ok(Self::Introspect(CommandCommand::All));
                     };
 
-                    Ok(Self::Info { section })
+                    let args = args.to_vec();
+                    match sub.to_ascii_lowercase().as_str() {
+                        "count" => Ok(Self::Introspect(CommandCommand::Count)),
+                        "list" => {
+                            let filter = match args.as_slice() {
+                                [] => None,
+                                [filterby, kind, value]
+                                    if filterby.eq_ignore_ascii_case("filterby") =>
+                                {
+                                    let value = value.clone();
+                                    match kind.to_ascii_lowercase().as_str() {
+                                        "module" => Some(CommandFilter::Module(value)),
+                                        "aclcat" => Some(CommandFilter::AclCat(value)),
+                                        "pattern" => Some(CommandFilter::Pattern(value)),
+                                        _ => return Err(CommandError::Syntax),
+                                    }
+                                }
+                                _ => return Err(CommandError::Syntax),
+                            };
+                            Ok(Self::Introspect(CommandCommand::List(filter)))
+                        }
+                        "info" => Ok(Self::Introspect(CommandCommand::Info(args))),
+                        "docs" => Ok(Self::Introspect(CommandCommand::Docs(args))),
+                        "getkeys" => Ok(Self::Introspect(CommandCommand::GetKeys(args))),
+                        "getkeysandflags" => {
+                            Ok(Self::Introspect(CommandCommand::GetKeysAndFlags(args)))
+                        }
+                        _ => Err(CommandError::UnknownSubcommand {
+                            cmd: "COMMAND",
+                            sub: sub.clone(),
+                        }),
+                    }
+                } else if cmd.eq_ignore_ascii_case("quit") {
+                    Ok(Self::Quit)
                 } else {
                     Err(CommandError::UnknownCommand(cmd.to_owned()))
                 }
@@ -242,3 +2259,169 @@ impl TryFrom<Value> for Command {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CommandResult<Command> {
+        Command::try_from(Value::from_iter(args.iter().map(Value::bulk)))
+    }
+
+    fn client(args: &[&str]) -> CommandResult<ClientCommand> {
+        let args = [&["client"][..], args].concat();
+        match parse(&args)? {
+            Command::Client(cmd) => Ok(cmd),
+            cmd => panic!("expected a CLIENT subcommand, got {cmd:?}"),
+        }
+    }
+
+    #[test]
+    fn should_parse_client_kill_filters() {
+        assert_eq!(
+            client(&["kill", "127.0.0.1:6000"]).unwrap(),
+            ClientCommand::Kill {
+                addr: Some("127.0.0.1:6000".to_owned()),
+                filter: KillFilter::default(),
+            }
+        );
+
+        let ClientCommand::Kill { addr: None, filter } = client(&[
+            "kill", "ID", "7", "user", "bob", "type", "pubsub", "maxage", "60", "skipme", "no",
+        ])
+        .unwrap() else {
+            panic!("expected the new form of CLIENT KILL");
+        };
+        assert_eq!(
+            filter,
+            KillFilter {
+                id: Some(7),
+                user: Some("bob".to_owned()),
+                ty: Some(ClientType::PubSub),
+                maxage: Some(60),
+                skipme: false,
+                ..KillFilter::default()
+            }
+        );
+
+        assert!(matches!(
+            client(&["kill", "id", "7", "user"]),
+            Err(CommandError::Syntax)
+        ));
+        assert!(matches!(
+            client(&["kill", "id", "seven"]),
+            Err(CommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            client(&["kill", "name", "app"]),
+            Err(CommandError::Syntax)
+        ));
+        assert!(matches!(
+            client(&["kill", "skipme", "maybe"]),
+            Err(CommandError::Syntax)
+        ));
+    }
+
+    #[test]
+    fn should_parse_client_pause() {
+        assert_eq!(
+            client(&["pause", "100"]).unwrap(),
+            ClientCommand::Pause {
+                timeout: 100,
+                mode: PauseMode::All,
+            }
+        );
+        assert_eq!(
+            client(&["pause", "100", "WRITE"]).unwrap(),
+            ClientCommand::Pause {
+                timeout: 100,
+                mode: PauseMode::Write,
+            }
+        );
+        assert_eq!(
+            client(&["pause", "100", "all"]).unwrap(),
+            ClientCommand::Pause {
+                timeout: 100,
+                mode: PauseMode::All,
+            }
+        );
+        assert!(matches!(
+            client(&["pause", "-1"]),
+            Err(CommandError::Client(ClientError::InvalidTimeout))
+        ));
+        assert!(matches!(
+            client(&["pause", "100", "read"]),
+            Err(CommandError::Syntax)
+        ));
+        assert!(matches!(
+            client(&["pause", "100", "write", "all"]),
+            Err(CommandError::Syntax)
+        ));
+
+        assert_eq!(client(&["unpause"]).unwrap(), ClientCommand::Unpause);
+        assert!(matches!(
+            client(&["unpause", "now"]),
+            Err(CommandError::WrongArity("client|unpause"))
+        ));
+    }
+
+    #[test]
+    fn should_parse_client_switches() {
+        assert_eq!(
+            client(&["no-evict", "on"]).unwrap(),
+            ClientCommand::NoEvict(true)
+        );
+        assert_eq!(
+            client(&["no-evict", "OFF"]).unwrap(),
+            ClientCommand::NoEvict(false)
+        );
+        assert!(matches!(
+            client(&["no-evict", "yes"]),
+            Err(CommandError::Syntax)
+        ));
+
+        assert_eq!(
+            client(&["reply", "off"]).unwrap(),
+            ClientCommand::Reply(ReplyMode::Off)
+        );
+        assert_eq!(
+            client(&["reply", "SKIP"]).unwrap(),
+            ClientCommand::Reply(ReplyMode::Skip)
+        );
+        assert_eq!(
+            client(&["reply", "on"]).unwrap(),
+            ClientCommand::Reply(ReplyMode::On)
+        );
+        assert!(matches!(
+            client(&["reply", "later"]),
+            Err(CommandError::Syntax)
+        ));
+    }
+
+    #[test]
+    fn should_validate_client_names() {
+        assert_eq!(
+            client(&["setname", "app-1"]).unwrap(),
+            ClientCommand::SetName("app-1".to_owned())
+        );
+        assert_eq!(
+            client(&["setname", ""]).unwrap(),
+            ClientCommand::SetName(String::new())
+        );
+        for name in ["my app", "app\n", "caf\u{e9}"] {
+            assert!(matches!(
+                client(&["setname", name]),
+                Err(CommandError::Client(ClientError::InvalidName))
+            ));
+        }
+    }
+
+    #[test]
+    fn should_convert_time_to_duration() {
+        assert_eq!(Duration::from(Time::Seconds(2)), Duration::from_secs(2));
+        assert_eq!(
+            Duration::from(Time::Millis(1500)),
+            Duration::from_millis(1500)
+        );
+    }
+}
@@ -0,0 +1,882 @@
+//! Runtime configuration of a memora instance.
+//!
+//! Every parameter is registered in [`PARAMS`], which tells how to parse it and how to render it
+//! back. The configuration gets loaded from a `redis.conf`-style file, whose directives can be
+//! overridden on the command line as `--name value`, and gets changed at runtime with `CONFIG SET`.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use super::{
+    evict::{EvictionPolicy, Lfu},
+    glob,
+    log::LogLevel,
+    notify::NotifyFlags,
+    tls::{AuthClients, TlsConfig},
+};
+
+/// Port accepting connections unless configured otherwise
+pub const DEFAULT_PORT: u16 = 6379;
+
+/// Number of logical databases unless configured otherwise
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// Size of the replication backlog unless configured otherwise
+pub const DEFAULT_REPL_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// Number of keys sampled by every database to pick a key to evict
+pub const DEFAULT_MAXMEMORY_SAMPLES: usize = 5;
+
+/// Execution time in microseconds from which commands get logged unless configured otherwise
+pub const DEFAULT_SLOWLOG_LOG_SLOWER_THAN: i64 = 10_000;
+
+/// Number of entries kept by the slow log unless configured otherwise
+pub const DEFAULT_SLOWLOG_MAX_LEN: usize = 128;
+
+/// How deep `include` directives may nest, which also stops include cycles
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Comment preceding the directives appended to the configuration file by `CONFIG REWRITE`
+const REWRITE_SIGNATURE: &str = "# Generated by CONFIG REWRITE";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Fatal error, can't open config file '{}': {source}", path.display())]
+    Open { path: PathBuf, source: io::Error },
+
+    #[error("*** FATAL CONFIG FILE ERROR *** Reading the configuration file, at line '{line}': {reason}")]
+    Directive { line: String, reason: String },
+
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownParameter(String),
+
+    #[error("Duplicate parameter - {0}")]
+    Duplicate(String),
+
+    #[error("CONFIG SET failed (possibly related to argument '{name}') - {reason}")]
+    Invalid { name: String, reason: String },
+
+    #[error("The server is running without a config file")]
+    NoConfigFile,
+
+    #[error("Rewriting config file: {0}")]
+    Rewrite(io::Error),
+}
+
+/// Configuration of a memora instance
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Configuration file the configuration got loaded from, if any
+    pub file: Option<PathBuf>,
+
+    pub port: u16,
+
+    /// Number of logical databases clients can select
+    pub databases: usize,
+
+    /// Master this instance replicates at startup, if any
+    pub replicaof: Option<(String, u16)>,
+
+    /// Size of the replication backlog used to serve partial resynchronizations
+    pub repl_backlog_size: usize,
+
+    /// Reject write commands from the clients of a replica
+    pub replica_read_only: bool,
+
+    /// Keep serving the clients of a replica while the link with its master is down
+    pub replica_serve_stale_data: bool,
+
+    /// Classes of keyspace events published to clients
+    pub notify_keyspace_events: NotifyFlags,
+
+    /// Password of the `default` user, which clients must authenticate with
+    pub requirepass: Option<String>,
+
+    /// File the ACL users are loaded from and saved to
+    pub aclfile: Option<PathBuf>,
+
+    pub tls: TlsConfig,
+
+    /// Path of the unix socket to accept connections on, and the permissions of its file
+    pub unixsocket: Option<PathBuf>,
+    pub unixsocketperm: Option<u32>,
+
+    /// Number of bytes the dataset may use before keys get evicted, or `0` for no limit
+    pub maxmemory: usize,
+
+    /// Which keys get evicted once `maxmemory` is reached
+    pub maxmemory_policy: EvictionPolicy,
+
+    /// Number of keys sampled by every database to pick a key to evict
+    pub maxmemory_samples: usize,
+
+    /// Number of bytes the buffers of the clients may use before the largest clients get
+    /// disconnected, or `0` for no limit
+    pub maxmemory_clients: usize,
+
+    pub lfu: Lfu,
+
+    pub loglevel: LogLevel,
+
+    /// Execution time in microseconds from which commands get logged, or a negative value to
+    /// log no command
+    pub slowlog_log_slower_than: i64,
+
+    /// Number of entries kept by the slow log
+    pub slowlog_max_len: usize,
+
+    /// Latency in milliseconds from which events get sampled by the latency monitor, or `0` to
+    /// sample no event
+    pub latency_monitor_threshold: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file: None,
+            port: DEFAULT_PORT,
+            databases: DEFAULT_DATABASES,
+            replicaof: None,
+            repl_backlog_size: DEFAULT_REPL_BACKLOG_SIZE,
+            replica_read_only: true,
+            replica_serve_stale_data: true,
+            notify_keyspace_events: NotifyFlags::NONE,
+            requirepass: None,
+            aclfile: None,
+            tls: TlsConfig::default(),
+            unixsocket: None,
+            unixsocketperm: None,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::default(),
+            maxmemory_samples: DEFAULT_MAXMEMORY_SAMPLES,
+            maxmemory_clients: 0,
+            lfu: Lfu::default(),
+            loglevel: LogLevel::default(),
+            slowlog_log_slower_than: DEFAULT_SLOWLOG_LOG_SLOWER_THAN,
+            slowlog_max_len: DEFAULT_SLOWLOG_MAX_LEN,
+            latency_monitor_threshold: 0,
+        }
+    }
+}
+
+/// A configuration parameter
+struct Param {
+    name: &'static str,
+
+    /// Former name of the parameter, still accepted
+    alias: Option<&'static str>,
+
+    /// Whether `CONFIG SET` can change the parameter while running
+    mutable: bool,
+
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<(), String>,
+}
+
+/// Every configuration parameter
+const PARAMS: &[Param] = &[
+    Param {
+        name: "port",
+        alias: None,
+        mutable: false,
+        get: |config| config.port.to_string(),
+        set: |config, value| {
+            config.port = parse_int(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "databases",
+        alias: None,
+        mutable: false,
+        get: |config| config.databases.to_string(),
+        set: |config, value| {
+            config.databases = parse_int(value)?;
+            if config.databases == 0 {
+                return Err("argument must be between 1 and 2147483647 inclusive".to_owned());
+            }
+            Ok(())
+        },
+    },
+    Param {
+        name: "replicaof",
+        alias: Some("slaveof"),
+        mutable: false,
+        get: |config| {
+            config
+                .replicaof
+                .as_ref()
+                .map(|(host, port)| format!("{host} {port}"))
+                .unwrap_or_default()
+        },
+        set: |config, value| {
+            config.replicaof = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => None,
+                [host, port] => Some((host.to_owned(), parse_int(port)?)),
+                _ => return Err("wrong number of arguments".to_owned()),
+            };
+            Ok(())
+        },
+    },
+    Param {
+        name: "repl-backlog-size",
+        alias: None,
+        mutable: false,
+        get: |config| config.repl_backlog_size.to_string(),
+        set: |config, value| {
+            config.repl_backlog_size = parse_memory(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "replica-read-only",
+        alias: Some("slave-read-only"),
+        mutable: true,
+        get: |config| yes_no(config.replica_read_only),
+        set: |config, value| {
+            config.replica_read_only = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "replica-serve-stale-data",
+        alias: Some("slave-serve-stale-data"),
+        mutable: true,
+        get: |config| yes_no(config.replica_serve_stale_data),
+        set: |config, value| {
+            config.replica_serve_stale_data = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "notify-keyspace-events",
+        alias: None,
+        mutable: true,
+        get: |config| config.notify_keyspace_events.to_string(),
+        set: |config, value| {
+            config.notify_keyspace_events = value.parse().map_err(|e| format!("{e}"))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "requirepass",
+        alias: None,
+        mutable: true,
+        get: |config| config.requirepass.clone().unwrap_or_default(),
+        set: |config, value| {
+            config.requirepass = (!value.is_empty()).then(|| value.to_owned());
+            Ok(())
+        },
+    },
+    Param {
+        name: "aclfile",
+        alias: None,
+        mutable: false,
+        get: |config| display_path(&config.aclfile),
+        set: |config, value| {
+            config.aclfile = parse_path(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-port",
+        alias: None,
+        mutable: false,
+        get: |config| config.tls.port.unwrap_or(0).to_string(),
+        set: |config, value| {
+            config.tls.port = Some(parse_int(value)?).filter(|port| *port != 0);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-cert-file",
+        alias: None,
+        mutable: false,
+        get: |config| display_path(&config.tls.cert_file),
+        set: |config, value| {
+            config.tls.cert_file = parse_path(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-key-file",
+        alias: None,
+        mutable: false,
+        get: |config| display_path(&config.tls.key_file),
+        set: |config, value| {
+            config.tls.key_file = parse_path(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        alias: None,
+        mutable: false,
+        get: |config| display_path(&config.tls.ca_cert_file),
+        set: |config, value| {
+            config.tls.ca_cert_file = parse_path(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-auth-clients",
+        alias: None,
+        mutable: false,
+        get: |config| config.tls.auth_clients.to_string(),
+        set: |config, value| {
+            config.tls.auth_clients = value.parse::<AuthClients>().map_err(|e| format!("{e}"))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-replication",
+        alias: None,
+        mutable: true,
+        get: |config| yes_no(config.tls.replication),
+        set: |config, value| {
+            config.tls.replication = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocket",
+        alias: None,
+        mutable: false,
+        get: |config| display_path(&config.unixsocket),
+        set: |config, value| {
+            config.unixsocket = parse_path(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocketperm",
+        alias: None,
+        mutable: false,
+        get: |config| format!("{:o}", config.unixsocketperm.unwrap_or(0)),
+        set: |config, value| {
+            let perm = u32::from_str_radix(value, 8)
+                .map_err(|_| "argument couldn't be parsed as octal permissions".to_owned())?;
+            config.unixsocketperm = Some(perm).filter(|perm| *perm != 0);
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory",
+        alias: None,
+        mutable: true,
+        get: |config| config.maxmemory.to_string(),
+        set: |config, value| {
+            config.maxmemory = parse_memory(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-policy",
+        alias: None,
+        mutable: true,
+        get: |config| config.maxmemory_policy.to_string(),
+        set: |config, value| {
+            config.maxmemory_policy = value.parse().map_err(|e| format!("{e}"))?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-samples",
+        alias: None,
+        mutable: true,
+        get: |config| config.maxmemory_samples.to_string(),
+        set: |config, value| {
+            let samples = parse_int(value)?;
+            if !(1..=64).contains(&samples) {
+                return Err("argument must be between 1 and 64 inclusive".to_owned());
+            }
+            config.maxmemory_samples = samples;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-clients",
+        alias: None,
+        mutable: true,
+        get: |config| config.maxmemory_clients.to_string(),
+        set: |config, value| {
+            config.maxmemory_clients = parse_memory(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "lfu-log-factor",
+        alias: None,
+        mutable: true,
+        get: |config| config.lfu.log_factor.to_string(),
+        set: |config, value| {
+            config.lfu.log_factor = parse_int(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "lfu-decay-time",
+        alias: None,
+        mutable: true,
+        get: |config| config.lfu.decay_time.to_string(),
+        set: |config, value| {
+            config.lfu.decay_time = parse_int(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "loglevel",
+        alias: None,
+        mutable: true,
+        get: |config| config.loglevel.to_string(),
+        set: |config, value| {
+            config.loglevel = value.parse()?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        alias: None,
+        mutable: true,
+        get: |config| config.slowlog_log_slower_than.to_string(),
+        set: |config, value| {
+            config.slowlog_log_slower_than = parse_int(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-max-len",
+        alias: None,
+        mutable: true,
+        get: |config| config.slowlog_max_len.to_string(),
+        set: |config, value| {
+            config.slowlog_max_len = parse_int(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        alias: None,
+        mutable: true,
+        get: |config| config.latency_monitor_threshold.to_string(),
+        set: |config, value| {
+            config.latency_monitor_threshold = parse_int(value)?;
+            Ok(())
+        },
+    },
+];
+
+/// The parameter named `name`, or one of its aliases
+fn lookup(name: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|param| {
+        param.name.eq_ignore_ascii_case(name)
+            || param
+                .alias
+                .is_some_and(|alias| alias.eq_ignore_ascii_case(name))
+    })
+}
+
+impl Config {
+    /// Load the configuration from `file` if any, then from the `args` given on the command
+    /// line, as `--name value [value ...]` directives overriding the ones of the file
+    pub fn load(file: Option<&Path>, args: &[String]) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        if let Some(file) = file {
+            let path = fs::canonicalize(file).map_err(|source| ConfigError::Open {
+                path: file.to_owned(),
+                source,
+            })?;
+            config.include(&path, 0)?;
+            config.file = Some(path);
+        }
+
+        let mut directives: Vec<Vec<String>> = Vec::new();
+        for arg in args {
+            match (arg.strip_prefix("--"), directives.last_mut()) {
+                (Some(name), _) => directives.push(vec![name.to_owned()]),
+                (None, Some(directive)) => directive.push(arg.clone()),
+                (None, None) => {
+                    return Err(ConfigError::Directive {
+                        line: arg.clone(),
+                        reason: "expected a directive given as --name value".to_owned(),
+                    })
+                }
+            }
+        }
+        for directive in directives {
+            let line = directive.join(" ");
+            config
+                .apply(&directive)
+                .map_err(|reason| ConfigError::Directive { line, reason })?;
+        }
+
+        Ok(config)
+    }
+
+    /// Apply the directives of the configuration file at `path`, included `depth` levels deep
+    fn include(&mut self, path: &Path, depth: usize) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Open {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        for line in text.lines() {
+            let directive = |reason: String| ConfigError::Directive {
+                line: line.to_owned(),
+                reason,
+            };
+
+            let args = split_args(line).map_err(directive)?;
+            match args.first() {
+                None => {}
+                Some(name) if name.eq_ignore_ascii_case("include") => {
+                    let [_, included] = &args[..] else {
+                        return Err(directive("wrong number of arguments".to_owned()));
+                    };
+                    if depth == MAX_INCLUDE_DEPTH {
+                        return Err(directive("too many nested includes".to_owned()));
+                    }
+                    self.include(Path::new(included), depth + 1)?;
+                }
+                Some(_) => self.apply(&args).map_err(directive)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a directive made of the name of a parameter followed by its value
+    fn apply(&mut self, args: &[String]) -> Result<(), String> {
+        let [name, values @ ..] = args else {
+            return Ok(());
+        };
+        let param = lookup(name).ok_or("Bad directive or wrong number of arguments")?;
+        (param.set)(self, &values.join(" "))
+    }
+
+    /// Name and value of the parameters matching any of the glob `patterns`
+    pub(super) fn get(&self, patterns: &[String]) -> Vec<(&'static str, String)> {
+        let mut found = Vec::new();
+        for param in PARAMS {
+            for pattern in patterns {
+                let pattern = pattern.to_ascii_lowercase();
+                if glob::matches(pattern.as_bytes(), param.name.as_bytes()) {
+                    found.push((param.name, (param.get)(self)));
+                    break;
+                }
+
+                // Aliases only get reported when asked for by name
+                if let Some(alias) = param.alias.filter(|alias| *alias == pattern) {
+                    found.push((alias, (param.get)(self)));
+                    break;
+                }
+            }
+        }
+        found
+    }
+
+    /// Set the value of several parameters at once. Either all of them get set, or none of them
+    pub(super) fn set(&mut self, pairs: &[(String, String)]) -> Result<(), ConfigError> {
+        let mut params = Vec::with_capacity(pairs.len());
+        let mut names = HashSet::new();
+        for (name, value) in pairs {
+            let param = lookup(name).ok_or_else(|| ConfigError::UnknownParameter(name.clone()))?;
+            if !names.insert(param.name) {
+                return Err(ConfigError::Duplicate(name.clone()));
+            }
+            params.push((param, name, value));
+        }
+
+        let mut config = self.clone();
+        for (param, name, value) in params {
+            let invalid = |reason: String| ConfigError::Invalid {
+                name: name.clone(),
+                reason,
+            };
+
+            if !param.mutable {
+                return Err(invalid("can't set immutable config".to_owned()));
+            }
+            (param.set)(&mut config, value).map_err(invalid)?;
+        }
+
+        *self = config;
+        Ok(())
+    }
+
+    /// Rewrite the configuration file so it matches the current configuration, keeping its
+    /// comments and the order of its directives
+    pub(super) fn rewrite(&self) -> Result<(), ConfigError> {
+        let path = self.file.as_ref().ok_or(ConfigError::NoConfigFile)?;
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(ConfigError::Rewrite(e)),
+        };
+
+        let line = |param: &Param| format!("{} {}", param.name, quote(&(param.get)(self)));
+
+        let mut lines = Vec::new();
+        let mut rewritten = HashSet::new();
+        for original in text.lines() {
+            let param = split_args(original)
+                .ok()
+                .and_then(|args| args.first().and_then(|name| lookup(name)));
+
+            match param {
+                // Parameters set more than once are only kept where they first appear
+                Some(param) if rewritten.insert(param.name) => lines.push(line(param)),
+                Some(_) => {}
+                None => lines.push(original.to_owned()),
+            }
+        }
+
+        // Parameters missing from the file only get written when they are not set to their default
+        let default = Self::default();
+        let mut signed = lines.iter().any(|line| line == REWRITE_SIGNATURE);
+        for param in PARAMS {
+            if rewritten.contains(param.name) || (param.get)(self) == (param.get)(&default) {
+                continue;
+            }
+            if !signed {
+                lines.push(REWRITE_SIGNATURE.to_owned());
+                signed = true;
+            }
+            lines.push(line(param));
+        }
+
+        // Write the new file aside, so the old one stays intact if anything fails
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut content = lines.join("\n");
+        content.push('\n');
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(ConfigError::Rewrite)
+    }
+}
+
+/// Split a configuration line into its arguments, which may be quoted. Comments yield no argument
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+
+    let unbalanced = || "Unbalanced quotes in configuration line".to_owned();
+
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(args);
+        };
+
+        let mut arg = String::new();
+        match first {
+            '"' => loop {
+                match chars.next().ok_or_else(unbalanced)? {
+                    '"' => break,
+                    '\\' => match chars.next().ok_or_else(unbalanced)? {
+                        'n' => arg.push('\n'),
+                        'r' => arg.push('\r'),
+                        't' => arg.push('\t'),
+                        c => arg.push(c),
+                    },
+                    c => arg.push(c),
+                }
+            },
+            '\'' => loop {
+                match chars.next().ok_or_else(unbalanced)? {
+                    '\'' => break,
+                    '\\' if chars.peek() == Some(&'\'') => arg.extend(chars.next()),
+                    c => arg.push(c),
+                }
+            },
+            c => {
+                arg.push(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+            }
+        }
+
+        // Closing quotes must be followed by a space or the end of the line
+        if matches!(first, '"' | '\'') && chars.peek().is_some_and(|c| !c.is_whitespace()) {
+            return Err(unbalanced());
+        }
+        args.push(arg);
+    }
+}
+
+/// Render `value` as an argument of a configuration line, quoting it if needed
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        return value.to_owned();
+    }
+
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_owned()
+}
+
+fn display_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn parse_path(value: &str) -> Option<PathBuf> {
+    (!value.is_empty()).then(|| PathBuf::from(value))
+}
+
+fn parse_int<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| "argument couldn't be parsed into an integer".to_owned())
+}
+
+/// Parse a boolean expressed as `yes` or `no`
+fn parse_bool(value: &str) -> Result<bool, String> {
+    if value.eq_ignore_ascii_case("yes") {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case("no") {
+        Ok(false)
+    } else {
+        Err("argument must be 'yes' or 'no'".to_owned())
+    }
+}
+
+/// Parse a memory size expressed with an optional unit (`b`, `k`, `kb`, `m`, `mb`, `g`, `gb`).
+/// Like Redis, `k`, `m` and `g` are powers of 1000 while `kb`, `mb` and `gb` are powers of 1024
+fn parse_memory(value: &str) -> Result<usize, String> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".to_owned()),
+    };
+
+    amount
+        .parse::<usize>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(multiplier))
+        .ok_or_else(|| "argument must be a memory value".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_split_quoted_arguments() {
+        assert_eq!(
+            split_args(r#"  requirepass "p a\"ss" 'it\'s'  "#).unwrap(),
+            ["requirepass", "p a\"ss", "it's"]
+        );
+        assert!(split_args("# maxmemory 1mb").unwrap().is_empty());
+        assert!(split_args(r#"requirepass "open"#).is_err());
+        assert!(split_args(r#"requirepass "a"b"#).is_err());
+        assert_eq!(
+            split_args(&format!("x {}", quote("a \"b\""))).unwrap()[1],
+            "a \"b\""
+        );
+    }
+
+    #[test]
+    fn should_set_parameters_atomically() {
+        let mut config = Config::default();
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        config
+            .set(&pairs(&[("maxmemory", "1mb"), ("slave-read-only", "no")]))
+            .unwrap();
+        assert_eq!(config.maxmemory, 1024 * 1024);
+        assert!(!config.replica_read_only);
+
+        assert!(matches!(
+            config.set(&pairs(&[("maxmemory", "2mb"), ("maxmemory-policy", "lru")])),
+            Err(ConfigError::Invalid { .. })
+        ));
+        assert!(matches!(
+            config.set(&pairs(&[("maxmemory", "2mb"), ("port", "7000")])),
+            Err(ConfigError::Invalid { .. })
+        ));
+        assert!(matches!(
+            config.set(&pairs(&[
+                ("replica-read-only", "yes"),
+                ("slave-read-only", "yes")
+            ])),
+            Err(ConfigError::Duplicate(_))
+        ));
+        assert_eq!(config.maxmemory, 1024 * 1024);
+
+        assert_eq!(
+            config.get(&["maxmemory*".to_owned()]),
+            [
+                ("maxmemory", "1048576".to_owned()),
+                ("maxmemory-policy", "noeviction".to_owned()),
+                ("maxmemory-samples", "5".to_owned()),
+                ("maxmemory-clients", "0".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_memory_values_and_samples() {
+        assert_eq!(parse_memory("3gb"), Ok(3 * 1024 * 1024 * 1024));
+        assert!(parse_memory("18446744073709551615kb").is_err());
+        assert!(parse_memory("10tb").is_err());
+
+        let mut config = Config::default();
+        let set = |config: &mut Config, name: &str, value: &str| {
+            config.set(&[(name.to_owned(), value.to_owned())])
+        };
+        set(&mut config, "maxmemory-samples", "64").unwrap();
+        for samples in ["0", "65"] {
+            assert!(matches!(
+                set(&mut config, "maxmemory-samples", samples),
+                Err(ConfigError::Invalid { .. })
+            ));
+        }
+        assert_eq!(config.maxmemory_samples, 64);
+    }
+}
@@ -0,0 +1,355 @@
+//! Logical databases of a memora instance, selected by clients with `SELECT`
+
+use std::mem;
+
+use chrono::Utc;
+use thiserror::Error;
+use tracing::debug;
+
+use super::{
+    cmd::FlushMode,
+    dict::Dict,
+    evict::{Access, Lfu},
+    glob,
+    object::{Encoding, Object},
+    rdb, MemoraResult,
+};
+
+/// Number of buckets rehashed by every database on each run of the server cron
+const CRON_REHASH_STEPS: usize = 100;
+
+/// Number of keys with an expiry sampled at once by the active expire cycle
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+
+/// Percentage of the sampled keys that must have expired for the active expire cycle to keep
+/// sampling the same database
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("DB index is out of range")]
+    OutOfRange,
+
+    #[error("source and destination objects are the same")]
+    SameObject,
+}
+
+/// Metadata kept along with every value
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Header {
+    /// How the key got accessed, to pick the keys to evict
+    pub(super) access: Access,
+
+    pub(super) encoding: Encoding,
+}
+
+#[derive(Debug)]
+pub(super) struct Entry {
+    pub(super) header: Header,
+    pub(super) value: Object,
+    pub(super) expiry: Option<chrono::DateTime<Utc>>,
+}
+
+impl Entry {
+    /// Approximate number of bytes used by an entry stored under `key`
+    pub(super) fn memory(key: &str, entry: &Entry) -> usize {
+        key.len() + entry.value.allocated() + mem::size_of::<(String, Entry)>()
+    }
+}
+
+/// The keys of a single database
+#[derive(Debug, Default)]
+pub(super) struct StringStore {
+    entries: Dict<String, Entry>,
+
+    /// Keys with an expiry, sampled by the volatile eviction policies
+    volatile: Dict<String, ()>,
+
+    /// Number of keys that got reclaimed after they expired
+    pub(super) expired: u64,
+
+    /// Keys that expired since the last call to [`Self::take_expired`]
+    pending_expired: Vec<String>,
+
+    /// Approximate number of bytes used by the entries
+    pub(super) memory: usize,
+
+    /// Tuning of the access counters of the entries
+    lfu: Lfu,
+}
+
+impl StringStore {
+    pub(super) fn new(lfu: Lfu) -> Self {
+        Self {
+            lfu,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn set_lfu(&mut self, lfu: Lfu) {
+        self.lfu = lfu;
+    }
+
+    pub(crate) fn store(
+        &mut self,
+        key: String,
+        value: String,
+        expiry: Option<chrono::DateTime<Utc>>,
+    ) -> MemoraResult<()> {
+        debug!("storing key {key} with value {value} and expiry {expiry:?}");
+
+        // Overwriting a key counts as an access to it
+        let access = match self.entries.get(&key) {
+            Some(old) => {
+                let mut access = old.header.access;
+                access.touch(self.lfu);
+                access
+            }
+            None => Access::new(),
+        };
+
+        let (value, encoding) = Object::encode(value);
+        let entry = Entry {
+            header: Header { access, encoding },
+            value,
+            expiry,
+        };
+        self.memory += Entry::memory(&key, &entry);
+
+        if entry.expiry.is_some() {
+            self.volatile.insert(key.clone(), ());
+        } else {
+            self.volatile.remove(&key);
+        }
+
+        if let Some(old) = self.entries.insert(key.clone(), entry) {
+            self.memory -= Entry::memory(&key, &old);
+        }
+        Ok(())
+    }
+
+    pub(super) fn exists(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Whether `key` has an expiry, which makes it a candidate for the volatile eviction policies
+    pub(super) fn is_volatile(&self, key: &str) -> bool {
+        self.volatile.contains_key(key)
+    }
+
+    /// Take the keys that expired since the last call, which still need to be notified
+    pub(super) fn take_expired(&mut self) -> Vec<String> {
+        mem::take(&mut self.pending_expired)
+    }
+
+    pub(super) fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.memory -= Entry::memory(key, &entry);
+        if entry.expiry.is_some() {
+            self.volatile.remove(key);
+        }
+        Some(entry)
+    }
+
+    /// Get the value of `key`, reclaiming it if it expired at `time`. The access gets recorded
+    /// for eviction if `touch` is set
+    pub(crate) fn try_get(
+        &mut self,
+        key: impl AsRef<str>,
+        time: impl FnOnce() -> chrono::DateTime<Utc>,
+        touch: bool,
+    ) -> Option<&Object> {
+        let key = key.as_ref();
+        if self.expire_if_needed(key, time) {
+            return None;
+        }
+
+        let lfu = self.lfu;
+        let entry = self.entries.get_mut(key)?;
+        if touch {
+            entry.header.access.touch(lfu);
+        }
+        Some(&entry.value)
+    }
+
+    /// Get the entry of `key` if it did not expire at `now`, without it counting as an access
+    pub(super) fn peek(&mut self, key: &str, now: chrono::DateTime<Utc>) -> Option<&Entry> {
+        if self.expire_if_needed(key, || now) {
+            return None;
+        }
+        self.entries.get(key)
+    }
+
+    /// Reclaim `key` if it expired at `time`, returning whether it did
+    fn expire_if_needed(
+        &mut self,
+        key: &str,
+        time: impl FnOnce() -> chrono::DateTime<Utc>,
+    ) -> bool {
+        let Some(entry) = self.entries.get(key) else {
+            return false;
+        };
+        if entry.expiry.is_none_or(|exp| exp > time()) {
+            return false;
+        }
+
+        self.remove(key);
+        self.expired += 1;
+        self.pending_expired.push(key.to_owned());
+        true
+    }
+
+    /// Reclaim the keys expired at `now` among random samples of the keys with an expiry, like the
+    /// active expire cycle of Redis. Sampling goes on while enough of the sampled keys expired,
+    /// until `deadline`. Returns the number of keys reclaimed
+    pub(super) fn expire_cycle(
+        &mut self,
+        now: chrono::DateTime<Utc>,
+        deadline: std::time::Instant,
+    ) -> usize {
+        let mut reclaimed = 0;
+        loop {
+            let sampled: Vec<_> = self
+                .volatile
+                .sample(ACTIVE_EXPIRE_KEYS_PER_LOOP)
+                .into_iter()
+                .map(|(key, _)| key.clone())
+                .collect();
+            if sampled.is_empty() {
+                break;
+            }
+
+            let expired = sampled
+                .iter()
+                .filter(|key| self.expire_if_needed(key, || now))
+                .count();
+            reclaimed += expired;
+
+            if expired * 100 <= sampled.len() * ACTIVE_EXPIRE_ACCEPTABLE_STALE
+                || std::time::Instant::now() >= deadline
+            {
+                break;
+            }
+        }
+        reclaimed
+    }
+
+    /// Sample up to `count` keys to evict, among the keys with an expiry if `volatile`
+    pub(super) fn sample(&self, volatile: bool, count: usize) -> Vec<(&str, &Entry)> {
+        if volatile {
+            self.volatile
+                .sample(count)
+                .into_iter()
+                .filter_map(|(key, _)| Some((key.as_str(), self.entries.get(key)?)))
+                .collect()
+        } else {
+            self.entries
+                .sample(count)
+                .into_iter()
+                .map(|(key, entry)| (key.as_str(), entry))
+                .collect()
+        }
+    }
+
+    /// Number of keys
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Keys matching the glob `pattern` that are not expired at `now`
+    pub(super) fn keys(&self, pattern: &str, now: chrono::DateTime<Utc>) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(key, entry)| {
+                entry.expiry.is_none_or(|exp| exp > now)
+                    && glob::matches(pattern.as_bytes(), key.as_bytes())
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Visit the buckets from `cursor` until at least `count` keys got collected, returning the
+    /// cursor to resume from along with the collected keys matching `pattern`. Expired keys are
+    /// reclaimed on the way
+    pub(super) fn scan(
+        &mut self,
+        mut cursor: u64,
+        count: usize,
+        pattern: Option<&str>,
+        now: chrono::DateTime<Utc>,
+    ) -> (u64, Vec<String>) {
+        let mut keys = Vec::new();
+
+        // Bound the work done on a sparse table, like Redis does
+        let mut visits = count.saturating_mul(10);
+        loop {
+            cursor = self.entries.scan(cursor, |key, _| keys.push(key.clone()));
+            visits -= 1;
+            if cursor == 0 || visits == 0 || keys.len() >= count {
+                break;
+            }
+        }
+
+        keys.retain(|key| {
+            pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes()))
+                && !self.expire_if_needed(key, || now)
+        });
+        (cursor, keys)
+    }
+
+    /// Incrementally rehash the keys, if the table of keys is being resized
+    pub(super) fn rehash(&mut self) {
+        self.entries.rehash(CRON_REHASH_STEPS);
+        self.volatile.rehash(CRON_REHASH_STEPS);
+    }
+
+    /// Approximate number of bytes used by the tables of keys and of keys with an expiry, not
+    /// counting the entries themselves
+    pub(super) fn overhead(&self) -> (usize, usize) {
+        (self.entries.overhead(), self.volatile.overhead())
+    }
+
+    /// Number of keys, number of keys with an expiry and average time to live of those keys in
+    /// milliseconds at `now`
+    pub(super) fn keyspace(&self, now: chrono::DateTime<Utc>) -> (usize, usize, i64) {
+        let (count, total) = self
+            .entries
+            .values()
+            .filter_map(|entry| entry.expiry)
+            .filter(|exp| *exp > now)
+            .fold((0, 0), |(count, total), exp| {
+                (count + 1, total + (exp - now).num_milliseconds())
+            });
+
+        let avg_ttl = if count > 0 { total / count } else { 0 };
+        (self.entries.len(), self.volatile.len(), avg_ttl)
+    }
+
+    /// The keys that are not expired at `now`, as entries of the snapshot of database `db`
+    pub(crate) fn snapshot(
+        &self,
+        db: usize,
+        now: chrono::DateTime<Utc>,
+    ) -> impl Iterator<Item = rdb::Entry> + '_ {
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.expiry.map(|exp| exp > now).unwrap_or(true))
+            .map(move |(key, entry)| rdb::Entry {
+                db,
+                key: key.clone(),
+                value: entry.value.to_string(),
+                expiry: entry.expiry,
+            })
+    }
+
+    /// Remove every key. With [`FlushMode::Async`], the keys are freed in the background
+    pub(super) fn flush(&mut self, mode: FlushMode) {
+        let entries = mem::take(&mut self.entries);
+        let volatile = mem::take(&mut self.volatile);
+        self.memory = 0;
+
+        if mode == FlushMode::Async && !entries.is_empty() {
+            std::thread::spawn(move || drop((entries, volatile)));
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! Hash table with incremental rehashing, modeled after the dictionaries of Redis.
+//!
+//! Growing or shrinking the table does not move every entry at once: a second table is allocated
+//! and buckets are moved to it a few at a time, on every write and periodically from the server
+//! cron. Both tables are looked up while rehashing.
+//!
+//! Tables have a power of two number of buckets, which lets [`Dict::scan`] iterate over them with
+//! a reverse binary cursor: every entry present for the whole duration of a scan is returned at
+//! least once, even if the table gets resized between two calls.
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    mem,
+};
+
+use rand::Rng;
+
+/// Number of buckets of a table when the first entry is inserted
+const INITIAL_SIZE: usize = 4;
+
+/// Number of empty buckets a single rehashing step may visit before giving up
+const EMPTY_VISITS: usize = 10;
+
+/// Tables get shrunk once less than one bucket out of `MIN_FILL` is used
+const MIN_FILL: usize = 8;
+
+struct Table<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+
+    /// Number of entries in the table
+    used: usize,
+}
+
+impl<K, V> Table<K, V> {
+    fn empty() -> Self {
+        Self {
+            buckets: Vec::new(),
+            used: 0,
+        }
+    }
+
+    fn with_size(size: usize) -> Self {
+        Self {
+            buckets: (0..size).map(|_| Vec::new()).collect(),
+            used: 0,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (self.buckets.len() as u64).wrapping_sub(1)
+    }
+
+    fn bucket(&self, hash: u64) -> usize {
+        (hash & self.mask()) as usize
+    }
+}
+
+pub(super) struct Dict<K, V> {
+    /// The second table is only allocated while rehashing
+    tables: [Table<K, V>; 2],
+
+    /// While rehashing, index of the next bucket of the first table to move to the second one
+    rehash_idx: Option<usize>,
+
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self {
+            tables: [Table::empty(), Table::empty()],
+            rehash_idx: None,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub(super) fn len(&self) -> usize {
+        self.tables[0].used + self.tables[1].used
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(super) fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, bucket, pos) = self.find(key)?;
+        Some(&self.tables[table].buckets[bucket][pos].1)
+    }
+
+    pub(super) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (table, bucket, pos) = self.find(key)?;
+        Some(&mut self.tables[table].buckets[bucket][pos].1)
+    }
+
+    pub(super) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Insert `value` under `key`, returning the value it replaced if any
+    pub(super) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.rehash(1);
+
+        if let Some((table, bucket, pos)) = self.find(&key) {
+            return Some(mem::replace(
+                &mut self.tables[table].buckets[bucket][pos].1,
+                value,
+            ));
+        }
+
+        self.expand_if_needed();
+
+        // New entries go to the new table while rehashing
+        let table = &mut self.tables[usize::from(self.rehash_idx.is_some())];
+        let bucket = table.bucket(self.hasher.hash_one(&key));
+        table.buckets[bucket].push((key, value));
+        table.used += 1;
+        None
+    }
+
+    pub(super) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash(1);
+
+        let (table, bucket, pos) = self.find(key)?;
+        let table = &mut self.tables[table];
+        let (_, value) = table.buckets[bucket].swap_remove(pos);
+        table.used -= 1;
+
+        self.shrink_if_needed();
+        Some(value)
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables
+            .iter()
+            .flat_map(|table| table.buckets.iter().flatten())
+            .map(|(key, value)| (key, value))
+    }
+
+    pub(super) fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Number of bytes allocated for the buckets, not counting the entries they hold
+    pub(super) fn overhead(&self) -> usize {
+        self.tables
+            .iter()
+            .map(|table| table.buckets.capacity() * mem::size_of::<Vec<(K, V)>>())
+            .sum()
+    }
+
+    /// Call `f` on the entries of the buckets at `cursor`, returning the cursor of the next
+    /// buckets to visit, or 0 once every bucket got visited
+    pub(super) fn scan(&self, cursor: u64, mut f: impl FnMut(&K, &V)) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+
+        let mut visit = |table: &Table<K, V>, cursor: u64| {
+            for (key, value) in &table.buckets[(cursor & table.mask()) as usize] {
+                f(key, value);
+            }
+        };
+
+        let mut cursor = cursor;
+        if self.rehash_idx.is_none() {
+            let table = &self.tables[0];
+            visit(table, cursor);
+            cursor = next_cursor(cursor, table.mask());
+        } else {
+            let [first, second] = &self.tables;
+            let (small, large) = if first.buckets.len() <= second.buckets.len() {
+                (first, second)
+            } else {
+                (second, first)
+            };
+
+            // Visit the bucket of the small table, then every bucket of the large table that
+            // its entries expand to
+            visit(small, cursor);
+            loop {
+                visit(large, cursor);
+                cursor = next_cursor(cursor, large.mask());
+                if cursor & (small.mask() ^ large.mask()) == 0 {
+                    break;
+                }
+            }
+        }
+
+        cursor
+    }
+
+    /// Sample up to `count` entries, from consecutive buckets starting at a random one. Entries
+    /// are not uniformly distributed but sampling is cheap, which is enough for eviction
+    pub(super) fn sample(&self, count: usize) -> Vec<(&K, &V)> {
+        let count = count.min(self.len());
+        let mut samples = Vec::with_capacity(count);
+        if count == 0 {
+            return samples;
+        }
+
+        let tables = if self.rehash_idx.is_some() { 2 } else { 1 };
+        let mask = (0..tables)
+            .map(|idx| self.tables[idx].mask())
+            .max()
+            .unwrap_or_default();
+
+        let mut rng = rand::thread_rng();
+        let mut bucket = rng.gen::<u64>() & mask;
+        let mut empty = 0;
+
+        for _ in 0..count * 10 {
+            for idx in 0..tables {
+                let table = &self.tables[idx];
+                let Some(entries) = table.buckets.get(bucket as usize) else {
+                    continue;
+                };
+
+                // Jump somewhere else after a run of empty buckets
+                if entries.is_empty() {
+                    empty += 1;
+                    if empty >= 5 && empty > count {
+                        bucket = rng.gen::<u64>() & mask;
+                        empty = 0;
+                    }
+                    continue;
+                }
+
+                empty = 0;
+                let missing = count - samples.len();
+                samples.extend(
+                    entries
+                        .iter()
+                        .take(missing)
+                        .map(|(key, value)| (key, value)),
+                );
+                if samples.len() == count {
+                    return samples;
+                }
+            }
+            bucket = (bucket + 1) & mask;
+        }
+
+        samples
+    }
+
+    /// Move up to `steps` buckets to the new table if rehashing, or start shrinking a table left
+    /// sparse by removals. Returns whether there is still some rehashing to do
+    pub(super) fn rehash(&mut self, steps: usize) -> bool {
+        let Some(mut idx) = self.rehash_idx else {
+            self.shrink_if_needed();
+            return self.rehash_idx.is_some();
+        };
+
+        let hasher = &self.hasher;
+        let [old, new] = &mut self.tables;
+        let mut empty_visits = steps * EMPTY_VISITS;
+
+        for _ in 0..steps {
+            if old.used == 0 {
+                break;
+            }
+
+            // Buckets before `idx` are empty, so there is a non empty one after it
+            while old.buckets[idx].is_empty() {
+                idx += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_idx = Some(idx);
+                    return true;
+                }
+            }
+
+            let entries = mem::take(&mut old.buckets[idx]);
+            old.used -= entries.len();
+            new.used += entries.len();
+            for (key, value) in entries {
+                let bucket = new.bucket(hasher.hash_one(&key));
+                new.buckets[bucket].push((key, value));
+            }
+            idx += 1;
+        }
+
+        if old.used == 0 {
+            *old = mem::replace(new, Table::empty());
+            self.rehash_idx = None;
+            self.shrink_if_needed();
+            return self.rehash_idx.is_some();
+        }
+
+        self.rehash_idx = Some(idx);
+        true
+    }
+
+    /// Locate `key`, as the index of its table, of its bucket and its position in the bucket
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hasher.hash_one(key);
+        let tables = if self.rehash_idx.is_some() { 2 } else { 1 };
+
+        (0..tables).find_map(|idx| {
+            let table = &self.tables[idx];
+            let bucket = table.bucket(hash);
+            table.buckets[bucket]
+                .iter()
+                .position(|(k, _)| k.borrow() == key)
+                .map(|pos| (idx, bucket, pos))
+        })
+    }
+
+    fn expand_if_needed(&mut self) {
+        if self.rehash_idx.is_some() {
+            return;
+        }
+
+        let table = &self.tables[0];
+        if table.buckets.is_empty() {
+            self.tables[0] = Table::with_size(INITIAL_SIZE);
+        } else if table.used >= table.buckets.len() {
+            self.resize((table.used + 1).next_power_of_two());
+        }
+    }
+
+    fn shrink_if_needed(&mut self) {
+        if self.rehash_idx.is_some() {
+            return;
+        }
+
+        let table = &self.tables[0];
+        if table.buckets.len() > INITIAL_SIZE && table.used * MIN_FILL < table.buckets.len() {
+            self.resize(table.used.max(INITIAL_SIZE).next_power_of_two());
+        }
+    }
+
+    /// Start rehashing the entries to a new table of `size` buckets
+    fn resize(&mut self, size: usize) {
+        self.tables[1] = Table::with_size(size);
+        self.rehash_idx = Some(0);
+    }
+}
+
+/// Increment the reversed bits of `cursor` that are covered by `mask`
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    (cursor | !mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn should_grow_and_shrink() {
+        let mut dict = Dict::default();
+        for i in 0..1000 {
+            assert_eq!(dict.insert(i, i * 2), None);
+        }
+        assert_eq!(dict.insert(10, 0), Some(20));
+        assert_eq!(dict.len(), 1000);
+
+        for i in 0..990 {
+            assert!(dict.remove(&i).is_some());
+        }
+        assert_eq!(dict.remove(&0), None);
+        while dict.rehash(100) {}
+
+        assert_eq!(dict.len(), 10);
+        assert_eq!(dict.get(&995), Some(&1990));
+        assert!(dict.tables[0].buckets.len() <= 16);
+    }
+
+    #[test]
+    fn should_sample_distinct_entries() {
+        let mut dict = Dict::default();
+        for i in 0..100 {
+            dict.insert(i, ());
+        }
+
+        let samples = dict.sample(5);
+        let keys = samples.iter().map(|(key, _)| **key).collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 5);
+        assert_eq!(dict.sample(1000).len(), 100);
+    }
+
+    #[test]
+    fn should_scan_every_entry_while_resizing() {
+        let mut dict = Dict::default();
+        for i in 0..500 {
+            dict.insert(i, ());
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut step = 0;
+        loop {
+            cursor = dict.scan(cursor, |key, _| {
+                seen.insert(*key);
+            });
+
+            // Grow the table then shrink it back while scanning
+            let batch = 500 + (step % 20) * 100..600 + (step % 20) * 100;
+            match step {
+                0..20 => batch.for_each(|i| {
+                    dict.insert(i, ());
+                }),
+                20..40 => batch.for_each(|i| {
+                    dict.remove(&i);
+                }),
+                _ => {}
+            }
+            step += 1;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert!(step > 40);
+
+        assert!((0..500).all(|i| seen.contains(&i)));
+    }
+}
@@ -2,11 +2,23 @@ use std::io::{self};
 
 use thiserror::Error;
 
-use super::cmd::CommandError;
-use crate::resp::RespError;
-
-/// Type-alias for standard generic error type that is safe to shared across threads
-pub type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+use super::{
+    acl::AclError,
+    cmd::{ClientError, CommandError},
+    config::ConfigError,
+    db::DbError,
+    evict::EvictError,
+    latency::LatencyError,
+    multi::MultiError,
+    pubsub::PubSubError,
+    rdb::RdbError,
+    role::ReplicaError,
+    script::{FunctionError, ScriptError},
+    tls::TlsError,
+    tracking::TrackingError,
+    wait::WaitError,
+};
+use crate::{dispatch::table::GetKeysError, resp::RespError};
 
 #[derive(Debug, Error)]
 pub enum EncodeError {
@@ -30,7 +42,71 @@ pub enum MemoraError {
     Command(#[from] CommandError),
 
     #[error(transparent)]
-    Standard(StdError),
+    Replica(#[from] ReplicaError),
+
+    #[error(transparent)]
+    Rdb(#[from] RdbError),
+
+    #[error(transparent)]
+    Wait(#[from] WaitError),
+
+    #[error(transparent)]
+    PubSub(#[from] PubSubError),
+
+    #[error(transparent)]
+    Multi(#[from] MultiError),
+
+    #[error(transparent)]
+    Script(#[from] ScriptError),
+
+    #[error(transparent)]
+    Function(#[from] FunctionError),
+
+    #[error(transparent)]
+    Acl(#[from] AclError),
+
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+
+    #[error(transparent)]
+    Db(#[from] DbError),
+
+    #[error(transparent)]
+    Evict(#[from] EvictError),
+
+    #[error(transparent)]
+    Tracking(#[from] TrackingError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Latency(#[from] LatencyError),
+
+    #[error(transparent)]
+    GetKeys(#[from] GetKeysError),
+}
+
+impl MemoraError {
+    /// Error code sent to clients as the first word of an error reply
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Replica(ReplicaError::ReadOnly) => "READONLY",
+            Self::Replica(ReplicaError::MasterDown) => "MASTERDOWN",
+            Self::Multi(MultiError::ExecAbort) => "EXECABORT",
+            Self::Script(ScriptError::NoScript) => "NOSCRIPT",
+            Self::Script(ScriptError::NotBusy) => "NOTBUSY",
+            Self::Script(ScriptError::Unkillable) => "UNKILLABLE",
+            Self::Acl(AclError::NoAuth) => "NOAUTH",
+            Self::Command(CommandError::Client(ClientError::NoProto)) => "NOPROTO",
+            Self::Acl(AclError::WrongPass) => "WRONGPASS",
+            Self::Acl(
+                AclError::CommandDenied { .. } | AclError::KeyDenied | AclError::ChannelDenied,
+            ) => "NOPERM",
+            Self::Evict(EvictError::Oom) => "OOM",
+            _ => "ERR",
+        }
+    }
 }
 
 pub type MemoraResult<T> = std::result::Result<T, MemoraError>;
@@ -0,0 +1,314 @@
+//! Eviction of keys once the dataset grows above `maxmemory`, with the approximated LRU and LFU
+//! algorithms of Redis.
+//!
+//! Instead of keeping every key ordered by access time or frequency, a few keys are sampled on
+//! each eviction and the best candidates are kept in a small pool across evictions.
+//!
+//! LFU counters are logarithmic: the more a key got accessed, the less likely the next access
+//! increments its counter. Counters also decay with the time elapsed since the last access.
+
+use std::{fmt, str::FromStr};
+
+use chrono::Utc;
+use rand::Rng;
+use thiserror::Error;
+
+/// Number of candidates kept in the eviction pool
+const POOL_SIZE: usize = 16;
+
+/// The LRU clock has a resolution of one second and wraps around after 194 days
+const LRU_CLOCK_MAX: u32 = (1 << 24) - 1;
+
+/// Counter of the keys that just got created, so that they are not evicted right away
+const LFU_INIT_VAL: u8 = 5;
+
+#[derive(Debug, Error)]
+pub enum EvictError {
+    #[error("command not allowed when used memory > 'maxmemory'.")]
+    Oom,
+
+    #[error("invalid maxmemory-policy '{0}'")]
+    InvalidPolicy(String),
+
+    #[error("An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.")]
+    LfuNotSelected,
+
+    #[error("An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.")]
+    LfuSelected,
+}
+
+/// Which keys get evicted once `maxmemory` is reached
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum EvictionPolicy {
+    /// Do not evict anything, refusing writes instead
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+
+    /// Evict the keys with an expiry that are the closest to expire
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    const ALL: &'static [Self] = &[
+        Self::NoEviction,
+        Self::AllKeysLru,
+        Self::VolatileLru,
+        Self::AllKeysLfu,
+        Self::VolatileLfu,
+        Self::AllKeysRandom,
+        Self::VolatileRandom,
+        Self::VolatileTtl,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::VolatileLru => "volatile-lru",
+            Self::AllKeysLfu => "allkeys-lfu",
+            Self::VolatileLfu => "volatile-lfu",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileRandom => "volatile-random",
+            Self::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only the keys with an expiry can be evicted
+    pub fn volatile(self) -> bool {
+        matches!(
+            self,
+            Self::VolatileLru | Self::VolatileLfu | Self::VolatileRandom | Self::VolatileTtl
+        )
+    }
+
+    pub fn lfu(self) -> bool {
+        matches!(self, Self::AllKeysLfu | Self::VolatileLfu)
+    }
+
+    pub fn random(self) -> bool {
+        matches!(self, Self::AllKeysRandom | Self::VolatileRandom)
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = EvictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| EvictError::InvalidPolicy(s.to_owned()))
+    }
+}
+
+/// Tuning of the LFU counters
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Lfu {
+    /// How hard it gets to increment a counter as it grows
+    pub log_factor: u32,
+
+    /// Number of minutes after which an idle counter gets decremented
+    pub decay_time: u32,
+}
+
+impl Default for Lfu {
+    fn default() -> Self {
+        Self {
+            log_factor: 10,
+            decay_time: 1,
+        }
+    }
+}
+
+/// When a key got last accessed and how often, used to pick the keys to evict
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) struct Access {
+    /// LRU clock of the last access
+    lru: u32,
+
+    /// Logarithmic access counter
+    counter: u8,
+
+    /// Time of the last decrement of the counter, in minutes
+    decremented: u16,
+}
+
+impl Access {
+    pub(super) fn new() -> Self {
+        Self {
+            lru: lru_clock(),
+            counter: LFU_INIT_VAL,
+            decremented: lfu_clock(),
+        }
+    }
+
+    /// Record an access to the key
+    pub(super) fn touch(&mut self, lfu: Lfu) {
+        self.lru = lru_clock();
+        self.counter = log_incr(self.frequency(lfu), lfu.log_factor);
+        self.decremented = lfu_clock();
+    }
+
+    /// Number of seconds since the last access
+    pub(super) fn idle(&self) -> u64 {
+        let now = lru_clock();
+        u64::from(if now >= self.lru {
+            now - self.lru
+        } else {
+            now + (LRU_CLOCK_MAX - self.lru)
+        })
+    }
+
+    /// Access counter, decayed by the time elapsed since its last decrement
+    pub(super) fn frequency(&self, lfu: Lfu) -> u8 {
+        let now = lfu_clock();
+        let elapsed = if now >= self.decremented {
+            now - self.decremented
+        } else {
+            u16::MAX - self.decremented + now
+        };
+
+        let periods = u32::from(elapsed).checked_div(lfu.decay_time).unwrap_or(0);
+        self.counter
+            .saturating_sub(periods.min(u32::from(u8::MAX)) as u8)
+    }
+}
+
+/// Increment a logarithmic `counter`, with a probability decreasing as the counter grows
+fn log_incr(counter: u8, log_factor: u32) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+
+    let base = f64::from(counter.saturating_sub(LFU_INIT_VAL));
+    let p = 1.0 / (base * f64::from(log_factor) + 1.0);
+    if rand::thread_rng().gen::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+fn lru_clock() -> u32 {
+    (Utc::now().timestamp() as u32) & LRU_CLOCK_MAX
+}
+
+fn lfu_clock() -> u16 {
+    (Utc::now().timestamp() / 60) as u16
+}
+
+/// A key that may get evicted, along with how good a candidate it is: the higher the better
+#[derive(Debug)]
+struct Candidate {
+    score: u64,
+    db: usize,
+    key: String,
+}
+
+/// The best candidates for eviction sampled so far, ordered by ascending score
+#[derive(Debug, Default)]
+pub(super) struct EvictionPool(Vec<Candidate>);
+
+impl EvictionPool {
+    /// Consider `key` of the database `db` for eviction, given its `score`
+    pub(super) fn insert(&mut self, score: u64, db: usize, key: &str) {
+        let pool = &mut self.0;
+        if pool.len() == POOL_SIZE && pool.first().is_some_and(|worst| score <= worst.score) {
+            return;
+        }
+        if pool
+            .iter()
+            .any(|candidate| candidate.db == db && candidate.key == key)
+        {
+            return;
+        }
+
+        let pos = pool.partition_point(|candidate| candidate.score < score);
+        pool.insert(
+            pos,
+            Candidate {
+                score,
+                db,
+                key: key.to_owned(),
+            },
+        );
+        if pool.len() > POOL_SIZE {
+            pool.remove(0);
+        }
+    }
+
+    /// Take the best candidate, as its database and key
+    pub(super) fn pop(&mut self) -> Option<(usize, String)> {
+        self.0.pop().map(|candidate| (candidate.db, candidate.key))
+    }
+
+    /// Forget every candidate, whose scores no longer compare with the ones to come
+    pub(super) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Score of a key as a candidate for eviction by `policy`: its idle time, how rarely it is
+/// accessed, or how soon it expires
+pub(super) fn score(
+    policy: EvictionPolicy,
+    access: &Access,
+    expiry: Option<chrono::DateTime<Utc>>,
+    lfu: Lfu,
+) -> u64 {
+    match policy {
+        EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+            u64::from(u8::MAX - access.frequency(lfu))
+        }
+        EvictionPolicy::VolatileTtl => {
+            let expiry = expiry.map_or(i64::MAX, |expiry| expiry.timestamp_millis());
+            u64::MAX - expiry.max(0) as u64
+        }
+        _ => access.idle(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_best_candidates() {
+        let mut pool = EvictionPool::default();
+        for score in 0..40 {
+            pool.insert(score, 0, &format!("key:{score}"));
+        }
+        pool.insert(39, 0, "key:39");
+
+        assert_eq!(pool.0.len(), POOL_SIZE);
+        assert_eq!(pool.pop(), Some((0, "key:39".to_owned())));
+        assert_eq!(pool.pop(), Some((0, "key:38".to_owned())));
+    }
+
+    #[test]
+    fn should_increment_counters_logarithmically() {
+        let lfu = Lfu::default();
+        let mut access = Access::new();
+        for _ in 0..1000 {
+            access.touch(lfu);
+        }
+
+        // With the default log factor, a thousand hits only bring the counter to about 18
+        let frequency = access.frequency(lfu);
+        assert!((10..40).contains(&frequency), "{frequency}");
+        assert_eq!(log_incr(u8::MAX, lfu.log_factor), u8::MAX);
+    }
+}
@@ -1,12 +1,30 @@
-use bytes::{Buf, BufMut, BytesMut};
+use std::sync::Arc;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use logos::Logos;
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::resp::{self, RespError, RespResult};
 
-use super::{error::MemoraError, Response};
+use super::{error::MemoraError, stats::NetStats, Response};
+
+#[derive(Default)]
+pub struct RespFramer {
+    /// Counters of the bytes read and written by this framer
+    net: Option<Arc<NetStats>>,
+}
 
-pub struct RespFramer;
+impl RespFramer {
+    pub(super) fn with_stats(net: Arc<NetStats>) -> Self {
+        Self { net: Some(net) }
+    }
+
+    fn written(&self, bytes: usize) {
+        if let Some(net) = &self.net {
+            net.written(bytes);
+        }
+    }
+}
 
 impl Decoder for RespFramer {
     type Item = resp::Value;
@@ -15,10 +33,13 @@ impl Decoder for RespFramer {
     fn decode(&mut self, buf: &mut BytesMut) -> RespResult<Option<Self::Item>> {
         let len = buf.len();
 
-        match resp::Value::parse(resp::Token::lexer(&buf)) {
+        match resp::Value::parse(resp::Token::lexer(buf)) {
             Ok(Some((value, remainder))) => {
                 let parsed_len = len - remainder.len();
                 buf.advance(parsed_len);
+                if let Some(net) = &self.net {
+                    net.read(parsed_len);
+                }
                 Ok(Some(value))
             }
             Ok(None) => Ok(None),
@@ -31,8 +52,10 @@ impl Encoder<resp::Value> for RespFramer {
     type Error = RespError;
 
     fn encode(&mut self, item: resp::Value, dst: &mut BytesMut) -> RespResult<()> {
-        let mut writer = dst.writer();
-        item.encode(&mut writer)
+        let len = dst.len();
+        item.encode(&mut dst.writer())?;
+        self.written(dst.len() - len);
+        Ok(())
     }
 }
 
@@ -44,7 +67,19 @@ impl Encoder<Response> for RespFramer {
         item: Response,
         dst: &mut BytesMut,
     ) -> std::prelude::v1::Result<(), Self::Error> {
-        let mut writer = dst.writer();
-        item.encode(&mut writer)
+        let len = dst.len();
+        item.encode(&mut dst.writer())?;
+        self.written(dst.len() - len);
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for RespFramer {
+    type Error = MemoraError;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item);
+        self.written(item.len());
+        Ok(())
     }
 }
@@ -0,0 +1,140 @@
+//! Glob-style pattern matching, with the same semantics as Redis.
+//!
+//! Supported patterns:
+//! - `?` matches any single character
+//! - `*` matches any sequence of characters, including an empty one
+//! - `[abc]` matches one of the characters between brackets, `[^abc]` any character but those,
+//!   and `[a-z]` a range of characters
+//! - `\x` matches the character `x` literally
+
+/// Check whether `s` matches the glob `pattern`
+pub fn matches(mut pattern: &[u8], mut s: &[u8]) -> bool {
+    while let Some(&p) = pattern.first() {
+        match p {
+            b'*' => {
+                // Consecutive stars are equivalent to a single one
+                while pattern.get(1) == Some(&b'*') {
+                    pattern = &pattern[1..];
+                }
+
+                if pattern.len() == 1 {
+                    return true;
+                }
+
+                return (0..=s.len()).any(|start| matches(&pattern[1..], &s[start..]));
+            }
+            b'?' => {
+                if s.is_empty() {
+                    return false;
+                }
+                s = &s[1..];
+            }
+            b'[' => {
+                let Some(&c) = s.first() else {
+                    return false;
+                };
+
+                let (matched, rest) = match_class(&pattern[1..], c);
+                if !matched {
+                    return false;
+                }
+
+                // `rest` starts at the closing bracket, if any
+                pattern = rest;
+                s = &s[1..];
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+                match s.first() {
+                    Some(&c) if pattern[0] == c => s = &s[1..],
+                    _ => return false,
+                }
+            }
+            _ => match s.first() {
+                Some(&c) if p == c => s = &s[1..],
+                _ => return false,
+            },
+        }
+
+        if pattern.is_empty() {
+            break;
+        }
+        pattern = &pattern[1..];
+    }
+
+    pattern.is_empty() && s.is_empty()
+}
+
+/// Match `c` against the character class at the start of `pattern`, right after the opening
+/// bracket. Returns whether it matched along with the pattern starting at the closing bracket
+fn match_class(mut pattern: &[u8], c: u8) -> (bool, &[u8]) {
+    let not = pattern.first() == Some(&b'^');
+    if not {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+
+    loop {
+        match pattern {
+            // Unterminated class, which Redis treats as ending at the end of the pattern
+            [] => return (matched != not, pattern),
+            [b']', ..] => return (matched != not, pattern),
+            [b'\\', escaped, ..] => {
+                matched |= *escaped == c;
+                pattern = &pattern[2..];
+            }
+            [start, b'-', end, ..] if *end != b']' => {
+                let (mut start, mut end) = (*start, *end);
+                if start > end {
+                    std::mem::swap(&mut start, &mut end);
+                }
+
+                matched |= (start..=end).contains(&c);
+                pattern = &pattern[3..];
+            }
+            [first, ..] => {
+                matched |= *first == c;
+                pattern = &pattern[1..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_globs() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("*", "anything", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hello world", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[b-a]llo", "hallo", true),
+            ("h[a-b]llo", "hcllo", false),
+            ("news.*", "news.tech", true),
+            ("news.*", "weather", false),
+            ("a\\*b", "a*b", true),
+            ("a\\*b", "axb", false),
+            ("*a*b*", "xxaxxbxx", true),
+            ("**", "", true),
+        ];
+
+        for (pattern, s, expected) in cases {
+            assert_eq!(
+                matches(pattern.as_bytes(), s.as_bytes()),
+                *expected,
+                "{pattern} ~ {s}"
+            );
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Rendering of the `INFO` command
+
+use std::fmt::Write;
+
+/// Sections returned when `INFO` is called without argument or with `default`
+const DEFAULT_SECTIONS: &[&str] = &[
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "cpu",
+    "errorstats",
+    "keyspace",
+];
+
+/// Sections that are only returned when explicitly requested or with `all` and `everything`
+const EXTRA_SECTIONS: &[&str] = &["commandstats", "latencystats"];
+
+/// Output of the `INFO` command, made of the sections requested by the client
+pub(super) struct Info {
+    sections: Vec<String>,
+    out: String,
+}
+
+impl Info {
+    pub(super) fn new(args: &[String]) -> Self {
+        let mut sections = Vec::new();
+
+        let mut add = |names: &[&str]| {
+            for name in names {
+                if !sections.iter().any(|s| s == name) {
+                    sections.push((*name).to_owned());
+                }
+            }
+        };
+
+        if args.is_empty() {
+            add(DEFAULT_SECTIONS);
+        }
+
+        for arg in args {
+            let arg = arg.to_ascii_lowercase();
+            match arg.as_str() {
+                "default" => add(DEFAULT_SECTIONS),
+                "all" | "everything" => {
+                    add(DEFAULT_SECTIONS);
+                    add(EXTRA_SECTIONS);
+                }
+                section => add(&[section]),
+            }
+        }
+
+        Self {
+            sections,
+            out: String::new(),
+        }
+    }
+
+    /// Whether the `section` was requested
+    pub(super) fn wants(&self, section: &str) -> bool {
+        self.sections.iter().any(|s| s == section)
+    }
+
+    /// Append a section named `title` holding the `fields` lines
+    pub(super) fn section<I, S>(&mut self, title: &str, fields: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        if !self.out.is_empty() {
+            self.out.push_str("\r\n");
+        }
+
+        let _ = write!(self.out, "# {title}\r\n");
+        for field in fields {
+            self.out.push_str(field.as_ref());
+            self.out.push_str("\r\n");
+        }
+    }
+
+    pub(super) fn into_string(self) -> String {
+        self.out
+    }
+}
+
+/// Format `bytes` the way humans read memory sizes
+pub(super) fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T", "P"];
+
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+
+    format!("{value:.2}{unit}")
+}
+
+/// Resources used by the current process
+#[derive(Debug, Default)]
+pub(super) struct ProcessUsage {
+    /// CPU time spent in user mode, in seconds
+    pub(super) user: f64,
+
+    /// CPU time spent in kernel mode, in seconds
+    pub(super) sys: f64,
+
+    /// Resident set size, in bytes
+    pub(super) rss: u64,
+}
+
+impl ProcessUsage {
+    /// Read the resources used by the current process from procfs.
+    /// Returns empty usage on platforms without procfs
+    pub(super) fn current() -> Self {
+        // Clock ticks per second and page size used by virtually every Linux system
+        const TICKS: f64 = 100.0;
+        const PAGE_SIZE: u64 = 4096;
+
+        let mut usage = Self::default();
+
+        // The command name is enclosed in parentheses and may contain spaces
+        if let Ok(stat) = std::fs::read_to_string("/proc/self/stat") {
+            if let Some((_, fields)) = stat.rsplit_once(')') {
+                let fields = fields.split_whitespace().collect::<Vec<_>>();
+                let ticks = |idx: usize| {
+                    fields
+                        .get(idx)
+                        .and_then(|f| f.parse::<u64>().ok())
+                        .unwrap_or(0)
+                };
+
+                // utime and stime are the 14th and 15th fields of the whole line
+                usage.user = ticks(11) as f64 / TICKS;
+                usage.sys = ticks(12) as f64 / TICKS;
+            }
+        }
+
+        if let Ok(statm) = std::fs::read_to_string("/proc/self/statm") {
+            let resident = statm.split_whitespace().nth(1).and_then(|f| f.parse().ok());
+            usage.rss = resident.unwrap_or(0) * PAGE_SIZE;
+        }
+
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_select_sections() {
+        let info = Info::new(&[]);
+        assert!(info.wants("server") && info.wants("keyspace"));
+        assert!(!info.wants("commandstats"));
+
+        let info = Info::new(&["CPU".to_owned(), "commandstats".to_owned()]);
+        assert!(info.wants("cpu") && info.wants("commandstats"));
+        assert!(!info.wants("server"));
+
+        let info = Info::new(&["everything".to_owned()]);
+        assert!(info.wants("latencystats") && info.wants("replication"));
+    }
+
+    #[test]
+    fn should_format_human_bytes() {
+        assert_eq!(human_bytes(512), "512B");
+        assert_eq!(human_bytes(1536), "1.50K");
+        assert_eq!(human_bytes(3 * 1024 * 1024), "3.00M");
+    }
+}
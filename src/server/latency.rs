@@ -0,0 +1,322 @@
+//! Latency monitor, keeping the history of the events that took longer than
+//! `latency-monitor-threshold` milliseconds.
+//!
+//! Like Redis, at most one sample per second is kept for every event, holding the worst latency
+//! observed during that second.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+};
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::resp::Value;
+
+/// Number of samples kept for every event
+const HISTORY_LEN: usize = 160;
+
+/// Width of the graphs drawn by `LATENCY GRAPH`
+const GRAPH_COLUMNS: usize = 80;
+
+/// Height of the graphs drawn by `LATENCY GRAPH`, not counting the labels
+const GRAPH_ROWS: usize = 4;
+
+/// Characters drawing the top of a bar of a graph, from the lowest to the highest
+const GRAPH_CHARS: [char; 3] = ['_', 'o', '#'];
+
+#[derive(Debug, Error)]
+pub enum LatencyError {
+    #[error("No samples available for event '{0}'")]
+    NoSamples(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Unix time of the sample, in seconds
+    time: i64,
+
+    /// Latency, in milliseconds
+    latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct Event {
+    /// Oldest samples first
+    samples: VecDeque<Sample>,
+
+    /// Worst latency ever observed, which outlives the samples
+    max: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct LatencyMonitor {
+    events: BTreeMap<String, Event>,
+}
+
+impl LatencyMonitor {
+    /// Record that `event` took `latency` milliseconds, if that is at least `threshold`. A
+    /// `threshold` of zero turns the monitor off
+    pub(super) fn add(&mut self, threshold: u64, event: &str, latency: u64) {
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+
+        self.add_at(Utc::now().timestamp(), event, latency);
+    }
+
+    /// Record that `event` took `latency` milliseconds at the unix `time`
+    fn add_at(&mut self, time: i64, event: &str, latency: u64) {
+        let event = self.events.entry(event.to_owned()).or_default();
+        event.max = event.max.max(latency);
+
+        match event.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                if event.samples.len() == HISTORY_LEN {
+                    event.samples.pop_front();
+                }
+                event.samples.push_back(Sample { time, latency });
+            }
+        }
+    }
+
+    /// Latest sample of every event, along with its worst latency
+    pub(super) fn latest(&self) -> Value {
+        Value::from_iter(self.events.iter().filter_map(|(name, event)| {
+            let last = event.samples.back()?;
+            Some(Value::from_iter([
+                Value::bulk(name),
+                Value::Int(last.time),
+                Value::Int(last.latency as i64),
+                Value::Int(event.max as i64),
+            ]))
+        }))
+    }
+
+    /// Every sample of `event`, oldest first
+    pub(super) fn history(&self, event: &str) -> Value {
+        let samples = self.events.get(event).map(|event| &event.samples);
+        Value::from_iter(samples.into_iter().flatten().map(|sample| {
+            Value::from_iter([Value::Int(sample.time), Value::Int(sample.latency as i64)])
+        }))
+    }
+
+    /// Forget about `events`, or about every event if empty. Returns the number of events reset
+    pub(super) fn reset(&mut self, events: &[String]) -> usize {
+        if events.is_empty() {
+            let count = self.events.len();
+            self.events.clear();
+            return count;
+        }
+
+        events
+            .iter()
+            .filter(|event| self.events.remove(event.as_str()).is_some())
+            .count()
+    }
+
+    /// ASCII art graph of the samples of `event`, labeled with how long ago they were taken
+    pub(super) fn graph(&self, name: &str) -> Result<String, LatencyError> {
+        let event = self
+            .events
+            .get(name)
+            .filter(|event| !event.samples.is_empty())
+            .ok_or_else(|| LatencyError::NoSamples(name.to_owned()))?;
+
+        let now = Utc::now().timestamp();
+        let latencies = event.samples.iter().map(|sample| sample.latency);
+        let (low, high) = (
+            latencies.clone().min().unwrap_or_default(),
+            latencies.max().unwrap_or_default(),
+        );
+
+        let mut graph = format!(
+            "{name} - high {high} ms, low {low} ms (all time high {} ms)\n{}\n",
+            event.max,
+            "-".repeat(GRAPH_COLUMNS)
+        );
+
+        let samples = event.samples.iter().copied().collect::<Vec<_>>();
+        for chunk in samples.chunks(GRAPH_COLUMNS) {
+            let labels = chunk
+                .iter()
+                .map(|sample| age(now - sample.time))
+                .collect::<Vec<_>>();
+            draw(&mut graph, chunk, &labels, low, high);
+        }
+
+        Ok(graph)
+    }
+
+    /// Human readable analysis of the events, with advice to lower their latency
+    pub(super) fn doctor(&self, threshold: u64) -> String {
+        if self.events.is_empty() && threshold == 0 {
+            return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this \
+                    instance. You may use \"CONFIG SET latency-monitor-threshold <milliseconds>.\" \
+                    in order to enable it.\n"
+                .to_owned();
+        }
+        if self.events.is_empty() {
+            return "Dave, no latency spike was observed during the lifetime of this instance, not \
+                    in the slightest bit. I honestly think you ought to sleep tonight.\n"
+                .to_owned();
+        }
+
+        let mut report = "Dave, I have observed latency spikes in this instance. You don't mind \
+                          talking about it, do you Dave?\n\n"
+            .to_owned();
+
+        let now = Utc::now().timestamp();
+        let mut advices = Vec::new();
+        for (i, (name, event)) in self.events.iter().enumerate() {
+            let count = event.samples.len().max(1) as u64;
+            let average = event.samples.iter().map(|s| s.latency).sum::<u64>() / count;
+            let deviation = event
+                .samples
+                .iter()
+                .map(|s| s.latency.abs_diff(average))
+                .sum::<u64>()
+                / count;
+            let oldest = event.samples.front().map_or(now, |s| s.time);
+            let period = (now - oldest) as f64 / count as f64;
+
+            let _ = writeln!(
+                report,
+                "{}. {name}: {} latency spikes (average {average}ms, mean deviation \
+                 {deviation}ms, period {period:.2} sec). Worst all time event {}ms.",
+                i + 1,
+                event.samples.len(),
+                event.max
+            );
+
+            let advice = match name.as_str() {
+                "command" => Some(
+                    "- Check your Slow Log to understand what are the commands you are running \
+                     which are too slow to execute. Please check SLOWLOG GET for more \
+                     information.",
+                ),
+                "fast-command" => Some(
+                    "- The system is slow to execute code paths not containing system calls. This \
+                     usually means the system does not provide this instance CPU time to run for \
+                     long periods. You should try to lower the system load, or to check if you \
+                     have a \"noisy neighbour\" problem.",
+                ),
+                "expire-cycle" => Some(
+                    "- Many keys may be expiring at the same time. Consider adding some jitter to \
+                     the expiry of your keys.",
+                ),
+                "eviction-cycle" => Some(
+                    "- Many keys are evicted at once to stay under maxmemory. Consider raising \
+                     maxmemory, or lowering maxmemory-samples.",
+                ),
+                _ => None,
+            };
+            if let Some(advice) = advice.filter(|advice| !advices.contains(advice)) {
+                advices.push(advice);
+            }
+        }
+
+        if advices.is_empty() {
+            report.push_str(
+                "\nWhile there are latency events logged, I'm not able to suggest any easy fix. \
+                 Please use the community to get some help, providing this report in your help \
+                 request.\n",
+            );
+        } else {
+            report.push_str("\nI have a few advices for you:\n\n");
+            for advice in advices {
+                report.push_str(advice);
+                report.push('\n');
+            }
+        }
+
+        report
+    }
+}
+
+/// How long ago something happened `secs` seconds ago, as labeled on graphs
+fn age(secs: i64) -> String {
+    if secs < 60 {
+        format!("{secs}s")
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h", secs / 3600)
+    }
+}
+
+/// Draw the bars of `samples` scaled between `low` and `high`, with their `labels` written
+/// vertically underneath
+fn draw(graph: &mut String, samples: &[Sample], labels: &[String], low: u64, high: u64) {
+    let steps = GRAPH_CHARS.len() * GRAPH_ROWS;
+    let range = (high - low).max(1) as f64;
+    let heights = samples
+        .iter()
+        .map(|sample| {
+            let step = ((sample.latency - low) as f64 * steps as f64 / range) as usize;
+            step.min(steps - 1)
+        })
+        .collect::<Vec<_>>();
+
+    for row in 0..GRAPH_ROWS {
+        // Steps below the top of the bars of this row
+        let base = (GRAPH_ROWS - row - 1) * GRAPH_CHARS.len();
+        let line = heights
+            .iter()
+            .map(|height| match height.checked_sub(base) {
+                Some(i) if i < GRAPH_CHARS.len() => GRAPH_CHARS[i],
+                Some(_) => '|',
+                None => ' ',
+            })
+            .collect::<String>();
+        graph.push_str(&line);
+        graph.push('\n');
+    }
+
+    graph.push('\n');
+    let rows = labels.iter().map(String::len).max().unwrap_or_default();
+    for row in 0..rows {
+        let line = labels
+            .iter()
+            .map(|label| label.chars().nth(row).unwrap_or(' '))
+            .collect::<String>();
+        graph.push_str(&line);
+        graph.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_the_worst_latency_of_every_second() {
+        let mut monitor = LatencyMonitor::default();
+        monitor.add(0, "command", 500);
+        monitor.add(100, "command", 50);
+        assert!(monitor.events.is_empty());
+
+        monitor.add_at(1, "command", 200);
+        monitor.add_at(1, "command", 300);
+        monitor.add_at(1, "command", 150);
+        monitor.add_at(2, "command", 100);
+
+        let event = &monitor.events["command"];
+        assert_eq!(event.samples.len(), 2);
+        assert_eq!(event.samples[0].latency, 300);
+        assert_eq!(event.max, 300);
+
+        assert!(monitor
+            .graph("command")
+            .unwrap()
+            .starts_with("command - high 300 ms, low 100 ms (all time high 300 ms)"));
+        assert_eq!(monitor.reset(&["unknown".to_owned()]), 0);
+        assert_eq!(monitor.reset(&[]), 1);
+        assert!(matches!(
+            monitor.graph("command"),
+            Err(LatencyError::NoSamples(_))
+        ));
+    }
+}
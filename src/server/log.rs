@@ -0,0 +1,83 @@
+//! Logging of a memora instance, whose verbosity can be changed while running
+
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use tracing_subscriber::{fmt as format, prelude::*, reload, EnvFilter, Registry};
+
+/// Handle changing the filter of the logs once they got initialized
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Names of the log levels
+const LEVELS: [&str; 5] = ["debug", "verbose", "notice", "warning", "nothing"];
+
+/// Verbosity of the logs, named after the log levels of Redis
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    #[default]
+    Notice,
+    Warning,
+    Nothing,
+}
+
+impl LogLevel {
+    fn filter(self) -> EnvFilter {
+        EnvFilter::new(match self {
+            Self::Debug => "debug",
+            Self::Verbose => "info",
+            Self::Notice => "warn",
+            Self::Warning => "error",
+            Self::Nothing => "off",
+        })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" => Ok(Self::Debug),
+            "verbose" => Ok(Self::Verbose),
+            "notice" => Ok(Self::Notice),
+            "warning" => Ok(Self::Warning),
+            "nothing" => Ok(Self::Nothing),
+            _ => Err(format!(
+                "argument(s) must be one of the following: {}",
+                LEVELS.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Debug => "debug",
+            Self::Verbose => "verbose",
+            Self::Notice => "notice",
+            Self::Warning => "warning",
+            Self::Nothing => "nothing",
+        })
+    }
+}
+
+/// Start logging at `level`, unless the `REDIS_LOG` environment variable sets another filter
+pub fn init(level: LogLevel) {
+    let filter = EnvFilter::try_from_env("REDIS_LOG").unwrap_or_else(|_| level.filter());
+    let (filter, handle) = reload::Layer::new(filter);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(format::layer())
+        .init();
+    let _ = FILTER.set(handle);
+}
+
+/// Log at `level` from now on
+pub(super) fn set_level(level: LogLevel) {
+    if let Some(handle) = FILTER.get() {
+        let _ = handle.reload(level.filter());
+    }
+}
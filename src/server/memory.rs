@@ -0,0 +1,204 @@
+//! Breakdown of the memory used by a memora instance, as reported by `MEMORY STATS` and diagnosed
+//! by `MEMORY DOCTOR`
+
+use crate::resp::Value;
+
+/// Below this amount of memory, there is not enough data for the diagnosis to be meaningful
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+
+/// Memory used by a single database
+#[derive(Debug)]
+pub(super) struct DbMemory {
+    pub(super) db: usize,
+
+    /// Bytes used by the table of keys, and by the table of keys with an expiry
+    pub(super) main: usize,
+    pub(super) expires: usize,
+}
+
+#[derive(Debug)]
+pub(super) struct MemoryStats {
+    pub(super) peak: usize,
+
+    /// Bytes used by the entries of every database
+    pub(super) dataset: usize,
+    pub(super) keys: usize,
+
+    pub(super) backlog: usize,
+    pub(super) scripts: usize,
+
+    /// Databases holding at least one key
+    pub(super) dbs: Vec<DbMemory>,
+
+    /// Resident set size of the process
+    pub(super) rss: usize,
+}
+
+impl MemoryStats {
+    /// Bytes used besides the entries themselves
+    fn overhead(&self) -> usize {
+        self.backlog
+            + self.scripts
+            + self
+                .dbs
+                .iter()
+                .map(|db| db.main + db.expires)
+                .sum::<usize>()
+    }
+
+    pub(super) fn total(&self) -> usize {
+        self.dataset + self.overhead()
+    }
+
+    fn fragmentation(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.rss as f64 / total as f64,
+        }
+    }
+
+    /// Reply of `MEMORY STATS`
+    pub(super) fn reply(&self) -> Value {
+        let total = self.total();
+        let percentage = |part: usize, whole: usize| match whole {
+            0 => 0.0,
+            whole => part as f64 * 100.0 / whole as f64,
+        };
+
+        let mut fields = vec![
+            (Value::bulk("peak.allocated"), Value::Int(self.peak as i64)),
+            (Value::bulk("total.allocated"), Value::Int(total as i64)),
+            (
+                Value::bulk("replication.backlog"),
+                Value::Int(self.backlog as i64),
+            ),
+            (Value::bulk("lua.caches"), Value::Int(self.scripts as i64)),
+        ];
+
+        fields.extend(self.dbs.iter().map(|db| {
+            (
+                Value::bulk(format!("db.{}", db.db)),
+                Value::from_iter([
+                    Value::bulk("overhead.hashtable.main"),
+                    Value::Int(db.main as i64),
+                    Value::bulk("overhead.hashtable.expires"),
+                    Value::Int(db.expires as i64),
+                ]),
+            )
+        }));
+
+        fields.extend([
+            (
+                Value::bulk("overhead.total"),
+                Value::Int(self.overhead() as i64),
+            ),
+            (Value::bulk("keys.count"), Value::Int(self.keys as i64)),
+            (
+                Value::bulk("keys.bytes-per-key"),
+                Value::Int(self.dataset.checked_div(self.keys).unwrap_or(0) as i64),
+            ),
+            (
+                Value::bulk("dataset.bytes"),
+                Value::Int(self.dataset as i64),
+            ),
+            (
+                Value::bulk("dataset.percentage"),
+                Value::bulk(percentage(self.dataset, total)),
+            ),
+            (
+                Value::bulk("peak.percentage"),
+                Value::bulk(percentage(total, self.peak)),
+            ),
+            (
+                Value::bulk("fragmentation"),
+                Value::bulk(self.fragmentation()),
+            ),
+            (
+                Value::bulk("fragmentation.bytes"),
+                Value::Int(self.rss as i64 - total as i64),
+            ),
+        ]);
+
+        Value::Array(
+            fields
+                .into_iter()
+                .flat_map(|(name, value)| [name, value])
+                .collect(),
+        )
+    }
+
+    /// Report of `MEMORY DOCTOR`
+    pub(super) fn doctor(&self) -> String {
+        let total = self.total();
+        if total < DOCTOR_MIN_MEMORY {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                    detector can't be used in these conditions. Please, leave for your mission on \
+                    Earth and fill it with some data. The new Sam and I will be back to our \
+                    programming as soon as I finished rebooting."
+                .to_owned();
+        }
+
+        let mut issues = Vec::new();
+
+        if self.peak as f64 > total as f64 * 1.5 {
+            issues.push(
+                " * Peak memory: In the past this instance used more than 150% the memory that is \
+                 currently using. The allocator is normally not able to release memory after a \
+                 peak, so you can expect to see a big fragmentation ratio, however this is \
+                 actually harmless and is only due to the memory peak.",
+            );
+        }
+
+        if self.fragmentation() > 1.4 {
+            issues.push(
+                " * High total RSS: This instance has a memory fragmentation and RSS overhead \
+                 greater than 1.4 (this means that the Resident Set Size of the process is much \
+                 larger than the memory the dataset accounts for).",
+            );
+        }
+
+        if self.backlog > total / 2 {
+            issues.push(
+                " * Big replication backlog: The replication backlog uses more than half of the \
+                 memory of this instance. Consider lowering repl-backlog-size.",
+            );
+        }
+
+        if issues.is_empty() {
+            return "Hi Sam, I can't find any memory issue in your instance. I can only account \
+                    for what occurs on this base."
+                .to_owned();
+        }
+
+        format!(
+            "Sam, I detected a few issues in this memora instance memory implants:\n\n{}\n\n\
+             I'm here to keep you safe, Sam. I want to help you.",
+            issues.join("\n\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_diagnose_memory_peaks() {
+        let mut stats = MemoryStats {
+            peak: 0,
+            dataset: 16 * 1024 * 1024,
+            keys: 1024,
+            backlog: 0,
+            scripts: 0,
+            dbs: Vec::new(),
+            rss: 16 * 1024 * 1024,
+        };
+        assert!(stats.doctor().contains("can't find any memory issue"));
+
+        stats.peak = 64 * 1024 * 1024;
+        assert!(stats.doctor().contains("Peak memory"));
+
+        stats.dataset = 1024;
+        assert!(stats.doctor().contains("very little memory"));
+    }
+}
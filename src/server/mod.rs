@@ -1,54 +1,353 @@
 //! Module that contains the main server implementation
 
+mod acl;
+
+mod client;
+
 mod cmd;
+
+pub mod config;
+pub use config::Config;
+
+mod db;
+
+mod dict;
+
 pub mod error;
 pub use error::{MemoraError, MemoraResult};
 
+pub mod evict;
+
 pub mod framer;
 
+mod glob;
+
+mod info;
+
+mod latency;
+
+pub mod log;
+
+mod memory;
+
+mod monitor;
+
+mod multi;
+
+pub mod notify;
+
+mod object;
+
+mod pubsub;
+
+mod rdb;
+
 pub mod role;
 pub use role::Role;
 
+mod script;
+
+#[allow(clippy::module_inception)]
 pub mod server;
 pub use server::Memora;
 
 mod session;
 use session::Session;
 
-use std::io::Write;
-use tokio::sync::oneshot;
+mod slot;
+
+mod slowlog;
+
+mod stats;
+
+pub mod tls;
+
+mod tracking;
+
+mod unix;
+
+mod wait;
+
+use std::{
+    fmt,
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
 
 use crate::resp;
 
-use self::cmd::Command;
+use self::{
+    client::ClientRecord,
+    cmd::{Argv, Command},
+};
+
+/// Maximum number of frames that can be pending to be pushed to a client
+const PUSH_CAPACITY: usize = 1024;
+
+/// Unique identifier of a client connection
+pub type ClientId = u64;
+
+/// Address a client is connected from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAddr {
+    Tcp(SocketAddr),
+
+    /// Path of the unix socket the client connected to
+    Unix(Arc<PathBuf>),
+}
+
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
+impl From<SocketAddr> for ClientAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+/// A handle to a connected client, used to push frames outside of the request/response cycle
+#[derive(Debug, Clone)]
+pub struct ClientHandle {
+    id: ClientId,
+    addr: ClientAddr,
+    push: mpsc::Sender<Bytes>,
+    kill: CancellationToken,
 
-struct Request {
-    cmd: Command,
+    /// Whether this client is the replication link with our master
+    master: bool,
+
+    /// Name of the user the client authenticated as, shared with the session of the client
+    user: Arc<Mutex<Option<String>>>,
+
+    /// Database selected by the client, sent along every request of its session
+    db: Arc<AtomicUsize>,
+
+    /// Metadata of the connection, shared with the session of the client
+    record: Arc<Mutex<ClientRecord>>,
+}
+
+impl ClientHandle {
+    fn new(addr: impl Into<ClientAddr>) -> (Self, mpsc::Receiver<Bytes>) {
+        Self::with_flags(addr.into(), false)
+    }
+
+    /// A handle for the replication link with our master at `addr`
+    fn master(addr: SocketAddr) -> (Self, mpsc::Receiver<Bytes>) {
+        Self::with_flags(addr.into(), true)
+    }
+
+    fn with_flags(addr: ClientAddr, master: bool) -> (Self, mpsc::Receiver<Bytes>) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+        let (push, rx) = mpsc::channel(PUSH_CAPACITY);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let kill = CancellationToken::new();
+        (
+            Self {
+                id,
+                addr,
+                push,
+                kill,
+                master,
+                user: Arc::default(),
+                db: Arc::default(),
+                record: Arc::default(),
+            },
+            rx,
+        )
+    }
+
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+
+    pub fn addr(&self) -> &ClientAddr {
+        &self.addr
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.master
+    }
+
+    /// Name of the user the client authenticated as, if it did
+    pub fn user(&self) -> Option<String> {
+        self.user
+            .lock()
+            .expect("client user lock should not be poisoned")
+            .clone()
+    }
+
+    /// Authenticate the client as the user named `user`
+    pub fn authenticate(&self, user: String) {
+        *self
+            .user
+            .lock()
+            .expect("client user lock should not be poisoned") = Some(user);
+    }
+
+    /// Index of the database selected by the client
+    pub fn db(&self) -> usize {
+        self.db.load(Ordering::Relaxed)
+    }
+
+    /// Select the database the following requests of the client apply to
+    pub fn select(&self, db: usize) {
+        self.db.store(db, Ordering::Relaxed);
+    }
+
+    /// Metadata of the connection
+    fn record(&self) -> MutexGuard<'_, ClientRecord> {
+        self.record
+            .lock()
+            .expect("client record lock should not be poisoned")
+    }
+
+    /// Number of frames pushed to the client that it did not receive yet
+    fn pending_pushes(&self) -> usize {
+        self.push.max_capacity() - self.push.capacity()
+    }
+
+    /// Push a raw `frame` to the client.
+    /// Returns `false` if the client went away or can not keep up with pushed frames
+    pub fn push(&self, frame: Bytes) -> bool {
+        self.push.try_send(frame).is_ok()
+    }
+
+    /// Close the connection of the client
+    pub fn kill(&self) {
+        self.kill.cancel();
+    }
+
+    /// Whether the client got killed, even if its connection is not closed yet
+    fn is_killed(&self) -> bool {
+        self.kill.is_cancelled()
+    }
+
+    /// Wait until the client gets killed
+    fn killed(&self) -> WaitForCancellationFuture<'_> {
+        self.kill.cancelled()
+    }
+}
+
+enum RequestKind {
+    /// A command, along with the arguments it got sent with
+    Command(Command, Argv),
+
+    /// Commands of a transaction, executed as a whole without interleaving with other clients
+    Exec(Vec<(Command, Argv)>),
+
+    /// Replace the whole dataset by a snapshot received from our master
+    Load(rdb::Snapshot),
+
+    /// The client went away
+    Disconnect,
+}
+
+pub struct Request {
+    client: ClientHandle,
+
+    /// Database selected by the client when it sent the request
+    db: usize,
+    kind: RequestKind,
 
     tx: oneshot::Sender<Response>,
 }
 
 impl Request {
-    fn new(cmd: Command) -> (Self, oneshot::Receiver<Response>) {
+    fn new(client: ClientHandle, cmd: Command, argv: Argv) -> (Self, oneshot::Receiver<Response>) {
+        Self::with_kind(client, RequestKind::Command(cmd, argv))
+    }
+
+    fn exec(
+        client: ClientHandle,
+        commands: Vec<(Command, Argv)>,
+    ) -> (Self, oneshot::Receiver<Response>) {
+        Self::with_kind(client, RequestKind::Exec(commands))
+    }
+
+    fn load(client: ClientHandle, snapshot: rdb::Snapshot) -> (Self, oneshot::Receiver<Response>) {
+        Self::with_kind(client, RequestKind::Load(snapshot))
+    }
+
+    /// Notify the server that `client` went away
+    fn disconnect(client: ClientHandle) -> Self {
+        Self::with_kind(client, RequestKind::Disconnect).0
+    }
+
+    fn with_kind(client: ClientHandle, kind: RequestKind) -> (Self, oneshot::Receiver<Response>) {
         let (tx, rx) = oneshot::channel();
-        (Self { cmd, tx }, rx)
+        let db = client.db();
+        (
+            Self {
+                client,
+                db,
+                kind,
+                tx,
+            },
+            rx,
+        )
     }
 }
 
-pub struct Response(resp::Value);
+/// Reply to a request. A response can be empty for commands that do not expect any reply, or made
+/// of several frames for commands that reply once per argument
+pub struct Response(Vec<resp::Value>);
 
 impl Response {
     fn encode(&self, buf: &mut impl Write) -> MemoraResult<()> {
-        self.0.encode(buf).map_err(MemoraError::Resp)
+        for value in &self.0 {
+            value.encode(buf)?;
+        }
+        Ok(())
+    }
+
+    /// A response that does not send anything back to the client
+    pub fn none() -> Self {
+        Self(Vec::new())
+    }
+
+    /// A response made of several frames
+    pub fn many(values: Vec<resp::Value>) -> Self {
+        Self(values)
     }
 
     pub fn ok() -> Self {
         resp::Value::Str(resp::StringValue::Simple("OK".to_owned())).into()
     }
+
+    pub fn error(e: &MemoraError) -> Self {
+        resp::Value::error(format!("{} {e}", e.code())).into()
+    }
 }
 
 impl From<resp::Value> for Response {
     fn from(value: resp::Value) -> Self {
-        Self(value)
+        Self(vec![value])
+    }
+}
+
+impl From<Response> for resp::Value {
+    /// The single frame of a response, or an array of its frames
+    fn from(mut resp: Response) -> Self {
+        if resp.0.len() == 1 {
+            resp.0.remove(0)
+        } else {
+            resp::Value::Array(resp.0)
+        }
     }
 }
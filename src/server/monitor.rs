@@ -0,0 +1,121 @@
+//! Clients in `MONITOR` mode, which get sent every command processed by the server
+
+use std::{collections::HashMap, fmt::Write};
+
+use bytes::Bytes;
+use chrono::Utc;
+use tracing::warn;
+
+use super::{cmd, ClientAddr, ClientHandle, ClientId};
+
+/// Where a monitored command came from
+pub(super) enum Source<'a> {
+    Client(&'a ClientAddr),
+
+    /// A script run by a client
+    Lua,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Monitors {
+    clients: HashMap<ClientId, ClientHandle>,
+}
+
+impl Monitors {
+    pub(super) fn add(&mut self, client: ClientHandle) {
+        client.record().monitor = true;
+        self.clients.insert(client.id(), client);
+    }
+
+    pub(super) fn remove(&mut self, client: ClientId) {
+        self.clients.remove(&client);
+    }
+
+    /// Send the command `argv` executed on the database `db` to every monitor. Monitors that can
+    /// not keep up get disconnected rather than slowing down the server
+    pub(super) fn feed(&mut self, db: usize, source: Source<'_>, argv: &[String]) {
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let frame = Bytes::from(line(db, source, argv));
+        self.clients.retain(|_, monitor| {
+            if monitor.push(frame.clone()) {
+                return true;
+            }
+
+            warn!(
+                "disconnecting monitor {} that can not keep up with the commands",
+                monitor.addr()
+            );
+            monitor.kill();
+            false
+        });
+    }
+}
+
+/// Status reply describing a command, as `+<timestamp> [<db> <addr>] "cmd" "arg" ...`
+fn line(db: usize, source: Source<'_>, argv: &[String]) -> String {
+    let mut argv = argv.to_vec();
+    cmd::redact(&mut argv);
+
+    let now = Utc::now();
+    let mut line = format!(
+        "+{}.{:06} [{db} ",
+        now.timestamp(),
+        now.timestamp_subsec_micros()
+    );
+    match source {
+        Source::Client(ClientAddr::Tcp(addr)) => {
+            let _ = write!(line, "{addr}]");
+        }
+        Source::Client(ClientAddr::Unix(path)) => {
+            let _ = write!(line, "unix:{}]", path.display());
+        }
+        Source::Lua => line.push_str("lua]"),
+    }
+
+    for arg in &argv {
+        line.push_str(" \"");
+        escape(&mut line, arg);
+        line.push('"');
+    }
+    line.push_str("\r\n");
+    line
+}
+
+/// Append `arg` to `line` with the quotes, backslashes and non printable characters escaped
+fn escape(line: &mut String, arg: &str) {
+    for c in arg.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '"' => line.push_str("\\\""),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            '\x07' => line.push_str("\\a"),
+            '\x08' => line.push_str("\\b"),
+            c if c.is_ascii_control() => {
+                let _ = write!(line, "\\x{:02x}", c as u32);
+            }
+            c => line.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_describe_commands() {
+        let addr = ClientAddr::Tcp("127.0.0.1:6000".parse().unwrap());
+        let argv = ["set", "k", "a \"b\"\n"].map(String::from);
+        let line = line(3, Source::Client(&addr), &argv);
+        assert!(line.ends_with(" [3 127.0.0.1:6000] \"set\" \"k\" \"a \\\"b\\\"\\n\"\r\n"));
+
+        let argv = ["AUTH", "secret"].map(String::from);
+        let line = super::line(0, Source::Lua, &argv);
+        assert!(line.ends_with(" [0 lua] \"AUTH\" \"(redacted)\"\r\n"));
+    }
+}
@@ -0,0 +1,159 @@
+//! `MULTI`/`EXEC` transactions and the keys watched by clients for optimistic locking
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use super::{
+    cmd::{Argv, Command},
+    ClientId,
+};
+
+#[derive(Debug, Error)]
+pub enum MultiError {
+    #[error("MULTI calls can not be nested")]
+    Nested,
+
+    #[error("EXEC without MULTI")]
+    ExecWithoutMulti,
+
+    #[error("DISCARD without MULTI")]
+    DiscardWithoutMulti,
+
+    #[error("WATCH inside MULTI is not allowed")]
+    WatchInsideMulti,
+
+    #[error("Transaction discarded because of previous errors.")]
+    ExecAbort,
+}
+
+/// Commands queued by a client after `MULTI`, to be executed as a whole by `EXEC`
+#[derive(Debug, Default)]
+pub(super) struct Transaction {
+    pub(super) commands: Vec<(Command, Argv)>,
+
+    /// Whether a command failed to be queued, in which case `EXEC` discards the transaction
+    pub(super) aborted: bool,
+}
+
+#[derive(Debug, Default)]
+struct Watched {
+    /// Watched keys, along with the database they belong to
+    keys: HashSet<(usize, String)>,
+
+    /// Whether one of the keys got touched since it was watched
+    dirty: bool,
+}
+
+/// Keys watched by clients, whose next `EXEC` fails if any of them gets modified
+#[derive(Debug, Default)]
+pub(super) struct Watches {
+    keys: HashMap<(usize, String), HashSet<ClientId>>,
+    clients: HashMap<ClientId, Watched>,
+}
+
+impl Watches {
+    /// Watch `keys` of the database `db` for `client`
+    pub(super) fn watch(&mut self, client: ClientId, db: usize, keys: Vec<String>) {
+        let watched = self.clients.entry(client).or_default();
+
+        for key in keys {
+            self.keys
+                .entry((db, key.clone()))
+                .or_default()
+                .insert(client);
+            watched.keys.insert((db, key));
+        }
+    }
+
+    /// Forget about the keys watched by `client`
+    pub(super) fn unwatch(&mut self, client: ClientId) {
+        let Some(watched) = self.clients.remove(&client) else {
+            return;
+        };
+
+        for key in watched.keys {
+            if let Some(clients) = self.keys.get_mut(&key) {
+                clients.remove(&client);
+                if clients.is_empty() {
+                    self.keys.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Flag the transactions of the clients watching `key` of the database `db` as failed
+    pub(super) fn touch(&mut self, db: usize, key: &str) {
+        let Some(clients) = self.keys.get(&(db, key.to_owned())) else {
+            return;
+        };
+
+        for client in clients {
+            if let Some(watched) = self.clients.get_mut(client) {
+                watched.dirty = true;
+            }
+        }
+    }
+
+    /// Flag the transactions of the clients watching a key of the database `db` as failed
+    pub(super) fn touch_db(&mut self, db: usize) {
+        for watched in self.clients.values_mut() {
+            if watched.keys.iter().any(|(watched_db, _)| *watched_db == db) {
+                watched.dirty = true;
+            }
+        }
+    }
+
+    /// Flag the transactions of every client watching a key as failed
+    pub(super) fn touch_all(&mut self) {
+        for watched in self.clients.values_mut() {
+            watched.dirty = true;
+        }
+    }
+
+    /// Whether a key watched by `client` got touched
+    pub(super) fn is_dirty(&self, client: ClientId) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|watched| watched.dirty)
+    }
+
+    /// Number of clients watching keys
+    pub(super) fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Number of keys watched by at least one client
+    pub(super) fn keys(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_flag_watching_clients() {
+        let mut watches = Watches::default();
+        watches.watch(1, 0, vec!["foo".to_owned(), "bar".to_owned()]);
+        watches.watch(2, 0, vec!["bar".to_owned()]);
+        watches.watch(3, 1, vec!["foo".to_owned()]);
+
+        watches.touch(0, "foo");
+        assert!(watches.is_dirty(1));
+        assert!(!watches.is_dirty(2));
+        assert!(!watches.is_dirty(3));
+
+        watches.unwatch(1);
+        assert!(!watches.is_dirty(1));
+        assert_eq!(watches.keys(), 2);
+        assert_eq!(watches.clients(), 2);
+
+        watches.touch(0, "bar");
+        assert!(watches.is_dirty(2));
+
+        watches.touch_db(1);
+        assert!(watches.is_dirty(3));
+    }
+}
@@ -0,0 +1,197 @@
+//! Keyspace event notifications, published on `__keyspace@<db>__` and `__keyevent@<db>__` channels
+
+use std::{fmt, ops::BitOr, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("invalid keyspace event class '{0}'")]
+    InvalidClass(char),
+}
+
+/// Classes of keyspace events to notify, as configured by `notify-keyspace-events`
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct NotifyFlags(u16);
+
+impl NotifyFlags {
+    pub const NONE: Self = Self(0);
+
+    /// Publish events on `__keyspace@<db>__:<key>` channels
+    pub const KEYSPACE: Self = Self(1 << 0);
+
+    /// Publish events on `__keyevent@<db>__:<event>` channels
+    pub const KEYEVENT: Self = Self(1 << 1);
+
+    /// Generic commands such as `DEL`, `EXPIRE` or `RENAME`
+    pub const GENERIC: Self = Self(1 << 2);
+    pub const STRING: Self = Self(1 << 3);
+    pub const LIST: Self = Self(1 << 4);
+    pub const SET: Self = Self(1 << 5);
+    pub const HASH: Self = Self(1 << 6);
+    pub const ZSET: Self = Self(1 << 7);
+
+    /// Keys that expired
+    pub const EXPIRED: Self = Self(1 << 8);
+
+    /// Keys that got evicted under `maxmemory`
+    pub const EVICTED: Self = Self(1 << 9);
+    pub const STREAM: Self = Self(1 << 10);
+
+    /// Lookups of keys that do not exist
+    pub const KEY_MISS: Self = Self(1 << 11);
+
+    /// Keys that got created
+    pub const NEW: Self = Self(1 << 12);
+
+    /// Every class of events but key misses and new keys
+    pub const ALL: Self = Self(
+        Self::GENERIC.0
+            | Self::STRING.0
+            | Self::LIST.0
+            | Self::SET.0
+            | Self::HASH.0
+            | Self::ZSET.0
+            | Self::EXPIRED.0
+            | Self::EVICTED.0
+            | Self::STREAM.0,
+    );
+
+    const CLASSES: &'static [(char, Self)] = &[
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('m', Self::KEY_MISS),
+        ('n', Self::NEW),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of `class` must be published on keyspace or keyevent channels
+    pub fn notifies(self, class: Self) -> bool {
+        self.0 & (Self::KEYSPACE.0 | Self::KEYEVENT.0) != 0 && self.0 & class.0 != 0
+    }
+}
+
+impl BitOr for NotifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl FromStr for NotifyFlags {
+    type Err = NotifyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars().try_fold(Self::NONE, |flags, c| {
+            let flag = match c {
+                'K' => Self::KEYSPACE,
+                'E' => Self::KEYEVENT,
+                'A' => Self::ALL,
+                c => Self::CLASSES
+                    .iter()
+                    .find(|(class, _)| *class == c)
+                    .map(|(_, flag)| *flag)
+                    .ok_or(NotifyError::InvalidClass(c))?,
+            };
+
+            Ok(flags | flag)
+        })
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = String::new();
+
+        if self.contains(Self::ALL) {
+            s.push('A');
+        }
+
+        for (class, flag) in Self::CLASSES {
+            if !(self.contains(Self::ALL) && Self::ALL.contains(*flag)) && self.contains(*flag) {
+                s.push(*class);
+            }
+        }
+
+        if self.contains(Self::KEYSPACE) {
+            s.push('K');
+        }
+        if self.contains(Self::KEYEVENT) {
+            s.push('E');
+        }
+
+        f.write_str(&s)
+    }
+}
+
+/// Channels on which an `event` on `key` of the database `db` is published, for the given
+/// notification `flags`
+pub(super) fn channels(
+    flags: NotifyFlags,
+    db: usize,
+    event: &str,
+    key: &str,
+) -> impl Iterator<Item = (String, String)> {
+    let keyspace = flags
+        .contains(NotifyFlags::KEYSPACE)
+        .then(|| (format!("__keyspace@{db}__:{key}"), event.to_owned()));
+    let keyevent = flags
+        .contains(NotifyFlags::KEYEVENT)
+        .then(|| (format!("__keyevent@{db}__:{event}"), key.to_owned()));
+
+    keyspace.into_iter().chain(keyevent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_flags() {
+        let flags: NotifyFlags = "Ex".parse().unwrap();
+        assert!(flags.notifies(NotifyFlags::EXPIRED));
+        assert!(!flags.notifies(NotifyFlags::STRING));
+
+        // Classes are not notified without keyspace or keyevent channels
+        let flags: NotifyFlags = "A".parse().unwrap();
+        assert!(!flags.notifies(NotifyFlags::STRING));
+
+        let flags: NotifyFlags = "KA".parse().unwrap();
+        assert!(flags.notifies(NotifyFlags::STRING));
+        assert!(!flags.notifies(NotifyFlags::KEY_MISS));
+
+        assert!("Kq".parse::<NotifyFlags>().is_err());
+    }
+
+    #[test]
+    fn should_format_flags() {
+        assert_eq!("".parse::<NotifyFlags>().unwrap().to_string(), "");
+        assert_eq!("$gKE".parse::<NotifyFlags>().unwrap().to_string(), "g$KE");
+        assert_eq!("KEA".parse::<NotifyFlags>().unwrap().to_string(), "AKE");
+        assert_eq!("AmK".parse::<NotifyFlags>().unwrap().to_string(), "AmK");
+    }
+
+    #[test]
+    fn should_name_channels() {
+        let flags: NotifyFlags = "KE$".parse().unwrap();
+        let channels = channels(flags, 0, "set", "foo").collect::<Vec<_>>();
+        assert_eq!(
+            channels,
+            [
+                ("__keyspace@0__:foo".to_owned(), "set".to_owned()),
+                ("__keyevent@0__:set".to_owned(), "foo".to_owned()),
+            ]
+        );
+    }
+}
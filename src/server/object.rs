@@ -0,0 +1,94 @@
+//! In-memory representation of the values of the keys, as reported by `OBJECT ENCODING`.
+//!
+//! Like Redis, strings holding a canonical 64 bits integer are stored as the integer itself, and
+//! short strings are told apart from the longer ones, which Redis allocates along with their
+//! object header.
+
+use std::fmt;
+
+/// Strings up to this length are `embstr` encoded, longer ones are `raw` encoded
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// How a value is laid out in memory
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Encoding {
+    /// A string holding an integer, stored as the integer
+    Int,
+
+    /// A short string
+    EmbStr,
+
+    /// A string too long to be embedded
+    Raw,
+}
+
+impl Encoding {
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::EmbStr => "embstr",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+/// A value stored under a key
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(super) enum Object {
+    Int(i64),
+    Str(Box<str>),
+}
+
+impl Object {
+    /// Encode `value` in its most compact representation
+    pub(super) fn encode(value: String) -> (Self, Encoding) {
+        // Only integers that render back to the same string can be stored as such
+        if let Ok(int) = value.parse::<i64>() {
+            if int.to_string() == value {
+                return (Self::Int(int), Encoding::Int);
+            }
+        }
+
+        let encoding = if value.len() <= EMBSTR_SIZE_LIMIT {
+            Encoding::EmbStr
+        } else {
+            Encoding::Raw
+        };
+        (Self::Str(value.into_boxed_str()), encoding)
+    }
+
+    /// Number of bytes allocated for the value, besides the object itself
+    pub(super) fn allocated(&self) -> usize {
+        match self {
+            Self::Int(_) => 0,
+            Self::Str(s) => s.len(),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(int) => write!(f, "{int}"),
+            Self::Str(s) => f.write_str(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pick_compact_encodings() {
+        let encode = |value: &str| Object::encode(value.to_owned());
+
+        assert_eq!(encode("-42"), (Object::Int(-42), Encoding::Int));
+        assert_eq!(encode("042").1, Encoding::EmbStr);
+        assert_eq!(encode("+1").1, Encoding::EmbStr);
+        assert_eq!(encode(&"x".repeat(44)).1, Encoding::EmbStr);
+        assert_eq!(encode(&"x".repeat(45)).1, Encoding::Raw);
+        assert_eq!(encode("99999999999999999999").1, Encoding::EmbStr);
+        assert_eq!(encode("-42").0.to_string(), "-42");
+    }
+}
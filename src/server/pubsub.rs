@@ -0,0 +1,459 @@
+//! Publish/subscribe messaging between clients
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::resp::Value;
+
+use super::{glob, slot, ClientHandle, ClientId};
+
+#[derive(Debug, Error)]
+pub enum PubSubError {
+    #[error("Can't execute '{0}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context")]
+    SubscriberMode(&'static str),
+}
+
+/// Kind of subscription a client can hold
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Kind {
+    /// Subscription to a channel by name
+    Channel,
+
+    /// Subscription to every channel matching a glob pattern
+    Pattern,
+
+    /// Subscription to a shard channel, which belongs to the hash slot of its name
+    Shard,
+}
+
+impl Kind {
+    fn subscribe(self) -> &'static str {
+        match self {
+            Self::Channel => "subscribe",
+            Self::Pattern => "psubscribe",
+            Self::Shard => "ssubscribe",
+        }
+    }
+
+    fn unsubscribe(self) -> &'static str {
+        match self {
+            Self::Channel => "unsubscribe",
+            Self::Pattern => "punsubscribe",
+            Self::Shard => "sunsubscribe",
+        }
+    }
+}
+
+/// Subscribers of a channel or pattern
+type Subscribers = BTreeMap<ClientId, ClientHandle>;
+
+/// Subscriptions held by a client
+#[derive(Debug, Default)]
+struct Subscriptions {
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    shard_channels: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    fn of(&mut self, kind: Kind) -> &mut BTreeSet<String> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shard_channels.len()
+    }
+
+    /// Number of subscriptions reported when confirming a subscription of `kind`.
+    /// Like Redis, shard channels are counted apart from other subscriptions
+    fn reported(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Channel | Kind::Pattern => self.channels.len() + self.patterns.len(),
+            Kind::Shard => self.shard_channels.len(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct PubSub {
+    channels: HashMap<String, Subscribers>,
+    patterns: HashMap<String, Subscribers>,
+
+    /// Shard channels, grouped by hash slot
+    shards: BTreeMap<u16, HashMap<String, Subscribers>>,
+
+    clients: HashMap<ClientId, Subscriptions>,
+}
+
+impl PubSub {
+    fn registry(&mut self, kind: Kind, name: &str) -> &mut HashMap<String, Subscribers> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => self
+                .shards
+                .entry(slot::key_slot(name.as_bytes()))
+                .or_default(),
+        }
+    }
+
+    /// Remove `client` from the subscribers of `name`, forgetting about `name` once it has no
+    /// subscribers left
+    fn unregister(&mut self, kind: Kind, name: &str, client: ClientId) {
+        let registry = self.registry(kind, name);
+        if let Some(subscribers) = registry.get_mut(name) {
+            subscribers.remove(&client);
+            if subscribers.is_empty() {
+                registry.remove(name);
+            }
+        }
+
+        if kind == Kind::Shard {
+            self.shards.retain(|_, channels| !channels.is_empty());
+        }
+    }
+
+    fn reported(&self, client: ClientId, kind: Kind) -> i64 {
+        self.clients
+            .get(&client)
+            .map_or(0, |subscriptions| subscriptions.reported(kind)) as i64
+    }
+
+    /// Number of subscriptions held by `client`
+    pub(super) fn subscriptions(&self, client: ClientId) -> usize {
+        self.clients.get(&client).map_or(0, Subscriptions::count)
+    }
+
+    /// Number of channels, patterns and shard channels `client` is subscribed to
+    pub(super) fn subscriptions_by_kind(&self, client: ClientId) -> [usize; 3] {
+        self.clients.get(&client).map_or([0; 3], |subscriptions| {
+            [
+                subscriptions.channels.len(),
+                subscriptions.patterns.len(),
+                subscriptions.shard_channels.len(),
+            ]
+        })
+    }
+
+    /// Subscribe `client` to `names`, returning a confirmation for every one of them
+    pub(super) fn subscribe(
+        &mut self,
+        client: &ClientHandle,
+        kind: Kind,
+        names: Vec<String>,
+    ) -> Vec<Value> {
+        let mut replies = Vec::with_capacity(names.len());
+
+        for name in names {
+            let subscriptions = self.clients.entry(client.id()).or_default();
+            if subscriptions.of(kind).insert(name.clone()) {
+                self.registry(kind, &name)
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(client.id(), client.clone());
+            }
+
+            replies.push(Value::from_iter([
+                Value::bulk(kind.subscribe()),
+                Value::bulk(name),
+                Value::Int(self.reported(client.id(), kind)),
+            ]));
+        }
+
+        replies
+    }
+
+    /// Unsubscribe `client` from `names`, or from every subscription of `kind` when `names` is
+    /// empty, returning a confirmation for every one of them
+    pub(super) fn unsubscribe(
+        &mut self,
+        client: &ClientHandle,
+        kind: Kind,
+        names: Vec<String>,
+    ) -> Vec<Value> {
+        let names = if names.is_empty() {
+            self.clients
+                .get_mut(&client.id())
+                .map(|subscriptions| subscriptions.of(kind).iter().cloned().collect())
+                .unwrap_or_default()
+        } else {
+            names
+        };
+
+        let mut replies = Vec::with_capacity(names.len());
+
+        for name in &names {
+            let removed = self
+                .clients
+                .get_mut(&client.id())
+                .is_some_and(|subscriptions| subscriptions.of(kind).remove(name));
+
+            if removed {
+                self.unregister(kind, name, client.id());
+            }
+
+            replies.push(Value::from_iter([
+                Value::bulk(kind.unsubscribe()),
+                Value::bulk(name),
+                Value::Int(self.reported(client.id(), kind)),
+            ]));
+        }
+
+        if self.subscriptions(client.id()) == 0 {
+            self.clients.remove(&client.id());
+        }
+
+        // Unsubscribing from everything while not subscribed to anything is still confirmed
+        if replies.is_empty() {
+            replies.push(Value::from_iter([
+                Value::bulk(kind.unsubscribe()),
+                Value::null_bulk(),
+                Value::Int(self.reported(client.id(), kind)),
+            ]));
+        }
+
+        replies
+    }
+
+    /// Drop every subscription of a client that went away
+    pub(super) fn remove(&mut self, client: ClientId) {
+        let Some(subscriptions) = self.clients.remove(&client) else {
+            return;
+        };
+
+        for (kind, names) in [
+            (Kind::Channel, subscriptions.channels),
+            (Kind::Pattern, subscriptions.patterns),
+            (Kind::Shard, subscriptions.shard_channels),
+        ] {
+            for name in names {
+                self.unregister(kind, &name, client);
+            }
+        }
+    }
+
+    /// Publish `message` on `channel`, returning the number of clients that received it
+    pub(super) fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut received = 0;
+
+        if let Some(subscribers) = self.channels.get(channel) {
+            let frame = encode(&[
+                Value::bulk("message"),
+                Value::bulk(channel),
+                Value::bulk(message),
+            ]);
+            received += deliver(subscribers, &frame);
+        }
+
+        for (pattern, subscribers) in &self.patterns {
+            if !glob::matches(pattern.as_bytes(), channel.as_bytes()) {
+                continue;
+            }
+
+            let frame = encode(&[
+                Value::bulk("pmessage"),
+                Value::bulk(pattern),
+                Value::bulk(channel),
+                Value::bulk(message),
+            ]);
+            received += deliver(subscribers, &frame);
+        }
+
+        received
+    }
+
+    /// Publish `message` on the shard `channel`, returning the number of clients that received it
+    pub(super) fn spublish(&self, channel: &str, message: &str) -> usize {
+        let Some(subscribers) = self.shard_subscribers(channel) else {
+            return 0;
+        };
+
+        let frame = encode(&[
+            Value::bulk("smessage"),
+            Value::bulk(channel),
+            Value::bulk(message),
+        ]);
+        deliver(subscribers, &frame)
+    }
+
+    fn shard_subscribers(&self, channel: &str) -> Option<&Subscribers> {
+        self.shards
+            .get(&slot::key_slot(channel.as_bytes()))?
+            .get(channel)
+    }
+
+    /// Active channels, optionally restricted to the ones matching the glob `pattern`
+    pub(super) fn channels(&self, pattern: Option<&str>) -> Vec<String> {
+        filter(self.channels.keys(), pattern)
+    }
+
+    /// Active shard channels, optionally restricted to the ones matching the glob `pattern`
+    pub(super) fn shard_channels(&self, pattern: Option<&str>) -> Vec<String> {
+        filter(self.shards.values().flat_map(HashMap::keys), pattern)
+    }
+
+    /// Number of subscribers of `channel`, not counting pattern subscribers
+    pub(super) fn numsub(&self, channel: &str) -> usize {
+        self.channels.get(channel).map_or(0, BTreeMap::len)
+    }
+
+    /// Number of subscribers of the shard `channel`
+    pub(super) fn shard_numsub(&self, channel: &str) -> usize {
+        self.shard_subscribers(channel).map_or(0, BTreeMap::len)
+    }
+
+    /// Number of unique patterns clients are subscribed to
+    pub(super) fn numpat(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Number of clients with at least one subscription
+    pub(super) fn clients(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+/// Collect the `names` matching the glob `pattern`, or all of them without pattern
+fn filter<'a>(names: impl Iterator<Item = &'a String>, pattern: Option<&str>) -> Vec<String> {
+    names
+        .filter(|name| {
+            pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), name.as_bytes()))
+        })
+        .cloned()
+        .collect()
+}
+
+fn encode(values: &[Value]) -> Bytes {
+    let mut buf = Vec::new();
+    Value::Array(values.to_vec())
+        .encode(&mut buf)
+        .expect("encoding to an in-memory buffer should not fail");
+    Bytes::from(buf)
+}
+
+/// Push `frame` to every subscriber, disconnecting the ones that can not keep up.
+/// Returns the number of subscribers the frame was pushed to
+fn deliver(subscribers: &Subscribers, frame: &Bytes) -> usize {
+    let mut delivered = 0;
+
+    for subscriber in subscribers.values() {
+        if subscriber.push(frame.clone()) {
+            delivered += 1;
+        } else {
+            warn!(
+                "disconnecting subscriber {} that can not keep up with published messages",
+                subscriber.addr()
+            );
+            subscriber.kill();
+        }
+    }
+
+    delivered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> (ClientHandle, tokio::sync::mpsc::Receiver<Bytes>) {
+        ClientHandle::new("127.0.0.1:6379".parse::<std::net::SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn should_deliver_to_channel_and_pattern_subscribers() {
+        let mut pubsub = PubSub::default();
+        let (a, mut a_rx) = client();
+        let (b, mut b_rx) = client();
+
+        pubsub.subscribe(&a, Kind::Channel, vec!["news.tech".to_owned()]);
+        pubsub.subscribe(&b, Kind::Pattern, vec!["news.*".to_owned()]);
+
+        assert_eq!(pubsub.publish("news.tech", "hello"), 2);
+        assert_eq!(pubsub.publish("weather", "sunny"), 0);
+
+        assert_eq!(
+            a_rx.try_recv().unwrap(),
+            &b"*3\r\n$7\r\nmessage\r\n$9\r\nnews.tech\r\n$5\r\nhello\r\n"[..]
+        );
+        assert_eq!(
+            b_rx.try_recv().unwrap(),
+            &b"*4\r\n$8\r\npmessage\r\n$6\r\nnews.*\r\n$9\r\nnews.tech\r\n$5\r\nhello\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn should_track_subscriptions() {
+        let mut pubsub = PubSub::default();
+        let (a, _a_rx) = client();
+
+        let replies = pubsub.subscribe(&a, Kind::Channel, vec!["x".to_owned(), "y".to_owned()]);
+        assert_eq!(replies.len(), 2);
+        pubsub.subscribe(&a, Kind::Pattern, vec!["z*".to_owned()]);
+
+        assert_eq!(pubsub.subscriptions(a.id()), 3);
+        let mut channels = pubsub.channels(None);
+        channels.sort();
+        assert_eq!(channels, ["x", "y"]);
+        assert_eq!(pubsub.channels(Some("[x]")), ["x"]);
+        assert_eq!(pubsub.numpat(), 1);
+
+        let replies = pubsub.unsubscribe(&a, Kind::Channel, Vec::new());
+        assert_eq!(replies.len(), 2);
+        assert_eq!(pubsub.subscriptions(a.id()), 1);
+        assert_eq!(pubsub.numsub("x"), 0);
+
+        pubsub.remove(a.id());
+        assert_eq!(pubsub.numpat(), 0);
+        assert_eq!(pubsub.clients(), 0);
+    }
+
+    #[test]
+    fn should_deliver_shard_messages() {
+        let mut pubsub = PubSub::default();
+        let (a, mut a_rx) = client();
+
+        let replies = pubsub.subscribe(&a, Kind::Channel, vec!["orders".to_owned()]);
+        assert_eq!(
+            replies[0],
+            Value::from_iter([
+                Value::bulk("subscribe"),
+                Value::bulk("orders"),
+                Value::Int(1)
+            ])
+        );
+
+        // Shard channels are counted on their own
+        let replies = pubsub.subscribe(&a, Kind::Shard, vec!["{user}.orders".to_owned()]);
+        assert_eq!(
+            replies[0],
+            Value::from_iter([
+                Value::bulk("ssubscribe"),
+                Value::bulk("{user}.orders"),
+                Value::Int(1)
+            ])
+        );
+        assert_eq!(pubsub.subscriptions(a.id()), 2);
+
+        assert_eq!(pubsub.spublish("{user}.orders", "new"), 1);
+        assert_eq!(pubsub.spublish("orders", "new"), 0);
+        assert_eq!(pubsub.publish("{user}.orders", "new"), 0);
+        assert_eq!(
+            a_rx.try_recv().unwrap(),
+            &b"*3\r\n$8\r\nsmessage\r\n$13\r\n{user}.orders\r\n$3\r\nnew\r\n"[..]
+        );
+
+        assert_eq!(pubsub.shard_channels(None), ["{user}.orders"]);
+        assert_eq!(pubsub.shard_numsub("{user}.orders"), 1);
+
+        pubsub.unsubscribe(&a, Kind::Shard, Vec::new());
+        assert!(pubsub.shards.is_empty());
+        assert_eq!(pubsub.subscriptions(a.id()), 1);
+    }
+}
@@ -0,0 +1,347 @@
+//! Minimal support for the RDB snapshot format, used to transfer the dataset to replicas during
+//! a full synchronization
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"REDIS";
+const VERSION: &[u8] = b"0011";
+
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+
+/// Error that can be raised when decoding a RDB snapshot
+#[derive(Debug, Error)]
+pub enum RdbError {
+    #[error("invalid RDB header")]
+    InvalidHeader,
+
+    #[error("unexpected end of RDB payload")]
+    UnexpectedEof,
+
+    #[error("unsupported RDB value type {0}")]
+    UnsupportedType(u8),
+
+    #[error("unsupported RDB string encoding {0}")]
+    UnsupportedEncoding(u8),
+
+    #[error("invalid string in RDB payload")]
+    InvalidString,
+
+    #[error("payload version or checksum are wrong")]
+    InvalidPayload,
+}
+
+pub type RdbResult<T> = std::result::Result<T, RdbError>;
+
+/// A single key stored in a snapshot
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Entry {
+    /// Index of the database the key belongs to
+    pub db: usize,
+    pub key: String,
+    pub value: String,
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+/// A point-in-time copy of the dataset
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct Snapshot {
+    pub entries: Vec<Entry>,
+
+    /// Source code of the function libraries
+    pub functions: Vec<String>,
+}
+
+impl Snapshot {
+    /// Encode this snapshot to the RDB binary format
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(VERSION);
+
+        for (key, value) in [("redis-ver", "7.2.0"), ("redis-bits", "64")] {
+            buf.push(OPCODE_AUX);
+            encode_string(&mut buf, key);
+            encode_string(&mut buf, value);
+        }
+
+        encode_functions(&mut buf, &self.functions);
+
+        let mut databases = BTreeMap::<_, Vec<_>>::new();
+        for entry in &self.entries {
+            databases.entry(entry.db).or_default().push(entry);
+        }
+
+        for (db, entries) in databases {
+            buf.push(OPCODE_SELECTDB);
+            encode_length(&mut buf, db as u64);
+
+            let expires = entries.iter().filter(|e| e.expiry.is_some()).count();
+            buf.push(OPCODE_RESIZEDB);
+            encode_length(&mut buf, entries.len() as u64);
+            encode_length(&mut buf, expires as u64);
+
+            for entry in entries {
+                if let Some(expiry) = entry.expiry {
+                    buf.push(OPCODE_EXPIRETIME_MS);
+                    buf.extend_from_slice(&(expiry.timestamp_millis() as u64).to_le_bytes());
+                }
+
+                buf.push(TYPE_STRING);
+                encode_string(&mut buf, &entry.key);
+                encode_string(&mut buf, &entry.value);
+            }
+        }
+
+        buf.push(OPCODE_EOF);
+        // A zero checksum tells the loader that checksum verification is disabled
+        buf.extend_from_slice(&[0u8; 8]);
+        buf
+    }
+
+    /// Decode a snapshot from its RDB binary representation
+    pub(crate) fn decode(buf: &[u8]) -> RdbResult<Self> {
+        let mut reader = Reader { buf };
+
+        let header = reader.take(MAGIC.len() + VERSION.len())?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(RdbError::InvalidHeader);
+        }
+
+        let mut snapshot = Self::default();
+        let mut db = 0;
+        let mut expiry = None;
+
+        loop {
+            match reader.byte()? {
+                OPCODE_EOF => break,
+                OPCODE_AUX => {
+                    reader.string()?;
+                    reader.string()?;
+                }
+                OPCODE_FUNCTION2 => snapshot.functions.push(reader.string()?),
+                OPCODE_RESIZEDB => {
+                    reader.length()?;
+                    reader.length()?;
+                }
+                OPCODE_SELECTDB => {
+                    db = reader.length()? as usize;
+                }
+                OPCODE_EXPIRETIME_MS => {
+                    let millis = u64::from_le_bytes(reader.array()?);
+                    expiry = DateTime::from_timestamp_millis(millis as i64);
+                }
+                OPCODE_EXPIRETIME => {
+                    let secs = u32::from_le_bytes(reader.array()?);
+                    expiry = DateTime::from_timestamp(secs as i64, 0);
+                }
+                TYPE_STRING => {
+                    let key = reader.string()?;
+                    let value = reader.string()?;
+                    snapshot.entries.push(Entry {
+                        db,
+                        key,
+                        value,
+                        expiry: expiry.take(),
+                    });
+                }
+                ty => return Err(RdbError::UnsupportedType(ty)),
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Encode the payload of `FUNCTION DUMP` for the function `libraries`: their RDB records followed
+/// by the RDB version and a checksum, like the payload of `DUMP`
+pub(crate) fn dump_functions(libraries: &[String]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_functions(&mut buf, libraries);
+
+    let version: u16 = std::str::from_utf8(VERSION)
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .expect("RDB version should be a number");
+    buf.extend_from_slice(&version.to_le_bytes());
+    // A zero checksum tells the loader that checksum verification is disabled
+    buf.extend_from_slice(&[0u8; 8]);
+    buf
+}
+
+/// Decode the function libraries of a `FUNCTION DUMP` payload
+pub(crate) fn restore_functions(payload: &[u8]) -> RdbResult<Vec<String>> {
+    // Records are followed by a 2 bytes version and a 8 bytes checksum
+    let Some(len) = payload.len().checked_sub(10) else {
+        return Err(RdbError::InvalidPayload);
+    };
+
+    let mut reader = Reader {
+        buf: &payload[..len],
+    };
+    let mut libraries = Vec::new();
+
+    while !reader.buf.is_empty() {
+        match reader.byte()? {
+            OPCODE_FUNCTION2 => libraries.push(reader.string()?),
+            _ => return Err(RdbError::InvalidPayload),
+        }
+    }
+
+    Ok(libraries)
+}
+
+fn encode_functions(buf: &mut Vec<u8>, libraries: &[String]) {
+    for code in libraries {
+        buf.push(OPCODE_FUNCTION2);
+        encode_string(buf, code);
+    }
+}
+
+fn encode_length(buf: &mut Vec<u8>, len: u64) {
+    if len < (1 << 6) {
+        buf.push(len as u8);
+    } else if len < (1 << 14) {
+        buf.push(0x40 | (len >> 8) as u8);
+        buf.push(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.push(0x80);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        buf.push(0x81);
+        buf.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn encode_string(buf: &mut Vec<u8>, s: &str) {
+    encode_length(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// A length prefix, which can either be a plain length or a special string encoding
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> RdbResult<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(RdbError::UnexpectedEof);
+        }
+
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> RdbResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> RdbResult<[u8; N]> {
+        Ok(self
+            .take(N)?
+            .try_into()
+            .expect("taking N bytes should be convertible to an array of N elements"))
+    }
+
+    fn encoded_length(&mut self) -> RdbResult<Length> {
+        let first = self.byte()?;
+        Ok(match first >> 6 {
+            0b00 => Length::Len((first & 0x3F) as u64),
+            0b01 => Length::Len((((first & 0x3F) as u64) << 8) | self.byte()? as u64),
+            0b10 if first == 0x80 => Length::Len(u32::from_be_bytes(self.array()?) as u64),
+            0b10 if first == 0x81 => Length::Len(u64::from_be_bytes(self.array()?)),
+            0b11 => Length::Encoded(first & 0x3F),
+            _ => return Err(RdbError::UnsupportedEncoding(first)),
+        })
+    }
+
+    fn length(&mut self) -> RdbResult<u64> {
+        match self.encoded_length()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(enc) => Err(RdbError::UnsupportedEncoding(enc)),
+        }
+    }
+
+    fn string(&mut self) -> RdbResult<String> {
+        match self.encoded_length()? {
+            Length::Len(len) => {
+                let bytes = self.take(len as usize)?;
+                String::from_utf8(bytes.to_vec()).map_err(|_| RdbError::InvalidString)
+            }
+            Length::Encoded(ENC_INT8) => Ok((self.byte()? as i8).to_string()),
+            Length::Encoded(ENC_INT16) => Ok(i16::from_le_bytes(self.array()?).to_string()),
+            Length::Encoded(ENC_INT32) => Ok(i32::from_le_bytes(self.array()?).to_string()),
+            Length::Encoded(enc) => Err(RdbError::UnsupportedEncoding(enc)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("valid hex"))
+            .collect()
+    }
+
+    #[test]
+    fn should_decode_empty_rdb() {
+        let rdb = hex("524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2");
+        let snapshot = Snapshot::decode(&rdb).expect("decode rdb");
+        assert!(snapshot.entries.is_empty());
+    }
+
+    #[test]
+    fn should_roundtrip() {
+        let snapshot = Snapshot {
+            entries: vec![
+                Entry {
+                    db: 0,
+                    key: "foo".to_owned(),
+                    value: "bar".to_owned(),
+                    expiry: None,
+                },
+                Entry {
+                    db: 3,
+                    key: "k".repeat(100),
+                    value: "v".repeat(20000),
+                    expiry: DateTime::from_timestamp_millis(1_700_000_000_123),
+                },
+            ],
+            functions: vec![
+                "#!lua name=lib\nredis.register_function('f', function() end)".to_owned(),
+            ],
+        };
+
+        let decoded = Snapshot::decode(&snapshot.encode()).expect("decode rdb");
+        assert_eq!(decoded, snapshot);
+
+        let payload = dump_functions(&snapshot.functions);
+        assert_eq!(restore_functions(&payload).unwrap(), snapshot.functions);
+        assert!(restore_functions(&payload[..5]).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Circular replication backlog used to serve partial resynchronizations
+
+/// A fixed-size circular buffer holding the most recent bytes of the replication stream.
+///
+/// Offsets follow the replication offset semantics: the first byte ever written to the stream has
+/// offset 1 and the backlog always ends at the current replication offset.
+pub(crate) struct Backlog {
+    buf: Box<[u8]>,
+
+    /// Position in `buf` where the next byte will be written
+    idx: usize,
+
+    /// Number of valid bytes currently held by the backlog
+    histlen: usize,
+
+    /// Replication offset of the last byte written to the backlog
+    end: u64,
+}
+
+impl Backlog {
+    /// Create a new empty backlog of `size` bytes, that will start receiving data right after the
+    /// replication `offset`
+    pub(crate) fn new(size: usize, offset: u64) -> Self {
+        Self {
+            buf: vec![0u8; size.max(1)].into_boxed_slice(),
+            idx: 0,
+            histlen: 0,
+            end: offset,
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn histlen(&self) -> usize {
+        self.histlen
+    }
+
+    /// Replication offset of the first byte held by the backlog
+    pub(crate) fn first_offset(&self) -> u64 {
+        self.end + 1 - self.histlen as u64
+    }
+
+    /// Append `data` to the backlog, evicting the oldest bytes if needed
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        let size = self.buf.len();
+        self.end += data.len() as u64;
+
+        // Only the last `size` bytes can fit in the backlog
+        let data = &data[data.len().saturating_sub(size)..];
+
+        let first = data.len().min(size - self.idx);
+        self.buf[self.idx..self.idx + first].copy_from_slice(&data[..first]);
+        self.buf[..data.len() - first].copy_from_slice(&data[first..]);
+
+        self.idx = (self.idx + data.len()) % size;
+        self.histlen = (self.histlen + data.len()).min(size);
+    }
+
+    /// Retrieve every byte of the stream starting at replication `offset`, up to the end of the
+    /// backlog. Returns `None` if `offset` is not covered by the backlog anymore
+    pub(crate) fn range(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.first_offset() || offset > self.end + 1 {
+            return None;
+        }
+
+        let size = self.buf.len();
+        let skip = (offset - self.first_offset()) as usize;
+        let len = self.histlen - skip;
+        let start = (self.idx + size - self.histlen + skip) % size;
+
+        let mut data = Vec::with_capacity(len);
+        let first = len.min(size - start);
+        data.extend_from_slice(&self.buf[start..start + first]);
+        data.extend_from_slice(&self.buf[..len - first]);
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_serve_range() {
+        let mut backlog = Backlog::new(16, 0);
+        backlog.feed(b"hello");
+        backlog.feed(b"world");
+
+        assert_eq!(backlog.first_offset(), 1);
+        assert_eq!(backlog.range(1).as_deref(), Some(&b"helloworld"[..]));
+        assert_eq!(backlog.range(6).as_deref(), Some(&b"world"[..]));
+        assert_eq!(backlog.range(11).as_deref(), Some(&b""[..]));
+        assert_eq!(backlog.range(12), None);
+    }
+
+    #[test]
+    fn should_wrap_around() {
+        let mut backlog = Backlog::new(8, 100);
+        backlog.feed(b"abcdef");
+        backlog.feed(b"ghijk");
+
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.first_offset(), 104);
+        assert_eq!(backlog.range(103), None);
+        assert_eq!(backlog.range(104).as_deref(), Some(&b"defghijk"[..]));
+        assert_eq!(backlog.range(109).as_deref(), Some(&b"ijk"[..]));
+    }
+
+    #[test]
+    fn should_keep_tail_of_large_writes() {
+        let mut backlog = Backlog::new(4, 0);
+        backlog.feed(b"0123456789");
+
+        assert_eq!(backlog.first_offset(), 7);
+        assert_eq!(backlog.range(7).as_deref(), Some(&b"6789"[..]));
+    }
+}
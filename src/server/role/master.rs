@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    future,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    resp,
+    server::{
+        cmd::ReplconfOption, rdb::Snapshot, ClientAddr, ClientHandle, ClientId, MemoraResult,
+        Request, Response,
+    },
+};
+
+use super::{Ack, Replication, ReplicationId, Role};
+
+/// Interval at which masters ping their replicas so that they can detect a broken link
+const REPL_PING_PERIOD: Duration = Duration::from_secs(10);
+
+/// Configuration announced by a replica with `REPLCONF`
+#[derive(Debug, Default)]
+struct ReplicaConf {
+    listening_port: Option<u16>,
+
+    /// Whether the replica understands the replication id sent with `+CONTINUE`
+    psync2: bool,
+}
+
+/// A replica attached to this master
+struct ReplicaLink {
+    client: ClientHandle,
+    conf: ReplicaConf,
+
+    /// Replication offset acknowledged by the replica
+    ack_offset: u64,
+
+    /// Replication offset the replica acknowledged as fsynced to its AOF
+    fsync_offset: u64,
+
+    last_ack: Instant,
+}
+
+pub struct Master {
+    repl: Replication,
+
+    /// Configuration of clients that did not issue a `PSYNC` yet
+    handshakes: HashMap<ClientId, ReplicaConf>,
+
+    replicas: Vec<ReplicaLink>,
+
+    last_ping: Instant,
+}
+
+impl Master {
+    pub fn new(backlog_size: usize) -> Self {
+        Self::with_replication(Replication::new(backlog_size))
+    }
+
+    fn with_replication(repl: Replication) -> Self {
+        Self {
+            repl,
+            handshakes: HashMap::new(),
+            replicas: Vec::new(),
+            last_ping: Instant::now(),
+        }
+    }
+
+    /// Promote a replica that followed the `repl` replication history to a master.
+    /// The history gets a new replication id, while replicas of our previous master can still
+    /// partially resynchronize with us
+    pub(super) fn promoted(mut repl: Replication) -> Self {
+        repl.switch_id(ReplicationId::random());
+        repl.create_backlog();
+        Self::with_replication(repl)
+    }
+
+    /// Stop being a master, disconnecting our replicas and handing back our replication history
+    pub(super) fn into_replication(self) -> Replication {
+        for replica in &self.replicas {
+            replica.client.kill();
+        }
+
+        self.repl
+    }
+
+    fn replica_mut(&mut self, client: &ClientHandle) -> Option<&mut ReplicaLink> {
+        self.replicas
+            .iter_mut()
+            .find(|replica| replica.client.id() == client.id())
+    }
+
+    /// Attach `client` as a replica, streaming `payload` first
+    fn attach(&mut self, client: ClientHandle, conf: ReplicaConf, payload: Bytes) {
+        if !client.push(payload) {
+            warn!("replica {} went away during synchronization", client.addr());
+            return;
+        }
+
+        self.replicas.retain(|r| r.client.id() != client.id());
+        self.replicas.push(ReplicaLink {
+            client,
+            conf,
+            ack_offset: 0,
+            fsync_offset: 0,
+            last_ack: Instant::now(),
+        });
+    }
+}
+
+impl Role for Master {
+    type StartFuture = future::Ready<MemoraResult<()>>;
+
+    fn info(&self) -> Vec<String> {
+        let fields = [
+            ("role", "master".to_owned()),
+            ("connected_slaves", self.replicas.len().to_string()),
+        ];
+
+        let replicas = self.replicas.iter().enumerate().map(|(idx, replica)| {
+            let (ip, port) = match replica.client.addr() {
+                ClientAddr::Tcp(addr) => (addr.ip().to_string(), addr.port()),
+                // Replicas connected over a unix socket run on the same host
+                ClientAddr::Unix(_) => ("127.0.0.1".to_owned(), 0),
+            };
+            let port = replica.conf.listening_port.unwrap_or(port);
+            format!(
+                "slave{idx}:ip={ip},port={port},state=online,offset={},lag={}",
+                replica.ack_offset,
+                replica.last_ack.elapsed().as_secs()
+            )
+        });
+
+        let mut info = fields
+            .into_iter()
+            .map(|(key, value)| format!("{key}:{value}"))
+            .chain(replicas)
+            .collect::<Vec<_>>();
+
+        info.extend(
+            self.repl
+                .info()
+                .into_iter()
+                .map(|(key, value)| format!("{key}:{value}")),
+        );
+        info
+    }
+
+    fn stats(&self) -> Vec<(&'static str, String)> {
+        self.repl.stats()
+    }
+
+    fn backlog_memory(&self) -> usize {
+        self.repl.backlog_memory()
+    }
+
+    fn reset_stats(&mut self) {
+        self.repl.reset_stats();
+    }
+
+    fn start(&mut self, _reqs: mpsc::Sender<Request>) -> Self::StartFuture {
+        future::ready(Ok(()))
+    }
+
+    fn propagate(&mut self, cmd: &resp::Value) {
+        // No replica ever attached to us, so nobody is interested in the replication stream
+        if self.repl.backlog.is_none() {
+            return;
+        }
+
+        let mut data = Vec::new();
+        cmd.encode(&mut data)
+            .expect("encoding to an in-memory buffer should not fail");
+
+        self.repl.feed(&data);
+
+        let data = Bytes::from(data);
+        self.replicas.retain(|replica| {
+            let alive = replica.client.push(data.clone());
+            if !alive {
+                warn!(
+                    "dropping replica {} that can not keep up with the replication stream",
+                    replica.client.addr()
+                );
+            }
+            alive
+        });
+    }
+
+    fn replconf(&mut self, client: &ClientHandle, options: Vec<ReplconfOption>) -> Response {
+        let mut reply = true;
+
+        for option in options {
+            match option {
+                ReplconfOption::ListeningPort(port) => {
+                    self.handshakes
+                        .entry(client.id())
+                        .or_default()
+                        .listening_port = Some(port)
+                }
+                ReplconfOption::Capa(capa) => {
+                    self.handshakes.entry(client.id()).or_default().psync2 |=
+                        capa.eq_ignore_ascii_case("psync2")
+                }
+                ReplconfOption::Ack(offset) => {
+                    // Acknowledgements are never replied to
+                    reply = false;
+                    if let Some(replica) = self.replica_mut(client) {
+                        replica.ack_offset = replica.ack_offset.max(offset);
+                        replica.last_ack = Instant::now();
+                    }
+                }
+                ReplconfOption::Fack(offset) => {
+                    reply = false;
+                    if let Some(replica) = self.replica_mut(client) {
+                        replica.fsync_offset = replica.fsync_offset.max(offset);
+                    }
+                }
+                ReplconfOption::GetAck => reply = false,
+            }
+        }
+
+        if reply {
+            Response::ok()
+        } else {
+            Response::none()
+        }
+    }
+
+    fn psync<F>(
+        &mut self,
+        client: ClientHandle,
+        replid: &str,
+        offset: i64,
+        snapshot: F,
+    ) -> MemoraResult<Response>
+    where
+        F: FnOnce() -> Snapshot,
+    {
+        self.repl.create_backlog();
+        let conf = self.handshakes.remove(&client.id()).unwrap_or_default();
+
+        if let Some(missing) = self.repl.partial(replid, offset) {
+            self.repl.syncs.partial_ok += 1;
+
+            info!(
+                "partial resynchronization accepted for replica {}, sending {} bytes of backlog",
+                client.addr(),
+                missing.len()
+            );
+
+            let reply = if conf.psync2 {
+                format!("CONTINUE {}", self.repl.id)
+            } else {
+                "CONTINUE".to_owned()
+            };
+
+            self.attach(client, conf, Bytes::from(missing));
+            return Ok(resp::Value::simple(reply).into());
+        }
+
+        info!(
+            "full resynchronization requested by replica {} (replid {replid}, offset {offset})",
+            client.addr()
+        );
+
+        if replid != "?" {
+            self.repl.syncs.partial_err += 1;
+        }
+        self.repl.syncs.full += 1;
+
+        let rdb = snapshot().encode();
+        let mut payload = format!("${}\r\n", rdb.len()).into_bytes();
+        payload.extend_from_slice(&rdb);
+
+        self.attach(client, conf, Bytes::from(payload));
+        Ok(resp::Value::simple(format!("FULLRESYNC {} {}", self.repl.id, self.repl.offset)).into())
+    }
+
+    fn cron(&mut self) {
+        if self.replicas.is_empty() || self.last_ping.elapsed() < REPL_PING_PERIOD {
+            return;
+        }
+
+        self.last_ping = Instant::now();
+        self.propagate(&resp::Value::from_iter([resp::Value::bulk("PING")]));
+    }
+
+    fn wait_offset(&self) -> MemoraResult<u64> {
+        Ok(self.repl.offset)
+    }
+
+    fn acked(&self, offset: u64, ack: Ack) -> usize {
+        self.replicas
+            .iter()
+            .filter(|replica| match ack {
+                Ack::Offset => replica.ack_offset >= offset,
+                Ack::Fsync => replica.fsync_offset >= offset,
+            })
+            .count()
+    }
+
+    fn request_acks(&mut self) {
+        let getack = resp::Value::from_iter([
+            resp::Value::bulk("REPLCONF"),
+            resp::Value::bulk("GETACK"),
+            resp::Value::bulk("*"),
+        ]);
+        self.propagate(&getack);
+    }
+}
@@ -0,0 +1,517 @@
+//! Replication roles of a memora instance
+
+use std::{fmt, str::FromStr};
+
+use futures::Future;
+use rand::Rng;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsConnector;
+use tracing::info;
+
+use crate::resp;
+
+use super::{cmd::ReplconfOption, rdb::Snapshot, ClientHandle, MemoraResult, Request, Response};
+
+mod backlog;
+use backlog::Backlog;
+
+mod master;
+pub use master::Master;
+
+mod replica;
+pub use replica::{HandshakeError, Replica};
+
+#[derive(Debug, Error)]
+pub enum ReplicaError {
+    #[error("error handshaking with master node: {0}")]
+    Handshare(#[from] HandshakeError),
+
+    #[error("PSYNC is not supported by replicas")]
+    PsyncUnsupported,
+
+    #[error("WAIT cannot be used with replica instances")]
+    WaitUnsupported,
+
+    #[error("You can't write against a read only replica.")]
+    ReadOnly,
+
+    #[error("Link with MASTER is down and replica-serve-stale-data is set to 'no'.")]
+    MasterDown,
+}
+
+/// Kind of acknowledgement sent by replicas
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Ack {
+    /// The replica processed the replication stream up to an offset
+    Offset,
+
+    /// The replica fsynced the replication stream up to an offset to its AOF
+    Fsync,
+}
+
+pub trait Role {
+    type StartFuture: Future<Output = MemoraResult<()>>;
+
+    fn info(&self) -> Vec<String>;
+
+    /// Replication statistics reported in the `stats` section of `INFO`
+    fn stats(&self) -> Vec<(&'static str, String)>;
+
+    /// Number of bytes allocated for the replication backlog
+    fn backlog_memory(&self) -> usize;
+
+    /// Reset the replication statistics, as done by `CONFIG RESETSTAT`
+    fn reset_stats(&mut self);
+
+    fn start(&mut self, reqs: mpsc::Sender<Request>) -> Self::StartFuture;
+
+    /// Feed a write command to the replication stream
+    fn propagate(&mut self, cmd: &resp::Value);
+
+    /// Handle a `REPLCONF` command issued by `client`
+    fn replconf(&mut self, client: &ClientHandle, options: Vec<ReplconfOption>) -> Response;
+
+    /// Handle a `PSYNC` command issued by `client`.
+    /// `snapshot` is only called when the replica needs a full resynchronization
+    fn psync<F>(
+        &mut self,
+        client: ClientHandle,
+        replid: &str,
+        offset: i64,
+        snapshot: F,
+    ) -> MemoraResult<Response>
+    where
+        F: FnOnce() -> Snapshot;
+
+    /// Replication offset that replicas must acknowledge for a `WAIT` issued now to be satisfied
+    fn wait_offset(&self) -> MemoraResult<u64>;
+
+    /// Number of replicas that sent an `ack` acknowledgement for at least `offset`
+    fn acked(&self, offset: u64, ack: Ack) -> usize;
+
+    /// Ask every replica to acknowledge its replication offset as soon as possible
+    fn request_acks(&mut self);
+
+    /// Periodic housekeeping, called every second
+    fn cron(&mut self) {}
+}
+
+/// The role of an instance, which can be switched at runtime with `REPLICAOF`
+pub enum AnyRole {
+    Master(Box<Master>),
+    Replica(Replica),
+}
+
+impl AnyRole {
+    /// Turn this instance into a replica of the master at `target`, or into a master if `target`
+    /// is `None`. Our replication history is kept so that we can partially resynchronize with our
+    /// new master, and our replicas with us
+    pub(super) fn replica_of(
+        &mut self,
+        target: Option<(String, u16)>,
+        listening_port: u16,
+        reqs: &mpsc::Sender<Request>,
+        tls: Option<TlsConnector>,
+    ) -> Response {
+        match (&*self, &target) {
+            (Self::Master(_), None) => return Response::ok(),
+            (Self::Replica(replica), Some(addr)) if replica.master_addr() == addr => {
+                return resp::Value::simple("OK Already connected to specified master").into()
+            }
+            _ => {}
+        }
+
+        // The placeholder is never observed: nothing below can fail before it gets replaced
+        let repl = match std::mem::replace(self, Self::Master(Box::new(Master::new(0)))) {
+            Self::Master(master) => master.into_replication(),
+            Self::Replica(replica) => replica.stop(),
+        };
+
+        *self = match target {
+            Some((host, port)) => {
+                info!("connecting to master {host}:{port}");
+
+                let mut repl = repl;
+                repl.create_backlog();
+
+                let mut replica = Replica::following(listening_port, (host, port), repl, tls);
+                replica.follow(reqs.clone());
+                Self::Replica(replica)
+            }
+            None => {
+                info!("master mode enabled");
+                Self::Master(Box::new(Master::promoted(repl)))
+            }
+        };
+
+        Response::ok()
+    }
+}
+
+impl From<Master> for AnyRole {
+    fn from(master: Master) -> Self {
+        Self::Master(Box::new(master))
+    }
+}
+
+impl From<Replica> for AnyRole {
+    fn from(replica: Replica) -> Self {
+        Self::Replica(replica)
+    }
+}
+
+macro_rules! delegate {
+    ($self:expr, $role:ident => $e:expr) => {
+        match $self {
+            AnyRole::Master($role) => $e,
+            AnyRole::Replica($role) => $e,
+        }
+    };
+}
+
+impl Role for AnyRole {
+    type StartFuture = std::future::Ready<MemoraResult<()>>;
+
+    fn info(&self) -> Vec<String> {
+        delegate!(self, role => role.info())
+    }
+
+    fn stats(&self) -> Vec<(&'static str, String)> {
+        delegate!(self, role => role.stats())
+    }
+
+    fn backlog_memory(&self) -> usize {
+        delegate!(self, role => role.backlog_memory())
+    }
+
+    fn reset_stats(&mut self) {
+        delegate!(self, role => role.reset_stats())
+    }
+
+    fn start(&mut self, reqs: mpsc::Sender<Request>) -> Self::StartFuture {
+        delegate!(self, role => role.start(reqs))
+    }
+
+    fn propagate(&mut self, cmd: &resp::Value) {
+        delegate!(self, role => role.propagate(cmd))
+    }
+
+    fn replconf(&mut self, client: &ClientHandle, options: Vec<ReplconfOption>) -> Response {
+        delegate!(self, role => role.replconf(client, options))
+    }
+
+    fn psync<F>(
+        &mut self,
+        client: ClientHandle,
+        replid: &str,
+        offset: i64,
+        snapshot: F,
+    ) -> MemoraResult<Response>
+    where
+        F: FnOnce() -> Snapshot,
+    {
+        delegate!(self, role => role.psync(client, replid, offset, snapshot))
+    }
+
+    fn wait_offset(&self) -> MemoraResult<u64> {
+        delegate!(self, role => role.wait_offset())
+    }
+
+    fn acked(&self, offset: u64, ack: Ack) -> usize {
+        delegate!(self, role => role.acked(offset, ack))
+    }
+
+    fn request_acks(&mut self) {
+        delegate!(self, role => role.request_acks())
+    }
+
+    fn cron(&mut self) {
+        delegate!(self, role => role.cron())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ReplicationId([u8; 40]);
+
+impl fmt::Display for ReplicationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // SAFETY: this is safe because a ReplicationId can only be built from valid alphanumeric characters
+        let str = unsafe { std::str::from_utf8_unchecked(&self.0) };
+        f.write_str(str)
+    }
+}
+
+impl ReplicationId {
+    fn random() -> Self {
+        let chars = rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(40)
+            .collect::<Vec<_>>();
+
+        Self(chars.try_into().expect(
+            "taking 40 values from an iterator should be convertible to an array of 40 elements",
+        ))
+    }
+}
+
+impl FromStr for ReplicationId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(());
+        }
+
+        Ok(Self(s.as_bytes().try_into().map_err(|_| ())?))
+    }
+}
+
+/// Number of resynchronizations served to replicas
+#[derive(Debug, Default, Clone, Copy)]
+struct SyncStats {
+    full: u64,
+    partial_ok: u64,
+    partial_err: u64,
+}
+
+/// Replication state shared by masters and replicas.
+///
+/// Replicas keep track of the replication stream of their master, so that they can both
+/// partially resynchronize with it and serve partial resynchronizations once promoted.
+struct Replication {
+    id: ReplicationId,
+
+    /// Replication id of our previous master, along with the first offset that we did not
+    /// receive from it
+    id2: Option<(ReplicationId, u64)>,
+
+    /// Replication offset of the last byte of the replication stream
+    offset: u64,
+
+    backlog_size: usize,
+    backlog: Option<Backlog>,
+
+    syncs: SyncStats,
+}
+
+impl Replication {
+    fn new(backlog_size: usize) -> Self {
+        Self {
+            id: ReplicationId::random(),
+            id2: None,
+            offset: 0,
+            backlog_size,
+            backlog: None,
+            syncs: SyncStats::default(),
+        }
+    }
+
+    /// Append `data` to the replication stream
+    fn feed(&mut self, data: &[u8]) {
+        self.offset += data.len() as u64;
+        if let Some(backlog) = self.backlog.as_mut() {
+            backlog.feed(data);
+        }
+    }
+
+    /// Create the replication backlog if it does not exist yet
+    fn create_backlog(&mut self) {
+        if self.backlog.is_none() {
+            self.backlog = Some(Backlog::new(self.backlog_size, self.offset));
+        }
+    }
+
+    /// Drop the replication history and start a new one at `offset` under the `id` replication id
+    fn reset(&mut self, id: ReplicationId, offset: u64) {
+        self.id = id;
+        self.id2 = None;
+        self.offset = offset;
+        self.backlog = Some(Backlog::new(self.backlog_size, offset));
+    }
+
+    /// Switch to a new replication id, remembering the current one as our secondary id so that
+    /// replicas of the same history can still partially resynchronize with us
+    fn switch_id(&mut self, id: ReplicationId) {
+        let old = std::mem::replace(&mut self.id, id);
+        self.id2 = Some((old, self.offset + 1));
+    }
+
+    /// Attempt to serve a partial resynchronization for a replica that was following the `replid`
+    /// history up to `offset`.
+    /// Returns the part of the stream the replica missed or `None` if a full resynchronization is needed
+    fn partial(&self, replid: &str, offset: i64) -> Option<Vec<u8>> {
+        let offset = u64::try_from(offset).ok()?;
+
+        let known = replid == self.id.to_string()
+            || self
+                .id2
+                .as_ref()
+                .is_some_and(|(id2, until)| replid == id2.to_string() && offset <= *until);
+
+        if !known {
+            return None;
+        }
+
+        self.backlog.as_ref()?.range(offset)
+    }
+
+    fn backlog_memory(&self) -> usize {
+        self.backlog.as_ref().map_or(0, Backlog::size)
+    }
+
+    fn reset_stats(&mut self) {
+        self.syncs = SyncStats::default();
+    }
+
+    fn stats(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("sync_full", self.syncs.full.to_string()),
+            ("sync_partial_ok", self.syncs.partial_ok.to_string()),
+            ("sync_partial_err", self.syncs.partial_err.to_string()),
+        ]
+    }
+
+    fn info(&self) -> Vec<(&'static str, String)> {
+        let (id2, second_offset) = match &self.id2 {
+            Some((id, offset)) => (id.to_string(), *offset as i64),
+            None => ("0".repeat(40), -1),
+        };
+
+        let mut fields = vec![
+            ("master_replid", self.id.to_string()),
+            ("master_replid2", id2),
+            ("master_repl_offset", self.offset.to_string()),
+            ("second_repl_offset", second_offset.to_string()),
+        ];
+
+        match &self.backlog {
+            Some(backlog) => fields.extend([
+                ("repl_backlog_active", "1".to_owned()),
+                ("repl_backlog_size", backlog.size().to_string()),
+                (
+                    "repl_backlog_first_byte_offset",
+                    backlog.first_offset().to_string(),
+                ),
+                ("repl_backlog_histlen", backlog.histlen().to_string()),
+            ]),
+            None => fields.extend([
+                ("repl_backlog_active", "0".to_owned()),
+                ("repl_backlog_size", self.backlog_size.to_string()),
+                ("repl_backlog_first_byte_offset", "0".to_owned()),
+                ("repl_backlog_histlen", "0".to_owned()),
+            ]),
+        }
+
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    /// Value of the `name` field of the replication section of `INFO` for `role`
+    fn field(role: &AnyRole, name: &str) -> String {
+        role.info()
+            .into_iter()
+            .find_map(|line| Some(line.strip_prefix(name)?.strip_prefix(':')?.to_owned()))
+            .unwrap_or_else(|| panic!("missing field {name}"))
+    }
+
+    fn replica_client() -> (ClientHandle, mpsc::Receiver<bytes::Bytes>) {
+        ClientHandle::new("127.0.0.1:6001".parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn should_continue_from_known_history() {
+        let mut repl = Replication::new(64);
+        repl.create_backlog();
+        repl.feed(b"first");
+
+        let id = repl.id.to_string();
+        assert_eq!(repl.partial(&id, 1).as_deref(), Some(&b"first"[..]));
+        assert_eq!(repl.partial(&id, 6).as_deref(), Some(&b""[..]));
+        assert_eq!(repl.partial(&id, 7), None);
+        assert_eq!(repl.partial("?", -1), None);
+        assert_eq!(repl.partial(&ReplicationId::random().to_string(), 1), None);
+    }
+
+    #[test]
+    fn should_continue_after_failover() {
+        let mut repl = Replication::new(64);
+        repl.create_backlog();
+        repl.feed(b"before");
+
+        let old = repl.id.to_string();
+        repl.switch_id(ReplicationId::random());
+        repl.feed(b"after");
+
+        // A replica of the previous master can continue as long as it did not go past the switch
+        assert_eq!(repl.partial(&old, 7).as_deref(), Some(&b"after"[..]));
+        assert_eq!(repl.partial(&old, 8), None);
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_replication_history_across_role_switches() {
+        let (reqs, _reqs_rx) = mpsc::channel(1);
+        let mut role = AnyRole::from(Master::new(64));
+
+        let (client, _stream) = replica_client();
+        role.psync(client, "?", -1, Snapshot::default).unwrap();
+        role.propagate(&resp::Value::from_iter([resp::Value::bulk("PING")]));
+        let (id, offset) = (
+            field(&role, "master_replid"),
+            field(&role, "master_repl_offset"),
+        );
+        assert_eq!(offset, "14");
+
+        // Nothing changes for a master asked to become a master
+        role.replica_of(None, 6380, &reqs, None);
+        assert_eq!(field(&role, "master_replid"), id);
+
+        let master = ("127.0.0.1".to_owned(), 1);
+        role.replica_of(Some(master.clone()), 6380, &reqs, None);
+        assert!(matches!(role, AnyRole::Replica(_)));
+        assert_eq!(field(&role, "master_replid"), id);
+        assert_eq!(field(&role, "slave_repl_offset"), offset);
+        assert_eq!(
+            resp::Value::from(role.replica_of(Some(master), 6380, &reqs, None)),
+            resp::Value::simple("OK Already connected to specified master")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_switch_replication_id_once_promoted() {
+        let (reqs, _reqs_rx) = mpsc::channel(1);
+        let mut repl = Replication::new(64);
+        repl.create_backlog();
+        repl.feed(b"replicated");
+        let id = repl.id.to_string();
+
+        let mut role = AnyRole::from(Replica::following(
+            6380,
+            ("127.0.0.1".to_owned(), 1),
+            repl,
+            None,
+        ));
+        assert_eq!(field(&role, "master_replid2"), "0".repeat(40));
+        assert_eq!(field(&role, "second_repl_offset"), "-1");
+
+        role.replica_of(None, 6380, &reqs, None);
+        assert!(matches!(role, AnyRole::Master(_)));
+        assert_ne!(field(&role, "master_replid"), id);
+        assert_eq!(field(&role, "master_replid2"), id);
+        assert_eq!(field(&role, "master_repl_offset"), "10");
+        assert_eq!(field(&role, "second_repl_offset"), "11");
+
+        // Replicas of our previous master continue from where they stopped
+        let (client, _stream) = replica_client();
+        let reply = role.psync(client, &id, 11, Snapshot::default).unwrap();
+        let reply = resp::Value::from(reply);
+        assert!(reply.as_str().unwrap().starts_with("CONTINUE"));
+        assert!(role.stats().contains(&("sync_partial_ok", "1".to_owned())));
+    }
+}
@@ -0,0 +1,684 @@
+use std::{
+    future, io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use bytes::Buf;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use rustls::pki_types::ServerName;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    net::TcpStream,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_rustls::TlsConnector;
+use tokio_util::codec::{Decoder, Framed};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    resp::{self, RespError, RespResult},
+    server::{
+        cmd::{self, Command, ReplconfOption},
+        framer::RespFramer,
+        rdb::{RdbError, Snapshot},
+        ClientHandle, MemoraError, MemoraResult, Request, Response,
+    },
+};
+
+use super::{Ack, ReplicaError, Replication, ReplicationId, Role};
+
+/// Interval at which replicas acknowledge the replication offset they processed
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after which the link with a silent master is considered broken
+const REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Bounds of the exponential backoff between two connection attempts to the master
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Resp(#[from] resp::RespError),
+
+    #[error(transparent)]
+    Rdb(#[from] RdbError),
+
+    #[error("connection has been closed prematurely")]
+    Closed,
+
+    #[error("invalid TLS server name '{0}'")]
+    InvalidServerName(String),
+
+    #[error("got an invalid response from master")]
+    InvalidResponse(resp::Value),
+}
+
+/// A connection to our master, either over plain TCP or over TLS
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> Connection for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+type MasterConnection = Framed<Box<dyn Connection>, RespFramer>;
+
+/// Outcome of the `PSYNC` step of the handshake
+#[derive(Debug)]
+enum Sync {
+    /// The master will send its whole dataset, followed by the replication stream starting right
+    /// after `offset`
+    Full { id: ReplicationId, offset: u64 },
+
+    /// The master will send the part of the replication stream we missed.
+    /// Masters supporting PSYNC2 also advertise their current replication id
+    Continue { id: Option<ReplicationId> },
+}
+
+impl TryFrom<resp::Value> for Sync {
+    type Error = HandshakeError;
+
+    fn try_from(value: resp::Value) -> Result<Self, Self::Error> {
+        let Some(line) = value.as_str() else {
+            return Err(HandshakeError::InvalidResponse(value));
+        };
+
+        let mut parts = line.split_ascii_whitespace();
+        let sync = match parts.next() {
+            Some(s) if s.eq_ignore_ascii_case("fullresync") => {
+                let id = parts.next().and_then(|id| id.parse().ok());
+                let offset = parts.next().and_then(|offset| offset.parse().ok());
+
+                id.zip(offset).map(|(id, offset)| Self::Full { id, offset })
+            }
+            Some(s) if s.eq_ignore_ascii_case("continue") => match parts.next() {
+                Some(id) => id.parse().ok().map(|id| Self::Continue { id: Some(id) }),
+                None => Some(Self::Continue { id: None }),
+            },
+            _ => None,
+        };
+
+        sync.ok_or(HandshakeError::InvalidResponse(value))
+    }
+}
+
+/// Status of the replication link with our master
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum LinkStatus {
+    /// Connecting and handshaking with the master
+    Connecting,
+
+    /// Receiving the dataset from the master
+    Sync,
+
+    /// Following the replication stream of the master
+    Up,
+
+    /// Not connected to the master
+    Down,
+}
+
+/// State of a replica, shared with the task supervising the link with the master
+struct State {
+    repl: Replication,
+    link: LinkStatus,
+
+    /// Last time we received something from the master
+    last_io: Option<Instant>,
+
+    /// Since when the link with the master is down
+    down_since: Instant,
+
+    /// Database selected by the replication stream, which a partial resynchronization resumes
+    db: usize,
+}
+
+pub struct Replica {
+    listening_port: u16,
+    addr: (String, u16),
+
+    /// Connector used to connect to our master over TLS, if enabled
+    tls: Option<TlsConnector>,
+
+    state: Arc<Mutex<State>>,
+    link: Option<JoinHandle<()>>,
+}
+
+impl Replica {
+    pub fn of(
+        listening_port: u16,
+        host: impl Into<String>,
+        port: impl Into<u16>,
+        backlog_size: usize,
+        tls: Option<TlsConnector>,
+    ) -> Self {
+        Self::following(
+            listening_port,
+            (host.into(), port.into()),
+            Replication::new(backlog_size),
+            tls,
+        )
+    }
+
+    /// Create a replica of the master at `addr`, continuing the `repl` replication history
+    pub(super) fn following(
+        listening_port: u16,
+        addr: (String, u16),
+        repl: Replication,
+        tls: Option<TlsConnector>,
+    ) -> Self {
+        let state = State {
+            repl,
+            link: LinkStatus::Down,
+            last_io: None,
+            down_since: Instant::now(),
+            db: 0,
+        };
+
+        Self {
+            listening_port,
+            addr,
+            tls,
+            state: Arc::new(Mutex::new(state)),
+            link: None,
+        }
+    }
+
+    /// Whether we are following the replication stream of our master
+    pub(crate) fn link_up(&self) -> bool {
+        lock(&self.state).link == LinkStatus::Up
+    }
+
+    pub(super) fn master_addr(&self) -> &(String, u16) {
+        &self.addr
+    }
+
+    /// Start the link with our master in the background. It keeps reconnecting until stopped, so
+    /// starting it can not fail
+    pub(super) fn follow(&mut self, reqs: mpsc::Sender<Request>) {
+        if let Some(link) = self.link.take() {
+            link.abort();
+        }
+
+        self.link = Some(tokio::spawn(supervise(
+            self.addr.clone(),
+            self.tls.clone(),
+            self.listening_port,
+            Arc::clone(&self.state),
+            reqs,
+        )));
+    }
+
+    /// Stop replicating from our master, handing back the replication history we followed
+    pub(super) fn stop(mut self) -> Replication {
+        if let Some(link) = self.link.take() {
+            link.abort();
+        }
+
+        let mut state = lock(&self.state);
+        let backlog_size = state.repl.backlog_size;
+        std::mem::replace(&mut state.repl, Replication::new(backlog_size))
+    }
+}
+
+impl Drop for Replica {
+    fn drop(&mut self) {
+        if let Some(link) = self.link.take() {
+            link.abort();
+        }
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .expect("replica state lock should not be poisoned")
+}
+
+async fn replconf<S, Args>(mut conn: S, args: Args) -> Result<(), HandshakeError>
+where
+    S: Sink<resp::Value, Error = RespError> + Stream<Item = RespResult<resp::Value>> + Unpin,
+    Args: IntoIterator<Item = resp::Value>,
+{
+    // Create the `REPLCONF` command
+    let replconf =
+        resp::Value::from_iter(std::iter::once(resp::Value::bulk("REPLCONF")).chain(args));
+
+    // Send it
+    conn.send(replconf).await?;
+
+    // Make sure we received an OK
+    let resp = conn.next().await.ok_or(HandshakeError::Closed)??;
+    let Some(ok) = resp.as_str() else {
+        return Err(HandshakeError::InvalidResponse(resp));
+    };
+
+    if !ok.eq_ignore_ascii_case("ok") {
+        return Err(HandshakeError::InvalidResponse(resp));
+    }
+
+    Ok(())
+}
+
+async fn handshake(
+    (host, master_port): &(String, u16),
+    tls: Option<&TlsConnector>,
+    port: u16,
+    (replid, offset): (String, i64),
+) -> Result<(MasterConnection, SocketAddr, Sync), HandshakeError> {
+    // Connect to the master
+    let conn = TcpStream::connect((host.as_str(), *master_port)).await?;
+    let peer = conn.peer_addr()?;
+
+    let conn: Box<dyn Connection> = match tls {
+        Some(connector) => {
+            let name = ServerName::try_from(host.clone())
+                .map_err(|_| HandshakeError::InvalidServerName(host.clone()))?;
+            Box::new(connector.connect(name, conn).await?)
+        }
+        None => Box::new(conn),
+    };
+
+    // Frame the connection
+    let mut conn = RespFramer::default().framed(conn);
+
+    info!("handshasking with master node...");
+
+    // Step 1. Send a PING to the master and wait for an answer
+    debug!("sending `PING` to master node...");
+    let ping = resp::Value::from_iter([resp::Value::bulk("PING")]);
+    conn.send(ping).await?;
+
+    // Attempt to read response from handshake
+    let _resp = conn.next().await.ok_or(HandshakeError::Closed)??;
+    // TODO(oktal): check that the response is a valid response from a PING
+
+    // Step 2. Send the first REPLCONF message to configure the port the replica is listening to
+    debug!("configuring listening-port with the master node...");
+    replconf(
+        &mut conn,
+        [resp::Value::bulk("listening-port"), resp::Value::bulk(port)],
+    )
+    .await?;
+
+    // Send the second REPLCONF to configure the capabilities of the replica
+    debug!("configuring replica capabilities with the master node...");
+    replconf(
+        &mut conn,
+        [resp::Value::bulk("capa"), resp::Value::bulk("psync2")],
+    )
+    .await?;
+
+    // Step 3. Send the PSYNC command to initiate the replication stream with the master
+    debug!("initiate replication stream with the master node from {replid} {offset}...");
+    let psync = resp::Value::from_iter([
+        resp::Value::bulk("PSYNC"),
+        resp::Value::bulk(replid),
+        resp::Value::bulk(offset),
+    ]);
+    conn.send(psync).await?;
+
+    let resp = conn.next().await.ok_or(HandshakeError::Closed)??;
+    debug!("received final handshake synchronization state {resp:?} from master");
+
+    let sync = Sync::try_from(resp)?;
+
+    // Handshake is done
+    info!("... done handshaking");
+    Ok((conn, peer, sync))
+}
+
+/// Read the RDB payload sent by the master after a full resynchronization.
+///
+/// The payload is sent as `$<len>\r\n<bytes>` without a trailing CRLF, so it can not be decoded
+/// as a regular RESP bulk string
+async fn read_snapshot(
+    conn: MasterConnection,
+) -> Result<(MasterConnection, Vec<u8>), HandshakeError> {
+    let mut parts = conn.into_parts();
+
+    loop {
+        let buf = &parts.read_buf;
+        if let Some(end) = buf.windows(2).position(|w| w == b"\r\n") {
+            let header = std::str::from_utf8(&buf[..end]).ok();
+            let len = header
+                .and_then(|h| h.strip_prefix('$'))
+                .and_then(|len| len.parse::<usize>().ok());
+
+            let Some(len) = len else {
+                let header = resp::Value::bulk(String::from_utf8_lossy(&buf[..end]));
+                return Err(HandshakeError::InvalidResponse(header));
+            };
+
+            if buf.len() >= end + 2 + len {
+                parts.read_buf.advance(end + 2);
+                let rdb = parts.read_buf.split_to(len).to_vec();
+                return Ok((Framed::from_parts(parts), rdb));
+            }
+        }
+
+        if parts.io.read_buf(&mut parts.read_buf).await? == 0 {
+            return Err(HandshakeError::Closed);
+        }
+    }
+}
+
+/// Keep a replication link with the master at `addr`, reconnecting with an exponential backoff
+/// whenever the link breaks
+async fn supervise(
+    addr: (String, u16),
+    tls: Option<TlsConnector>,
+    listening_port: u16,
+    state: Arc<Mutex<State>>,
+    reqs: mpsc::Sender<Request>,
+) {
+    let mut delay = RECONNECT_MIN_DELAY;
+
+    loop {
+        info!("connecting to {}:{} ...", addr.0, addr.1);
+
+        // Attempt a partial resynchronization if we already followed a replication stream
+        let psync = {
+            let mut state = lock(&state);
+            state.link = LinkStatus::Connecting;
+            match state.repl.backlog {
+                Some(_) => (state.repl.id.to_string(), state.repl.offset as i64 + 1),
+                None => ("?".to_owned(), -1),
+            }
+        };
+
+        match handshake(&addr, tls.as_ref(), listening_port, psync).await {
+            Ok((conn, peer, sync)) => {
+                delay = RECONNECT_MIN_DELAY;
+
+                if let Err(e) = link(conn, peer, sync, &state, &reqs).await {
+                    error!("replication link with master failed: {e}");
+                }
+            }
+            Err(e) => warn!(
+                "failed to connect to master {}:{}: {e}, retrying in {delay:?}",
+                addr.0, addr.1
+            ),
+        }
+
+        {
+            let mut state = lock(&state);
+            state.link = LinkStatus::Down;
+            state.down_since = Instant::now();
+        }
+
+        // The server is shutting down
+        if reqs.is_closed() {
+            return;
+        }
+
+        time::sleep(delay).await;
+        delay = next_delay(delay);
+    }
+}
+
+/// Delay before the connection attempt that follows a failed one, made after `delay`
+fn next_delay(delay: Duration) -> Duration {
+    (delay * 2).min(RECONNECT_MAX_DELAY)
+}
+
+/// Apply the replication stream received from the master
+async fn link(
+    mut conn: MasterConnection,
+    peer: SocketAddr,
+    sync: Sync,
+    state: &Mutex<State>,
+    reqs: &mpsc::Sender<Request>,
+) -> MemoraResult<()> {
+    let (master, _push) = ClientHandle::master(peer);
+    master.select(lock(state).db);
+
+    match sync {
+        Sync::Full { id, offset } => {
+            lock(state).link = LinkStatus::Sync;
+
+            let (framed, rdb) = read_snapshot(conn).await.map_err(ReplicaError::from)?;
+            conn = framed;
+
+            let snapshot = Snapshot::decode(&rdb)?;
+            info!(
+                "loading {} keys received from master",
+                snapshot.entries.len()
+            );
+
+            let (req, rx) = Request::load(master.clone(), snapshot);
+            if reqs.send(req).await.is_err() {
+                return Ok(());
+            }
+            let _ = rx.await;
+
+            // The stream of a full resynchronization selects its database first
+            let mut state = lock(state);
+            state.repl.reset(id, offset);
+            state.db = 0;
+            master.select(0);
+        }
+        Sync::Continue { id: Some(id) } => {
+            let repl = &mut lock(state).repl;
+            if repl.id != id {
+                info!("master replication id changed to {id}");
+                repl.switch_id(id);
+            }
+        }
+        Sync::Continue { id: None } => {}
+    }
+
+    {
+        let mut state = lock(state);
+        state.link = LinkStatus::Up;
+        state.last_io = Some(Instant::now());
+    }
+
+    let mut acks = time::interval(ACK_INTERVAL);
+    let mut last_io = Instant::now();
+
+    // Writes of a transaction propagated by our master, applied as a whole on `EXEC`
+    let mut multi: Option<Vec<(Command, cmd::Argv)>> = None;
+
+    loop {
+        tokio::select! {
+            value = conn.next() => {
+                let Some(value) = value else {
+                    break;
+                };
+                let value = value?;
+
+                last_io = Instant::now();
+                lock(state).last_io = Some(last_io);
+
+                let mut data = Vec::new();
+                value.encode(&mut data)?;
+
+                let argv = cmd::argv(&value);
+                match Command::try_from(value) {
+                    // Acknowledge the offset we processed before this request, which is not part of it
+                    Ok(Command::Replconf(options)) if options.contains(&ReplconfOption::GetAck) => {
+                        let offset = lock(state).repl.offset;
+                        conn.send(ack(offset)).await?;
+                    }
+                    // Masters periodically ping their replicas to keep the link alive
+                    Ok(Command::Ping(_)) => {}
+                    Ok(Command::Multi) => multi = Some(Vec::new()),
+                    Ok(cmd) if multi.is_some() && cmd != Command::Exec => {
+                        if let Some(commands) = &mut multi {
+                            commands.push((cmd, argv));
+                        }
+                    }
+                    Ok(cmd) => {
+                        let (req, rx) = match multi.take() {
+                            Some(commands) => Request::exec(master.clone(), commands),
+                            None => Request::new(master.clone(), cmd, argv),
+                        };
+                        if reqs.send(req).await.is_err() {
+                            break;
+                        }
+                        let _ = rx.await;
+                        lock(state).db = master.db();
+                    }
+                    Err(e) => warn!("ignoring invalid command from master: {e}"),
+                }
+
+                lock(state).repl.feed(&data);
+            }
+
+            _ = acks.tick() => {
+                let offset = lock(state).repl.offset;
+                conn.send(ack(offset)).await?;
+            }
+
+            _ = time::sleep_until(last_io + REPL_TIMEOUT) => {
+                warn!("timeout receiving data from master");
+                break;
+            }
+        }
+    }
+
+    info!("connection with master lost");
+    Ok(())
+}
+
+/// Build a `REPLCONF ACK` acknowledging the replication stream up to `offset`. Like Redis, the
+/// offset fsynced to the append-only file is reported along with it, which is always 0 since
+/// there is no such file
+fn ack(offset: u64) -> resp::Value {
+    resp::Value::from_iter([
+        resp::Value::bulk("REPLCONF"),
+        resp::Value::bulk("ACK"),
+        resp::Value::bulk(offset),
+        resp::Value::bulk("FACK"),
+        resp::Value::bulk(0),
+    ])
+}
+
+impl Role for Replica {
+    type StartFuture = future::Ready<MemoraResult<()>>;
+
+    fn info(&self) -> Vec<String> {
+        let state = lock(&self.state);
+
+        let status = match state.link {
+            LinkStatus::Up => "up",
+            _ => "down",
+        };
+
+        let last_io = state
+            .last_io
+            .map(|last_io| last_io.elapsed().as_secs() as i64)
+            .unwrap_or(-1);
+
+        let mut fields = vec![
+            ("role", "slave".to_owned()),
+            ("master_host", self.addr.0.clone()),
+            ("master_port", self.addr.1.to_string()),
+            ("master_link_status", status.to_owned()),
+            ("master_last_io_seconds_ago", last_io.to_string()),
+            (
+                "master_sync_in_progress",
+                u8::from(state.link == LinkStatus::Sync).to_string(),
+            ),
+            ("slave_read_repl_offset", state.repl.offset.to_string()),
+            ("slave_repl_offset", state.repl.offset.to_string()),
+        ];
+
+        if state.link != LinkStatus::Up {
+            fields.push((
+                "master_link_down_since_seconds",
+                state.down_since.elapsed().as_secs().to_string(),
+            ));
+        }
+
+        fields.push(("connected_slaves", "0".to_owned()));
+
+        fields
+            .into_iter()
+            .chain(state.repl.info())
+            .map(|(key, value)| format!("{key}:{value}"))
+            .collect()
+    }
+
+    fn stats(&self) -> Vec<(&'static str, String)> {
+        lock(&self.state).repl.stats()
+    }
+
+    fn backlog_memory(&self) -> usize {
+        lock(&self.state).repl.backlog_memory()
+    }
+
+    fn reset_stats(&mut self) {
+        lock(&self.state).repl.reset_stats();
+    }
+
+    fn start(&mut self, reqs: mpsc::Sender<Request>) -> Self::StartFuture {
+        self.follow(reqs);
+        future::ready(Ok(()))
+    }
+
+    fn propagate(&mut self, _cmd: &resp::Value) {
+        // The replication stream is fed by the link with our master
+    }
+
+    fn replconf(&mut self, _client: &ClientHandle, options: Vec<ReplconfOption>) -> Response {
+        if options
+            .iter()
+            .any(|option| matches!(option, ReplconfOption::Ack(_) | ReplconfOption::GetAck))
+        {
+            Response::none()
+        } else {
+            Response::ok()
+        }
+    }
+
+    fn psync<F>(
+        &mut self,
+        _client: ClientHandle,
+        _replid: &str,
+        _offset: i64,
+        _snapshot: F,
+    ) -> MemoraResult<Response>
+    where
+        F: FnOnce() -> Snapshot,
+    {
+        Err(MemoraError::Replica(ReplicaError::PsyncUnsupported))
+    }
+
+    fn wait_offset(&self) -> MemoraResult<u64> {
+        Err(MemoraError::Replica(ReplicaError::WaitUnsupported))
+    }
+
+    fn acked(&self, _offset: u64, _ack: Ack) -> usize {
+        0
+    }
+
+    fn request_acks(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_back_off_exponentially_up_to_a_bound() {
+        let delays: Vec<_> =
+            std::iter::successors(Some(RECONNECT_MIN_DELAY), |delay| Some(next_delay(*delay)))
+                .take(10)
+                .map(|delay| delay.as_millis())
+                .collect();
+
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1600, 3200, 6400, 10_000, 10_000, 10_000]
+        );
+    }
+}
@@ -0,0 +1,614 @@
+//! Redis Functions: libraries of Lua functions loaded with `FUNCTION LOAD` and called with `FCALL`.
+//!
+//! A library starts with a `#!lua name=<library>` shebang, and registers its functions with
+//! `redis.register_function` when it gets loaded. Unlike scripts, libraries are part of the
+//! dataset: they are persisted in snapshots and replicated
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use mlua::{Lua, MultiValue, RegistryKey, Table};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{
+    dispatch::{CommandHandler, CommandHandlerInvoker},
+    resp::Value,
+    server::{
+        cmd::{Argv, Command, RestorePolicy},
+        glob, rdb,
+        session::Forward,
+    },
+};
+
+use super::{message, Scripts};
+
+/// Flags a function can be registered with
+const FLAGS: &[&str] = &[
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+#[derive(Debug, Error)]
+pub enum FunctionError {
+    #[error("Missing library metadata")]
+    MissingMetadata,
+
+    #[error("Engine '{0}' not found")]
+    EngineNotFound(String),
+
+    #[error("Invalid metadata value given: {0}")]
+    InvalidMetadata(String),
+
+    #[error("Library name was not given")]
+    MissingName,
+
+    #[error("Library names can only contain letters, numbers, or underscores(_) and must be at least one character long")]
+    InvalidLibraryName,
+
+    #[error("Error compiling function: {0}")]
+    Compile(String),
+
+    #[error("Error registering functions: {0}")]
+    Register(String),
+
+    #[error("No functions registered")]
+    NoFunctions,
+
+    #[error("Library '{0}' already exists")]
+    LibraryExists(String),
+
+    #[error("Function {0} already exists")]
+    FunctionExists(String),
+
+    #[error("Library not found")]
+    LibraryNotFound,
+
+    #[error("Function not found")]
+    FunctionNotFound,
+
+    #[error("Can not execute a script with write flag using *_ro command.")]
+    WriteFlag,
+
+    #[error("Error running function {name}: {message}")]
+    Runtime { name: String, message: String },
+
+    #[error("payload version or checksum are wrong")]
+    InvalidPayload,
+}
+
+/// A function registered by a library
+struct Function {
+    description: Option<String>,
+    flags: Vec<&'static str>,
+    callback: RegistryKey,
+}
+
+struct Library {
+    code: String,
+    functions: BTreeMap<String, Function>,
+}
+
+/// Loaded libraries by name, along with the library of each function
+#[derive(Default)]
+pub(super) struct Libraries {
+    libraries: BTreeMap<String, Library>,
+    functions: HashMap<String, String>,
+}
+
+impl Libraries {
+    fn function(&self, name: &str) -> Option<&Function> {
+        let library = self.functions.get(name)?;
+        self.libraries.get(library)?.functions.get(name)
+    }
+
+    /// Add a `library`, which can only replace a library with the same name if `replace` is set
+    fn insert(
+        &mut self,
+        name: String,
+        library: Library,
+        replace: bool,
+    ) -> Result<(), FunctionError> {
+        if self.libraries.contains_key(&name) && !replace {
+            return Err(FunctionError::LibraryExists(name));
+        }
+
+        if let Some(function) = library.functions.keys().find(|function| {
+            self.functions
+                .get(*function)
+                .is_some_and(|owner| *owner != name)
+        }) {
+            return Err(FunctionError::FunctionExists(function.clone()));
+        }
+
+        self.remove(&name);
+        for function in library.functions.keys() {
+            self.functions.insert(function.clone(), name.clone());
+        }
+        self.libraries.insert(name, library);
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Option<Library> {
+        let library = self.libraries.remove(name)?;
+        for function in library.functions.keys() {
+            self.functions.remove(function);
+        }
+        Some(library)
+    }
+}
+
+impl Scripts {
+    /// Load the library of the given `code`, replacing an existing library with the same name if
+    /// `replace` is set. Returns the name of the library
+    pub(crate) fn load_library(&self, code: &str, replace: bool) -> Result<String, FunctionError> {
+        let (name, library) = self.compile_library(code)?;
+        self.libraries
+            .borrow_mut()
+            .insert(name.clone(), library, replace)?;
+
+        debug!("loaded library {name}");
+        Ok(name)
+    }
+
+    pub(crate) fn delete_library(&self, name: &str) -> Result<(), FunctionError> {
+        self.libraries
+            .borrow_mut()
+            .remove(name)
+            .ok_or(FunctionError::LibraryNotFound)?;
+        self.lua.expire_registry_values();
+        Ok(())
+    }
+
+    pub(crate) fn flush_libraries(&self) {
+        *self.libraries.borrow_mut() = Libraries::default();
+        self.lua.expire_registry_values();
+    }
+
+    /// Source code of every library
+    pub(crate) fn library_codes(&self) -> Vec<String> {
+        self.libraries
+            .borrow()
+            .libraries
+            .values()
+            .map(|library| library.code.clone())
+            .collect()
+    }
+
+    /// Replace every library by the ones of the given `codes`, as received in a snapshot
+    pub(crate) fn reload_libraries(&self, codes: Vec<String>) {
+        self.flush_libraries();
+
+        for code in codes {
+            if let Err(e) = self.load_library(&code, false) {
+                warn!("failed to load library from snapshot: {e}");
+            }
+        }
+    }
+
+    /// Number of loaded libraries and number of functions they registered
+    pub(crate) fn library_counts(&self) -> (usize, usize) {
+        let libraries = self.libraries.borrow();
+        (libraries.libraries.len(), libraries.functions.len())
+    }
+
+    /// Reply of `FUNCTION LIST` for the libraries whose name matches `pattern`
+    pub(crate) fn list_libraries(&self, pattern: Option<&str>, withcode: bool) -> Value {
+        let libraries = self.libraries.borrow();
+        let libraries = libraries.libraries.iter().filter(|(name, _)| {
+            pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), name.as_bytes()))
+        });
+
+        Value::from_iter(libraries.map(|(name, library)| {
+            let functions = library.functions.iter().map(|(name, function)| {
+                Value::from_iter([
+                    Value::bulk("name"),
+                    Value::bulk(name),
+                    Value::bulk("description"),
+                    function
+                        .description
+                        .as_ref()
+                        .map_or_else(Value::null_bulk, Value::bulk),
+                    Value::bulk("flags"),
+                    Value::from_iter(function.flags.iter().map(Value::bulk)),
+                ])
+            });
+
+            let mut fields = vec![
+                Value::bulk("library_name"),
+                Value::bulk(name),
+                Value::bulk("engine"),
+                Value::bulk("LUA"),
+                Value::bulk("functions"),
+                Value::from_iter(functions),
+            ];
+            if withcode {
+                fields.extend([Value::bulk("library_code"), Value::bulk(&library.code)]);
+            }
+
+            Value::Array(fields)
+        }))
+    }
+
+    /// Serialize every library for `FUNCTION RESTORE`. The binary payload is hex-encoded, as
+    /// bulk strings only carry UTF-8 text
+    pub(crate) fn dump_libraries(&self) -> String {
+        rdb::dump_functions(&self.library_codes())
+            .iter()
+            .fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            })
+    }
+
+    /// Restore the libraries of a `payload` produced by `FUNCTION DUMP`. Either every library
+    /// gets restored or none of them does
+    pub(crate) fn restore_libraries(
+        &self,
+        payload: &str,
+        policy: RestorePolicy,
+    ) -> Result<(), FunctionError> {
+        let payload = (0..payload.len())
+            .step_by(2)
+            .map(|i| {
+                payload
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(FunctionError::InvalidPayload)?;
+        let codes = rdb::restore_functions(&payload).map_err(|_| FunctionError::InvalidPayload)?;
+
+        let compiled = codes
+            .iter()
+            .map(|code| self.compile_library(code))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut libraries = self.libraries.borrow_mut();
+
+        // Check for conflicts before touching the loaded libraries
+        let (mut names, mut functions) = match policy {
+            RestorePolicy::Flush => (HashSet::new(), HashMap::new()),
+            _ => (
+                libraries.libraries.keys().cloned().collect(),
+                libraries.functions.clone(),
+            ),
+        };
+        for (name, library) in &compiled {
+            if !names.insert(name.clone()) {
+                if policy == RestorePolicy::Append {
+                    return Err(FunctionError::LibraryExists(name.clone()));
+                }
+                functions.retain(|_, owner| owner != name);
+            }
+
+            for function in library.functions.keys() {
+                if functions.insert(function.clone(), name.clone()).is_some() {
+                    return Err(FunctionError::FunctionExists(function.clone()));
+                }
+            }
+        }
+
+        if policy == RestorePolicy::Flush {
+            *libraries = Libraries::default();
+        }
+        for (name, library) in compiled {
+            libraries.insert(name, library, true)?;
+        }
+
+        drop(libraries);
+        self.lua.expire_registry_values();
+        Ok(())
+    }
+
+    /// Call the function `name` with the given keys and arguments, executing the commands it
+    /// calls with `call`. Only functions with the `no-writes` flag can be called `readonly`
+    pub(crate) fn call_function(
+        &self,
+        name: &str,
+        keys: Vec<String>,
+        args: Vec<String>,
+        readonly: bool,
+        call: impl FnMut(Command, Argv) -> Value,
+    ) -> Result<Value, FunctionError> {
+        let (callback, no_writes) = {
+            let libraries = self.libraries.borrow();
+            let function = libraries
+                .function(name)
+                .ok_or(FunctionError::FunctionNotFound)?;
+
+            let callback: mlua::Function = self
+                .lua
+                .registry_value(&function.callback)
+                .map_err(|_| FunctionError::FunctionNotFound)?;
+            (callback, function.flags.contains(&"no-writes"))
+        };
+
+        if readonly && !no_writes {
+            return Err(FunctionError::WriteFlag);
+        }
+
+        debug!("calling function {name}");
+        self.invoke(callback, (keys, args), readonly || no_writes, call)
+            .map_err(|e| FunctionError::Runtime {
+                name: name.to_owned(),
+                message: message(&e),
+            })
+    }
+
+    /// Run the code of a library to collect the functions it registers
+    fn compile_library(&self, code: &str) -> Result<(String, Library), FunctionError> {
+        let (name, body) = metadata(code)?;
+        let lua = &self.lua;
+
+        // Replace the shebang by an empty line to preserve line numbers in errors
+        let chunk = lua
+            .load(format!("\n{body}"))
+            .set_name("@user_function")
+            .into_function()
+            .map_err(|e| FunctionError::Compile(message(&e)))?;
+
+        let functions = RefCell::new(BTreeMap::new());
+
+        lua.scope(|scope| {
+            let redis: Table = lua.globals().get("redis")?;
+
+            let register = scope.create_function(|lua, args: MultiValue| {
+                let (name, function) = registration(lua, args)?;
+                if functions.borrow().contains_key(&name) {
+                    return Err(mlua::Error::RuntimeError(
+                        "Function already exists in the library".to_owned(),
+                    ));
+                }
+                functions.borrow_mut().insert(name, function);
+                Ok(())
+            })?;
+
+            redis.raw_set("register_function", register)?;
+            let res = chunk.call::<_, ()>(());
+            redis.raw_set("register_function", mlua::Value::Nil)?;
+            res
+        })
+        .map_err(|e| FunctionError::Register(message(&e)))?;
+
+        let functions = functions.into_inner();
+        if functions.is_empty() {
+            return Err(FunctionError::NoFunctions);
+        }
+
+        Ok((
+            name,
+            Library {
+                code: code.to_owned(),
+                functions,
+            },
+        ))
+    }
+}
+
+/// Handler of `FCALL` and `FCALL_RO`, calling the function on the server
+async fn fcall((cmd, argv): (Command, Argv), forward: Forward) -> Value {
+    forward.request(cmd, argv).await.into()
+}
+
+/// Register the handlers of `FCALL` and `FCALL_RO` with the dispatcher of a session
+pub(in crate::server) fn register(
+    dispatcher: &mut CommandHandlerInvoker<Forward, (Command, Argv)>,
+) {
+    // The session hands over the command it already parsed, so handlers get it as it is
+    dispatcher
+        .handles(fcall.into_service("fcall"))
+        .handles(fcall.into_service("fcall_ro"));
+}
+
+/// Parse the `#!lua name=<library>` shebang of a library, returning the name of the library along
+/// with its body
+fn metadata(code: &str) -> Result<(String, &str), FunctionError> {
+    let shebang = code
+        .strip_prefix("#!")
+        .ok_or(FunctionError::MissingMetadata)?;
+    let (shebang, body) = shebang.split_once('\n').unwrap_or((shebang, ""));
+
+    let mut parts = shebang.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(FunctionError::EngineNotFound(engine.to_owned()));
+    }
+
+    let mut name = None;
+    for part in parts {
+        match part.split_once('=') {
+            Some(("name", value)) => name = Some(value),
+            _ => return Err(FunctionError::InvalidMetadata(part.to_owned())),
+        }
+    }
+
+    let name = name.ok_or(FunctionError::MissingName)?;
+    if !valid_name(name) {
+        return Err(FunctionError::InvalidLibraryName);
+    }
+
+    Ok((name.to_owned(), body))
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the arguments of `redis.register_function`, either `(name, callback)` or a single table
+/// with `function_name`, `callback` and optional `flags` and `description` fields
+fn registration<'lua>(lua: &'lua Lua, args: MultiValue<'lua>) -> mlua::Result<(String, Function)> {
+    let invalid = |msg: &str| mlua::Error::RuntimeError(msg.to_owned());
+
+    let mut args = args.into_iter();
+    let (name, callback, flags, description) = match (args.next(), args.next()) {
+        (Some(mlua::Value::Table(table)), None) => (
+            table.get::<_, String>("function_name")?,
+            table.get::<_, mlua::Function>("callback")?,
+            table
+                .get::<_, Option<Vec<String>>>("flags")?
+                .unwrap_or_default(),
+            table.get::<_, Option<String>>("description")?,
+        ),
+        (Some(mlua::Value::String(name)), Some(mlua::Value::Function(callback))) => {
+            (name.to_str()?.to_owned(), callback, Vec::new(), None)
+        }
+        _ => return Err(invalid("wrong arguments to redis.register_function")),
+    };
+
+    if !valid_name(&name) {
+        return Err(invalid("Function names can only contain letters, numbers, or underscores(_) and must be at least one character long"));
+    }
+
+    let flags = flags
+        .iter()
+        .map(|flag| {
+            FLAGS
+                .iter()
+                .find(|known| **known == flag)
+                .copied()
+                .ok_or_else(|| invalid("unknown flag given"))
+        })
+        .collect::<mlua::Result<_>>()?;
+
+    Ok((
+        name,
+        Function {
+            description,
+            flags,
+            callback: lua.create_registry_value(callback)?,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use tokio::sync::mpsc;
+
+    use crate::{
+        dispatch,
+        server::{ClientHandle, RequestKind},
+    };
+
+    use super::*;
+
+    const LIBRARY: &str = "#!lua name=mylib
+        local function echo(keys, args)
+            return {keys[1], args[1]}
+        end
+        redis.register_function('echo', echo)
+        redis.register_function{function_name='ro', callback=echo, flags={'no-writes'}}";
+
+    #[test]
+    fn should_load_and_call_libraries() {
+        let scripts = Scripts::new(Arc::default());
+        assert_eq!(scripts.load_library(LIBRARY, false).unwrap(), "mylib");
+        assert!(matches!(
+            scripts.load_library(LIBRARY, false),
+            Err(FunctionError::LibraryExists(_))
+        ));
+        assert!(scripts.load_library(LIBRARY, true).is_ok());
+        assert_eq!(scripts.library_counts(), (1, 2));
+
+        let call = |name, readonly| {
+            scripts.call_function(
+                name,
+                vec!["k".to_owned()],
+                vec!["a".to_owned()],
+                readonly,
+                |_, _| Value::null_bulk(),
+            )
+        };
+        assert_eq!(
+            call("echo", false).unwrap(),
+            Value::from_iter([Value::bulk("k"), Value::bulk("a")])
+        );
+        assert!(matches!(call("echo", true), Err(FunctionError::WriteFlag)));
+        assert!(call("ro", true).is_ok());
+
+        let dump = scripts.dump_libraries();
+        scripts.delete_library("mylib").unwrap();
+        assert!(matches!(
+            call("echo", false),
+            Err(FunctionError::FunctionNotFound)
+        ));
+
+        scripts
+            .restore_libraries(&dump, RestorePolicy::Append)
+            .unwrap();
+        assert!(matches!(
+            scripts.restore_libraries(&dump, RestorePolicy::Append),
+            Err(FunctionError::LibraryExists(_))
+        ));
+        assert!(call("echo", false).is_ok());
+    }
+
+    #[test]
+    fn should_reject_invalid_libraries() {
+        let scripts = Scripts::new(Arc::default());
+        assert!(matches!(
+            scripts.load_library("return 1", false),
+            Err(FunctionError::MissingMetadata)
+        ));
+        assert!(matches!(
+            scripts.load_library("#!js name=lib\n", false),
+            Err(FunctionError::EngineNotFound(_))
+        ));
+        assert!(matches!(
+            scripts.load_library("#!lua name=lib\nlocal x = 1", false),
+            Err(FunctionError::NoFunctions)
+        ));
+
+        scripts.load_library(LIBRARY, false).unwrap();
+        assert!(matches!(
+            scripts.load_library(&LIBRARY.replace("mylib", "other"), false),
+            Err(FunctionError::FunctionExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_dispatch_function_calls_to_the_server() {
+        let (client, _push_rx) = ClientHandle::new("127.0.0.1:6000".parse::<SocketAddr>().unwrap());
+        let (reqs_tx, mut reqs_rx) = mpsc::channel(1);
+        let mut dispatcher = CommandHandlerInvoker::with_state(Forward::new(client, reqs_tx));
+        register(&mut dispatcher);
+        assert!(dispatcher.dispatches("fcall") && dispatcher.dispatches("fcall_ro"));
+
+        let server = tokio::spawn(async move {
+            let req = reqs_rx.recv().await.unwrap();
+            let _ = req.tx.send(Value::bulk("done").into());
+            req.kind
+        });
+
+        let cmd = Command::FCall {
+            function: "echo".to_owned(),
+            keys: vec!["k".to_owned()],
+            args: vec!["a".to_owned()],
+            readonly: true,
+        };
+        let argv: Argv = ["fcall_ro", "echo", "1", "k", "a"]
+            .map(str::to_owned)
+            .to_vec();
+        let replies = dispatcher
+            .call(dispatch::Command::new(
+                cmd.name(),
+                (cmd.clone(), argv.clone()),
+            ))
+            .await;
+        assert_eq!(replies, [Value::bulk("done")]);
+
+        let RequestKind::Command(forwarded, forwarded_argv) = server.await.unwrap() else {
+            panic!("expected a command");
+        };
+        assert_eq!(forwarded, cmd);
+        assert_eq!(forwarded_argv, argv);
+    }
+}
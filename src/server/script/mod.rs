@@ -0,0 +1,518 @@
+//! Server-side Lua scripting with `EVAL`, `EVALSHA` and the scripts cache.
+//!
+//! Scripts run on the server loop, which makes them atomic: no other command can run while a
+//! script is being executed. Values are converted between RESP and Lua with the same rules as
+//! Redis:
+//! - integers are converted to Lua numbers, and Lua numbers are truncated to integers
+//! - bulk strings are converted to Lua strings and back
+//! - null bulk strings and null arrays are converted to `false`, and `false` or `nil` to a null
+//!   bulk string. `true` is converted to the integer 1
+//! - arrays are converted to Lua sequences, which are converted back up to their first `nil`
+//! - status and error replies are converted to a table with a single `ok` or `err` field, and back
+
+mod function;
+pub(super) use function::register;
+pub use function::FunctionError;
+use function::Libraries;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use mlua::{
+    HookTriggers, IntoLua, IntoLuaMulti, Lua, LuaOptions, MultiValue, RegistryKey, StdLib, Table,
+};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::resp::{StringValue, Value};
+
+use super::cmd::{self, Argv, Command, CommandFlags};
+
+/// Number of Lua instructions after which a running script checks whether it got killed
+const KILL_CHECK_INSTRUCTIONS: u32 = 100_000;
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("No matching script. Please use EVAL.")]
+    NoScript,
+
+    #[error("Error compiling script (new function): {0}")]
+    Compile(String),
+
+    #[error("Error running script (call to f_{sha}): {message}")]
+    Runtime { sha: String, message: String },
+
+    #[error("Please specify at least one argument for this redis lib call")]
+    MissingCommand,
+
+    #[error("Lua redis lib command arguments must be strings or integers")]
+    InvalidArgument,
+
+    #[error("This Redis command is not allowed from script")]
+    NotAllowed,
+
+    #[error("Write commands are not allowed from read-only scripts.")]
+    WriteNotAllowed,
+
+    #[error("No scripts in execution right now.")]
+    NotBusy,
+
+    #[error("Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.")]
+    Unkillable,
+}
+
+/// State of the script being executed, shared with sessions so that `SCRIPT KILL` can be served
+/// while the server loop is busy running the script
+#[derive(Debug, Default)]
+pub struct Running {
+    busy: AtomicBool,
+    wrote: AtomicBool,
+    killed: AtomicBool,
+}
+
+impl Running {
+    fn start(&self) {
+        self.wrote.store(false, Ordering::SeqCst);
+        self.killed.store(false, Ordering::SeqCst);
+        self.busy.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(&self) {
+        self.busy.store(false, Ordering::SeqCst);
+    }
+
+    /// Kill the running script, unless it already performed writes which can not be rolled back
+    pub(super) fn kill(&self) -> Result<(), ScriptError> {
+        if !self.busy.load(Ordering::SeqCst) {
+            return Err(ScriptError::NotBusy);
+        }
+
+        if self.wrote.load(Ordering::SeqCst) {
+            return Err(ScriptError::Unkillable);
+        }
+
+        self.killed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// SHA1 digest of `s`, as lowercase hexadecimal characters
+pub(super) fn sha1hex(s: &str) -> String {
+    sha1_smol::Sha1::from(s).digest().to_string()
+}
+
+/// A Lua interpreter along with the cache of the scripts it compiled
+pub(super) struct Scripts {
+    lua: Lua,
+
+    /// Compiled scripts and the length of their source code, by SHA1 digest
+    cache: RefCell<HashMap<String, (RegistryKey, usize)>>,
+
+    /// Libraries loaded with `FUNCTION LOAD`
+    libraries: RefCell<Libraries>,
+
+    running: Arc<Running>,
+}
+
+impl Scripts {
+    pub(super) fn new(running: Arc<Running>) -> Self {
+        let lua = Lua::new_with(
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+            LuaOptions::default(),
+        )
+        .expect("failed to create the Lua interpreter");
+
+        setup(&lua).expect("failed to set up the Lua interpreter");
+
+        let hook_running = Arc::clone(&running);
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS),
+            move |_, _| {
+                if hook_running.killed.load(Ordering::SeqCst) {
+                    return Err(mlua::Error::RuntimeError(
+                        "Script killed by user with SCRIPT KILL...".to_owned(),
+                    ));
+                }
+                Ok(())
+            },
+        );
+
+        Self {
+            lua,
+            cache: RefCell::default(),
+            libraries: RefCell::default(),
+            running,
+        }
+    }
+
+    /// Compile `body` and add it to the cache, returning its SHA1 digest
+    pub(super) fn load(&self, body: &str) -> Result<String, ScriptError> {
+        let sha = sha1hex(body);
+        if self.exists(&sha) {
+            return Ok(sha);
+        }
+
+        let function = self
+            .lua
+            .load(body)
+            .set_name("@user_script")
+            .into_function()
+            .map_err(|e| ScriptError::Compile(message(&e)))?;
+        let key = self
+            .lua
+            .create_registry_value(function)
+            .map_err(|e| ScriptError::Compile(message(&e)))?;
+
+        debug!("loaded script {sha}");
+        self.cache
+            .borrow_mut()
+            .insert(sha.clone(), (key, body.len()));
+        Ok(sha)
+    }
+
+    pub(super) fn exists(&self, sha: &str) -> bool {
+        self.cache.borrow().contains_key(&sha.to_ascii_lowercase())
+    }
+
+    /// Remove every script from the cache
+    pub(super) fn flush(&self) {
+        self.cache.borrow_mut().clear();
+        self.lua.expire_registry_values();
+    }
+
+    /// Number of cached scripts
+    pub(super) fn len(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    /// Number of bytes of source code of the cached scripts
+    pub(super) fn memory(&self) -> usize {
+        self.cache.borrow().values().map(|(_, len)| len).sum()
+    }
+
+    /// Run the script identified by `sha` with the given `KEYS` and `ARGV`, executing the
+    /// commands it calls with `call`. A `readonly` script can not call write commands
+    pub(super) fn run(
+        &self,
+        sha: &str,
+        keys: Vec<String>,
+        args: Vec<String>,
+        readonly: bool,
+        call: impl FnMut(Command, Argv) -> Value,
+    ) -> Result<Value, ScriptError> {
+        let sha = sha.to_ascii_lowercase();
+        let function: mlua::Function = {
+            let cache = self.cache.borrow();
+            let (key, _) = cache.get(&sha).ok_or(ScriptError::NoScript)?;
+            self.lua
+                .registry_value(key)
+                .map_err(|_| ScriptError::NoScript)?
+        };
+
+        let globals = self.lua.globals();
+        let res = globals
+            .raw_set("KEYS", keys)
+            .and_then(|_| globals.raw_set("ARGV", args))
+            .and_then(|_| self.invoke(function, (), readonly, call));
+
+        res.map_err(|e| ScriptError::Runtime {
+            sha,
+            message: message(&e),
+        })
+    }
+
+    /// Call a Lua `function` with `args`, binding `redis.call` and `redis.pcall` to `call`
+    fn invoke<'lua>(
+        &'lua self,
+        function: mlua::Function<'lua>,
+        args: impl IntoLuaMulti<'lua>,
+        readonly: bool,
+        call: impl FnMut(Command, Argv) -> Value,
+    ) -> mlua::Result<Value> {
+        let lua = &self.lua;
+        let call = RefCell::new(call);
+
+        self.running.start();
+
+        let res = lua.scope(|scope| {
+            let redis: Table = lua.globals().get("redis")?;
+
+            // `redis.call` raises errors while `redis.pcall` returns them as error tables
+            for (name, raise) in [("call", true), ("pcall", false)] {
+                let call = &call;
+                let function = scope.create_function(move |lua, args: MultiValue| {
+                    let reply = match self.command(args, readonly) {
+                        Ok((cmd, argv)) => (call.borrow_mut())(cmd, argv),
+                        Err(e) => Value::error(format!("ERR {e}")),
+                    };
+
+                    match reply {
+                        Value::Error(e) if raise => Err(mlua::Error::RuntimeError(e)),
+                        reply => to_lua(lua, reply),
+                    }
+                })?;
+                redis.raw_set(name, function)?;
+            }
+
+            to_resp(function.call(args)?)
+        });
+
+        self.running.finish();
+        res
+    }
+
+    /// Build the command called by a script from the arguments of `redis.call`, along with those
+    /// arguments
+    fn command(
+        &self,
+        args: MultiValue,
+        readonly: bool,
+    ) -> Result<(Command, Argv), Box<dyn std::error::Error>> {
+        if args.is_empty() {
+            return Err(ScriptError::MissingCommand.into());
+        }
+
+        let args = args
+            .into_iter()
+            .map(|arg| match arg {
+                mlua::Value::String(s) => Ok(Value::bulk(s.to_string_lossy())),
+                mlua::Value::Integer(i) => Ok(Value::bulk(i)),
+                mlua::Value::Number(n) => Ok(Value::bulk(n)),
+                _ => Err(ScriptError::InvalidArgument),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let args = Value::Array(args);
+        let argv = cmd::argv(&args);
+        let cmd = Command::try_from(args)?;
+        let flags = cmd.flags();
+
+        if flags.contains(CommandFlags::NOSCRIPT) {
+            return Err(ScriptError::NotAllowed.into());
+        }
+
+        if flags.contains(CommandFlags::WRITE) {
+            if readonly {
+                return Err(ScriptError::WriteNotAllowed.into());
+            }
+            self.running.wrote.store(true, Ordering::SeqCst);
+        }
+
+        Ok((cmd, argv))
+    }
+}
+
+/// Install the `redis` library and protect the global environment of scripts
+fn setup(lua: &Lua) -> mlua::Result<()> {
+    let redis = lua.create_table()?;
+
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, s: mlua::String| Ok(sha1hex(&s.to_string_lossy())))?,
+    )?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, msg: mlua::String| reply_table(lua, "err", msg))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, msg: mlua::String| reply_table(lua, "ok", msg))?,
+    )?;
+
+    for (level, name) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"]
+        .into_iter()
+        .enumerate()
+    {
+        redis.set(name, level)?;
+    }
+    redis.set(
+        "log",
+        lua.create_function(|_, (level, message): (u8, MultiValue)| {
+            let message = message
+                .into_iter()
+                .filter_map(|value| value.to_string().ok())
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            match level {
+                0 | 1 => debug!("script: {message}"),
+                2 => info!("script: {message}"),
+                _ => warn!("script: {message}"),
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let globals = lua.globals();
+    globals.set("redis", redis)?;
+
+    // Scripts can not access the file system
+    globals.set("loadfile", mlua::Value::Nil)?;
+    globals.set("dofile", mlua::Value::Nil)?;
+
+    // Scripts must not leak state to the following ones through global variables
+    lua.load(
+        r#"
+        setmetatable(_G, {
+            __newindex = function(_, name)
+                error("Script attempted to create global variable '" .. tostring(name) .. "'", 2)
+            end,
+            __index = function(_, name)
+                error("Script attempted to access nonexistent global variable '" .. tostring(name) .. "'", 2)
+            end,
+        })
+        "#,
+    )
+    .set_name("@redis")
+    .exec()
+}
+
+/// A table with a single `field` set to `msg`, as returned by `redis.error_reply` and
+/// `redis.status_reply`
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, msg: mlua::String) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.raw_set(field, msg)?;
+    Ok(table)
+}
+
+/// Convert a RESP `value` to a Lua value
+fn to_lua(lua: &Lua, value: Value) -> mlua::Result<mlua::Value<'_>> {
+    match value {
+        Value::Int(i) => i.into_lua(lua),
+        Value::Str(StringValue::Bulk(Some(s))) => s.into_lua(lua),
+        Value::Str(StringValue::Bulk(None)) | Value::NullArray => Ok(mlua::Value::Boolean(false)),
+        Value::Str(StringValue::Simple(s)) => {
+            reply_table(lua, "ok", lua.create_string(s)?).map(mlua::Value::Table)
+        }
+        Value::Error(e) => reply_table(lua, "err", lua.create_string(e)?).map(mlua::Value::Table),
+        Value::Array(values) | Value::Push(values) => {
+            let table = lua.create_table_with_capacity(values.len(), 0)?;
+            for value in values {
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+            Ok(mlua::Value::Table(table))
+        }
+        // Scripts speak RESP2, where maps are flattened to arrays
+        Value::Map(pairs) => {
+            let table = lua.create_table_with_capacity(pairs.len() * 2, 0)?;
+            for (key, value) in pairs {
+                table.raw_push(to_lua(lua, key)?)?;
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+            Ok(mlua::Value::Table(table))
+        }
+    }
+}
+
+/// Convert a Lua `value` returned by a script to a RESP value
+fn to_resp(value: mlua::Value) -> mlua::Result<Value> {
+    Ok(match value {
+        mlua::Value::Boolean(true) => Value::Int(1),
+        mlua::Value::Integer(i) => Value::Int(i),
+        mlua::Value::Number(n) => Value::Int(n as i64),
+        mlua::Value::String(s) => Value::bulk(s.to_string_lossy()),
+        mlua::Value::Table(table) => {
+            if let Some(err) = table.raw_get::<_, Option<mlua::String>>("err")? {
+                Value::error(err.to_string_lossy())
+            } else if let Some(ok) = table.raw_get::<_, Option<mlua::String>>("ok")? {
+                Value::simple(ok.to_string_lossy())
+            } else {
+                Value::Array(
+                    table
+                        .sequence_values()
+                        .map(|value| to_resp(value?))
+                        .collect::<mlua::Result<_>>()?,
+                )
+            }
+        }
+        _ => Value::null_bulk(),
+    })
+}
+
+/// Message of a Lua error, without the details added by the callbacks it went through nor its
+/// traceback, as error replies are made of a single line
+fn message(e: &mlua::Error) -> String {
+    let message = match e {
+        mlua::Error::CallbackError { cause, .. } => return message(cause),
+        mlua::Error::RuntimeError(message) => message.clone(),
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        e => e.to_string(),
+    };
+
+    message.lines().next().unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(scripts: &Scripts, body: &str, args: &[&str]) -> Result<Value, ScriptError> {
+        let sha = scripts.load(body)?;
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        scripts.run(&sha, Vec::new(), args, false, |cmd, _| match cmd {
+            Command::Get { key } => Value::bulk(key),
+            _ => Value::simple("OK"),
+        })
+    }
+
+    #[test]
+    fn should_convert_values() {
+        let scripts = Scripts::new(Arc::default());
+
+        assert_eq!(run(&scripts, "return 3.99", &[]).unwrap(), Value::Int(3));
+        assert_eq!(
+            run(&scripts, "return {1, 'two', false, nil, 5}", &[]).unwrap(),
+            Value::from_iter([Value::Int(1), Value::bulk("two"), Value::null_bulk()])
+        );
+        assert_eq!(
+            run(&scripts, "return redis.call('GET', ARGV[1])", &["foo"]).unwrap(),
+            Value::bulk("foo")
+        );
+        assert_eq!(
+            run(&scripts, "return redis.call('SET', 'k', 'v')", &[]).unwrap(),
+            Value::simple("OK")
+        );
+        assert_eq!(
+            run(&scripts, "return redis.error_reply('My Error')", &[]).unwrap(),
+            Value::error("My Error")
+        );
+        assert_eq!(
+            run(&scripts, "return redis.sha1hex('')", &[]).unwrap(),
+            Value::bulk("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        );
+    }
+
+    #[test]
+    fn should_report_errors() {
+        let scripts = Scripts::new(Arc::default());
+
+        assert!(matches!(
+            scripts.load("return +"),
+            Err(ScriptError::Compile(_))
+        ));
+        assert!(matches!(
+            run(&scripts, "x = 1", &[]),
+            Err(ScriptError::Runtime { .. })
+        ));
+        assert!(matches!(
+            run(&scripts, "return redis.call('SUBSCRIBE', 'c')", &[]),
+            Err(ScriptError::Runtime { .. })
+        ));
+        assert_eq!(
+            run(&scripts, "return redis.pcall('NOPE')", &[]).unwrap(),
+            Value::error("ERR unknown command NOPE")
+        );
+        assert!(matches!(
+            scripts.run("0000", Vec::new(), Vec::new(), false, |_, _| {
+                Value::null_bulk()
+            }),
+            Err(ScriptError::NoScript)
+        ));
+    }
+}
@@ -1,165 +1,2842 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{HashMap, VecDeque},
+    mem,
     net::SocketAddr,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
 };
 
-use crate::resp::{StringValue, Value};
+use crate::{
+    dispatch::table::{self, CommandSpec},
+    resp::{StringValue, Value},
+};
 
 use super::{
-    cmd::{Command, CommandError, InfoError},
-    MemoraError, MemoraResult, Request, Response, Role,
+    acl::{self, Acl},
+    client,
+    cmd::{
+        AclCommand, Argv, ClientCommand, ClientError, ClientInfoAttr, ClientType, Command,
+        CommandCommand, CommandError, CommandFilter, CommandFlags, ConfigCommand, FlushMode,
+        FunctionCommand, KillFilter, LatencyCommand, MemoryCommand, ObjectCommand, PauseMode,
+        PubSubCommand, RestorePolicy, Script, ScriptCommand, SetError, SlowLogCommand,
+        TrackingOptions,
+    },
+    db::{DbError, Entry, StringStore},
+    evict::{self, EvictError, EvictionPolicy, EvictionPool},
+    glob,
+    info::{self, Info, ProcessUsage},
+    latency::LatencyMonitor,
+    log,
+    memory::{DbMemory, MemoryStats},
+    monitor::{self, Monitors},
+    multi::{MultiError, Watches},
+    notify::{self, NotifyFlags},
+    pubsub::{self, PubSub, PubSubError},
+    rdb::{self, Snapshot},
+    role::{Ack, AnyRole, ReplicaError},
+    script::{Running, ScriptError, Scripts},
+    slowlog::SlowLog,
+    stats::{NetStats, Stats},
+    tls::{self, TlsError, TlsListener},
+    tracking::{Tracking, TrackingError},
+    unix::{self, UnixListener},
+    wait::{WaitError, Waiter, Waiters},
+    ClientAddr, ClientHandle, ClientId, Config, MemoraError, MemoraResult, Request, RequestKind,
+    Response, Role,
 };
 use chrono::Utc;
-use tokio::{net::ToSocketAddrs, sync::mpsc};
-use tracing::{debug, error, info};
+use rand::Rng;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    time::{self, Instant},
+};
+use tokio_rustls::{server::TlsStream, TlsConnector};
+use tracing::{error, info, warn};
 
 use super::Session;
 
-#[derive(Debug)]
-struct StringEntry {
-    value: String,
-    expiry: Option<chrono::DateTime<Utc>>,
+/// Reply to a command, which can be deferred until a blocking condition is met
+enum Reply {
+    Now(Response),
+    Blocked(Waiter),
 }
 
-#[derive(Debug, Default)]
-struct StringStore(HashMap<String, StringEntry>);
+/// Commands of the clients held back by `CLIENT PAUSE`
+struct Pause {
+    mode: PauseMode,
+    until: Instant,
+}
 
-impl StringStore {
-    pub(crate) fn store(
-        &mut self,
-        key: String,
-        value: String,
-        expiry: Option<chrono::DateTime<Utc>>,
-    ) -> MemoraResult<()> {
-        debug!("storing key {key} with value {value} and expiry {expiry:?}");
+/// Interval at which the periodic housekeeping of the server runs
+const CRON_INTERVAL: Duration = Duration::from_secs(1);
 
-        match self.0.entry(key) {
-            Entry::Occupied(mut e) => {
-                let entry = e.get_mut();
-                entry.expiry = expiry;
-                entry.value = value;
-            }
-            Entry::Vacant(e) => {
-                e.insert(StringEntry { value, expiry });
-            }
-        }
-        Ok(())
-    }
+/// Share of [`CRON_INTERVAL`] the active expire cycle may take, in percents
+const ACTIVE_EXPIRE_CYCLE_TIME_PERC: u32 = 25;
 
-    pub(crate) fn try_get(
-        &self,
-        key: impl AsRef<str>,
-        time: impl FnOnce() -> chrono::DateTime<Utc>,
-    ) -> Option<&str> {
-        let entry = self.0.get(key.as_ref())?;
+/// Number of keys `SCAN` collects unless told otherwise with `COUNT`
+const SCAN_COUNT: usize = 10;
 
-        let expired = entry.expiry.map(|exp| exp <= time()).unwrap_or(false);
+pub struct Memora {
+    listener: tokio::net::TcpListener,
 
-        // TODO(oktal): properly reclaim expired entry from memory
-        if expired {
-            None
-        } else {
-            Some(entry.value.as_str())
-        }
-    }
-}
+    /// Listener of the TLS port, if any
+    tls: Option<TlsListener>,
 
-pub struct Memora<R> {
-    listener: tokio::net::TcpListener,
+    /// Listener of the unix socket, if any
+    unix: Option<UnixListener>,
+
+    /// Connections of TLS clients that completed their handshake
+    handshakes_tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+    handshakes_rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
     sessions: Vec<tokio::task::JoinHandle<MemoraResult<()>>>,
 
-    role: R,
+    /// Connected clients, by id
+    clients: HashMap<ClientId, ClientHandle>,
+
+    reqs_tx: mpsc::Sender<Request>,
+    reqs_rx: mpsc::Receiver<Request>,
+
+    config: Config,
+
+    role: AnyRole,
+
+    /// Logical databases, selected by clients with `SELECT`
+    dbs: Vec<StringStore>,
+
+    /// Database selected by the client whose request is being handled
+    db: usize,
+
+    /// Client whose request is being handled, if any
+    caller: Option<ClientId>,
+
+    /// Whether the request being handled records its accesses to the keys for eviction
+    touch: bool,
+
+    /// Database selected by the last write propagated to our replicas, if any
+    propagated_db: Option<usize>,
+
+    /// Best candidates sampled so far for the next eviction
+    eviction_pool: EvictionPool,
+
+    /// Clients blocked until replicas acknowledge their writes
+    waiters: Waiters,
+
+    /// Pause started by `CLIENT PAUSE`, and the requests it held back
+    pause: Option<Pause>,
+    paused: VecDeque<Request>,
+
+    pubsub: PubSub,
+
+    /// Keys watched by clients for their next transaction
+    watches: Watches,
+
+    /// Keys cached by clients, which get invalidated once modified
+    tracking: Tracking,
+
+    /// Commands that took longer than `slowlog-log-slower-than` to execute
+    slowlog: SlowLog,
 
-    string: StringStore,
+    /// Events that took longer than `latency-monitor-threshold` to complete
+    latency: LatencyMonitor,
+
+    /// Clients sent every command processed, after `MONITOR`
+    monitors: Monitors,
+
+    /// Users that clients authenticate as, and their permissions
+    acl: Acl,
+
+    /// Lua interpreter and cache of the scripts run by `EVAL`
+    scripts: Rc<Scripts>,
+    running: Arc<Running>,
+
+    /// Whether a transaction is being executed, and if so whether its `MULTI` got propagated
+    transaction: Option<bool>,
+
+    /// Random identifier of this run of the server
+    run_id: String,
+    started: std::time::Instant,
+
+    stats: Stats,
+    net: Arc<NetStats>,
+    peak_memory: usize,
 }
 
-impl<R> Memora<R>
-where
-    R: Role,
-{
-    pub async fn new(addr: impl ToSocketAddrs, role: R) -> MemoraResult<Self> {
+impl Memora {
+    pub async fn new(
+        addr: impl ToSocketAddrs,
+        role: impl Into<AnyRole>,
+        config: Config,
+    ) -> MemoraResult<Self> {
         let listener = tokio::net::TcpListener::bind(addr).await?;
 
         let addr = listener.local_addr()?;
         info!("listening on {addr}");
 
+        let tls = match config.tls.port {
+            Some(port) => {
+                let listener = tokio::net::TcpListener::bind((addr.ip(), port)).await?;
+                info!(
+                    "listening for TLS connections on {}",
+                    listener.local_addr()?
+                );
+
+                Some(TlsListener {
+                    listener,
+                    acceptor: config.tls.acceptor()?,
+                })
+            }
+            None => None,
+        };
+        let (handshakes_tx, handshakes_rx) = mpsc::channel(128);
+
+        let unix = match &config.unixsocket {
+            Some(path) => {
+                let listener = UnixListener::bind(path.clone(), config.unixsocketperm)?;
+                info!("listening on unix socket {}", path.display());
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let (reqs_tx, reqs_rx) = mpsc::channel(128);
+        let running = Arc::<Running>::default();
+        let acl = Acl::new(config.requirepass.as_deref(), config.aclfile.clone())?;
+
         Ok(Self {
             listener,
+            tls,
+            unix,
+            handshakes_tx,
+            handshakes_rx,
             sessions: Vec::new(),
-            string: StringStore::default(),
-            role,
+            clients: HashMap::new(),
+            reqs_tx,
+            reqs_rx,
+            dbs: std::iter::repeat_with(|| StringStore::new(config.lfu))
+                .take(config.databases)
+                .collect(),
+            db: 0,
+            caller: None,
+            touch: true,
+            propagated_db: None,
+            eviction_pool: EvictionPool::default(),
+            config,
+            waiters: Waiters::default(),
+            pause: None,
+            paused: VecDeque::new(),
+            pubsub: PubSub::default(),
+            watches: Watches::default(),
+            tracking: Tracking::default(),
+            slowlog: SlowLog::default(),
+            latency: LatencyMonitor::default(),
+            monitors: Monitors::default(),
+            acl,
+            scripts: Rc::new(Scripts::new(Arc::clone(&running))),
+            running,
+            transaction: None,
+            role: role.into(),
+            run_id: run_id(),
+            started: std::time::Instant::now(),
+            stats: Stats::default(),
+            net: Arc::default(),
+            peak_memory: 0,
         })
     }
 
     pub async fn start(mut self) -> MemoraResult<()> {
-        self.role.start().await?;
+        self.role.start(self.reqs_tx.clone()).await?;
 
-        let (reqs_tx, mut reqs_rx) = mpsc::channel(128);
+        let mut cron = time::interval(CRON_INTERVAL);
 
         loop {
+            self.resume().await;
+
+            let deadline = self.waiters.next_deadline();
+            let unpause = self.pause.as_ref().map(|pause| pause.until);
+
             tokio::select! {
                 conn = self.listener.accept() => {
                     let (socket, addr) = conn?;
-                    self.handle_connection(socket, addr, reqs_tx.clone());
+                    let laddr = self.listener.local_addr()?;
+                    self.handle_connection(socket, addr, laddr);
                 }
 
-                Some(req) = reqs_rx.recv() => {
-                    let Request { cmd, tx } = req;
-                    match self.handle_command(cmd).await {
-                        Ok(resp) => {
-                            let _ = tx.send(resp);
-                        }
-                        Err(e) => error!("error handling command: {e}"),
+                conn = tls::accept(self.tls.as_ref()) => {
+                    let (socket, addr) = conn?;
+                    self.handshake(socket, addr);
+                }
+
+                Some((stream, addr)) = self.handshakes_rx.recv() => {
+                    if let Some(tls) = &self.tls {
+                        let laddr = tls.listener.local_addr()?;
+                        self.handle_connection(stream, addr, laddr);
+                    }
+                }
+
+                conn = unix::accept(self.unix.as_ref()) => {
+                    let socket = conn?;
+                    if let Some(unix) = &self.unix {
+                        let path = ClientAddr::Unix(Arc::new(unix.path().to_owned()));
+                        self.handle_connection(socket, path.clone(), path);
                     }
+                }
+
+                Some(req) = self.reqs_rx.recv() => {
+                    self.handle_request(req).await;
+                }
 
+                _ = cron.tick() => {
+                    self.cron();
                 }
+
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    self.waiters.unblock(&self.role, Instant::now());
+                }
+
+                // Held back requests get handled at the start of the next iteration
+                _ = time::sleep_until(unpause.unwrap_or_else(Instant::now)), if unpause.is_some() => {}
             }
         }
     }
 
-    fn handle_connection(
-        &mut self,
-        socket: tokio::net::TcpStream,
-        addr: SocketAddr,
-        reqs_tx: mpsc::Sender<Request>,
-    ) {
-        info!("got new connection from {addr:?}");
+    /// Handle the requests held back by `CLIENT PAUSE` once the pause is over
+    async fn resume(&mut self) {
+        if self
+            .pause
+            .as_ref()
+            .is_some_and(|pause| pause.until > Instant::now())
+        {
+            return;
+        }
 
-        let session = Session::new(socket, reqs_tx);
-        self.sessions.push(tokio::spawn(session.run()));
+        self.pause = None;
+        for req in mem::take(&mut self.paused) {
+            self.handle_request(req).await;
+        }
     }
 
-    async fn handle_command(&mut self, cmd: Command) -> MemoraResult<Response> {
-        match cmd {
-            Command::Info { section } => {
-                let section = section.as_deref().unwrap_or("default");
-                if section.eq_ignore_ascii_case("replication") {
-                    let fields = self.role.info().join("\r\n");
-                    Ok(Value::bulk(fields).into())
-                } else {
-                    Err(MemoraError::Command(CommandError::Info(
-                        InfoError::UnknownSection(section.to_owned()),
-                    )))
+    /// Whether `req` must be held back by the current `CLIENT PAUSE`, if any
+    fn postpones(&self, req: &Request) -> bool {
+        let Some(pause) = &self.pause else {
+            return false;
+        };
+
+        // The replication stream keeps flowing
+        if req.client.is_master() || req.client.record().replica {
+            return false;
+        }
+
+        match &req.kind {
+            RequestKind::Command(cmd, _) => pause.mode == PauseMode::All || cmd.may_replicate(),
+            RequestKind::Exec(commands) => {
+                pause.mode == PauseMode::All || commands.iter().any(|(cmd, _)| cmd.may_replicate())
+            }
+            RequestKind::Load(_) | RequestKind::Disconnect => false,
+        }
+    }
+
+    /// Periodic housekeeping
+    fn cron(&mut self) {
+        self.role.cron();
+        self.sessions.retain(|session| !session.is_finished());
+
+        for db in &mut self.dbs {
+            db.rehash();
+        }
+        self.active_expire(std::time::Instant::now());
+
+        let elapsed = self.started.elapsed();
+        self.stats
+            .ops
+            .sample(self.stats.commands_processed, elapsed);
+        self.stats.input.sample(self.net.input(), elapsed);
+        self.stats.output.sample(self.net.output(), elapsed);
+    }
+
+    async fn handle_request(&mut self, req: Request) {
+        if self.postpones(&req) {
+            self.paused.push_back(req);
+            return;
+        }
+
+        let Request {
+            client,
+            db,
+            kind,
+            tx,
+        } = req;
+        self.db = db;
+        self.caller = Some(client.id());
+        self.touch = !client.record().no_touch;
+
+        // `CLIENT CACHING` applies to the command that follows it, or to a whole transaction
+        let caching = matches!(
+            kind,
+            RequestKind::Command(Command::Client(ClientCommand::Caching(_)), _)
+        );
+
+        match kind {
+            RequestKind::Command(cmd, argv) => match self.execute(&client, cmd, &argv).await {
+                Reply::Now(resp) => {
+                    let _ = tx.send(resp);
                 }
+                Reply::Blocked(waiter) => self.waiters.block(waiter, tx),
+            },
+            RequestKind::Exec(commands) => {
+                let resp = self.exec(&client, commands).await;
+                let _ = tx.send(resp);
             }
-            Command::Set { key, value, expiry } => {
-                let expiry = match expiry {
-                    // TODO(oktal): properly handle error
-                    Some(expiry) => Some(expiry.into_utc().expect("invalid expiry time")),
-                    None => None,
-                };
-                self.string.store(key, value, expiry)?;
-                Ok(Value::Str(StringValue::Simple("OK".to_owned())).into())
+            RequestKind::Load(mut snapshot) => {
+                self.scripts
+                    .reload_libraries(std::mem::take(&mut snapshot.functions));
+                self.load(snapshot);
+                self.signal_flushed_db(None);
+                let _ = tx.send(Response::ok());
             }
-            Command::Get { key } => Ok(
-                if let Some(value) = self.string.try_get(&key, || Utc::now()) {
-                    Value::bulk(value)
+            RequestKind::Disconnect => {
+                self.clients.remove(&client.id());
+                self.pubsub.remove(client.id());
+                self.watches.unwatch(client.id());
+                self.tracking.disable(client.id());
+                self.monitors.remove(client.id());
+            }
+        }
+
+        if !caching {
+            self.tracking.reset_caching(client.id());
+        }
+        self.caller = None;
+    }
+
+    /// Execute a single command on behalf of `client`, recording its statistics and logging it if
+    /// it was slow. `argv` is the command as sent by the client
+    async fn execute(&mut self, client: &ClientHandle, cmd: Command, argv: &[String]) -> Reply {
+        let name = cmd.name();
+        let flags = cmd.flags();
+
+        let context = if self.transaction.is_some() {
+            acl::Context::Multi
+        } else {
+            acl::Context::Toplevel
+        };
+        if let Err(e) = self.check(client, &cmd, argv, context) {
+            return Reply::Now(self.reject(name, e));
+        }
+
+        let start = std::time::Instant::now();
+        let tracked = self.tracked_keys(client, &cmd, argv);
+
+        // Monitors are not told about themselves starting to monitor
+        if cmd != Command::Monitor {
+            self.monitors
+                .feed(self.db, monitor::Source::Client(client.addr()), argv);
+        }
+
+        let res = match cmd {
+            Command::Wait {
+                numreplicas,
+                timeout,
+            } => self.wait(Ack::Offset, numreplicas, timeout),
+            Command::WaitAof {
+                numlocal,
+                numreplicas,
+                timeout,
+            } => {
+                if numlocal > 0 {
+                    Err(MemoraError::Wait(WaitError::AppendOnlyDisabled))
                 } else {
-                    Value::null_bulk()
+                    self.wait(Ack::Fsync, numreplicas, timeout)
+                }
+            }
+            Command::ReplicaOf(target) => match self.listener.local_addr() {
+                Ok(addr) => match self.replication_tls() {
+                    Ok(tls) => {
+                        let res =
+                            self.role
+                                .replica_of(target.clone(), addr.port(), &self.reqs_tx, tls);
+                        self.config.replicaof = target;
+                        Ok(Reply::Now(res))
+                    }
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e.into()),
+            },
+            cmd => self.handle_command(client, cmd).map(Reply::Now),
+        };
+
+        if res.is_ok() {
+            self.tracking
+                .remember(client.id(), tracked.iter().map(String::as_str));
+        }
+        let res = self.record(name, start, res);
+        self.slowlog.push(
+            self.config.slowlog_log_slower_than,
+            self.config.slowlog_max_len,
+            client,
+            argv,
+            start.elapsed().as_micros() as u64,
+        );
+        let event = if flags.contains(CommandFlags::FAST) {
+            "fast-command"
+        } else {
+            "command"
+        };
+        self.sample_latency(event, start);
+        res.unwrap_or_else(|e| Reply::Now(Response::error(&e)))
+    }
+
+    /// Execute a command called by a script of `client`, with the arguments `argv`
+    fn call(&mut self, client: &ClientHandle, cmd: Command, argv: &[String]) -> Response {
+        let name = cmd.name();
+
+        if let Err(e) = self.check(client, &cmd, argv, acl::Context::Lua) {
+            return self.reject(name, e);
+        }
+
+        let start = std::time::Instant::now();
+        let tracked = self.tracked_keys(client, &cmd, argv);
+        self.monitors.feed(self.db, monitor::Source::Lua, argv);
+        let res = self.handle_command(client, cmd);
+
+        if res.is_ok() {
+            self.tracking
+                .remember(client.id(), tracked.iter().map(String::as_str));
+        }
+        self.record(name, start, res)
+            .unwrap_or_else(|e| Response::error(&e))
+    }
+
+    /// Keys read by `cmd` that `client` may have to be told about once they get modified
+    fn tracked_keys(&self, client: &ClientHandle, cmd: &Command, argv: &[String]) -> Vec<String> {
+        if self.tracking.get(client.id()).is_none() || !cmd.flags().contains(CommandFlags::READONLY)
+        {
+            return Vec::new();
+        }
+
+        cmd.keys(argv)
+            .into_iter()
+            .map(|(key, _)| key.to_owned())
+            .collect()
+    }
+
+    /// Fail the transactions watching `key` of the database `db`, and invalidate the copies of
+    /// the key cached by clients
+    fn signal_modified_key(&mut self, db: usize, key: &str) {
+        self.watches.touch(db, key);
+        self.tracking
+            .invalidate(key, self.caller, &self.clients, &self.pubsub);
+    }
+
+    /// Fail the transactions watching keys of the database `db`, or of every database, and
+    /// invalidate every key cached by clients
+    fn signal_flushed_db(&mut self, db: Option<usize>) {
+        match db {
+            Some(db) => self.watches.touch_db(db),
+            None => self.watches.touch_all(),
+        }
+        self.tracking.invalidate_all(&self.clients, &self.pubsub);
+    }
+
+    /// Reclaim the expired keys of every database that clients did not access, and notify them,
+    /// in a cycle that started at `start`
+    fn active_expire(&mut self, start: std::time::Instant) {
+        let deadline = start + CRON_INTERVAL * ACTIVE_EXPIRE_CYCLE_TIME_PERC / 100;
+        let now = Utc::now();
+
+        for db in &mut self.dbs {
+            db.expire_cycle(now, deadline);
+        }
+        self.notify_expired();
+        self.sample_latency("expire-cycle", start);
+    }
+
+    /// Notify the keys that expired since the last notification, in every database
+    fn notify_expired(&mut self) {
+        for db in 0..self.dbs.len() {
+            for key in self.dbs[db].take_expired() {
+                self.signal_modified_key(db, &key);
+                self.notify_in(db, NotifyFlags::EXPIRED, "expired", &key);
+            }
+        }
+    }
+
+    /// Sample the latency of `event`, which started at `start`
+    fn sample_latency(&mut self, event: &str, start: std::time::Instant) {
+        let latency = start.elapsed().as_millis() as u64;
+        self.latency
+            .add(self.config.latency_monitor_threshold, event, latency);
+    }
+
+    /// Account for a command that got rejected before being executed
+    fn reject(&mut self, name: &'static str, e: MemoraError) -> Response {
+        self.stats.command(name).rejected_calls += 1;
+        self.stats.error(e.code());
+        Response::error(&e)
+    }
+
+    /// Record the statistics of the `name` command that started at `start` and finished with
+    /// `res`, and notify the keys that expired while it executed
+    fn record<T>(
+        &mut self,
+        name: &'static str,
+        start: std::time::Instant,
+        res: MemoraResult<T>,
+    ) -> MemoraResult<T> {
+        self.notify_expired();
+
+        let usec = start.elapsed().as_micros() as u64;
+        self.stats.commands_processed += 1;
+
+        let stats = self.stats.command(name);
+        stats.calls += 1;
+        stats.usec += usec;
+        stats.latency.record(usec);
+
+        if let Err(e) = &res {
+            error!("error handling command: {e}");
+            stats.failed_calls += 1;
+            self.stats.error(e.code());
+        }
+
+        res
+    }
+
+    /// Execute the `commands` of a transaction of `client` as a whole, unless one of the keys it
+    /// watched got touched
+    async fn exec(&mut self, client: &ClientHandle, commands: Vec<(Command, Argv)>) -> Response {
+        let dirty = self.watches.is_dirty(client.id());
+        self.watches.unwatch(client.id());
+
+        // `EXEC` writes and grows the dataset as much as the commands it runs
+        let mut flags = Command::Exec.flags();
+        for (cmd, _) in &commands {
+            for flag in [CommandFlags::WRITE, CommandFlags::DENYOOM] {
+                if cmd.flags().contains(flag) {
+                    flags = flags | flag;
                 }
+            }
+        }
+
+        let argv = ["exec".to_owned()];
+        let context = acl::Context::Toplevel;
+        if let Err(e) = self.check_flags(client, &Command::Exec, flags, &argv, context) {
+            return self.reject("exec", e);
+        }
+
+        self.stats.commands_processed += 1;
+        self.stats.command("exec").calls += 1;
+
+        if dirty {
+            return Value::NullArray.into();
+        }
+
+        self.begin_transaction();
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for (cmd, argv) in commands {
+            let resp = match self.execute(client, cmd, &argv).await {
+                Reply::Now(resp) => resp,
+                // Clients can not block inside a transaction
+                Reply::Blocked(waiter) => waiter.reply(&self.role),
+            };
+            replies.extend(resp.0);
+        }
+
+        self.end_transaction();
+
+        Value::Array(replies).into()
+    }
+
+    /// Run a Lua `script` of `client` atomically
+    fn eval(
+        &mut self,
+        client: &ClientHandle,
+        script: Script,
+        keys: Vec<String>,
+        args: Vec<String>,
+        readonly: bool,
+    ) -> MemoraResult<Response> {
+        let scripts = Rc::clone(&self.scripts);
+        let sha = match script {
+            Script::Body(body) => scripts.load(&body)?,
+            Script::Sha(sha) => sha,
+        };
+
+        // Scripts called from a transaction are already part of it
+        let began = self.begin_transaction();
+        let db = self.db;
+
+        let res = scripts.run(&sha, keys, args, readonly, |cmd, argv| {
+            let resp = self.call(client, cmd, &argv);
+            resp.0.into_iter().next().unwrap_or_else(Value::null_bulk)
+        });
+
+        // Databases selected by the script do not outlive it
+        self.select(client, db);
+        if began {
+            self.end_transaction();
+        }
+
+        Ok(res?.into())
+    }
+
+    /// Call a `function` registered by a library on behalf of `client`, atomically
+    fn fcall(
+        &mut self,
+        client: &ClientHandle,
+        function: &str,
+        keys: Vec<String>,
+        args: Vec<String>,
+        readonly: bool,
+    ) -> MemoraResult<Response> {
+        let scripts = Rc::clone(&self.scripts);
+        let began = self.begin_transaction();
+        let db = self.db;
+
+        let res = scripts.call_function(function, keys, args, readonly, |cmd, argv| {
+            let resp = self.call(client, cmd, &argv);
+            resp.0.into_iter().next().unwrap_or_else(Value::null_bulk)
+        });
+
+        self.select(client, db);
+        if began {
+            self.end_transaction();
+        }
+
+        Ok(res?.into())
+    }
+
+    /// Run a subcommand of `ACL` on behalf of `client`
+    fn acl(&mut self, client: &ClientHandle, cmd: AclCommand) -> MemoraResult<Response> {
+        Ok(match cmd {
+            AclCommand::SetUser { username, rules } => {
+                self.acl.set_user(&username, &rules)?;
+                Response::ok()
+            }
+            AclCommand::GetUser(username) => self.acl.get_user(&username).into(),
+            AclCommand::DelUser(usernames) => {
+                let deleted = self.acl.delete_users(&usernames)?;
+                self.kill_users(&deleted);
+                Value::Int(deleted.len() as i64).into()
+            }
+            AclCommand::List => {
+                Value::from_iter(self.acl.list().into_iter().map(Value::bulk)).into()
+            }
+            AclCommand::Users => Value::from_iter(self.acl.usernames().map(Value::bulk)).into(),
+            AclCommand::WhoAmI => client
+                .user()
+                .map_or_else(Value::null_bulk, Value::bulk)
                 .into(),
-            ),
-            _ => todo!(),
+            AclCommand::Cat(category) => Acl::categories(category.as_deref())?.into(),
+            AclCommand::Log(count) => self.acl.log(count.unwrap_or(10)).into(),
+            AclCommand::LogReset => {
+                self.acl.reset_log();
+                Response::ok()
+            }
+            AclCommand::DryRun { username, args } => {
+                let cmd = Command::try_from(Value::from_iter(args.iter().map(Value::bulk)))?;
+                self.acl.dry_run(&username, &cmd, &args)?.into()
+            }
+            AclCommand::GenPass(bits) => Value::bulk(Acl::generate_password(bits)?).into(),
+            AclCommand::Save => {
+                self.acl.save()?;
+                Response::ok()
+            }
+            AclCommand::Load => {
+                let changed = self.acl.load()?;
+                self.kill_users(&changed);
+                Response::ok()
+            }
+        })
+    }
+
+    /// Select the database `db` for the following commands of `client`
+    fn select(&mut self, client: &ClientHandle, db: usize) {
+        self.db = db;
+        client.select(db);
+    }
+
+    /// Swap the content of the databases `first` and `second`
+    fn swap_db(&mut self, first: usize, second: usize) -> MemoraResult<Response> {
+        if first >= self.dbs.len() || second >= self.dbs.len() {
+            return Err(DbError::OutOfRange.into());
+        }
+
+        self.dbs.swap(first, second);
+        self.signal_flushed_db(Some(first));
+        self.signal_flushed_db(Some(second));
+        self.stats.dirty += 1;
+        self.propagate(&Value::from_iter([
+            Value::bulk("SWAPDB"),
+            Value::bulk(first),
+            Value::bulk(second),
+        ]));
+        Ok(Response::ok())
+    }
+
+    /// Move `key` from the selected database to the database `db`, unless it already exists there
+    fn move_key(&mut self, key: String, db: usize) -> MemoraResult<Response> {
+        if db >= self.dbs.len() {
+            return Err(DbError::OutOfRange.into());
+        }
+        if db == self.db {
+            return Err(DbError::SameObject.into());
+        }
+
+        let now = Utc::now();
+        let src = self.db;
+        if self.dbs[src].try_get(&key, || now, self.touch).is_none()
+            || self.dbs[db].try_get(&key, || now, self.touch).is_some()
+        {
+            return Ok(Value::Int(0).into());
+        }
+
+        let Some(entry) = self.dbs[src].remove(&key) else {
+            return Ok(Value::Int(0).into());
+        };
+        self.dbs[db].store(key.clone(), entry.value.to_string(), entry.expiry)?;
+
+        self.signal_modified_key(src, &key);
+        self.signal_modified_key(db, &key);
+        self.stats.dirty += 1;
+        self.notify_in(src, NotifyFlags::GENERIC, "move_from", &key);
+        self.notify_in(db, NotifyFlags::GENERIC, "move_to", &key);
+
+        self.propagate(&Value::from_iter([
+            Value::bulk("MOVE"),
+            Value::bulk(&key),
+            Value::bulk(db),
+        ]));
+        Ok(Value::Int(1).into())
+    }
+
+    /// Replace the content of every database by the content of `snapshot`
+    fn load(&mut self, snapshot: Snapshot) {
+        for db in &mut self.dbs {
+            db.flush(FlushMode::Sync);
+        }
+
+        for rdb::Entry {
+            db,
+            key,
+            value,
+            expiry,
+        } in snapshot.entries
+        {
+            match self.dbs.get_mut(db) {
+                Some(store) => {
+                    let _ = store.store(key, value, expiry);
+                }
+                None => warn!("ignoring key {key} of out of range database {db}"),
+            }
+        }
+    }
+
+    /// Close the connections of the clients authenticated as one of `users`
+    fn kill_users(&self, users: &[String]) {
+        for client in self.clients.values() {
+            if client.user().is_some_and(|user| users.contains(&user)) {
+                info!(
+                    "closing connection of client {} of user {:?}",
+                    client.addr(),
+                    client.user()
+                );
+                client.kill();
+            }
+        }
+    }
+
+    /// Run a subcommand of `FUNCTION`, propagating the ones that change the libraries
+    fn function(&mut self, cmd: FunctionCommand) -> MemoraResult<Response> {
+        let mut propagated = vec![Value::bulk("FUNCTION")];
+
+        let resp = match cmd {
+            FunctionCommand::Load { code, replace } => {
+                let name = self.scripts.load_library(&code, replace)?;
+                propagated.push(Value::bulk("LOAD"));
+                if replace {
+                    propagated.push(Value::bulk("REPLACE"));
+                }
+                propagated.push(Value::bulk(code));
+                Value::bulk(name).into()
+            }
+            FunctionCommand::List { pattern, withcode } => {
+                return Ok(self
+                    .scripts
+                    .list_libraries(pattern.as_deref(), withcode)
+                    .into())
+            }
+            FunctionCommand::Delete(name) => {
+                self.scripts.delete_library(&name)?;
+                propagated.extend([Value::bulk("DELETE"), Value::bulk(name)]);
+                Response::ok()
+            }
+            FunctionCommand::Flush => {
+                self.scripts.flush_libraries();
+                propagated.push(Value::bulk("FLUSH"));
+                Response::ok()
+            }
+            FunctionCommand::Dump => return Ok(Value::bulk(self.scripts.dump_libraries()).into()),
+            FunctionCommand::Restore { payload, policy } => {
+                self.scripts.restore_libraries(&payload, policy)?;
+                let policy = match policy {
+                    RestorePolicy::Append => "APPEND",
+                    RestorePolicy::Replace => "REPLACE",
+                    RestorePolicy::Flush => "FLUSH",
+                };
+                propagated.extend([
+                    Value::bulk("RESTORE"),
+                    Value::bulk(payload),
+                    Value::bulk(policy),
+                ]);
+                Response::ok()
+            }
+        };
+
+        self.stats.dirty += 1;
+        self.propagate(&Value::Array(propagated));
+        Ok(resp)
+    }
+
+    /// Start wrapping the writes propagated to our replicas in a `MULTI`/`EXEC` block, unless a
+    /// block is already started. Returns whether it started a new block
+    fn begin_transaction(&mut self) -> bool {
+        let begin = self.transaction.is_none();
+        if begin {
+            self.transaction = Some(false);
+        }
+        begin
+    }
+
+    /// Close the `MULTI`/`EXEC` block started by [`Self::begin_transaction`]
+    fn end_transaction(&mut self) {
+        if self.transaction.take() == Some(true) {
+            self.role
+                .propagate(&Value::from_iter([Value::bulk("EXEC")]));
+        }
+    }
+
+    /// Propagate a write `command` to our replicas, wrapping the writes of a transaction in a
+    /// `MULTI`/`EXEC` block so that replicas apply them atomically as well. Replicas are told to
+    /// select the database of the command first if it changed
+    fn propagate(&mut self, command: &Value) {
+        if self.transaction == Some(false) {
+            self.role
+                .propagate(&Value::from_iter([Value::bulk("MULTI")]));
+            self.transaction = Some(true);
+        }
+
+        if self.propagated_db != Some(self.db) {
+            self.role.propagate(&Value::from_iter([
+                Value::bulk("SELECT"),
+                Value::bulk(self.db),
+            ]));
+            self.propagated_db = Some(self.db);
+        }
+
+        self.role.propagate(command);
+    }
+
+    /// Publish a keyspace `event` of the given `class` on `key` of the selected database
+    fn notify(&self, class: NotifyFlags, event: &str, key: &str) {
+        self.notify_in(self.db, class, event, key);
+    }
+
+    /// Publish a keyspace `event` of the given `class` on `key` of the database `db`
+    fn notify_in(&self, db: usize, class: NotifyFlags, event: &str, key: &str) {
+        let flags = self.config.notify_keyspace_events;
+        if !flags.notifies(class) {
+            return;
+        }
+
+        for (channel, message) in notify::channels(flags, db, event, key) {
+            self.pubsub.publish(&channel, &message);
+        }
+    }
+
+    /// Check whether `client` is allowed to run `cmd` given its permissions and the role of this
+    /// instance
+    fn check(
+        &mut self,
+        client: &ClientHandle,
+        cmd: &Command,
+        argv: &[String],
+        context: acl::Context,
+    ) -> MemoraResult<()> {
+        self.check_flags(client, cmd, cmd.flags(), argv, context)
+    }
+
+    /// Same as [`Self::check`], with `flags` standing for the flags of `cmd`
+    fn check_flags(
+        &mut self,
+        client: &ClientHandle,
+        cmd: &Command,
+        flags: CommandFlags,
+        argv: &[String],
+        context: acl::Context,
+    ) -> MemoraResult<()> {
+        // Our master is the one that writes to us
+        if !client.is_master() {
+            self.acl.check(client, cmd, argv, context)?;
+        }
+
+        if self.pubsub.subscriptions(client.id()) > 0 && !cmd.allowed_when_subscribed() {
+            return Err(PubSubError::SubscriberMode(cmd.name()).into());
+        }
+
+        // Make room before running the command, refusing the commands that may grow the dataset
+        // when we can not. Scripts got checked when they started
+        if context != acl::Context::Lua
+            && !client.is_master()
+            && !self.evict()
+            && flags.contains(CommandFlags::DENYOOM)
+        {
+            return Err(EvictError::Oom.into());
         }
+
+        let AnyRole::Replica(replica) = &self.role else {
+            return Ok(());
+        };
+
+        // Our master is the one that writes to us
+        if client.is_master() {
+            return Ok(());
+        }
+
+        if self.config.replica_read_only && flags.contains(CommandFlags::WRITE) {
+            return Err(ReplicaError::ReadOnly.into());
+        }
+
+        if !self.config.replica_serve_stale_data
+            && !flags.contains(CommandFlags::STALE)
+            && !replica.link_up()
+        {
+            return Err(ReplicaError::MasterDown.into());
+        }
+
+        Ok(())
+    }
+
+    /// Run a subcommand of `LATENCY`
+    fn latency(&mut self, cmd: LatencyCommand) -> MemoraResult<Response> {
+        Ok(match cmd {
+            LatencyCommand::Latest => self.latency.latest().into(),
+            LatencyCommand::History(event) => self.latency.history(&event).into(),
+            LatencyCommand::Reset(events) => Value::Int(self.latency.reset(&events) as i64).into(),
+            LatencyCommand::Graph(event) => Value::bulk(self.latency.graph(&event)?).into(),
+            LatencyCommand::Doctor => {
+                Value::bulk(self.latency.doctor(self.config.latency_monitor_threshold)).into()
+            }
+            LatencyCommand::Histogram(names) => {
+                Value::from_iter(
+                    self.stats
+                        .commands
+                        .iter()
+                        .filter(|(name, stats)| {
+                            stats.calls > 0
+                                && (names.is_empty() || names.iter().any(|n| n == *name))
+                        })
+                        .flat_map(|(name, stats)| {
+                            let buckets = stats.latency.cumulative().into_iter().flat_map(
+                                |(bound, count)| {
+                                    [Value::Int(bound as i64), Value::Int(count as i64)]
+                                },
+                            );
+                            [
+                                Value::bulk(name),
+                                Value::from_iter([
+                                    Value::bulk("calls"),
+                                    Value::Int(stats.latency.total() as i64),
+                                    Value::bulk("histogram_usec"),
+                                    Value::from_iter(buckets),
+                                ]),
+                            ]
+                        }),
+                )
+                .into()
+            }
+        })
+    }
+
+    /// Run a subcommand of `COMMAND`
+    fn command(&self, cmd: CommandCommand) -> MemoraResult<Response> {
+        Ok(match cmd {
+            CommandCommand::All => {
+                Value::from_iter(table::COMMANDS.iter().map(CommandSpec::info)).into()
+            }
+            CommandCommand::Count => Value::Int(table::COMMANDS.len() as i64).into(),
+            CommandCommand::List(filter) => Value::from_iter(
+                table::all()
+                    .filter(|spec| match &filter {
+                        None => true,
+                        // Modules are not supported
+                        Some(CommandFilter::Module(_)) => false,
+                        Some(CommandFilter::AclCat(category)) => spec
+                            .categories
+                            .iter()
+                            .any(|c| c.eq_ignore_ascii_case(category)),
+                        Some(CommandFilter::Pattern(pattern)) => {
+                            glob::matches(pattern.as_bytes(), spec.name.as_bytes())
+                        }
+                    })
+                    .map(|spec| Value::bulk(spec.name)),
+            )
+            .into(),
+            CommandCommand::Info(names) if names.is_empty() => {
+                Value::from_iter(table::COMMANDS.iter().map(CommandSpec::info)).into()
+            }
+            CommandCommand::Info(names) => {
+                Value::from_iter(names.iter().map(|name| {
+                    table::lookup(name).map_or_else(Value::null_bulk, CommandSpec::info)
+                }))
+                .into()
+            }
+            CommandCommand::Docs(names) => {
+                let specs = if names.is_empty() {
+                    table::COMMANDS.iter().collect()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| table::lookup(name))
+                        .collect::<Vec<_>>()
+                };
+                Value::from_iter(
+                    specs
+                        .into_iter()
+                        .flat_map(|spec| [Value::bulk(spec.name), spec.docs()]),
+                )
+                .into()
+            }
+            CommandCommand::GetKeys(argv) => Value::from_iter(
+                table::keys(&argv)?
+                    .into_iter()
+                    .map(|(key, _)| Value::bulk(key)),
+            )
+            .into(),
+            CommandCommand::GetKeysAndFlags(argv) => {
+                Value::from_iter(table::keys(&argv)?.into_iter().map(|(key, flags)| {
+                    Value::from_iter([
+                        Value::bulk(key),
+                        Value::from_iter(flags.iter().map(|flag| Value::simple(*flag))),
+                    ])
+                }))
+                .into()
+            }
+        })
+    }
+
+    /// Run a subcommand of `CONFIG`
+    fn config(&mut self, cmd: ConfigCommand) -> MemoraResult<Response> {
+        match cmd {
+            ConfigCommand::Get(patterns) => Ok(Value::from_iter(
+                self.config
+                    .get(&patterns)
+                    .into_iter()
+                    .flat_map(|(name, value)| [Value::bulk(name), Value::bulk(value)]),
+            )
+            .into()),
+            ConfigCommand::Set(pairs) => {
+                let old = self.config.clone();
+                self.config.set(&pairs)?;
+                self.reconfigure(&old);
+                Ok(Response::ok())
+            }
+            ConfigCommand::Rewrite => {
+                self.config.rewrite()?;
+                info!("CONFIG REWRITE executed with success");
+                Ok(Response::ok())
+            }
+            ConfigCommand::ResetStat => {
+                self.stats.reset();
+                self.net.reset();
+                self.role.reset_stats();
+                self.acl.denials = Default::default();
+                for db in &mut self.dbs {
+                    db.expired = 0;
+                }
+                self.peak_memory = self.used_memory();
+                Ok(Response::ok())
+            }
+        }
+    }
+
+    /// Apply the parameters that changed from the `old` configuration
+    fn reconfigure(&mut self, old: &Config) {
+        if self.config.loglevel != old.loglevel {
+            log::set_level(self.config.loglevel);
+        }
+
+        if self.config.requirepass != old.requirepass {
+            self.acl.set_requirepass(self.config.requirepass.as_deref());
+        }
+
+        if self.config.lfu != old.lfu {
+            for db in &mut self.dbs {
+                db.set_lfu(self.config.lfu);
+            }
+        }
+
+        // Candidates sampled for another policy were scored differently, or may not be volatile
+        if self.config.maxmemory_policy != old.maxmemory_policy {
+            self.eviction_pool.clear();
+        }
+
+        // Shrinking the memory limit evicts keys right away rather than on the next write
+        if (self.config.maxmemory, self.config.maxmemory_policy)
+            != (old.maxmemory, old.maxmemory_policy)
+            && !self.evict()
+        {
+            warn!("used memory stays above maxmemory after changing the configuration");
+        }
+    }
+
+    /// Run a subcommand of `CLIENT` on behalf of `client`
+    fn client(&mut self, client: &ClientHandle, cmd: ClientCommand) -> MemoraResult<Response> {
+        Ok(match cmd {
+            ClientCommand::Id => Value::Int(client.id() as i64).into(),
+            ClientCommand::SetName(name) => {
+                client.record().name = (!name.is_empty()).then_some(name);
+                Response::ok()
+            }
+            ClientCommand::GetName => match client.record().name.clone() {
+                Some(name) => Value::bulk(name),
+                None => Value::null_bulk(),
+            }
+            .into(),
+            ClientCommand::List { ty, ids } => {
+                let mut clients = self
+                    .clients
+                    .values()
+                    .filter(|other| ids.is_empty() || ids.contains(&other.id()))
+                    .filter(|other| ty.is_none_or(|ty| self.client_type(other) == ty))
+                    .collect::<Vec<_>>();
+                clients.sort_by_key(|other| other.id());
+
+                let list = clients
+                    .into_iter()
+                    .map(|other| self.describe_client(other) + "\n")
+                    .collect::<String>();
+                Value::bulk(list).into()
+            }
+            ClientCommand::Info => Value::bulk(self.describe_client(client) + "\n").into(),
+            ClientCommand::Kill {
+                addr: Some(addr), ..
+            } => {
+                let killed = self.kill_clients(
+                    client,
+                    &KillFilter {
+                        addr: Some(addr),
+                        skipme: false,
+                        ..KillFilter::default()
+                    },
+                );
+                if killed == 0 {
+                    return Err(CommandError::from(ClientError::NoSuchClient).into());
+                }
+                Response::ok()
+            }
+            ClientCommand::Kill { addr: None, filter } => {
+                Value::Int(self.kill_clients(client, &filter) as i64).into()
+            }
+            ClientCommand::Pause { timeout, mode } => {
+                self.pause = Some(Pause {
+                    mode,
+                    until: Instant::now() + Duration::from_millis(timeout),
+                });
+                Response::ok()
+            }
+            ClientCommand::Unpause => {
+                // Held back requests get handled once back in the server loop
+                if let Some(pause) = &mut self.pause {
+                    pause.until = Instant::now();
+                }
+                Response::ok()
+            }
+            ClientCommand::NoEvict(on) => {
+                client.record().no_evict = on;
+                Response::ok()
+            }
+            ClientCommand::NoTouch(on) => {
+                client.record().no_touch = on;
+                Response::ok()
+            }
+            // Sessions handle the replies of their client on their own
+            ClientCommand::Reply(_) => Response::ok(),
+            ClientCommand::Tracking { on: true, options } => {
+                self.enable_tracking(client, options)?;
+                Response::ok()
+            }
+            ClientCommand::Tracking { on: false, .. } => {
+                self.tracking.disable(client.id());
+                Response::ok()
+            }
+            ClientCommand::Caching(yes) => {
+                self.tracking.caching(client.id(), yes)?;
+                Response::ok()
+            }
+            ClientCommand::TrackingInfo => self.tracking_info(client).into(),
+            ClientCommand::GetRedir => {
+                let redirect = match self.tracking.get(client.id()) {
+                    None => -1,
+                    Some(tracker) => tracker.redirect.map_or(0, |id| id as i64),
+                };
+                Value::Int(redirect).into()
+            }
+            ClientCommand::SetInfo { attr, value } => {
+                let mut record = client.record();
+                let value = (!value.is_empty()).then_some(value);
+                match attr {
+                    ClientInfoAttr::LibName => record.lib_name = value,
+                    ClientInfoAttr::LibVer => record.lib_ver = value,
+                }
+                Response::ok()
+            }
+        })
+    }
+
+    /// Switch `client` to `protover`, after authenticating it and naming it, and describe the
+    /// server in the protocol the client now speaks
+    fn hello(
+        &mut self,
+        client: &ClientHandle,
+        protover: Option<u8>,
+        auth: Option<(String, String)>,
+        setname: Option<String>,
+    ) -> MemoraResult<Response> {
+        if let Some((username, password)) = auth {
+            self.acl.authenticate(client, Some(&username), &password)?;
+        }
+
+        let mut record = client.record();
+        if let Some(protover) = protover {
+            record.resp = protover;
+        }
+        if let Some(name) = setname {
+            record.name = (!name.is_empty()).then_some(name);
+        }
+
+        let role = match self.role {
+            AnyRole::Master(_) => "master",
+            AnyRole::Replica(_) => "replica",
+        };
+        let fields = [
+            ("server", Value::bulk("redis")),
+            ("version", Value::bulk("7.2.0")),
+            ("proto", Value::Int(record.resp.into())),
+            ("id", Value::Int(client.id() as i64)),
+            ("mode", Value::bulk("standalone")),
+            ("role", Value::bulk(role)),
+            ("modules", Value::Array(Vec::new())),
+        ];
+
+        Ok(if record.resp == 3 {
+            Value::Map(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (Value::bulk(field), value))
+                    .collect(),
+            )
+        } else {
+            Value::Array(
+                fields
+                    .into_iter()
+                    .flat_map(|(field, value)| [Value::bulk(field), value])
+                    .collect(),
+            )
+        }
+        .into())
+    }
+
+    /// Turn on the tracking of the keys cached by `client`
+    fn enable_tracking(
+        &mut self,
+        client: &ClientHandle,
+        options: TrackingOptions,
+    ) -> MemoraResult<()> {
+        if options
+            .redirect
+            .is_some_and(|redirect| !self.clients.contains_key(&redirect))
+        {
+            return Err(TrackingError::NoSuchRedirect.into());
+        }
+        if options.redirect.is_none() && client.record().resp < 3 {
+            return Err(TrackingError::NoRedirectOnResp2.into());
+        }
+
+        self.tracking.enable(client.id(), options)?;
+        Ok(())
+    }
+
+    /// Reply of `CLIENT TRACKINGINFO`
+    fn tracking_info(&self, client: &ClientHandle) -> Value {
+        let tracker = self.tracking.get(client.id());
+
+        let mut flags = Vec::new();
+        let (redirect, prefixes) = match tracker {
+            None => {
+                flags.push("off");
+                (-1, Vec::new())
+            }
+            Some(tracker) => {
+                flags.push("on");
+                if tracker.bcast {
+                    flags.push("bcast");
+                }
+                if tracker.optin {
+                    flags.push("optin");
+                    if tracker.caching == Some(true) {
+                        flags.push("caching-yes");
+                    }
+                }
+                if tracker.optout {
+                    flags.push("optout");
+                    if tracker.caching == Some(false) {
+                        flags.push("caching-no");
+                    }
+                }
+                if tracker.noloop {
+                    flags.push("noloop");
+                }
+                if tracker.broken_redirect {
+                    flags.push("broken_redirect");
+                }
+
+                (
+                    tracker.redirect.map_or(0, |id| id as i64),
+                    tracker.prefixes.iter().map(Value::bulk).collect(),
+                )
+            }
+        };
+
+        Value::from_iter([
+            Value::bulk("flags"),
+            Value::from_iter(flags.into_iter().map(Value::bulk)),
+            Value::bulk("redirect"),
+            Value::Int(redirect),
+            Value::bulk("prefixes"),
+            Value::Array(prefixes),
+        ])
+    }
+
+    fn client_type(&self, client: &ClientHandle) -> ClientType {
+        client::client_type(client, self.pubsub.subscriptions(client.id()))
+    }
+
+    /// Line describing `client` in the replies of `CLIENT LIST` and `CLIENT INFO`
+    fn describe_client(&self, client: &ClientHandle) -> String {
+        client::describe(
+            client,
+            self.pubsub.subscriptions_by_kind(client.id()),
+            self.tracking.get(client.id()),
+        )
+    }
+
+    /// Close the connection of the clients matching `filter`, on behalf of `caller`. Returns the
+    /// number of clients killed
+    fn kill_clients(&self, caller: &ClientHandle, filter: &KillFilter) -> usize {
+        let matches = |client: &ClientHandle| {
+            let record = client.record();
+            let laddr = record.laddr.as_ref().map(ToString::to_string);
+
+            filter.id.is_none_or(|id| client.id() == id)
+                && filter
+                    .addr
+                    .as_ref()
+                    .is_none_or(|addr| client.addr().to_string() == *addr)
+                && filter
+                    .laddr
+                    .as_ref()
+                    .is_none_or(|addr| laddr.as_ref() == Some(addr))
+                && filter
+                    .maxage
+                    .is_none_or(|maxage| record.age().as_secs() > maxage)
+        };
+
+        let mut killed = 0;
+        for client in self.clients.values() {
+            if filter.skipme && client.id() == caller.id() {
+                continue;
+            }
+            if !matches(client)
+                || filter
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| client.user().as_ref() != Some(user))
+                || filter.ty.is_some_and(|ty| self.client_type(client) != ty)
+            {
+                continue;
+            }
+
+            info!(
+                "killing client {} on behalf of {}",
+                client.addr(),
+                caller.addr()
+            );
+            client.kill();
+            killed += 1;
+        }
+        killed
+    }
+
+    /// Approximate number of bytes used by the dataset
+    fn used_memory(&self) -> usize {
+        self.dbs.iter().map(|db| db.memory).sum()
+    }
+
+    /// Evict keys with the `maxmemory-policy` until the dataset fits in `maxmemory`. Returns
+    /// whether it does
+    fn evict(&mut self) -> bool {
+        self.evict_clients();
+
+        let maxmemory = self.config.maxmemory;
+
+        // Replicas get their keys evicted by their master, and nothing gets evicted while clients
+        // are paused
+        if maxmemory == 0 || matches!(self.role, AnyRole::Replica(_)) || self.pause.is_some() {
+            return true;
+        }
+
+        let policy = self.config.maxmemory_policy;
+        if self.used_memory() <= maxmemory {
+            return true;
+        }
+        if policy == EvictionPolicy::NoEviction {
+            return false;
+        }
+
+        let start = std::time::Instant::now();
+        let mut fits = true;
+        while self.used_memory() > maxmemory {
+            let Some((db, key)) = self.eviction_candidate(policy) else {
+                fits = false;
+                break;
+            };
+            self.evict_key(db, key);
+        }
+
+        self.sample_latency("eviction-cycle", start);
+        fits
+    }
+
+    /// Disconnect the clients using the most memory until the buffers of every client fit in
+    /// `maxmemory-clients`. Our master, our replicas and clients that turned on
+    /// `CLIENT NO-EVICT` are never disconnected
+    fn evict_clients(&mut self) {
+        let limit = self.config.maxmemory_clients;
+        if limit == 0 {
+            return;
+        }
+
+        let mut used = 0;
+        let mut candidates = Vec::new();
+        // Clients killed earlier free their buffers once their connection closes
+        for client in self.clients.values().filter(|client| !client.is_killed()) {
+            let record = client.record();
+            let memory = record.memory();
+            used += memory;
+            if !client.is_master() && !record.replica && !record.no_evict {
+                candidates.push((memory, client));
+            }
+        }
+
+        candidates.sort_unstable_by_key(|(memory, _)| std::cmp::Reverse(*memory));
+        for (memory, client) in candidates {
+            if used <= limit {
+                break;
+            }
+            warn!(
+                "evicting client {} using {memory} bytes to stay under maxmemory-clients",
+                client.addr()
+            );
+            client.kill();
+            used -= memory;
+            self.stats.evicted_clients += 1;
+        }
+    }
+
+    /// Pick the next key to evict with `policy`, as its database and key
+    fn eviction_candidate(&mut self, policy: EvictionPolicy) -> Option<(usize, String)> {
+        let volatile = policy.volatile();
+
+        if policy.random() {
+            // Start from a random database so that every database gets evicted from
+            let count = self.dbs.len();
+            let start = rand::thread_rng().gen_range(0..count);
+            return (0..count).map(|i| (start + i) % count).find_map(|db| {
+                let sample = self.dbs[db].sample(volatile, 1);
+                sample.first().map(|(key, _)| (db, (*key).to_owned()))
+            });
+        }
+
+        let (lfu, samples) = (self.config.lfu, self.config.maxmemory_samples);
+        for (db, store) in self.dbs.iter().enumerate() {
+            for (key, entry) in store.sample(volatile, samples) {
+                let score = evict::score(policy, &entry.header.access, entry.expiry, lfu);
+                self.eviction_pool.insert(score, db, key);
+            }
+        }
+
+        // Candidates may have been removed, or may have lost their expiry, since they got sampled
+        while let Some((db, key)) = self.eviction_pool.pop() {
+            let store = &self.dbs[db];
+            if store.exists(&key) && (!volatile || store.is_volatile(&key)) {
+                return Some((db, key));
+            }
+        }
+        None
+    }
+
+    /// Evict `key` from the database `db`, telling our replicas to delete it as well
+    fn evict_key(&mut self, db: usize, key: String) {
+        self.dbs[db].remove(&key);
+        self.stats.evicted_keys += 1;
+        self.signal_modified_key(db, &key);
+        self.notify_in(db, NotifyFlags::EVICTED, "evicted", &key);
+
+        let selected = mem::replace(&mut self.db, db);
+        self.propagate(&Value::from_iter([Value::bulk("DEL"), Value::bulk(key)]));
+        self.db = selected;
+    }
+
+    /// Remove the `keys` of the selected database that exist, returning how many got removed
+    fn del(&mut self, keys: Vec<String>) -> Response {
+        let (db, now) = (self.db, Utc::now());
+
+        let mut deleted = Vec::new();
+        for key in keys {
+            if self.dbs[db].peek(&key, now).is_none() {
+                continue;
+            }
+
+            self.dbs[db].remove(&key);
+            self.signal_modified_key(db, &key);
+            self.stats.dirty += 1;
+            self.notify(NotifyFlags::GENERIC, "del", &key);
+            deleted.push(key);
+        }
+
+        let count = deleted.len();
+        if count > 0 {
+            self.propagate(&Value::from_iter(
+                std::iter::once(Value::bulk("DEL")).chain(deleted.into_iter().map(Value::bulk)),
+            ));
+        }
+        Value::Int(count as i64).into()
+    }
+
+    /// Report the internals of a key of the selected database, from the header of its entry
+    fn object(&mut self, cmd: ObjectCommand) -> MemoraResult<Response> {
+        let (ObjectCommand::Freq(key)
+        | ObjectCommand::IdleTime(key)
+        | ObjectCommand::Encoding(key)
+        | ObjectCommand::RefCount(key)) = &cmd;
+        let Some(entry) = self.dbs[self.db].peek(key, Utc::now()) else {
+            return Ok(Value::null_bulk().into());
+        };
+        let header = entry.header;
+
+        let lfu = self.config.maxmemory_policy.lfu();
+        match cmd {
+            ObjectCommand::Freq(_) if !lfu => Err(EvictError::LfuNotSelected.into()),
+            ObjectCommand::Freq(_) => {
+                Ok(Value::Int(i64::from(header.access.frequency(self.config.lfu))).into())
+            }
+            ObjectCommand::IdleTime(_) if lfu => Err(EvictError::LfuSelected.into()),
+            ObjectCommand::IdleTime(_) => Ok(Value::Int(header.access.idle() as i64).into()),
+            ObjectCommand::Encoding(_) => Ok(Value::bulk(header.encoding.name()).into()),
+            // Values are never shared between keys
+            ObjectCommand::RefCount(_) => Ok(Value::Int(1).into()),
+        }
+    }
+
+    /// Breakdown of the memory used by the server
+    fn memory_stats(&mut self) -> MemoryStats {
+        let dbs = self
+            .dbs
+            .iter()
+            .enumerate()
+            .filter(|(_, store)| store.len() > 0)
+            .map(|(db, store)| {
+                let (main, expires) = store.overhead();
+                DbMemory { db, main, expires }
+            })
+            .collect();
+
+        let mut stats = MemoryStats {
+            peak: self.peak_memory,
+            dataset: self.used_memory(),
+            keys: self.dbs.iter().map(StringStore::len).sum(),
+            backlog: self.role.backlog_memory(),
+            scripts: self.scripts.memory(),
+            dbs,
+            rss: ProcessUsage::current().rss as usize,
+        };
+
+        self.peak_memory = self.peak_memory.max(stats.total());
+        stats.peak = self.peak_memory;
+        stats
+    }
+
+    fn memory(&mut self, cmd: MemoryCommand) -> Response {
+        match cmd {
+            MemoryCommand::Usage { key, .. } => match self.dbs[self.db].peek(&key, Utc::now()) {
+                Some(entry) => Value::Int(Entry::memory(&key, entry) as i64),
+                None => Value::null_bulk(),
+            }
+            .into(),
+            MemoryCommand::Stats => self.memory_stats().reply().into(),
+            MemoryCommand::Doctor => Value::bulk(self.memory_stats().doctor()).into(),
+        }
+    }
+
+    /// Block the client until `numreplicas` replicas sent an `ack` acknowledgement for the current
+    /// replication offset, or until `timeout` milliseconds elapsed
+    fn wait(&mut self, ack: Ack, numreplicas: usize, timeout: u64) -> MemoraResult<Reply> {
+        let offset = self.role.wait_offset()?;
+
+        let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout));
+        let waiter = Waiter::new(ack, offset, numreplicas, deadline);
+        if waiter.satisfied(&self.role) {
+            return Ok(Reply::Now(waiter.reply(&self.role)));
+        }
+
+        self.role.request_acks();
+        Ok(Reply::Blocked(waiter))
+    }
+
+    /// Connector to our master when the replication link must use TLS
+    fn replication_tls(&self) -> Result<Option<TlsConnector>, TlsError> {
+        let tls = &self.config.tls;
+        tls.replication.then(|| tls.connector()).transpose()
+    }
+
+    /// Complete the TLS handshake of a client connected to the TLS port in the background, before
+    /// handling its connection
+    fn handshake(&self, socket: TcpStream, addr: SocketAddr) {
+        let Some(tls) = &self.tls else {
+            return;
+        };
+
+        let acceptor = tls.acceptor.clone();
+        let handshakes = self.handshakes_tx.clone();
+        tokio::spawn(async move {
+            match acceptor.accept(socket).await {
+                Ok(stream) => {
+                    let _ = handshakes.send((stream, addr)).await;
+                }
+                Err(e) => warn!("TLS handshake with {addr} failed: {e}"),
+            }
+        });
+    }
+
+    /// Serve a client connected from `addr` to our address `laddr`
+    fn handle_connection<S>(
+        &mut self,
+        socket: S,
+        addr: impl Into<ClientAddr>,
+        laddr: impl Into<ClientAddr>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static,
+    {
+        let addr = addr.into();
+        info!("got new connection from {addr}");
+
+        self.stats.connections_received += 1;
+
+        let (client, push_rx) = ClientHandle::new(addr);
+        client.record().laddr = Some(laddr.into());
+        if let Some(user) = self.acl.default_user() {
+            client.authenticate(user.to_owned());
+        }
+        self.clients.insert(client.id(), client.clone());
+        let session = Session::new(
+            socket,
+            client,
+            push_rx,
+            self.reqs_tx.clone(),
+            Arc::clone(&self.net),
+            Arc::clone(&self.running),
+        );
+        self.sessions.push(tokio::spawn(session.run()));
+    }
+
+    fn handle_command(&mut self, client: &ClientHandle, cmd: Command) -> MemoraResult<Response> {
+        match cmd {
+            // Subscribed clients can only receive arrays in RESP2
+            Command::Ping(msg) if self.pubsub.subscriptions(client.id()) > 0 => Ok(
+                Value::from_iter([Value::bulk("pong"), Value::bulk(msg.unwrap_or_default())])
+                    .into(),
+            ),
+            Command::Ping(msg) => Ok(if let Some(msg) = msg {
+                Value::from_iter([Value::bulk("PONG"), Value::bulk(msg)])
+            } else {
+                Value::Str(StringValue::Simple("PONG".to_owned()))
+            }
+            .into()),
+            Command::Echo(msg) => Ok(Value::bulk(msg).into()),
+            Command::Info { sections } => Ok(Value::bulk(self.info(&sections)?).into()),
+            Command::Set { key, value, expiry } => {
+                let expiry = expiry
+                    .map(|expiry| expiry.into_utc().ok_or(SetError::InvalidExpireTime))
+                    .transpose()
+                    .map_err(CommandError::from)?;
+
+                // Propagate relative expiries as absolute timestamps so that replicas expire the
+                // key at the same time as we do
+                let mut propagated =
+                    vec![Value::bulk("SET"), Value::bulk(&key), Value::bulk(&value)];
+                if let Some(expiry) = expiry {
+                    propagated
+                        .extend([Value::bulk("PXAT"), Value::bulk(expiry.timestamp_millis())]);
+                }
+
+                let db = self.db;
+                let new = !self.dbs[db].exists(&key);
+                self.dbs[db].store(key.clone(), value, expiry)?;
+                self.signal_modified_key(db, &key);
+                self.stats.dirty += 1;
+
+                if new {
+                    self.notify(NotifyFlags::NEW, "new", &key);
+                }
+                self.notify(NotifyFlags::STRING, "set", &key);
+                if expiry.is_some() {
+                    self.notify(NotifyFlags::GENERIC, "expire", &key);
+                }
+
+                self.propagate(&Value::Array(propagated));
+                Ok(Value::Str(StringValue::Simple("OK".to_owned())).into())
+            }
+            Command::Get { key } => Ok(if let Some(value) =
+                self.dbs[self.db].try_get(&key, Utc::now, self.touch)
+            {
+                self.stats.keyspace_hits += 1;
+                Value::bulk(value)
+            } else {
+                self.stats.keyspace_misses += 1;
+                self.notify(NotifyFlags::KEY_MISS, "keymiss", &key);
+                Value::null_bulk()
+            }
+            .into()),
+            Command::Replconf(options) => {
+                let resp = self.role.replconf(client, options);
+                self.waiters.unblock(&self.role, Instant::now());
+                Ok(resp)
+            }
+            Command::Psync { replid, offset } => {
+                // Make sure the stream sent to the new replica starts by selecting a database
+                self.propagated_db = None;
+                client.record().replica = true;
+
+                let (dbs, scripts) = (&self.dbs, &self.scripts);
+                self.role.psync(client.clone(), &replid, offset, || {
+                    let now = Utc::now();
+                    Snapshot {
+                        entries: dbs
+                            .iter()
+                            .enumerate()
+                            .flat_map(|(db, store)| store.snapshot(db, now))
+                            .collect(),
+                        functions: scripts.library_codes(),
+                    }
+                })
+            }
+            Command::Subscribe(channels) => Ok(Response::many(self.pubsub.subscribe(
+                client,
+                pubsub::Kind::Channel,
+                channels,
+            ))),
+            Command::Unsubscribe(channels) => Ok(Response::many(self.pubsub.unsubscribe(
+                client,
+                pubsub::Kind::Channel,
+                channels,
+            ))),
+            Command::PSubscribe(patterns) => Ok(Response::many(self.pubsub.subscribe(
+                client,
+                pubsub::Kind::Pattern,
+                patterns,
+            ))),
+            Command::PUnsubscribe(patterns) => Ok(Response::many(self.pubsub.unsubscribe(
+                client,
+                pubsub::Kind::Pattern,
+                patterns,
+            ))),
+            Command::SSubscribe(channels) => Ok(Response::many(self.pubsub.subscribe(
+                client,
+                pubsub::Kind::Shard,
+                channels,
+            ))),
+            Command::SUnsubscribe(channels) => Ok(Response::many(self.pubsub.unsubscribe(
+                client,
+                pubsub::Kind::Shard,
+                channels,
+            ))),
+            Command::SPublish { channel, message } => {
+                let received = self.pubsub.spublish(&channel, &message);
+
+                // Like Redis Cluster, replicas of the shard deliver the message to their own
+                // subscribers
+                self.propagate(&Value::from_iter([
+                    Value::bulk("SPUBLISH"),
+                    Value::bulk(channel),
+                    Value::bulk(message),
+                ]));
+                Ok(Value::Int(received as i64).into())
+            }
+            Command::Publish { channel, message } => {
+                let received = self.pubsub.publish(&channel, &message);
+
+                // Replicas deliver the messages published on their master to their own subscribers
+                self.propagate(&Value::from_iter([
+                    Value::bulk("PUBLISH"),
+                    Value::bulk(channel),
+                    Value::bulk(message),
+                ]));
+                Ok(Value::Int(received as i64).into())
+            }
+            Command::PubSub(PubSubCommand::Channels(pattern)) => Ok(Value::from_iter(
+                self.pubsub
+                    .channels(pattern.as_deref())
+                    .into_iter()
+                    .map(Value::bulk),
+            )
+            .into()),
+            Command::PubSub(PubSubCommand::NumSub(channels)) => Ok(Value::Array(
+                channels
+                    .into_iter()
+                    .flat_map(|channel| {
+                        let count = self.pubsub.numsub(&channel);
+                        [Value::bulk(channel), Value::Int(count as i64)]
+                    })
+                    .collect(),
+            )
+            .into()),
+            Command::PubSub(PubSubCommand::NumPat) => {
+                Ok(Value::Int(self.pubsub.numpat() as i64).into())
+            }
+            Command::PubSub(PubSubCommand::ShardChannels(pattern)) => Ok(Value::from_iter(
+                self.pubsub
+                    .shard_channels(pattern.as_deref())
+                    .into_iter()
+                    .map(Value::bulk),
+            )
+            .into()),
+            Command::PubSub(PubSubCommand::ShardNumSub(channels)) => Ok(Value::Array(
+                channels
+                    .into_iter()
+                    .flat_map(|channel| {
+                        let count = self.pubsub.shard_numsub(&channel);
+                        [Value::bulk(channel), Value::Int(count as i64)]
+                    })
+                    .collect(),
+            )
+            .into()),
+            Command::Watch(keys) => {
+                self.watches.watch(client.id(), self.db, keys);
+                Ok(Response::ok())
+            }
+            Command::Unwatch | Command::Discard => {
+                self.watches.unwatch(client.id());
+                Ok(Response::ok())
+            }
+            // Sessions queue transactions on their own and send them as a whole
+            Command::Multi => Ok(Response::ok()),
+            Command::Exec => Err(MultiError::ExecWithoutMulti.into()),
+            // Sessions close their connection on their own
+            Command::Quit => Ok(Response::ok()),
+            Command::Eval {
+                script,
+                keys,
+                args,
+                readonly,
+            } => self.eval(client, script, keys, args, readonly),
+            Command::Script(ScriptCommand::Load(body)) => {
+                Ok(Value::bulk(self.scripts.load(&body)?).into())
+            }
+            Command::Script(ScriptCommand::Exists(shas)) => Ok(Value::from_iter(
+                shas.iter()
+                    .map(|sha| Value::Int(i64::from(self.scripts.exists(sha)))),
+            )
+            .into()),
+            Command::Script(ScriptCommand::Flush) => {
+                self.scripts.flush();
+                Ok(Response::ok())
+            }
+            // Sessions kill running scripts on their own, so none can be running here
+            Command::Script(ScriptCommand::Kill) => Err(ScriptError::NotBusy.into()),
+            Command::Function(cmd) => self.function(cmd),
+            Command::Auth { username, password } => {
+                self.acl
+                    .authenticate(client, username.as_deref(), &password)?;
+                Ok(Response::ok())
+            }
+            Command::Hello {
+                protover,
+                auth,
+                setname,
+            } => self.hello(client, protover, auth, setname),
+            Command::Acl(cmd) => self.acl(client, cmd),
+            Command::FCall {
+                function,
+                keys,
+                args,
+                readonly,
+            } => self.fcall(client, &function, keys, args, readonly),
+            Command::Select(db) => {
+                if db >= self.dbs.len() {
+                    return Err(DbError::OutOfRange.into());
+                }
+                self.select(client, db);
+                Ok(Response::ok())
+            }
+            Command::SwapDb(first, second) => self.swap_db(first, second),
+            Command::Move { key, db } => self.move_key(key, db),
+            Command::FlushDb(mode) => {
+                self.dbs[self.db].flush(mode);
+                self.signal_flushed_db(Some(self.db));
+                self.stats.dirty += 1;
+                self.propagate(&Value::from_iter([
+                    Value::bulk("FLUSHDB"),
+                    Value::bulk(flush_mode(mode)),
+                ]));
+                Ok(Response::ok())
+            }
+            Command::FlushAll(mode) => {
+                for db in &mut self.dbs {
+                    db.flush(mode);
+                }
+                self.signal_flushed_db(None);
+                self.stats.dirty += 1;
+                self.propagate(&Value::from_iter([
+                    Value::bulk("FLUSHALL"),
+                    Value::bulk(flush_mode(mode)),
+                ]));
+                Ok(Response::ok())
+            }
+            Command::Keys(pattern) => Ok(Value::from_iter(
+                self.dbs[self.db]
+                    .keys(&pattern, Utc::now())
+                    .into_iter()
+                    .map(Value::bulk),
+            )
+            .into()),
+            Command::Scan {
+                cursor,
+                pattern,
+                count,
+                ty,
+            } => {
+                let (cursor, mut keys) = self.dbs[self.db].scan(
+                    cursor,
+                    count.unwrap_or(SCAN_COUNT),
+                    pattern.as_deref(),
+                    Utc::now(),
+                );
+                // Every key is a string
+                if ty.is_some_and(|ty| !ty.eq_ignore_ascii_case("string")) {
+                    keys.clear();
+                }
+
+                Ok(Value::from_iter([
+                    Value::bulk(cursor),
+                    Value::from_iter(keys.into_iter().map(Value::bulk)),
+                ])
+                .into())
+            }
+            Command::Del(keys) => Ok(self.del(keys)),
+            Command::Object(cmd) => self.object(cmd),
+            Command::Memory(cmd) => Ok(self.memory(cmd)),
+            Command::Config(cmd) => self.config(cmd),
+            Command::Latency(cmd) => self.latency(cmd),
+            Command::Introspect(cmd) => self.command(cmd),
+            Command::Monitor => {
+                self.monitors.add(client.clone());
+                Ok(Response::ok())
+            }
+            Command::SlowLog(cmd) => Ok(match cmd {
+                SlowLogCommand::Get(count) => self.slowlog.get(count).into(),
+                SlowLogCommand::Len => Value::Int(self.slowlog.len() as i64).into(),
+                SlowLogCommand::Reset => {
+                    self.slowlog.reset();
+                    Response::ok()
+                }
+            }),
+            Command::Client(cmd) => self.client(client, cmd),
+            Command::Wait { .. } | Command::WaitAof { .. } | Command::ReplicaOf(_) => {
+                unreachable!("commands that may await are handled by execute")
+            }
+        }
+    }
+
+    /// Render the `sections` of `INFO`
+    fn info(&mut self, sections: &[String]) -> MemoraResult<String> {
+        let mut info = Info::new(sections);
+        let uptime = self.started.elapsed().as_secs();
+
+        let memory = self.used_memory();
+        self.peak_memory = self.peak_memory.max(memory);
+
+        if info.wants("server") {
+            let addr = self.listener.local_addr()?;
+            let now = Utc::now();
+            info.section(
+                "Server",
+                [
+                    "redis_version:7.2.0".to_owned(),
+                    format!("memora_version:{}", env!("CARGO_PKG_VERSION")),
+                    "redis_mode:standalone".to_owned(),
+                    format!("os:{} {}", std::env::consts::OS, std::env::consts::ARCH),
+                    format!("arch_bits:{}", usize::BITS),
+                    "multiplexing_api:tokio".to_owned(),
+                    format!("process_id:{}", std::process::id()),
+                    format!("run_id:{}", self.run_id),
+                    format!("tcp_port:{}", addr.port()),
+                    format!("server_time_usec:{}", now.timestamp_micros()),
+                    format!("uptime_in_seconds:{uptime}"),
+                    format!("uptime_in_days:{}", uptime / (24 * 3600)),
+                    format!("hz:{}", 1000 / CRON_INTERVAL.as_millis()),
+                    format!(
+                        "executable:{}",
+                        std::env::current_exe()
+                            .map(|exe| exe.display().to_string())
+                            .unwrap_or_default()
+                    ),
+                    format!(
+                        "config_file:{}",
+                        self.config
+                            .file
+                            .as_ref()
+                            .map(|file| file.display().to_string())
+                            .unwrap_or_default()
+                    ),
+                ],
+            );
+        }
+
+        if info.wants("clients") {
+            info.section(
+                "Clients",
+                [
+                    format!("connected_clients:{}", self.clients.len()),
+                    format!("blocked_clients:{}", self.waiters.len()),
+                    format!("pubsub_clients:{}", self.pubsub.clients()),
+                    format!("watching_clients:{}", self.watches.clients()),
+                    format!("total_watched_keys:{}", self.watches.keys()),
+                    format!("tracking_clients:{}", self.tracking.clients()),
+                ],
+            );
+        }
+
+        let usage = ProcessUsage::current();
+
+        if info.wants("memory") {
+            let used = memory as u64;
+            let peak = self.peak_memory as u64;
+            let (libraries, functions) = self.scripts.library_counts();
+            let fragmentation = if used > 0 {
+                usage.rss as f64 / used as f64
+            } else {
+                0.0
+            };
+
+            info.section(
+                "Memory",
+                [
+                    format!("used_memory:{used}"),
+                    format!("used_memory_human:{}", info::human_bytes(used)),
+                    format!("used_memory_rss:{}", usage.rss),
+                    format!("used_memory_rss_human:{}", info::human_bytes(usage.rss)),
+                    format!("used_memory_peak:{peak}"),
+                    format!("used_memory_peak_human:{}", info::human_bytes(peak)),
+                    format!("used_memory_dataset:{used}"),
+                    format!("used_memory_scripts_eval:{}", self.scripts.memory()),
+                    format!("number_of_cached_scripts:{}", self.scripts.len()),
+                    format!("number_of_functions:{functions}"),
+                    format!("number_of_libraries:{libraries}"),
+                    format!("maxmemory:{}", self.config.maxmemory),
+                    format!(
+                        "maxmemory_human:{}",
+                        info::human_bytes(self.config.maxmemory as u64)
+                    ),
+                    format!("maxmemory_policy:{}", self.config.maxmemory_policy),
+                    format!("mem_fragmentation_ratio:{fragmentation:.2}"),
+                ],
+            );
+        }
+
+        if info.wants("persistence") {
+            let started = Utc::now().timestamp() - uptime as i64;
+            info.section(
+                "Persistence",
+                [
+                    "loading:0".to_owned(),
+                    "async_loading:0".to_owned(),
+                    format!("rdb_changes_since_last_save:{}", self.stats.dirty),
+                    "rdb_bgsave_in_progress:0".to_owned(),
+                    format!("rdb_last_save_time:{started}"),
+                    "rdb_last_bgsave_status:ok".to_owned(),
+                    "aof_enabled:0".to_owned(),
+                    "aof_rewrite_in_progress:0".to_owned(),
+                ],
+            );
+        }
+
+        if info.wants("stats") {
+            let stats = &self.stats;
+            let mut fields = vec![
+                format!("total_connections_received:{}", stats.connections_received),
+                format!("total_commands_processed:{}", stats.commands_processed),
+                format!("instantaneous_ops_per_sec:{:.0}", stats.ops.rate()),
+                format!("total_net_input_bytes:{}", self.net.input()),
+                format!("total_net_output_bytes:{}", self.net.output()),
+                format!(
+                    "instantaneous_input_kbps:{:.2}",
+                    stats.input.rate() / 1024.0
+                ),
+                format!(
+                    "instantaneous_output_kbps:{:.2}",
+                    stats.output.rate() / 1024.0
+                ),
+                "rejected_connections:0".to_owned(),
+                format!(
+                    "expired_keys:{}",
+                    self.dbs.iter().map(|db| db.expired).sum::<u64>()
+                ),
+                format!("evicted_keys:{}", stats.evicted_keys),
+                format!("evicted_clients:{}", stats.evicted_clients),
+                format!("keyspace_hits:{}", stats.keyspace_hits),
+                format!("keyspace_misses:{}", stats.keyspace_misses),
+                format!("pubsub_channels:{}", self.pubsub.channels(None).len()),
+                format!("pubsub_patterns:{}", self.pubsub.numpat()),
+                format!(
+                    "pubsubshard_channels:{}",
+                    self.pubsub.shard_channels(None).len()
+                ),
+                format!("tracking_total_keys:{}", self.tracking.keys()),
+                format!("tracking_total_prefixes:{}", self.tracking.prefixes()),
+                format!("total_error_replies:{}", stats.error_replies),
+                format!("acl_access_denied_auth:{}", self.acl.denials.auth),
+                format!("acl_access_denied_cmd:{}", self.acl.denials.command),
+                format!("acl_access_denied_key:{}", self.acl.denials.key),
+                format!("acl_access_denied_channel:{}", self.acl.denials.channel),
+            ];
+            fields.extend(
+                self.role
+                    .stats()
+                    .into_iter()
+                    .map(|(key, value)| format!("{key}:{value}")),
+            );
+            info.section("Stats", fields);
+        }
+
+        if info.wants("replication") {
+            info.section("Replication", self.role.info());
+        }
+
+        if info.wants("cpu") {
+            info.section(
+                "CPU",
+                [
+                    format!("used_cpu_sys:{:.6}", usage.sys),
+                    format!("used_cpu_user:{:.6}", usage.user),
+                    "used_cpu_sys_children:0.000000".to_owned(),
+                    "used_cpu_user_children:0.000000".to_owned(),
+                ],
+            );
+        }
+
+        if info.wants("commandstats") {
+            let fields = self.stats.commands.iter().map(|(name, stats)| {
+                format!(
+                    "cmdstat_{name}:calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}",
+                    stats.calls,
+                    stats.usec,
+                    stats.usec as f64 / stats.calls.max(1) as f64,
+                    stats.rejected_calls,
+                    stats.failed_calls
+                )
+            });
+            info.section("Commandstats", fields.collect::<Vec<_>>());
+        }
+
+        if info.wants("errorstats") {
+            let fields = self
+                .stats
+                .errors
+                .iter()
+                .map(|(code, count)| format!("errorstat_{code}:count={count}"));
+            info.section("Errorstats", fields.collect::<Vec<_>>());
+        }
+
+        if info.wants("latencystats") {
+            let fields = self
+                .stats
+                .commands
+                .iter()
+                .filter(|(_, stats)| stats.calls > 0)
+                .map(|(name, stats)| {
+                    format!(
+                        "latency_percentiles_usec_{name}:p50={:.3},p99={:.3},p99.9={:.3}",
+                        stats.latency.percentile(50.0) as f64,
+                        stats.latency.percentile(99.0) as f64,
+                        stats.latency.percentile(99.9) as f64
+                    )
+                });
+            info.section("Latencystats", fields.collect::<Vec<_>>());
+        }
+
+        if info.wants("keyspace") {
+            let now = Utc::now();
+            let fields = self
+                .dbs
+                .iter()
+                .enumerate()
+                .filter(|(_, db)| db.len() > 0)
+                .map(|(idx, db)| {
+                    let (keys, expires, avg_ttl) = db.keyspace(now);
+                    format!("db{idx}:keys={keys},expires={expires},avg_ttl={avg_ttl}")
+                });
+            info.section("Keyspace", fields.collect::<Vec<_>>());
+        }
+
+        Ok(info.into_string())
+    }
+}
+
+/// Argument of `FLUSHDB` and `FLUSHALL` for the given `mode`
+fn flush_mode(mode: FlushMode) -> &'static str {
+    match mode {
+        FlushMode::Sync => "SYNC",
+        FlushMode::Async => "ASYNC",
+    }
+}
+
+/// Generate a random identifier of 40 hexadecimal characters
+fn run_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).expect("digit should be below 16"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{
+        super::role::{Master, Replica},
+        *,
+    };
+
+    async fn memora() -> Memora {
+        with_role(Master::new(1024)).await
+    }
+
+    async fn with_role(role: impl Into<AnyRole>) -> Memora {
+        Memora::new(("127.0.0.1", 0), role, Config::default())
+            .await
+            .expect("bind a local port")
+    }
+
+    /// Connect a new client, authenticated as the default user
+    fn connect(memora: &mut Memora) -> (ClientHandle, mpsc::Receiver<Bytes>) {
+        let (client, push_rx) = ClientHandle::new("127.0.0.1:6000".parse::<SocketAddr>().unwrap());
+        client.authenticate("default".to_owned());
+        memora.clients.insert(client.id(), client.clone());
+        (client, push_rx)
+    }
+
+    /// Run the command `args` on behalf of `client`, and get its reply
+    async fn run(memora: &mut Memora, client: &ClientHandle, args: &[&str]) -> Value {
+        let argv: Vec<_> = args.iter().map(ToString::to_string).collect();
+        let cmd = match Command::try_from(Value::from_iter(args.iter().map(Value::bulk))) {
+            Ok(cmd) => cmd,
+            Err(e) => return Response::error(&e.into()).into(),
+        };
+
+        memora.db = client.db();
+        match memora.execute(client, cmd, &argv).await {
+            Reply::Now(res) => res.into(),
+            Reply::Blocked(_) => panic!("{args:?} should not block"),
+        }
+    }
+
+    /// Run the transaction made of `commands` on behalf of `client`, and get its reply
+    async fn exec(memora: &mut Memora, client: &ClientHandle, commands: &[&[&str]]) -> Value {
+        let commands = commands
+            .iter()
+            .map(|args| {
+                let argv = args.iter().map(ToString::to_string).collect();
+                let cmd = Command::try_from(Value::from_iter(args.iter().map(Value::bulk)))
+                    .expect("a valid command");
+                (cmd, argv)
+            })
+            .collect();
+
+        memora.db = client.db();
+        memora.exec(client, commands).await.into()
+    }
+
+    #[tokio::test]
+    async fn should_serve_the_slowlog() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "slowlog-log-slower-than", "0"],
+        )
+        .await;
+        run(&mut memora, &client, &["ping"]).await;
+        run(&mut memora, &client, &["echo", "hey"]).await;
+
+        let count = |reply: Value| match reply {
+            Value::Array(entries) => entries.len(),
+            reply => panic!("unexpected reply {reply:?}"),
+        };
+        assert_eq!(
+            count(run(&mut memora, &client, &["slowlog", "get", "1"]).await),
+            1
+        );
+        // CONFIG SET, PING, ECHO and the first SLOWLOG GET got logged
+        assert_eq!(
+            count(run(&mut memora, &client, &["slowlog", "get", "-1"]).await),
+            4
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["slowlog", "get", "-2"]).await,
+            Value::error("ERR count should be greater than or equal to -1")
+        );
+
+        assert_eq!(
+            run(&mut memora, &client, &["slowlog", "reset"]).await,
+            Value::simple("OK")
+        );
+        // The reset itself got logged
+        assert_eq!(
+            run(&mut memora, &client, &["slowlog", "len"]).await,
+            Value::Int(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_count_connected_clients() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        connect(&mut memora);
+
+        let Value::Str(info) = run(&mut memora, &client, &["info", "clients"]).await else {
+            panic!("INFO should reply with a bulk string");
+        };
+        assert!(info
+            .as_str()
+            .unwrap()
+            .contains("\r\nconnected_clients:2\r\n"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_expire_times_out_of_range() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+
+        for args in [
+            ["set", "k", "v", "ex", "9223372036854775807"],
+            ["set", "k", "v", "px", "9223372036854775807"],
+            ["set", "k", "v", "exat", "18446744073709551615"],
+        ] {
+            assert_eq!(
+                run(&mut memora, &client, &args).await,
+                Value::error("ERR invalid expire time in 'set' command")
+            );
+        }
+        assert_eq!(memora.dbs[0].len(), 0);
+    }
+
+    #[tokio::test]
+    async fn should_select_and_swap_databases() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+
+        assert_eq!(
+            run(&mut memora, &client, &["select", "16"]).await,
+            Value::error("ERR DB index is out of range")
+        );
+        assert_eq!(client.db(), 0);
+
+        run(&mut memora, &client, &["set", "a", "0"]).await;
+        run(&mut memora, &client, &["select", "1"]).await;
+        run(&mut memora, &client, &["set", "b", "1"]).await;
+
+        assert_eq!(
+            run(&mut memora, &client, &["swapdb", "0", "16"]).await,
+            Value::error("ERR DB index is out of range")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["swapdb", "0", "1"]).await,
+            Value::simple("OK")
+        );
+
+        // The client stays on the same index, which now holds the other keys
+        assert_eq!(
+            run(&mut memora, &client, &["get", "a"]).await,
+            Value::bulk("0")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["get", "b"]).await,
+            Value::null_bulk()
+        );
+        run(&mut memora, &client, &["select", "0"]).await;
+        assert_eq!(
+            run(&mut memora, &client, &["get", "b"]).await,
+            Value::bulk("1")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_move_keys_between_databases() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+
+        run(&mut memora, &client, &["select", "1"]).await;
+        run(&mut memora, &client, &["set", "k", "1"]).await;
+        run(&mut memora, &client, &["select", "0"]).await;
+        run(&mut memora, &client, &["set", "k", "0"]).await;
+        run(&mut memora, &client, &["set", "other", "0"]).await;
+
+        // Keys existing in the destination are left alone
+        assert_eq!(
+            run(&mut memora, &client, &["move", "k", "1"]).await,
+            Value::Int(0)
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["get", "k"]).await,
+            Value::bulk("0")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["move", "k", "0"]).await,
+            Value::error("ERR source and destination objects are the same")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["move", "k", "16"]).await,
+            Value::error("ERR DB index is out of range")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["move", "missing", "1"]).await,
+            Value::Int(0)
+        );
+
+        assert_eq!(
+            run(&mut memora, &client, &["move", "other", "1"]).await,
+            Value::Int(1)
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["get", "other"]).await,
+            Value::null_bulk()
+        );
+        run(&mut memora, &client, &["select", "1"]).await;
+        assert_eq!(
+            run(&mut memora, &client, &["get", "other"]).await,
+            Value::bulk("0")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_flush_the_selected_database() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+
+        for mode in ["sync", "ASYNC"] {
+            run(&mut memora, &client, &["set", "k", "v"]).await;
+            assert_eq!(
+                run(&mut memora, &client, &["flushdb", mode]).await,
+                Value::simple("OK")
+            );
+            assert_eq!(memora.dbs[0].len(), 0);
+        }
+
+        run(&mut memora, &client, &["set", "k", "v"]).await;
+        for args in [&["flushdb", "lazy"][..], &["flushdb", "sync", "async"]] {
+            assert_eq!(
+                run(&mut memora, &client, args).await,
+                Value::error("ERR syntax error")
+            );
+        }
+        assert_eq!(memora.dbs[0].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_only_evict_volatile_candidates_sampled_for_the_policy() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        run(&mut memora, &client, &["set", "persistent", "v"]).await;
+        run(&mut memora, &client, &["set", "volatile", "v", "ex", "100"]).await;
+
+        // Candidates sampled by an allkeys policy are dropped when the policy changes
+        memora.eviction_pool.insert(u64::MAX, 0, "persistent");
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "maxmemory-policy", "volatile-lru"],
+        )
+        .await;
+        assert_eq!(memora.eviction_pool.pop(), None);
+
+        // A candidate that lost its expiry since it got sampled is skipped
+        memora.eviction_pool.insert(u64::MAX, 0, "persistent");
+        assert_eq!(
+            memora.eviction_candidate(EvictionPolicy::VolatileLru),
+            Some((0, "volatile".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn should_evict_the_largest_clients_except_no_evict_ones() {
+        let mut memora = memora().await;
+        let (small, _) = connect(&mut memora);
+        let (large, _) = connect(&mut memora);
+        let (protected, _) = connect(&mut memora);
+        let (replica, _) = connect(&mut memora);
+
+        run(&mut memora, &protected, &["client", "no-evict", "on"]).await;
+        memora.config.maxmemory_clients = 14000;
+
+        small.record().omem = 1000;
+        large.record().omem = 5000;
+        protected.record().omem = 8000;
+        replica.record().replica = true;
+        replica.record().omem = 4000;
+
+        run(&mut memora, &small, &["ping"]).await;
+        assert!(large.is_killed());
+        assert!(!small.is_killed() && !protected.is_killed() && !replica.is_killed());
+        assert_eq!(memora.stats.evicted_clients, 1);
+
+        // Killed clients no longer count, and protected clients are never evicted
+        small.record().omem = 3000;
+        run(&mut memora, &protected, &["ping"]).await;
+        assert!(small.is_killed());
+        assert!(!protected.is_killed() && !replica.is_killed());
+        assert_eq!(memora.stats.evicted_clients, 2);
+    }
+
+    #[tokio::test]
+    async fn should_guard_the_dataset_of_replicas() {
+        // The link with the master never comes up, as it is not started
+        let mut memora = with_role(Replica::of(6380, "127.0.0.1", 1u16, 1024, None)).await;
+        let (client, _) = connect(&mut memora);
+        let (master, _) = ClientHandle::master("127.0.0.1:6379".parse().unwrap());
+
+        assert_eq!(
+            run(&mut memora, &client, &["set", "k", "v"]).await,
+            Value::error("READONLY You can't write against a read only replica.")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["get", "k"]).await,
+            Value::null_bulk()
+        );
+
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "replica-serve-stale-data", "no"],
+        )
+        .await;
+        assert_eq!(
+            run(&mut memora, &client, &["get", "k"]).await,
+            Value::error(
+                "MASTERDOWN Link with MASTER is down and replica-serve-stale-data is set to 'no'."
+            )
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["ping"]).await,
+            Value::simple("PONG")
+        );
+
+        // Our master is the one that writes to us
+        assert_eq!(
+            run(&mut memora, &master, &["set", "k", "v"]).await,
+            Value::simple("OK")
+        );
+        assert_eq!(
+            run(&mut memora, &master, &["get", "k"]).await,
+            Value::bulk("v")
+        );
+
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "replica-read-only", "no"],
+        )
+        .await;
+        assert!(matches!(
+            run(&mut memora, &client, &["set", "k", "v"]).await,
+            Value::Error(e) if e.starts_with("MASTERDOWN")
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_check_exec_before_running_the_transaction() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        run(
+            &mut memora,
+            &client,
+            &["acl", "setuser", "alice", "on", "nopass", "+@all", "-exec"],
+        )
+        .await;
+        client.authenticate("alice".to_owned());
+        assert!(matches!(
+            exec(&mut memora, &client, &[&["set", "k", "v"]]).await,
+            Value::Error(e) if e.starts_with("NOPERM")
+        ));
+        assert_eq!(memora.stats.command("exec").rejected_calls, 1);
+        assert_eq!(memora.dbs[0].len(), 0);
+
+        // The queued commands decide whether the transaction grows the dataset
+        client.authenticate("default".to_owned());
+        run(&mut memora, &client, &["set", "k", "v"]).await;
+        run(&mut memora, &client, &["config", "set", "maxmemory", "1"]).await;
+        assert!(matches!(
+            exec(&mut memora, &client, &[&["set", "other", "v"]]).await,
+            Value::Error(e) if e.starts_with("OOM")
+        ));
+        assert_eq!(
+            exec(&mut memora, &client, &[]).await,
+            Value::Array(Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_transactions_writing_to_read_only_replicas() {
+        let mut memora = with_role(Replica::of(6380, "127.0.0.1", 1u16, 1024, None)).await;
+        let (client, _) = connect(&mut memora);
+
+        assert_eq!(
+            exec(&mut memora, &client, &[&["set", "k", "v"]]).await,
+            Value::error("READONLY You can't write against a read only replica.")
+        );
+        assert_eq!(
+            exec(&mut memora, &client, &[&["get", "k"]]).await,
+            Value::from_iter([Value::null_bulk()])
+        );
+    }
+
+    #[tokio::test]
+    async fn should_notify_keys_expired_by_the_active_expire_cycle() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        let (subscriber, mut push_rx) = connect(&mut memora);
+
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "notify-keyspace-events", "Ex"],
+        )
+        .await;
+        run(
+            &mut memora,
+            &subscriber,
+            &["subscribe", "__keyevent@0__:expired"],
+        )
+        .await;
+        run(&mut memora, &client, &["set", "k", "v", "px", "1"]).await;
+        run(&mut memora, &client, &["set", "other", "v"]).await;
+
+        time::sleep(Duration::from_millis(5)).await;
+        memora.cron();
+
+        assert_eq!(
+            push_rx.try_recv().unwrap(),
+            "*3\r\n$7\r\nmessage\r\n$22\r\n__keyevent@0__:expired\r\n$1\r\nk\r\n"
+        );
+        assert_eq!(memora.dbs[0].len(), 1);
+        assert_eq!(memora.dbs[0].expired, 1);
+    }
+
+    #[tokio::test]
+    async fn should_sample_the_latency_of_the_expire_cycle() {
+        let mut memora = memora().await;
+        let (client, _) = connect(&mut memora);
+        run(
+            &mut memora,
+            &client,
+            &["config", "set", "latency-monitor-threshold", "1"],
+        )
+        .await;
+
+        // The cycle started early enough to always reach the threshold
+        memora.active_expire(std::time::Instant::now() - Duration::from_millis(5));
+
+        let Value::Array(events) = run(&mut memora, &client, &["latency", "latest"]).await else {
+            panic!("LATENCY LATEST should reply with an array");
+        };
+        let Some(Value::Array(event)) = events.first() else {
+            panic!("the expire cycle should have been sampled");
+        };
+        assert_eq!(event[0], Value::bulk("expire-cycle"));
+    }
+
+    #[tokio::test]
+    async fn should_switch_protocols_with_hello() {
+        let mut memora = memora().await;
+        let (client, _push_rx) = connect(&mut memora);
+
+        assert_eq!(
+            run(&mut memora, &client, &["hello", "4"]).await,
+            Value::error("NOPROTO unsupported protocol version")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["hello", "three"]).await,
+            Value::error("ERR Protocol version is not an integer or out of range")
+        );
+        assert_eq!(
+            run(&mut memora, &client, &["hello", "3", "setname"]).await,
+            Value::error("ERR syntax error")
+        );
+        assert_eq!(client.record().resp, 2);
+
+        let Value::Map(fields) = run(&mut memora, &client, &["hello", "3", "setname", "app"]).await
+        else {
+            panic!("HELLO 3 should reply with a map");
+        };
+        assert!(fields.contains(&(Value::bulk("proto"), Value::Int(3))));
+        assert_eq!(client.record().name.as_deref(), Some("app"));
+
+        let Value::Array(fields) = run(&mut memora, &client, &["hello", "2"]).await else {
+            panic!("HELLO 2 should reply with an array");
+        };
+        assert_eq!(fields[..2], [Value::bulk("server"), Value::bulk("redis")]);
+        assert_eq!(client.record().resp, 2);
+    }
+
+    #[tokio::test]
+    async fn should_push_invalidations_to_resp3_clients() {
+        let mut memora = memora().await;
+        let (client, mut push_rx) = connect(&mut memora);
+        let (writer, _) = connect(&mut memora);
+
+        assert_eq!(
+            run(&mut memora, &client, &["client", "tracking", "on"]).await,
+            Value::error(
+                "ERR Tracking without REDIRECT needs RESP3 push messages, switch protocols with \
+                 HELLO 3 first"
+            )
+        );
+
+        run(&mut memora, &client, &["hello", "3"]).await;
+        assert_eq!(
+            run(&mut memora, &client, &["client", "tracking", "on"]).await,
+            Value::simple("OK")
+        );
+        run(&mut memora, &client, &["get", "k"]).await;
+        run(&mut memora, &writer, &["set", "k", "v"]).await;
+
+        assert_eq!(
+            push_rx.try_recv().unwrap(),
+            ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n"
+        );
     }
 }
@@ -1,72 +1,285 @@
+use std::{future::Future, sync::Arc, time::Instant};
+
+use bytes::Bytes;
 use futures::SinkExt;
-use tokio::sync::mpsc;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, oneshot},
+};
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Framed};
 use tracing::{error, info};
 
-use crate::resp::{StringValue, Value};
+use crate::{
+    dispatch::{self, CommandHandlerInvoker},
+    resp::Value,
+};
 
-use super::{cmd::Command, framer::RespFramer, MemoraError, MemoraResult, Request};
+use super::{
+    acl::AclError,
+    cmd::{self, Argv, ClientCommand, Command, ReplyMode, ScriptCommand},
+    framer::RespFramer,
+    multi::{MultiError, Transaction},
+    script::{self, Running},
+    stats::NetStats,
+    ClientHandle, MemoraError, MemoraResult, Request, Response,
+};
 
-pub(super) struct Session {
-    conn: Framed<tokio::net::TcpStream, RespFramer>,
+/// A client connection, over any stream such as a TCP or TLS stream
+pub(super) struct Session<S> {
+    conn: Framed<S, RespFramer>,
+    client: ClientHandle,
+    push_rx: mpsc::Receiver<Bytes>,
     reqs_tx: mpsc::Sender<Request>,
+
+    /// Transaction started by `MULTI`, if any
+    multi: Option<Transaction>,
+
+    /// Script being executed by the server
+    running: Arc<Running>,
+
+    /// Which replies get sent to the client, as set by `CLIENT REPLY`
+    reply: ReplyMode,
+
+    /// Handlers of the commands that go through the dispatcher rather than straight to the server
+    dispatcher: CommandHandlerInvoker<Forward, (Command, Argv)>,
+}
+
+/// State of the command handlers of a session, forwarding commands to the server on behalf of
+/// its client
+#[derive(Clone)]
+pub(super) struct Forward {
+    client: ClientHandle,
+    reqs_tx: mpsc::Sender<Request>,
+}
+
+impl Forward {
+    pub(super) fn new(client: ClientHandle, reqs_tx: mpsc::Sender<Request>) -> Self {
+        Self { client, reqs_tx }
+    }
+
+    /// Send `cmd` to the server and wait for its response
+    pub(super) async fn request(&self, cmd: Command, argv: Argv) -> Response {
+        let req = Request::new(self.client.clone(), cmd, argv);
+        request(self.reqs_tx.clone(), req).await
+    }
 }
 
-impl Session {
-    pub(super) fn new(conn: tokio::net::TcpStream, reqs_tx: mpsc::Sender<Request>) -> Self {
+impl<S> Session<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub(super) fn new(
+        conn: S,
+        client: ClientHandle,
+        push_rx: mpsc::Receiver<Bytes>,
+        reqs_tx: mpsc::Sender<Request>,
+        net: Arc<NetStats>,
+        running: Arc<Running>,
+    ) -> Self {
+        let mut dispatcher =
+            CommandHandlerInvoker::with_state(Forward::new(client.clone(), reqs_tx.clone()));
+        script::register(&mut dispatcher);
+
         Self {
-            conn: RespFramer.framed(conn),
+            conn: RespFramer::with_stats(net).framed(conn),
+            client,
+            push_rx,
             reqs_tx,
+            multi: None,
+            running,
+            reply: ReplyMode::On,
+            dispatcher,
         }
     }
 
     pub(super) async fn run(mut self) -> MemoraResult<()> {
+        let res = self.serve().await;
+
+        // Let the server forget about this client
+        let _ = self
+            .reqs_tx
+            .send(Request::disconnect(self.client.clone()))
+            .await;
+
+        res
+    }
+
+    async fn serve(&mut self) -> MemoraResult<()> {
         loop {
-            let Some(Ok(value)) = self.conn.next().await else {
-                break;
-            };
+            tokio::select! {
+                value = self.conn.next() => {
+                    let Some(Ok(value)) = value else {
+                        break;
+                    };
+
+                    let argv = cmd::argv(&value);
+                    let command = Command::try_from(value);
 
-            let command = Command::try_from(value);
+                    let res = match command {
+                        Ok(Command::Quit) => {
+                            self.reply(Response::ok()).await?;
+                            break;
+                        }
+                        Ok(cmd) => self.handle_command(cmd, argv).await,
+                        Err(e) => {
+                            // A command that can not be queued discards the whole transaction
+                            if let Some(multi) = &mut self.multi {
+                                multi.aborted = true;
+                            }
 
-            let res = match command {
-                Ok(cmd) => self.handle_command(cmd).await,
-                Err(e) => Err(MemoraError::Command(e)),
-            };
+                            let e = MemoraError::Command(e);
+                            error!("failed to parse command: {e}");
+                            self.reply(Response::error(&e)).await
+                        }
+                    };
 
-            if let Err(e) = res {
-                error!("failed to handle message: {e}");
+                    if let Err(e) = res {
+                        error!("failed to handle message: {e}");
+                    }
+                }
+
+                Some(frame) = self.push_rx.recv() => {
+                    // Clients killed for not keeping up with pushed frames may never read them
+                    tokio::select! {
+                        res = self.conn.send(frame) => res?,
+                        _ = self.client.killed() => {
+                            info!("closing connection of killed client {}", self.client.addr());
+                            break;
+                        }
+                    }
+                }
+
+                _ = self.client.killed() => {
+                    info!("closing connection of killed client {}", self.client.addr());
+                    break;
+                }
             }
         }
 
         Ok(())
     }
 
-    async fn handle_command(&mut self, cmd: Command) -> MemoraResult<()> {
+    async fn handle_command(&mut self, cmd: Command, argv: Argv) -> MemoraResult<()> {
         info!("handling {cmd:?}");
+        self.update_record(Some(cmd.name()));
+
+        // Clients must authenticate before running anything else
+        if self.client.user().is_none()
+            && !matches!(
+                cmd,
+                Command::Auth { .. } | Command::Hello { auth: Some(_), .. }
+            )
+        {
+            self.reply(Response::error(&AclError::NoAuth.into()))
+                .await?;
+            return Ok(());
+        }
+
+        // Replies are turned back on right away, and turned off starting with the next command
+        if let Command::Client(ClientCommand::Reply(mode)) = cmd {
+            self.reply = mode;
+            if mode == ReplyMode::On {
+                self.conn.send(Response::ok()).await?;
+            }
+            return Ok(());
+        }
 
-        let resp = match cmd {
-            Command::Ping(msg) => {
-                if let Some(msg) = msg {
-                    Value::from_iter([Value::bulk("PONG"), Value::bulk(msg)]).into()
+        let resp = match (cmd, &mut self.multi) {
+            // The server loop is busy while a script runs, so killing it can not wait for it
+            (Command::Script(ScriptCommand::Kill), None) => match self.running.kill() {
+                Ok(()) => Response::ok(),
+                Err(e) => Response::error(&e.into()),
+            },
+            (Command::Multi, Some(_)) => Response::error(&MultiError::Nested.into()),
+            (Command::Multi, None) => {
+                self.multi = Some(Transaction::default());
+                Response::ok()
+            }
+            (Command::Exec, None) => Response::error(&MultiError::ExecWithoutMulti.into()),
+            (Command::Discard, None) => Response::error(&MultiError::DiscardWithoutMulti.into()),
+            (Command::Watch(_), Some(_)) => Response::error(&MultiError::WatchInsideMulti.into()),
+            (Command::Exec, Some(_)) => {
+                let multi = self.multi.take().unwrap_or_default();
+                if multi.aborted {
+                    // Discarding the transaction also unwatches the keys
+                    self.request(Request::new(self.client.clone(), Command::Discard, argv))
+                        .await;
+                    Response::error(&MultiError::ExecAbort.into())
                 } else {
-                    Value::Str(StringValue::Simple("PONG".to_owned())).into()
+                    self.request(Request::exec(self.client.clone(), multi.commands))
+                        .await
                 }
             }
+            (Command::Discard, Some(_)) => {
+                self.multi = None;
+                self.request(Request::new(self.client.clone(), Command::Discard, argv))
+                    .await
+            }
+            (cmd, Some(multi)) => {
+                multi.commands.push((cmd, argv));
+                Value::simple("QUEUED").into()
+            }
+            (cmd, None) if self.dispatcher.dispatches(cmd.name()) => {
+                let cmd = dispatch::Command::new(cmd.name(), (cmd, argv));
+                Response::many(self.dispatcher.call(cmd).await)
+            }
+            (cmd, None) => {
+                self.request(Request::new(self.client.clone(), cmd, argv))
+                    .await
+            }
+        };
 
-            Command::Echo(msg) => Value::bulk(msg).into(),
-
-            cmd => {
-                let (req, rx) = Request::new(cmd);
-                let _ = self.reqs_tx.send(req).await;
+        self.update_record(None);
+        self.reply(resp).await
+    }
 
-                // TODO(oktal): properly handle channel closing
-                let resp = rx.await.unwrap();
-                resp
+    /// Send the reply of a command, unless replies are turned off with `CLIENT REPLY`
+    async fn reply(&mut self, resp: Response) -> MemoraResult<()> {
+        match self.reply {
+            ReplyMode::On => self.conn.send(resp).await,
+            ReplyMode::Off => Ok(()),
+            ReplyMode::Skip => {
+                self.reply = ReplyMode::On;
+                Ok(())
             }
-        };
+        }
+    }
 
-        self.conn.send(resp).await?;
-        Ok(())
+    /// Refresh the metadata of the connection, when receiving the command named `cmd` if any
+    fn update_record(&self, cmd: Option<&'static str>) {
+        let mut record = self.client.record();
+        if let Some(cmd) = cmd {
+            record.last_interaction = Instant::now();
+            record.last_cmd = cmd;
+        }
+
+        record.multi = self.multi.as_ref().map(|multi| multi.commands.len());
+
+        let (read, write) = (self.conn.read_buffer(), self.conn.write_buffer());
+        record.qbuf = read.len();
+        record.qbuf_free = read.capacity() - read.len();
+        record.obl = write.len();
+        record.omem = write.capacity();
     }
+
+    /// Send a request to the server and wait for its response
+    fn request(
+        &self,
+        req: (Request, oneshot::Receiver<Response>),
+    ) -> impl Future<Output = Response> + 'static {
+        // Not borrowing the session across awaits, as its dispatcher can not be shared
+        request(self.reqs_tx.clone(), req)
+    }
+}
+
+/// Send a request to the server and wait for its response
+async fn request(
+    reqs_tx: mpsc::Sender<Request>,
+    (req, rx): (Request, oneshot::Receiver<Response>),
+) -> Response {
+    let _ = reqs_tx.send(req).await;
+
+    // TODO(oktal): properly handle channel closing
+    rx.await.unwrap()
 }
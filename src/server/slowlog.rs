@@ -0,0 +1,234 @@
+//! Log of the commands that took longer than `slowlog-log-slower-than` to execute
+
+use std::collections::VecDeque;
+
+use chrono::Utc;
+
+use crate::resp::Value;
+
+use super::{cmd, ClientHandle};
+
+/// Number of arguments recorded per command, the last one telling how many were left out
+const MAX_ARGC: usize = 32;
+
+/// Number of bytes recorded per argument
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+
+    /// Unix time at which the command got logged, in seconds
+    timestamp: i64,
+
+    /// Execution time of the command, in microseconds
+    duration: u64,
+
+    argv: Vec<String>,
+    addr: String,
+    name: String,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct SlowLog {
+    /// Most recent entries first
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    /// Log the command sent by `client` with `argv` if it took at least `slower_than`
+    /// microseconds, keeping no more than `max_len` entries. A negative `slower_than` turns the
+    /// log off
+    pub(super) fn push(
+        &mut self,
+        slower_than: i64,
+        max_len: usize,
+        client: &ClientHandle,
+        argv: &[String],
+        duration: u64,
+    ) {
+        if slower_than < 0 || duration < slower_than as u64 {
+            return;
+        }
+
+        let mut argv = truncate(argv);
+        cmd::redact(&mut argv);
+
+        self.entries.push_front(Entry {
+            id: self.next_id,
+            timestamp: Utc::now().timestamp(),
+            duration,
+            argv,
+            addr: client.addr().to_string(),
+            name: client.record().name.clone().unwrap_or_default(),
+        });
+        self.next_id += 1;
+        self.entries.truncate(max_len);
+    }
+
+    /// The `count` most recent entries, or every entry if `None`
+    pub(super) fn get(&self, count: Option<usize>) -> Value {
+        Value::from_iter(
+            self.entries
+                .iter()
+                .take(count.unwrap_or(usize::MAX))
+                .map(|entry| {
+                    Value::from_iter([
+                        Value::Int(entry.id as i64),
+                        Value::Int(entry.timestamp),
+                        Value::Int(entry.duration as i64),
+                        Value::from_iter(entry.argv.iter().map(Value::bulk)),
+                        Value::bulk(&entry.addr),
+                        Value::bulk(&entry.name),
+                    ])
+                }),
+        )
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Shorten the arguments of a command the way Redis does, so large commands do not bloat the log
+fn truncate(argv: &[String]) -> Vec<String> {
+    let kept = if argv.len() > MAX_ARGC {
+        MAX_ARGC - 1
+    } else {
+        argv.len()
+    };
+
+    let mut truncated = argv[..kept]
+        .iter()
+        .map(|arg| {
+            if arg.len() <= MAX_ARG_LEN {
+                return arg.clone();
+            }
+
+            let end = (0..=MAX_ARG_LEN)
+                .rev()
+                .find(|i| arg.is_char_boundary(*i))
+                .unwrap_or(0);
+            format!("{}... ({} more bytes)", &arg[..end], arg.len() - end)
+        })
+        .collect::<Vec<_>>();
+
+    if kept < argv.len() {
+        truncated.push(format!("... ({} more arguments)", argv.len() - kept));
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn client() -> ClientHandle {
+        let (client, _) = ClientHandle::new("127.0.0.1:6000".parse::<SocketAddr>().unwrap());
+        client.record().name = Some("app".to_owned());
+        client
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    /// Ids of the entries returned by `SLOWLOG GET count`
+    fn ids(log: &SlowLog, count: Option<usize>) -> Vec<i64> {
+        let Value::Array(entries) = log.get(count) else {
+            panic!("SLOWLOG GET should reply with an array");
+        };
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                Value::Array(fields) => match fields[0] {
+                    Value::Int(id) => id,
+                    _ => panic!("the id should be an integer"),
+                },
+                _ => panic!("an entry should be an array"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_only_log_commands_over_the_threshold() {
+        let (mut log, client) = (SlowLog::default(), client());
+
+        log.push(100, 128, &client, &argv(&["get", "fast"]), 99);
+        assert_eq!(log.len(), 0);
+
+        log.push(100, 128, &client, &argv(&["get", "slow"]), 100);
+        assert_eq!(
+            log.get(None),
+            Value::from_iter([Value::from_iter([
+                Value::Int(0),
+                Value::Int(log.entries[0].timestamp),
+                Value::Int(100),
+                Value::from_iter([Value::bulk("get"), Value::bulk("slow")]),
+                Value::bulk("127.0.0.1:6000"),
+                Value::bulk("app"),
+            ])])
+        );
+
+        // Every command gets logged with a zero threshold, and none with a negative one
+        log.push(0, 128, &client, &argv(&["ping"]), 0);
+        log.push(-1, 128, &client, &argv(&["get", "slow"]), u64::MAX);
+        assert_eq!(log.len(), 2);
+    }
+
+    #[test]
+    fn should_keep_the_most_recent_entries() {
+        let (mut log, client) = (SlowLog::default(), client());
+
+        for _ in 0..5 {
+            log.push(0, 3, &client, &argv(&["ping"]), 10);
+        }
+        assert_eq!(log.len(), 3);
+        assert_eq!(ids(&log, None), [4, 3, 2]);
+
+        // Lowering the limit trims the log on the next entry
+        log.push(0, 1, &client, &argv(&["ping"]), 10);
+        assert_eq!(ids(&log, None), [5]);
+    }
+
+    #[test]
+    fn should_get_and_reset_entries() {
+        let (mut log, client) = (SlowLog::default(), client());
+
+        for _ in 0..4 {
+            log.push(0, 128, &client, &argv(&["ping"]), 10);
+        }
+        assert_eq!(ids(&log, Some(2)), [3, 2]);
+        assert_eq!(ids(&log, Some(0)), Vec::<i64>::new());
+        assert_eq!(ids(&log, Some(10)), [3, 2, 1, 0]);
+
+        // Ids keep growing after a reset
+        log.reset();
+        assert_eq!(log.len(), 0);
+        assert_eq!(log.get(None), Value::Array(Vec::new()));
+        log.push(0, 128, &client, &argv(&["ping"]), 10);
+        assert_eq!(ids(&log, None), [4]);
+    }
+
+    #[test]
+    fn should_truncate_large_commands() {
+        let argv = (0..40).map(|i| i.to_string()).collect::<Vec<_>>();
+        let truncated = truncate(&argv);
+        assert_eq!(truncated.len(), MAX_ARGC);
+        assert_eq!(truncated[30], "30");
+        assert_eq!(truncated[31], "... (9 more arguments)");
+
+        let truncated = truncate(&["SET".to_owned(), "k".to_owned(), "x".repeat(200)]);
+        assert_eq!(
+            truncated[2],
+            format!("{}... (72 more bytes)", "x".repeat(128))
+        );
+    }
+}
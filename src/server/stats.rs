@@ -0,0 +1,273 @@
+//! Counters and statistics reported by `INFO`
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Number of linear sub-buckets each power of two is split into by a [`Histogram`]
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKETS_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// Number of samples used to compute instantaneous metrics
+const INSTANTANEOUS_SAMPLES: usize = 16;
+
+/// Histogram of values with a bounded relative error, used to track command latencies.
+///
+/// Values are recorded in logarithmic buckets, each power of two being split into
+/// [`SUB_BUCKETS`] linear buckets
+#[derive(Debug, Default, Clone)]
+pub(super) struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Histogram {
+    fn index(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+
+        let exp = 63 - value.leading_zeros();
+        let shift = exp - SUB_BUCKETS_BITS;
+        let sub = (value >> shift) - SUB_BUCKETS;
+        (SUB_BUCKETS * (1 + shift as u64) + sub) as usize
+    }
+
+    /// Highest value that falls in the bucket at `index`
+    fn highest(index: usize) -> u64 {
+        let index = index as u64;
+        if index < SUB_BUCKETS {
+            return index;
+        }
+
+        let shift = index / SUB_BUCKETS - 1;
+        let sub = index % SUB_BUCKETS + SUB_BUCKETS;
+        u64::try_from(u128::from(sub + 1) << shift).map_or(u64::MAX, |end| end - 1)
+    }
+
+    pub(super) fn record(&mut self, value: u64) {
+        let index = Self::index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+
+        self.counts[index] += 1;
+        self.total += 1;
+    }
+
+    /// Value below which `percentile` percents of the recorded values fall
+    pub(super) fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::highest(index);
+            }
+        }
+
+        0
+    }
+
+    /// Number of values recorded
+    pub(super) fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of values at most every power of two, as reported by `LATENCY HISTOGRAM`. Only the
+    /// powers of two at which the count grows are listed
+    pub(super) fn cumulative(&self) -> Vec<(u64, u64)> {
+        let mut buckets: Vec<(u64, u64)> = Vec::new();
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+
+            seen += count;
+            let bound = Self::highest(index)
+                .max(1)
+                .checked_next_power_of_two()
+                .unwrap_or(u64::MAX);
+            match buckets.last_mut() {
+                Some(last) if last.0 == bound => last.1 = seen,
+                _ => buckets.push((bound, seen)),
+            }
+        }
+        buckets
+    }
+}
+
+/// Statistics of a single command
+#[derive(Debug, Default)]
+pub(super) struct CommandStats {
+    pub(super) calls: u64,
+    pub(super) usec: u64,
+
+    /// Calls rejected before the command was executed
+    pub(super) rejected_calls: u64,
+
+    /// Calls that failed while the command was executed
+    pub(super) failed_calls: u64,
+
+    pub(super) latency: Histogram,
+}
+
+/// Network counters, shared with every session
+#[derive(Debug, Default)]
+pub struct NetStats {
+    input: AtomicU64,
+    output: AtomicU64,
+}
+
+impl NetStats {
+    pub(super) fn read(&self, bytes: usize) {
+        self.input.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn written(&self, bytes: usize) {
+        self.output.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn input(&self) -> u64 {
+        self.input.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn output(&self) -> u64 {
+        self.output.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn reset(&self) {
+        self.input.store(0, Ordering::Relaxed);
+        self.output.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Metric whose instantaneous rate is sampled periodically
+#[derive(Debug, Default)]
+pub(super) struct Instantaneous {
+    last: Option<(u64, Duration)>,
+    samples: VecDeque<f64>,
+}
+
+impl Instantaneous {
+    /// Sample the `value` of the metric at `elapsed` time since the server started
+    pub(super) fn sample(&mut self, value: u64, elapsed: Duration) {
+        if let Some((last, last_elapsed)) = self.last {
+            let secs = (elapsed - last_elapsed).as_secs_f64();
+            if secs > 0.0 {
+                if self.samples.len() == INSTANTANEOUS_SAMPLES {
+                    self.samples.pop_front();
+                }
+                self.samples
+                    .push_back(value.saturating_sub(last) as f64 / secs);
+            }
+        }
+
+        self.last = Some((value, elapsed));
+    }
+
+    /// Average rate per second over the recent samples
+    pub(super) fn rate(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+}
+
+/// Server-wide counters
+#[derive(Debug, Default)]
+pub(super) struct Stats {
+    pub(super) connections_received: u64,
+    pub(super) commands_processed: u64,
+    pub(super) keyspace_hits: u64,
+    pub(super) keyspace_misses: u64,
+
+    /// Number of changes to the dataset
+    pub(super) dirty: u64,
+
+    pub(super) error_replies: u64,
+
+    /// Number of keys evicted to stay under `maxmemory`
+    pub(super) evicted_keys: u64,
+
+    /// Number of clients disconnected to stay under `maxmemory-clients`
+    pub(super) evicted_clients: u64,
+
+    pub(super) commands: BTreeMap<&'static str, CommandStats>,
+
+    /// Number of error replies per error code
+    pub(super) errors: BTreeMap<&'static str, u64>,
+
+    pub(super) ops: Instantaneous,
+    pub(super) input: Instantaneous,
+    pub(super) output: Instantaneous,
+}
+
+impl Stats {
+    /// Reset the counters, as done by `CONFIG RESETSTAT`. Changes to the dataset keep being
+    /// counted since they are not statistics
+    pub(super) fn reset(&mut self) {
+        *self = Self {
+            dirty: self.dirty,
+            ..Self::default()
+        };
+    }
+
+    pub(super) fn command(&mut self, name: &'static str) -> &mut CommandStats {
+        self.commands.entry(name).or_default()
+    }
+
+    pub(super) fn error(&mut self, code: &'static str) {
+        self.error_replies += 1;
+        *self.errors.entry(code).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_bucket_values() {
+        for value in [0, 1, 15, 16, 17, 31, 32, 33, 1000, 123_456, u64::MAX] {
+            let index = Histogram::index(value);
+            assert!(Histogram::highest(index) >= value, "{value}");
+            assert!(
+                index == 0 || Histogram::highest(index - 1) < value,
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_compute_percentiles() {
+        let mut histogram = Histogram::default();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.percentile(50.0), 51);
+        assert_eq!(histogram.percentile(99.0), 99);
+        assert_eq!(histogram.percentile(100.0), 103);
+        assert_eq!(Histogram::default().percentile(50.0), 0);
+    }
+
+    #[test]
+    fn should_count_values_by_power_of_two() {
+        let mut histogram = Histogram::default();
+        for value in [0, 1, 3, 4, 100, u64::MAX] {
+            histogram.record(value);
+        }
+
+        assert_eq!(
+            histogram.cumulative(),
+            [(1, 2), (4, 4), (128, 5), (u64::MAX, 6)]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! TLS with rustls, for the connections of clients on the TLS port and for the replication link
+//! with our master
+
+use std::{
+    fmt,
+    fs::File,
+    future, io,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig,
+};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("no certificate found in {0}")]
+    NoCertificate(PathBuf),
+
+    #[error("no private key found in {0}")]
+    NoPrivateKey(PathBuf),
+
+    #[error("tls-cert-file and tls-key-file must be set to enable TLS")]
+    MissingCertificate,
+
+    #[error("tls-ca-cert-file must be set to authenticate TLS peers")]
+    MissingCaCertificate,
+
+    #[error("invalid tls-auth-clients value '{0}', must be one of yes, no or optional")]
+    InvalidAuthClients(String),
+
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+
+    #[error(transparent)]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+pub type TlsResult<T> = std::result::Result<T, TlsError>;
+
+/// Whether clients connecting over TLS must authenticate with a certificate
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum AuthClients {
+    No,
+    #[default]
+    Yes,
+
+    /// Clients may authenticate with a certificate, which is then verified
+    Optional,
+}
+
+impl FromStr for AuthClients {
+    type Err = TlsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("yes") {
+            Ok(Self::Yes)
+        } else if s.eq_ignore_ascii_case("no") {
+            Ok(Self::No)
+        } else if s.eq_ignore_ascii_case("optional") {
+            Ok(Self::Optional)
+        } else {
+            Err(TlsError::InvalidAuthClients(s.to_owned()))
+        }
+    }
+}
+
+impl fmt::Display for AuthClients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::No => "no",
+            Self::Yes => "yes",
+            Self::Optional => "optional",
+        })
+    }
+}
+
+/// TLS configuration of a memora instance
+#[derive(Debug, Default, Clone)]
+pub struct TlsConfig {
+    /// Port accepting TLS connections, if any
+    pub port: Option<u16>,
+
+    /// Certificate chain and private key presented to clients, and to our master
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+
+    /// Certificates of the authorities trusted to authenticate clients and our master
+    pub ca_cert_file: Option<PathBuf>,
+
+    pub auth_clients: AuthClients,
+
+    /// Connect to our master over TLS
+    pub replication: bool,
+}
+
+impl TlsConfig {
+    /// Acceptor terminating TLS for the connections of clients
+    pub fn acceptor(&self) -> TlsResult<TlsAcceptor> {
+        let (certs, key) = self.identity()?.ok_or(TlsError::MissingCertificate)?;
+
+        let builder = ServerConfig::builder();
+        let builder = match self.auth_clients {
+            AuthClients::No => builder.with_no_client_auth(),
+            auth => {
+                let verifier = WebPkiClientVerifier::builder(Arc::new(self.roots()?));
+                let verifier = if auth == AuthClients::Optional {
+                    verifier.allow_unauthenticated().build()?
+                } else {
+                    verifier.build()?
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+
+        let config = builder.with_single_cert(certs, key)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    /// Connector to our master, authenticating with our own certificate if we have one
+    pub fn connector(&self) -> TlsResult<TlsConnector> {
+        let builder = ClientConfig::builder().with_root_certificates(self.roots()?);
+        let config = match self.identity()? {
+            Some((certs, key)) => builder.with_client_auth_cert(certs, key)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    /// Our certificate chain and private key, if configured
+    fn identity(
+        &self,
+    ) -> TlsResult<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>> {
+        let (Some(cert_file), Some(key_file)) = (&self.cert_file, &self.key_file) else {
+            return Ok(None);
+        };
+
+        let certs = certificates(cert_file)?;
+        let key = rustls_pemfile::private_key(&mut reader(key_file)?)
+            .map_err(|source| read_error(key_file, source))?
+            .ok_or_else(|| TlsError::NoPrivateKey(key_file.clone()))?;
+
+        Ok(Some((certs, key)))
+    }
+
+    fn roots(&self) -> TlsResult<RootCertStore> {
+        let ca_cert_file = self
+            .ca_cert_file
+            .as_deref()
+            .ok_or(TlsError::MissingCaCertificate)?;
+
+        let mut roots = RootCertStore::empty();
+        for cert in certificates(ca_cert_file)? {
+            roots.add(cert)?;
+        }
+        Ok(roots)
+    }
+}
+
+/// A listener whose connections are encrypted with TLS
+pub(super) struct TlsListener {
+    pub(super) listener: TcpListener,
+    pub(super) acceptor: TlsAcceptor,
+}
+
+/// Accept a connection on the TLS `listener`, which never completes if there is none.
+/// The TLS handshake is left to the caller, so that it does not hold up other connections
+pub(super) async fn accept(listener: Option<&TlsListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(tls) => tls.listener.accept().await,
+        None => future::pending().await,
+    }
+}
+
+fn certificates(path: &Path) -> TlsResult<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut reader(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| read_error(path, source))?;
+
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_owned()));
+    }
+    Ok(certs)
+}
+
+fn reader(path: &Path) -> TlsResult<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| read_error(path, source))
+}
+
+fn read_error(path: &Path, source: io::Error) -> TlsError {
+    TlsError::Read {
+        path: path.to_owned(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// Generate a certificate authority and a certificate for `localhost` signed by it, returning
+    /// the configuration using them
+    fn config(dir: &Path, auth_clients: AuthClients) -> TlsConfig {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+        std::fs::create_dir_all(dir).unwrap();
+        let path = |name: &str, content: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            Some(path)
+        };
+
+        TlsConfig {
+            port: None,
+            cert_file: path("memora.crt", cert.pem()),
+            key_file: path("memora.key", key.serialize_pem()),
+            ca_cert_file: path("ca.crt", ca.pem()),
+            auth_clients,
+            replication: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_authenticate_both_ends() {
+        let dir = std::env::temp_dir().join(format!("memora-tls-{}", std::process::id()));
+        let config = config(&dir, AuthClients::Yes);
+
+        let (client, server) = tokio::io::duplex(4096);
+        let acceptor = config.acceptor().unwrap();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(server).await.unwrap();
+            let mut buf = [0; 6];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(b"+PONG\r\n").await.unwrap();
+            stream.flush().await.unwrap();
+            buf
+        });
+
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = config
+            .connector()
+            .unwrap()
+            .connect(name, client)
+            .await
+            .unwrap();
+        stream.write_all(b"PING\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+        assert_eq!(&server.await.unwrap(), b"PING\r\n");
+
+        // Clients without a certificate are rejected
+        let anonymous = TlsConfig {
+            cert_file: None,
+            key_file: None,
+            ..config.clone()
+        };
+        let (client, server) = tokio::io::duplex(4096);
+        let acceptor = config.acceptor().unwrap();
+        let server = tokio::spawn(async move { acceptor.accept(server).await.is_ok() });
+
+        let name = ServerName::try_from("localhost").unwrap();
+        if let Ok(mut stream) = anonymous.connector().unwrap().connect(name, client).await {
+            // TLS 1.3 clients only learn that they got rejected on their first read
+            let _ = stream.write_all(b"PING\r\n").await;
+            let _ = stream.flush().await;
+            assert!(stream.read(&mut [0; 1]).await.map_or(true, |n| n == 0));
+        }
+        assert!(!server.await.unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
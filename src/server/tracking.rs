@@ -0,0 +1,395 @@
+//! Tracking of the keys cached by clients, which get told to invalidate their copy of a key once
+//! it gets modified.
+//!
+//! Like Redis, clients either track the keys they read, or get invalidations broadcasted for
+//! every key matching a set of prefixes. The table of the keys read does not tell databases apart.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::resp::Value;
+
+use super::{cmd::TrackingOptions, pubsub::PubSub, ClientHandle, ClientId};
+
+/// Channel the invalidation messages get published to, for the clients redirected to
+pub(super) const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+#[derive(Debug, Error)]
+pub enum TrackingError {
+    #[error("The client ID you want redirect to does not exist")]
+    NoSuchRedirect,
+
+    #[error(
+        "Tracking without REDIRECT needs RESP3 push messages, switch protocols with HELLO 3 first"
+    )]
+    NoRedirectOnResp2,
+
+    #[error(
+        "You can't switch BCAST mode on/off before disabling tracking for this client, and then \
+         re-enabling it with a different mode."
+    )]
+    SwitchBcast,
+
+    #[error(
+        "You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then \
+         re-enabling it with a different mode."
+    )]
+    SwitchOptMode,
+
+    #[error(
+        "Prefix '{0}' overlaps with an existing prefix '{1}'. Prefixes for a single client must \
+         not overlap."
+    )]
+    PrefixOverlap(String, String),
+
+    #[error(
+        "CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or \
+         OPTOUT mode enabled"
+    )]
+    CachingWithoutOptMode,
+
+    #[error("CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")]
+    CachingYesWithoutOptIn,
+
+    #[error("CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")]
+    CachingNoWithoutOptOut,
+}
+
+/// Tracking state of a client
+#[derive(Debug, Default)]
+pub(super) struct Tracker {
+    /// Client the invalidation messages get sent to, if not the tracking client
+    pub(super) redirect: Option<ClientId>,
+
+    pub(super) bcast: bool,
+    pub(super) optin: bool,
+    pub(super) optout: bool,
+    pub(super) noloop: bool,
+
+    /// Prefixes of the keys broadcasted to the client
+    pub(super) prefixes: BTreeSet<String>,
+
+    /// Set by `CLIENT CACHING` for the next command of the client
+    pub(super) caching: Option<bool>,
+
+    /// Whether the client redirected to went away
+    pub(super) broken_redirect: bool,
+}
+
+impl Tracker {
+    /// Whether the keys read by the current command of the client get tracked
+    fn tracks_reads(&self) -> bool {
+        if self.bcast {
+            false
+        } else if self.optin {
+            self.caching == Some(true)
+        } else if self.optout {
+            self.caching != Some(false)
+        } else {
+            true
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Tracking {
+    clients: HashMap<ClientId, Tracker>,
+
+    /// Clients that read every key since it last got invalidated
+    keys: HashMap<String, HashSet<ClientId>>,
+
+    /// Clients in `BCAST` mode, by prefix
+    prefixes: BTreeMap<String, HashSet<ClientId>>,
+}
+
+impl Tracking {
+    /// Turn on tracking for `client`, or update the options of its tracking
+    pub(super) fn enable(
+        &mut self,
+        client: ClientId,
+        options: TrackingOptions,
+    ) -> Result<(), TrackingError> {
+        let mut prefixes = options.prefixes;
+        if options.bcast && prefixes.is_empty() {
+            prefixes.push(String::new());
+        }
+
+        if let Some(tracker) = self.clients.get(&client) {
+            if tracker.bcast != options.bcast {
+                return Err(TrackingError::SwitchBcast);
+            }
+            if tracker.optin != options.optin || tracker.optout != options.optout {
+                return Err(TrackingError::SwitchOptMode);
+            }
+        }
+
+        let existing = self.clients.get(&client).map(|tracker| &tracker.prefixes);
+        for (i, prefix) in prefixes.iter().enumerate() {
+            let others = existing
+                .into_iter()
+                .flatten()
+                .chain(&prefixes[i + 1..])
+                .filter(|other| *other != prefix);
+            for other in others {
+                if prefix.starts_with(other.as_str()) || other.starts_with(prefix.as_str()) {
+                    return Err(TrackingError::PrefixOverlap(prefix.clone(), other.clone()));
+                }
+            }
+        }
+
+        let tracker = self.clients.entry(client).or_default();
+        tracker.redirect = options.redirect;
+        tracker.bcast = options.bcast;
+        tracker.optin = options.optin;
+        tracker.optout = options.optout;
+        tracker.noloop = options.noloop;
+        tracker.broken_redirect = false;
+
+        for prefix in prefixes {
+            self.prefixes
+                .entry(prefix.clone())
+                .or_default()
+                .insert(client);
+            tracker.prefixes.insert(prefix);
+        }
+
+        Ok(())
+    }
+
+    /// Turn off tracking for `client`
+    pub(super) fn disable(&mut self, client: ClientId) {
+        let Some(tracker) = self.clients.remove(&client) else {
+            return;
+        };
+
+        // Keys read by the client are forgotten about once they get invalidated
+        for prefix in tracker.prefixes {
+            if let Some(clients) = self.prefixes.get_mut(&prefix) {
+                clients.remove(&client);
+                if clients.is_empty() {
+                    self.prefixes.remove(&prefix);
+                }
+            }
+        }
+    }
+
+    pub(super) fn get(&self, client: ClientId) -> Option<&Tracker> {
+        self.clients.get(&client)
+    }
+
+    /// Track or not the keys read by the next command of `client`, depending on `yes`
+    pub(super) fn caching(&mut self, client: ClientId, yes: bool) -> Result<(), TrackingError> {
+        let tracker = self
+            .clients
+            .get_mut(&client)
+            .filter(|tracker| tracker.optin || tracker.optout)
+            .ok_or(TrackingError::CachingWithoutOptMode)?;
+
+        if yes && !tracker.optin {
+            return Err(TrackingError::CachingYesWithoutOptIn);
+        }
+        if !yes && !tracker.optout {
+            return Err(TrackingError::CachingNoWithoutOptOut);
+        }
+
+        tracker.caching = Some(yes);
+        Ok(())
+    }
+
+    /// Forget about the `CLIENT CACHING` of `client`, once its next command executed
+    pub(super) fn reset_caching(&mut self, client: ClientId) {
+        if let Some(tracker) = self.clients.get_mut(&client) {
+            tracker.caching = None;
+        }
+    }
+
+    /// Remember that `client` read `keys`, if it tracks them
+    pub(super) fn remember<'a>(
+        &mut self,
+        client: ClientId,
+        keys: impl IntoIterator<Item = &'a str>,
+    ) {
+        if !self.clients.get(&client).is_some_and(Tracker::tracks_reads) {
+            return;
+        }
+
+        for key in keys {
+            self.keys.entry(key.to_owned()).or_default().insert(client);
+        }
+    }
+
+    /// Tell the clients that read `key`, or that get it broadcasted, to invalidate it.
+    /// `caller` is the client that modified the key, if any
+    pub(super) fn invalidate(
+        &mut self,
+        key: &str,
+        caller: Option<ClientId>,
+        clients: &HashMap<ClientId, ClientHandle>,
+        pubsub: &PubSub,
+    ) {
+        let readers = self.keys.remove(key).unwrap_or_default();
+        let broadcasted = self
+            .prefixes
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .flat_map(|(_, clients)| clients.iter().copied())
+            .collect::<HashSet<_>>();
+
+        for client in readers.into_iter().chain(broadcasted) {
+            let Some(tracker) = self.clients.get(&client) else {
+                continue;
+            };
+            if tracker.noloop && caller == Some(client) {
+                continue;
+            }
+
+            self.send(
+                client,
+                Value::from_iter([Value::bulk(key)]),
+                clients,
+                pubsub,
+            );
+        }
+    }
+
+    /// Tell every tracking client to invalidate all of its keys, after a database got flushed
+    pub(super) fn invalidate_all(
+        &mut self,
+        clients: &HashMap<ClientId, ClientHandle>,
+        pubsub: &PubSub,
+    ) {
+        self.keys.clear();
+
+        let tracking = self.clients.keys().copied().collect::<Vec<_>>();
+        for client in tracking {
+            self.send(client, Value::null_bulk(), clients, pubsub);
+        }
+    }
+
+    /// Send the invalidation of `keys` to the tracking `client`, or to the client it redirects to
+    fn send(
+        &mut self,
+        client: ClientId,
+        keys: Value,
+        clients: &HashMap<ClientId, ClientHandle>,
+        pubsub: &PubSub,
+    ) {
+        let Some(tracker) = self.clients.get_mut(&client) else {
+            return;
+        };
+
+        let (target, message) = match tracker.redirect {
+            // Invalidations are sent to the tracking client itself as RESP3 push messages
+            None => {
+                let Some(target) = clients.get(&client) else {
+                    return;
+                };
+                if target.record().resp < 3 {
+                    return;
+                }
+                (target, Value::Push(vec![Value::bulk("invalidate"), keys]))
+            }
+            Some(redirect) => {
+                let Some(target) = clients.get(&redirect) else {
+                    tracker.broken_redirect = true;
+                    return;
+                };
+
+                // Like Redis, the client redirected to must be in pub/sub mode to get the messages
+                if pubsub.subscriptions(redirect) == 0 {
+                    return;
+                }
+
+                let message = Value::from_iter([
+                    Value::bulk("message"),
+                    Value::bulk(INVALIDATE_CHANNEL),
+                    keys,
+                ]);
+                (target, message)
+            }
+        };
+
+        let mut buf = Vec::new();
+        message
+            .encode(&mut buf)
+            .expect("encoding to an in-memory buffer should not fail");
+
+        if !target.push(Bytes::from(buf)) {
+            warn!(
+                "disconnecting client {} that can not keep up with invalidation messages",
+                target.addr()
+            );
+            target.kill();
+        }
+    }
+
+    /// Number of clients with tracking turned on
+    pub(super) fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Number of keys read by at least one tracking client
+    pub(super) fn keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Number of prefixes broadcasted to at least one client
+    pub(super) fn prefixes(&self) -> usize {
+        self.prefixes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_honor_caching_in_opt_modes() {
+        let mut tracking = Tracking::default();
+
+        let optin = TrackingOptions {
+            optin: true,
+            ..TrackingOptions::default()
+        };
+        tracking.enable(1, optin).unwrap();
+        tracking.remember(1, ["ignored"]);
+        tracking.caching(1, true).unwrap();
+        tracking.remember(1, ["cached"]);
+        tracking.reset_caching(1);
+        tracking.remember(1, ["forgotten"]);
+        assert_eq!(tracking.keys(), 1);
+
+        assert!(matches!(
+            tracking.caching(1, false),
+            Err(TrackingError::CachingNoWithoutOptOut)
+        ));
+        assert!(matches!(
+            tracking.enable(1, TrackingOptions::default()),
+            Err(TrackingError::SwitchOptMode)
+        ));
+    }
+
+    #[test]
+    fn should_reject_overlapping_prefixes() {
+        let mut tracking = Tracking::default();
+        let bcast = |prefixes: &[&str]| TrackingOptions {
+            bcast: true,
+            prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+            ..TrackingOptions::default()
+        };
+
+        tracking.enable(1, bcast(&["user:", "item:"])).unwrap();
+        assert!(matches!(
+            tracking.enable(1, bcast(&["user:1"])),
+            Err(TrackingError::PrefixOverlap(..))
+        ));
+        assert!(matches!(
+            tracking.enable(2, bcast(&["a", "ab"])),
+            Err(TrackingError::PrefixOverlap(..))
+        ));
+        assert_eq!(tracking.prefixes(), 2);
+    }
+}
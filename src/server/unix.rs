@@ -0,0 +1,84 @@
+//! Listener accepting the connections of clients on a Unix domain socket
+
+use std::{
+    fs::{self, Permissions},
+    future, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use tokio::net::UnixStream;
+use tracing::warn;
+
+/// A listener bound to a Unix domain socket, which removes its socket file when dropped
+pub(super) struct UnixListener {
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+}
+
+impl UnixListener {
+    /// Bind to the socket at `path`, replacing any stale socket file, and restrict its
+    /// permissions to `perm` if set
+    pub(super) fn bind(path: PathBuf, perm: Option<u32>) -> io::Result<Self> {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        if let Some(perm) = perm {
+            fs::set_permissions(&path, Permissions::from_mode(perm))?;
+        }
+
+        Ok(Self { listener, path })
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("failed to remove unix socket {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Accept a connection on the unix `listener`, which never completes if there is none
+pub(super) async fn accept(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(unix) => unix.listener.accept().await.map(|(stream, _)| stream),
+        None => future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_accept_with_permissions() {
+        let path = std::env::temp_dir().join(format!("memora-{}.sock", std::process::id()));
+        // A stale socket file does not prevent binding
+        fs::write(&path, b"").unwrap();
+
+        let listener = UnixListener::bind(path.clone(), Some(0o700)).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        let mut server = accept(Some(&listener)).await.unwrap();
+        client.write_all(b"PING\r\n").await.unwrap();
+
+        let mut buf = [0; 6];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"PING\r\n");
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,198 @@
+//! Clients blocked by `WAIT` and `WAITAOF` until enough replicas acknowledge the replication stream
+
+use thiserror::Error;
+use tokio::{sync::oneshot, time::Instant};
+
+use crate::resp::Value;
+
+use super::{role::Ack, Response, Role};
+
+#[derive(Debug, Error)]
+pub enum WaitError {
+    #[error("WAITAOF cannot be used when numlocal is set but appendonly is disabled")]
+    AppendOnlyDisabled,
+}
+
+/// A client waiting for `numreplicas` replicas to acknowledge `offset`
+pub(super) struct Waiter {
+    ack: Ack,
+    offset: u64,
+    numreplicas: usize,
+    deadline: Option<Instant>,
+}
+
+impl Waiter {
+    pub(super) fn new(
+        ack: Ack,
+        offset: u64,
+        numreplicas: usize,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            ack,
+            offset,
+            numreplicas,
+            deadline,
+        }
+    }
+
+    /// Whether enough replicas acknowledged the offset already, so the client does not block
+    pub(super) fn satisfied(&self, role: &impl Role) -> bool {
+        role.acked(self.offset, self.ack) >= self.numreplicas
+    }
+
+    /// Reply right away with the number of replicas that acknowledged the offset so far
+    pub(super) fn reply(&self, role: &impl Role) -> Response {
+        reply(self.ack, role.acked(self.offset, self.ack))
+    }
+}
+
+/// Build the reply to a wait for `ack` acknowledgements that `acked` replicas satisfied
+fn reply(ack: Ack, acked: usize) -> Response {
+    match ack {
+        Ack::Offset => Value::Int(acked as i64).into(),
+        // No local AOF is ever fsynced
+        Ack::Fsync => Value::from_iter([Value::Int(0), Value::Int(acked as i64)]).into(),
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Waiters(Vec<(Waiter, oneshot::Sender<Response>)>);
+
+impl Waiters {
+    /// Block a client until the `waiter` condition is met, replying on `tx`
+    pub(super) fn block(&mut self, waiter: Waiter, tx: oneshot::Sender<Response>) {
+        self.0.push((waiter, tx));
+    }
+
+    /// Number of blocked clients
+    pub(super) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The earliest instant at which a waiter times out
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.0
+            .iter()
+            .filter_map(|(waiter, _)| waiter.deadline)
+            .min()
+    }
+
+    /// Reply to every waiter that got enough acknowledgements or timed out at `now`
+    pub(super) fn unblock(&mut self, role: &impl Role, now: Instant) {
+        let waiters = std::mem::take(&mut self.0);
+
+        for (waiter, tx) in waiters {
+            // The client went away
+            if tx.is_closed() {
+                continue;
+            }
+
+            let acked = role.acked(waiter.offset, waiter.ack);
+            let expired = waiter.deadline.is_some_and(|deadline| deadline <= now);
+
+            if acked >= waiter.numreplicas || expired {
+                let _ = tx.send(reply(waiter.ack, acked));
+            } else {
+                self.0.push((waiter, tx));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    use crate::server::{cmd::ReplconfOption, rdb::Snapshot, role::Master, ClientHandle};
+
+    use super::*;
+
+    /// A master with a single replica attached, and the offset of its replication stream
+    fn master() -> (Master, ClientHandle, mpsc::Receiver<Bytes>, u64) {
+        let mut master = Master::new(1024);
+        let (replica, stream) = ClientHandle::new("127.0.0.1:6001".parse::<SocketAddr>().unwrap());
+        master
+            .psync(replica.clone(), "?", -1, Snapshot::default)
+            .unwrap();
+        master.propagate(&Value::from_iter([Value::bulk("PING")]));
+        let offset = master.wait_offset().unwrap();
+        (master, replica, stream, offset)
+    }
+
+    fn ack(master: &mut Master, replica: &ClientHandle, offset: u64) {
+        master.replconf(
+            replica,
+            vec![ReplconfOption::Ack(offset), ReplconfOption::Fack(0)],
+        );
+    }
+
+    fn block(waiters: &mut Waiters, waiter: Waiter) -> oneshot::Receiver<Response> {
+        let (tx, rx) = oneshot::channel();
+        waiters.block(waiter, tx);
+        rx
+    }
+
+    #[test]
+    fn should_reply_with_the_acks_so_far_on_timeout() {
+        let (mut master, replica, _stream, offset) = master();
+        ack(&mut master, &replica, offset - 1);
+
+        let now = Instant::now();
+        let deadline = now + Duration::from_millis(100);
+        let mut waiters = Waiters::default();
+        let mut rx = block(
+            &mut waiters,
+            Waiter::new(Ack::Offset, offset, 1, Some(deadline)),
+        );
+        assert_eq!(waiters.next_deadline(), Some(deadline));
+
+        waiters.unblock(&master, now);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(waiters.len(), 1);
+
+        waiters.unblock(&master, deadline);
+        assert_eq!(rx.try_recv().unwrap().0, vec![Value::Int(0)]);
+        assert_eq!(waiters.len(), 0);
+    }
+
+    #[test]
+    fn should_not_block_when_enough_replicas_acked() {
+        let (mut master, replica, _stream, offset) = master();
+
+        let waiter = Waiter::new(Ack::Offset, offset, 1, None);
+        assert!(!waiter.satisfied(&master));
+        assert!(Waiter::new(Ack::Offset, offset, 0, None).satisfied(&master));
+
+        ack(&mut master, &replica, offset);
+        assert!(waiter.satisfied(&master));
+        assert_eq!(waiter.reply(&master).0, vec![Value::Int(1)]);
+
+        // Replicas have no AOF, so they never acknowledge anything as fsynced
+        let waiter = Waiter::new(Ack::Fsync, offset, 1, None);
+        assert!(!waiter.satisfied(&master));
+        assert_eq!(
+            waiter.reply(&master).0,
+            vec![Value::from_iter([Value::Int(0), Value::Int(0)])]
+        );
+    }
+
+    #[test]
+    fn should_unblock_once_replicas_ack() {
+        let (mut master, replica, _stream, offset) = master();
+
+        let mut waiters = Waiters::default();
+        let mut rx = block(&mut waiters, Waiter::new(Ack::Offset, offset, 1, None));
+        assert_eq!(waiters.next_deadline(), None);
+
+        waiters.unblock(&master, Instant::now());
+        assert!(rx.try_recv().is_err());
+
+        ack(&mut master, &replica, offset);
+        waiters.unblock(&master, Instant::now());
+        assert_eq!(rx.try_recv().unwrap().0, vec![Value::Int(1)]);
+    }
+}